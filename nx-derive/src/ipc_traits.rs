@@ -292,7 +292,7 @@ pub fn ipc_trait(_args: TokenStream, ipc_trait: TokenStream) -> syn::Result<Toke
                     walker.reset_with(ctx.in_params.data_offset);
                     #( ::nx::ipc::client::RequestCommandParameter::before_send_sync_request(&#client_in_param_names, &mut walker, &mut ctx)?; )*
 
-                    ::nx::svc::send_sync_request(self.get_session().object_info.handle)?;
+                    ::nx::ipc::client::send_sync_request(self.get_session().object_info.handle)?;
 
                     match self.get_session().object_info.protocol {
                         ::nx::ipc::CommandProtocol::Cmif => ::nx::ipc::cmif::client::read_request_command_response_from_msg_buffer(&mut ctx)?,
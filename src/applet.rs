@@ -2,16 +2,21 @@
 
 use crate::hbl::{AppletType, get_applet_type};
 use crate::ipc::sf;
+use crate::ipc::sf::account::Uid;
 use crate::result::*;
 use crate::service;
 use crate::svc;
 use crate::sync::{ReadGuard, RwLock};
 use crate::version::{Version, get_version};
 
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 use core::sync::atomic::AtomicU64;
 
 pub use crate::service::applet::*;
 
+pub mod rc;
+
 static ALL_SYSTEM_APPLET_PROXY_SERVICE: RwLock<Option<AllSystemAppletProxiesService>> =
     RwLock::new(None);
 static LIBRARY_APPLET_PROXY: RwLock<Option<AppletProxy>> = RwLock::new(None);
@@ -192,3 +197,310 @@ pub fn get_applet_proxy<'a>() -> ReadGuard<'a, Option<AppletProxy>> {
 pub fn get_system_proxy_service<'a>() -> ReadGuard<'a, Option<AllSystemAppletProxiesService>> {
     ALL_SYSTEM_APPLET_PROXY_SERVICE.read()
 }
+
+/// Represents the launch parameter with the user account preselected when launching the application
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct PreselectedUserLaunchParameter {
+    magic: u32,
+    is_user_selected: u8,
+    pad: [u8; 3],
+    uid: Uid,
+    unk: [u8; 0x70],
+}
+const_assert!(core::mem::size_of::<PreselectedUserLaunchParameter>() == 0x88);
+
+impl PreselectedUserLaunchParameter {
+    const MAGIC: u32 = 0xC79497CA;
+}
+
+/// Pops a launch parameter the application was launched with
+///
+/// This will fail with [`ResultNotInitialized`][`crate::rc::ResultNotInitialized`] if applet support isn't initialized, or with [`ResultNotSupported`][`crate::rc::ResultNotSupported`] if the current applet isn't an application
+///
+/// # Arguments
+///
+/// * `kind`: The kind of launch parameter to pop
+pub fn pop_launch_parameter(kind: LaunchParameterKind) -> Result<Storage> {
+    let proxy_guard = get_applet_proxy();
+    match proxy_guard
+        .as_ref()
+        .ok_or(crate::rc::ResultNotInitialized::make())?
+    {
+        AppletProxy::Application(proxy) => proxy
+            .get_application_functions()?
+            .pop_launch_parameter(kind),
+        _ => crate::rc::ResultNotSupported::make_err(),
+    }
+}
+
+/// Gets the user account preselected when launching the application (when the application's startup user account setting requires it)
+///
+/// Note that the launch parameter is popped, so this can only succeed once
+pub fn get_preselected_user() -> Result<Uid> {
+    let storage = pop_launch_parameter(LaunchParameterKind::PreselectedUser)?;
+    let mut param: PreselectedUserLaunchParameter = unsafe { core::mem::zeroed() };
+    let storage_accessor = storage.open()?;
+    storage_accessor.read(0, sf::Buffer::from_other_mut_var(&mut param))?;
+    result_return_unless!(
+        (param.magic == PreselectedUserLaunchParameter::MAGIC) && (param.is_user_selected != 0),
+        rc::ResultInvalidLaunchParameter
+    );
+
+    Ok(param.uid)
+}
+
+/// Represents how the applet behaves when it loses focus
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum FocusHandlingMode {
+    /// The applet is suspended when the HOME menu is opened or the console goes to sleep
+    #[default]
+    SuspendHomeSleep,
+    /// The applet is never suspended
+    NoSuspend,
+    /// Like [`FocusHandlingMode::SuspendHomeSleep`], but the applet is notified about focus changes
+    SuspendHomeSleepNotify,
+    /// The applet is always suspended when it loses focus
+    AlwaysSuspend,
+}
+
+/// Represents the applet state tracked by a [`MessageLoop`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct AppletState {
+    /// The current focus state
+    pub focus_state: FocusState,
+    /// The current operation mode (handheld/docked)
+    pub operation_mode: OperationMode,
+    /// The current performance mode
+    pub performance_mode: PerformanceMode,
+    /// Whether the system requested the applet to exit
+    pub exit_requested: bool,
+}
+
+/// Represents a callback run for every received message (both known and unknown ones, see [`AppletMessage::from_raw`]), after the [`AppletState`] is updated
+pub type MessageCallback = Box<dyn FnMut(u32, &AppletState)>;
+
+/// Result value returned by `ReceiveMessage` when there are no more messages
+const NO_MESSAGE_RESULT_VALUE: u32 = 0x680;
+
+const fn operation_mode_from_raw(raw: u8) -> OperationMode {
+    match raw {
+        1 => OperationMode::Console,
+        _ => OperationMode::Handheld,
+    }
+}
+
+const fn performance_mode_from_raw(raw: u32) -> PerformanceMode {
+    match raw {
+        0 => PerformanceMode::Normal,
+        1 => PerformanceMode::Boost,
+        _ => PerformanceMode::Invalid,
+    }
+}
+
+const fn focus_state_from_raw(raw: u8) -> FocusState {
+    match raw {
+        2 => FocusState::OutOfFocus,
+        3 => FocusState::Background,
+        _ => FocusState::InFocus,
+    }
+}
+
+/// Represents the applet message loop, which receives the messages the system sends to the applet and tracks the applet state with them
+///
+/// Exit is locked while this exists, so the system (for instance, when closing the application from the HOME menu) sends [`AppletMessage::ExitRequest`] instead of terminating the process right away.
+/// Once that happens, [`process`][`MessageLoop::process`] returns `false` and the applet is expected to clean up and exit, which unlocks exit again (see [`Drop`])
+///
+/// ```ignore
+/// let mut message_loop = nx::applet::MessageLoop::new()?;
+/// message_loop.add_callback(|msg, state| {
+///     if msg == AppletMessage::OperationModeChanged as u32 {
+///         // Resize the UI for state.operation_mode...
+///     }
+/// });
+///
+/// while message_loop.process()? {
+///     // Render a frame...
+/// }
+/// // Save and clean up, then exit
+/// ```
+pub struct MessageLoop {
+    common_state_getter: CommonStateGetter,
+    self_controller: SelfController,
+    event_handle: svc::Handle,
+    state: AppletState,
+    callbacks: Vec<MessageCallback>,
+    exit_locked: bool,
+}
+
+impl MessageLoop {
+    /// Creates a new [`MessageLoop`], which enables operation/performance mode change notifications and locks exit
+    ///
+    /// This will fail with [`ResultNotInitialized`][`crate::rc::ResultNotInitialized`] if applet support isn't initialized (see [`initialize`])
+    pub fn new() -> Result<Self> {
+        let (common_state_getter, self_controller) = {
+            let proxy_guard = get_applet_proxy();
+            let proxy = proxy_guard
+                .as_ref()
+                .ok_or(crate::rc::ResultNotInitialized::make())?;
+            (
+                proxy.get_common_state_getter()?,
+                proxy.get_self_controller()?,
+            )
+        };
+
+        let event_handle = common_state_getter.get_event()?.handle;
+        let state = AppletState {
+            focus_state: focus_state_from_raw(common_state_getter.get_current_focus_state()?),
+            operation_mode: operation_mode_from_raw(common_state_getter.get_operation_mode()?),
+            performance_mode: performance_mode_from_raw(
+                common_state_getter.get_performance_mode()?,
+            ),
+            exit_requested: false,
+        };
+        let mut message_loop = Self {
+            common_state_getter,
+            self_controller,
+            event_handle,
+            state,
+            callbacks: Vec::new(),
+            exit_locked: false,
+        };
+
+        message_loop
+            .self_controller
+            .set_operation_mode_changed_notification(true)?;
+        message_loop
+            .self_controller
+            .set_performance_mode_changed_notification(true)?;
+        message_loop.self_controller.lock_exit()?;
+        message_loop.exit_locked = true;
+
+        Ok(message_loop)
+    }
+
+    /// Registers a callback, run for every received message
+    ///
+    /// # Arguments
+    ///
+    /// * `callback`: The callback to register
+    pub fn add_callback(&mut self, callback: impl FnMut(u32, &AppletState) + 'static) {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    /// Sets how the applet behaves when it loses focus
+    ///
+    /// # Arguments
+    ///
+    /// * `mode`: The mode to set
+    pub fn set_focus_handling_mode(&mut self, mode: FocusHandlingMode) -> Result<()> {
+        let (suspend, notify_lost, notify_gained, out_of_focus_suspending) = match mode {
+            FocusHandlingMode::SuspendHomeSleep => (false, false, true, false),
+            FocusHandlingMode::NoSuspend => (false, false, false, false),
+            FocusHandlingMode::SuspendHomeSleepNotify => (true, false, true, false),
+            FocusHandlingMode::AlwaysSuspend => (true, true, false, true),
+        };
+
+        self.self_controller
+            .set_focus_handling_mode(suspend, notify_lost, notify_gained)?;
+        if get_version() >= Version::new(2, 0, 0) {
+            self.self_controller
+                .set_out_of_focus_suspending_enabled(out_of_focus_suspending)?;
+        }
+        Ok(())
+    }
+
+    fn handle_message(&mut self, msg: u32) -> Result<()> {
+        match AppletMessage::from_raw(msg) {
+            Some(AppletMessage::ExitRequest) => self.state.exit_requested = true,
+            Some(AppletMessage::FocusStateChanged) => {
+                self.state.focus_state =
+                    focus_state_from_raw(self.common_state_getter.get_current_focus_state()?)
+            }
+            Some(AppletMessage::OperationModeChanged) => {
+                self.state.operation_mode =
+                    operation_mode_from_raw(self.common_state_getter.get_operation_mode()?)
+            }
+            Some(AppletMessage::PerformanceMode) => {
+                self.state.performance_mode =
+                    performance_mode_from_raw(self.common_state_getter.get_performance_mode()?)
+            }
+            _ => {}
+        }
+
+        for callback in self.callbacks.iter_mut() {
+            callback(msg, &self.state);
+        }
+        Ok(())
+    }
+
+    fn receive_messages(&mut self, timeout: i64) -> Result<()> {
+        if svc::wait_synchronization_one(self.event_handle, timeout).is_err() {
+            // No messages (or the wait timed out)
+            return Ok(());
+        }
+
+        loop {
+            match self.common_state_getter.receive_raw_message() {
+                Ok(msg) => self.handle_message(msg)?,
+                Err(rc) if rc.get_value() == NO_MESSAGE_RESULT_VALUE => break Ok(()),
+                Err(rc) => break Err(rc),
+            }
+        }
+    }
+
+    /// Handles all the pending messages without blocking, returning whether the applet should keep running (`false` once exit was requested)
+    ///
+    /// This is meant to be called once per frame, like libnx's `appletMainLoop`
+    pub fn process(&mut self) -> Result<bool> {
+        self.receive_messages(0)?;
+        Ok(!self.state.exit_requested)
+    }
+
+    /// Waits for messages and handles them, returning whether the applet should keep running (`false` once exit was requested)
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout`: The wait timeout (in nanoseconds), `None` meaning no timeout
+    pub fn wait(&mut self, timeout: Option<i64>) -> Result<bool> {
+        self.receive_messages(timeout.unwrap_or(-1))?;
+        Ok(!self.state.exit_requested)
+    }
+
+    /// Gets the current applet state
+    #[inline]
+    pub fn get_state(&self) -> &AppletState {
+        &self.state
+    }
+
+    /// Gets whether the applet currently has focus
+    #[inline]
+    pub fn is_in_focus(&self) -> bool {
+        self.state.focus_state == FocusState::InFocus
+    }
+
+    /// Gets whether the system requested the applet to exit
+    #[inline]
+    pub fn is_exit_requested(&self) -> bool {
+        self.state.exit_requested
+    }
+
+    /// Unlocks exit, allowing the system to terminate the process right away
+    ///
+    /// After an exit request, this tells the system the applet is done cleaning up (this is also done when the [`MessageLoop`] is dropped)
+    pub fn unlock_exit(&mut self) -> Result<()> {
+        if self.exit_locked {
+            self.self_controller.unlock_exit()?;
+            self.exit_locked = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MessageLoop {
+    /// Unlocks exit (if still locked) and closes the message event handle
+    fn drop(&mut self) {
+        let _ = self.unlock_exit();
+        let _ = svc::close_handle(self.event_handle);
+    }
+}
@@ -88,6 +88,11 @@ pub mod vty {
         fn to_raw(self) -> Self::RawType {
             RGBA8::new_scaled(self.r(), self.g(), self.b(), 255).to_raw()
         }
+
+        #[inline(always)]
+        fn to_scaled(self) -> (u8, u8, u8, u8) {
+            (self.r(), self.g(), self.b(), 255)
+        }
     }
 
     impl OriginDimensions for PersistentBufferedCanvas {
@@ -6,6 +6,9 @@ use core::sync::atomic::Ordering::SeqCst;
 use unwinding::custom_eh_frame_finder::{FrameInfo, FrameInfoKind};
 
 pub mod mod0;
+pub mod nacp;
+pub mod nro;
+pub mod nso;
 pub mod rc;
 
 /// Represents ELF tags.
@@ -0,0 +1,260 @@
+//! NACP (application control property) format utils
+
+use super::rc;
+use crate::result::*;
+use crate::util;
+use crate::util::ArrayString;
+use core::mem;
+
+/// Represents the languages of the [`ApplicationTitle`]s in a [`Nacp`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u8)]
+#[allow(missing_docs)]
+pub enum Language {
+    #[default]
+    AmericanEnglish = 0,
+    BritishEnglish = 1,
+    Japanese = 2,
+    French = 3,
+    German = 4,
+    LatinAmericanSpanish = 5,
+    Spanish = 6,
+    Italian = 7,
+    Dutch = 8,
+    CanadianFrench = 9,
+    Portuguese = 10,
+    Russian = 11,
+    Korean = 12,
+    TraditionalChinese = 13,
+    SimplifiedChinese = 14,
+    BrazilianPortuguese = 15,
+}
+
+impl Language {
+    /// The number of languages
+    pub const COUNT: usize = 16;
+}
+
+/// Represents the title information for a [`Language`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct ApplicationTitle {
+    /// The application name
+    pub name: ArrayString<0x200>,
+    /// The application publisher
+    pub publisher: ArrayString<0x100>,
+}
+const_assert!(mem::size_of::<ApplicationTitle>() == 0x300);
+
+/// Represents the NACP structure, containing application metadata.
+///
+/// Only the commonly used fields are named, the rest of the structure is kept as raw reserved data.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct Nacp {
+    /// The titles, indexed by [`Language`]
+    pub titles: [ApplicationTitle; Language::COUNT],
+    /// The ISBN
+    pub isbn: ArrayString<0x25>,
+    /// The startup user account mode
+    pub startup_user_account: u8,
+    /// The user account switch lock mode
+    pub user_account_switch_lock: u8,
+    /// The add-on content registration type
+    pub add_on_content_registration_type: u8,
+    /// The attribute flags
+    pub attribute_flag: u32,
+    /// The supported languages, as a bit set of [`Language`]s
+    pub supported_language_flag: u32,
+    /// The parental control flags
+    pub parental_control_flag: u32,
+    /// The screenshot mode
+    pub screenshot: u8,
+    /// The video capture mode
+    pub video_capture: u8,
+    /// The data loss confirmation mode
+    pub data_loss_confirmation: u8,
+    /// The play log policy
+    pub play_log_policy: u8,
+    /// The presence group ID
+    pub presence_group_id: u64,
+    /// The rating ages, per rating organization
+    pub rating_age: [i8; 0x20],
+    /// The display version string
+    pub display_version: ArrayString<0x10>,
+    /// The base ID of add-on contents
+    pub add_on_content_base_id: u64,
+    /// The save data owner ID
+    pub save_data_owner_id: u64,
+    /// The size of user account save data
+    pub user_account_save_data_size: i64,
+    /// The journal size of user account save data
+    pub user_account_save_data_journal_size: i64,
+    /// The size of device save data
+    pub device_save_data_size: i64,
+    /// The journal size of device save data
+    pub device_save_data_journal_size: i64,
+    /// The size of the BCAT delivery cache storage
+    pub bcat_delivery_cache_storage_size: i64,
+    /// The application error code category
+    pub application_error_code_category: ArrayString<0x8>,
+    /// The local communication IDs
+    pub local_communication_id: [u64; 0x8],
+    /// The logo type
+    pub logo_type: u8,
+    /// The logo handling mode
+    pub logo_handling: u8,
+    /// The runtime add-on content install mode
+    pub runtime_add_on_content_install: u8,
+    /// The runtime parameter delivery mode
+    pub runtime_parameter_delivery: u8,
+    /// Reserved
+    pub reserved: [u8; 0x2],
+    /// The crash report mode
+    pub crash_report: u8,
+    /// The HDCP mode
+    pub hdcp: u8,
+    /// The seed for the pseudo device ID
+    pub seed_for_pseudo_device_id: u64,
+    /// The BCAT passphrase
+    pub bcat_passphrase: ArrayString<0x41>,
+    /// The startup user account options
+    pub startup_user_account_option: u8,
+    /// Reserved
+    pub reserved_2: [u8; 0x6],
+    /// The maximum size of user account save data
+    pub user_account_save_data_size_max: i64,
+    /// The maximum journal size of user account save data
+    pub user_account_save_data_journal_size_max: i64,
+    /// The maximum size of device save data
+    pub device_save_data_size_max: i64,
+    /// The maximum journal size of device save data
+    pub device_save_data_journal_size_max: i64,
+    /// The size of temporary storage
+    pub temporary_storage_size: i64,
+    /// The size of cache storage
+    pub cache_storage_size: i64,
+    /// The journal size of cache storage
+    pub cache_storage_journal_size: i64,
+    /// The maximum data and journal size of cache storage
+    pub cache_storage_data_and_journal_size_max: i64,
+    /// The maximum cache storage index
+    pub cache_storage_index_max: u16,
+    /// Reserved (the remaining, less commonly used fields)
+    pub reserved_3: [u8; 0xE76],
+}
+const_assert!(mem::size_of::<Nacp>() == 0x4000);
+
+impl Nacp {
+    /// Parses a [`Nacp`] from the given data
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The NACP data, which must be at least [`Nacp`]-sized
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        // SAFETY: the NACP only has integer/byte array fields (strings are validated below)
+        let nacp: Self =
+            unsafe { util::read_unaligned_at(data, 0) }.ok_or(rc::ResultInvalidNacpSize::make())?;
+
+        // ArrayString expects to always contain a NUL-terminator, which crafted NACPs could lack
+        fn is_terminated<const S: usize>(string: &ArrayString<S>) -> bool {
+            string.as_buffer().contains(&0)
+        }
+        result_return_unless!(
+            nacp.titles
+                .iter()
+                .all(|title| is_terminated(&title.name) && is_terminated(&title.publisher))
+                && is_terminated(&nacp.isbn)
+                && is_terminated(&nacp.display_version)
+                && is_terminated(&nacp.application_error_code_category)
+                && is_terminated(&nacp.bcat_passphrase),
+            rc::ResultInvalidNacpString
+        );
+        Ok(nacp)
+    }
+
+    /// Gets the [`ApplicationTitle`] for a given [`Language`]
+    ///
+    /// # Arguments
+    ///
+    /// * `language`: The [`Language`]
+    #[inline]
+    pub fn get_title(&self, language: Language) -> &ApplicationTitle {
+        &self.titles[language as usize]
+    }
+
+    /// Finds a non-empty [`ApplicationTitle`], preferring the given [`Language`] and falling back to the first non-empty one
+    ///
+    /// # Arguments
+    ///
+    /// * `language`: The preferred [`Language`]
+    pub fn find_title(&self, language: Language) -> Option<&ApplicationTitle> {
+        let preferred_title = self.get_title(language);
+        if !preferred_title.name.is_empty() {
+            return Some(preferred_title);
+        }
+
+        self.titles.iter().find(|title| !title.name.is_empty())
+    }
+
+    /// Gets whether a given [`Language`] is supported
+    ///
+    /// # Arguments
+    ///
+    /// * `language`: The [`Language`]
+    #[inline]
+    pub const fn supports_language(&self, language: Language) -> bool {
+        (self.supported_language_flag & bit!(language as u32)) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn make_nacp_data() -> Vec<u8> {
+        let mut data = vec![0u8; mem::size_of::<Nacp>()];
+        let title_offset = Language::Japanese as usize * mem::size_of::<ApplicationTitle>();
+        data[title_offset..title_offset + 4].copy_from_slice(b"Game");
+        data[mem::offset_of!(Nacp, display_version)..][..5].copy_from_slice(b"1.0.0");
+        data
+    }
+
+    #[test]
+    fn parse_titles() {
+        let nacp = Nacp::parse(&make_nacp_data()).unwrap();
+        assert_eq!(nacp.display_version.get_str(), Ok("1.0.0"));
+        assert!(nacp.get_title(Language::AmericanEnglish).name.is_empty());
+
+        let title = nacp.find_title(Language::AmericanEnglish).unwrap();
+        assert_eq!(title.name.get_str(), Ok("Game"));
+        assert!(title.publisher.is_empty());
+    }
+
+    #[test]
+    fn parse_invalid_size() {
+        let data = make_nacp_data();
+        let rc = Nacp::parse(&data[..0x3000]).unwrap_err();
+        assert!(rc::ResultInvalidNacpSize::matches(rc));
+    }
+
+    #[test]
+    fn parse_unterminated_strings() {
+        let fields = [
+            mem::offset_of!(ApplicationTitle, name),
+            mem::offset_of!(ApplicationTitle, publisher),
+            mem::offset_of!(Nacp, isbn),
+            mem::offset_of!(Nacp, display_version),
+            mem::offset_of!(Nacp, application_error_code_category),
+            mem::offset_of!(Nacp, bcat_passphrase),
+        ];
+        let sizes = [0x200, 0x100, 0x25, 0x10, 0x8, 0x41];
+        for (offset, size) in fields.into_iter().zip(sizes) {
+            let mut data = make_nacp_data();
+            data[offset..offset + size].fill(b'A');
+            let rc = Nacp::parse(&data).unwrap_err();
+            assert!(rc::ResultInvalidNacpString::matches(rc));
+        }
+    }
+}
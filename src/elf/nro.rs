@@ -0,0 +1,398 @@
+//! NRO format utils
+//!
+//! Besides the in-memory layout, this allows reading the metadata (icon, [`Nacp`][`nacp::Nacp`], RomFS location) of any NRO, either from its data or from a [`File`][`fs::File`]:
+//!
+//! ```ignore
+//! let mut nro_file = nx::fs::open_file("sdmc:/switch/app.nro", nx::fs::FileOpenOption::Read())?.into_file();
+//! let nro = Nro::read(nro_file.as_mut())?;
+//! let nacp = nro.read_nacp(nro_file.as_mut())?;
+//! let icon_jpeg = nro.read_icon(nro_file.as_mut())?;
+//! ```
+
+use super::nacp;
+use super::rc;
+#[cfg(feature = "fs")]
+use crate::fs;
+use crate::result::*;
+use crate::util;
+#[cfg(feature = "fs")]
+use alloc::vec::Vec;
+use core::mem;
+
+/// Represents the NRO start layout, which are the first `0x10` bytes of the NRO file (and of the `.text` segment).
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct Start {
+    /// Unused (the entrypoint branch instruction when loaded in memory)
+    pub unused: u32,
+    /// The offset of the `MOD0` header
+    pub mod0_offset: u32,
+    /// Padding
+    pub pad: [u8; 8],
+}
+const_assert!(mem::size_of::<Start>() == 0x10);
+
+/// Represents the location of a NRO segment inside the NRO file.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct Segment {
+    /// The segment file offset
+    pub file_offset: u32,
+    /// The segment size
+    pub size: u32,
+}
+const_assert!(mem::size_of::<Segment>() == 0x8);
+
+/// Represents the NRO header, located right after the [`Start`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct Header {
+    /// The magic, whose expected value is [`MAGIC`][`Header::MAGIC`]
+    pub magic: u32,
+    /// The format version
+    pub version: u32,
+    /// The size of the NRO itself, which is where the [`AssetHeader`] (if any) is located
+    pub size: u32,
+    /// The flags
+    pub flags: u32,
+    /// The `.text`, `.rodata` and `.data` segments
+    pub segments: [Segment; 3],
+    /// The BSS size
+    pub bss_size: u32,
+    /// Reserved
+    pub reserved: u32,
+    /// The module ID (build ID)
+    pub module_id: [u8; 0x20],
+    /// The DSO handle offset
+    pub dso_handle_offset: u32,
+    /// Reserved
+    pub reserved_2: u32,
+    /// The API info, `.dynstr` and `.dynsym` segments
+    pub segments_2: [Segment; 3],
+}
+const_assert!(mem::size_of::<Header>() == 0x70);
+
+impl Header {
+    /// The header magic value (`NRO0`)
+    pub const MAGIC: u32 = u32::from_le_bytes(*b"NRO0");
+
+    /// The offset of the header inside the NRO file
+    pub const OFFSET: usize = mem::size_of::<Start>();
+}
+
+/// Represents the location of an asset inside the asset section.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct AssetSection {
+    /// The asset offset, relative to the start of the [`AssetHeader`]
+    pub offset: u64,
+    /// The asset size
+    pub size: u64,
+}
+const_assert!(mem::size_of::<AssetSection>() == 0x10);
+
+impl AssetSection {
+    /// Gets whether the asset is present
+    #[inline]
+    pub const fn is_present(&self) -> bool {
+        self.size > 0
+    }
+}
+
+/// Represents the header of the (optional) asset section, located right after the NRO itself (see [`Header::size`]).
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct AssetHeader {
+    /// The magic, whose expected value is [`MAGIC`][`AssetHeader::MAGIC`]
+    pub magic: u32,
+    /// The format version
+    pub version: u32,
+    /// The JPEG icon
+    pub icon: AssetSection,
+    /// The NACP
+    pub nacp: AssetSection,
+    /// The RomFS image
+    pub romfs: AssetSection,
+}
+const_assert!(mem::size_of::<AssetHeader>() == 0x38);
+
+impl AssetHeader {
+    /// The asset header magic value (`ASET`)
+    pub const MAGIC: u32 = u32::from_le_bytes(*b"ASET");
+}
+
+/// Represents a parsed NRO, along with its asset section (if present).
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Nro {
+    header: Header,
+    asset_header: Option<AssetHeader>,
+}
+
+impl Nro {
+    /// Reads and validates the [`Header`] from the given data
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The NRO data, which must contain at least the [`Start`] and the [`Header`]
+    pub fn read_header(data: &[u8]) -> Result<Header> {
+        // SAFETY: the header only has integer/array fields
+        let header: Header = unsafe { util::read_unaligned_at(data, Header::OFFSET) }
+            .ok_or(rc::ResultInvalidNroMagic::make())?;
+        result_return_unless!(header.magic == Header::MAGIC, rc::ResultInvalidNroMagic);
+        Ok(header)
+    }
+
+    /// Reads and validates the [`AssetHeader`] from the given data
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The asset section data, which must be at least [`AssetHeader`]-sized
+    pub fn read_asset_header(data: &[u8]) -> Result<AssetHeader> {
+        // SAFETY: the asset header only has integer fields
+        let asset_header: AssetHeader = unsafe { util::read_unaligned_at(data, 0) }
+            .ok_or(rc::ResultInvalidAssetMagic::make())?;
+        result_return_unless!(
+            asset_header.magic == AssetHeader::MAGIC,
+            rc::ResultInvalidAssetMagic
+        );
+        Ok(asset_header)
+    }
+
+    /// Parses the NRO and its asset section from the given data
+    ///
+    /// NROs without an asset section (whose data ends right after the NRO itself) are accepted
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The whole NRO data
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let header = Self::read_header(data)?;
+        let asset_data = data.get(header.size as usize..).unwrap_or_default();
+        let asset_header = match asset_data.is_empty() {
+            true => None,
+            false => Some(Self::read_asset_header(asset_data)?),
+        };
+
+        Ok(Self {
+            header,
+            asset_header,
+        })
+    }
+
+    /// Reads the NRO and its asset section from the given [`File`][`fs::File`]
+    ///
+    /// NROs without an asset section (whose file ends right after the NRO itself) are accepted
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: The [`File`][`fs::File`] containing the NRO
+    #[cfg(feature = "fs")]
+    pub fn read(file: &mut dyn fs::File) -> Result<Self> {
+        let mut header_data = [0u8; Header::OFFSET + mem::size_of::<Header>()];
+        fs::read_file_exact(file, 0, &mut header_data)?;
+        let header = Self::read_header(&header_data)?;
+
+        let asset_header_offset = header.size as usize;
+        let asset_header = match file.get_size()? > asset_header_offset {
+            true => {
+                let mut asset_header_data = [0u8; mem::size_of::<AssetHeader>()];
+                fs::read_file_exact(file, asset_header_offset, &mut asset_header_data)?;
+                Some(Self::read_asset_header(&asset_header_data)?)
+            }
+            false => None,
+        };
+
+        Ok(Self {
+            header,
+            asset_header,
+        })
+    }
+
+    /// Gets the NRO [`Header`]
+    #[inline]
+    pub fn get_header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Gets the [`AssetHeader`], if the NRO has an asset section
+    #[inline]
+    pub fn get_asset_header(&self) -> Option<&AssetHeader> {
+        self.asset_header.as_ref()
+    }
+
+    /// Gets the absolute offset and the size of an asset inside the NRO, if present
+    ///
+    /// Assets whose region overflows are treated as not present
+    ///
+    /// # Arguments
+    ///
+    /// * `get_section`: Fn selecting the [`AssetSection`] from the [`AssetHeader`]
+    fn get_asset_region(
+        &self,
+        get_section: fn(&AssetHeader) -> &AssetSection,
+    ) -> Option<(usize, usize)> {
+        let section = get_section(self.asset_header.as_ref()?);
+        if !section.is_present() {
+            return None;
+        }
+
+        let offset = usize::try_from(section.offset)
+            .ok()?
+            .checked_add(self.header.size as usize)?;
+        let size = usize::try_from(section.size).ok()?;
+        offset.checked_add(size)?;
+        Some((offset, size))
+    }
+
+    fn get_asset_data(data: &[u8], region: Option<(usize, usize)>) -> Result<&[u8]> {
+        let (offset, size) = region.ok_or(rc::ResultAssetNotFound::make())?;
+        offset
+            .checked_add(size)
+            .and_then(|end| data.get(offset..end))
+            .ok_or(rc::ResultAssetNotFound::make())
+    }
+
+    /// Gets the absolute offset and the size of the JPEG icon, if present
+    #[inline]
+    pub fn get_icon_region(&self) -> Option<(usize, usize)> {
+        self.get_asset_region(|asset_header| &asset_header.icon)
+    }
+
+    /// Gets the absolute offset and the size of the NACP, if present
+    #[inline]
+    pub fn get_nacp_region(&self) -> Option<(usize, usize)> {
+        self.get_asset_region(|asset_header| &asset_header.nacp)
+    }
+
+    /// Gets the absolute offset and the size of the RomFS image, if present
+    #[inline]
+    pub fn get_romfs_region(&self) -> Option<(usize, usize)> {
+        self.get_asset_region(|asset_header| &asset_header.romfs)
+    }
+
+    /// Gets the JPEG icon data from the whole NRO data
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The whole NRO data
+    pub fn get_icon_data<'a>(&self, data: &'a [u8]) -> Result<&'a [u8]> {
+        Self::get_asset_data(data, self.get_icon_region())
+    }
+
+    /// Gets the [`Nacp`][`nacp::Nacp`] from the whole NRO data
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The whole NRO data
+    pub fn get_nacp(&self, data: &[u8]) -> Result<nacp::Nacp> {
+        nacp::Nacp::parse(Self::get_asset_data(data, self.get_nacp_region())?)
+    }
+
+    /// Reads the JPEG icon data from the given [`File`][`fs::File`]
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: The [`File`][`fs::File`] containing the NRO
+    #[cfg(feature = "fs")]
+    pub fn read_icon(&self, file: &mut dyn fs::File) -> Result<Vec<u8>> {
+        let (offset, size) = self
+            .get_icon_region()
+            .ok_or(rc::ResultAssetNotFound::make())?;
+        // The icon size is untrusted, so it must fit in the file before being allocated
+        result_return_if!(
+            offset + size > file.get_size()?,
+            fs::rc::ResultUnexpectedEndOfFile
+        );
+
+        let mut icon_data = vec![0u8; size];
+        fs::read_file_exact(file, offset, &mut icon_data)?;
+        Ok(icon_data)
+    }
+
+    /// Reads the [`Nacp`][`nacp::Nacp`] from the given [`File`][`fs::File`]
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: The [`File`][`fs::File`] containing the NRO
+    #[cfg(feature = "fs")]
+    pub fn read_nacp(&self, file: &mut dyn fs::File) -> Result<nacp::Nacp> {
+        let (offset, size) = self
+            .get_nacp_region()
+            .ok_or(rc::ResultAssetNotFound::make())?;
+        result_return_if!(
+            size < mem::size_of::<nacp::Nacp>(),
+            rc::ResultInvalidNacpSize
+        );
+
+        let mut nacp_data = vec![0u8; mem::size_of::<nacp::Nacp>()];
+        fs::read_file_exact(file, offset, &mut nacp_data)?;
+        nacp::Nacp::parse(&nacp_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn make_nro(icon: AssetSection) -> Vec<u8> {
+        let nro_size = Header::OFFSET + mem::size_of::<Header>();
+        let header = Header {
+            magic: Header::MAGIC,
+            size: nro_size as u32,
+            ..Default::default()
+        };
+        let asset_header = AssetHeader {
+            magic: AssetHeader::MAGIC,
+            icon,
+            ..Default::default()
+        };
+
+        let mut data = vec![0u8; Header::OFFSET];
+        data.extend_from_slice(unsafe {
+            core::slice::from_raw_parts(
+                &header as *const Header as *const u8,
+                mem::size_of::<Header>(),
+            )
+        });
+        data.extend_from_slice(unsafe {
+            core::slice::from_raw_parts(
+                &asset_header as *const AssetHeader as *const u8,
+                mem::size_of::<AssetHeader>(),
+            )
+        });
+        data.extend_from_slice(b"icon");
+        data
+    }
+
+    #[test]
+    fn get_icon_data() {
+        let data = make_nro(AssetSection {
+            offset: mem::size_of::<AssetHeader>() as u64,
+            size: 4,
+        });
+        let nro = Nro::parse(&data).unwrap();
+        assert_eq!(nro.get_icon_data(&data), Ok(&b"icon"[..]));
+
+        let rc = nro.get_nacp(&data).unwrap_err();
+        assert!(rc::ResultAssetNotFound::matches(rc));
+    }
+
+    #[test]
+    fn get_icon_data_out_of_bounds() {
+        let data = make_nro(AssetSection {
+            offset: mem::size_of::<AssetHeader>() as u64,
+            size: 5,
+        });
+        let rc = Nro::parse(&data).unwrap().get_icon_data(&data).unwrap_err();
+        assert!(rc::ResultAssetNotFound::matches(rc));
+
+        for (offset, size) in [(u64::MAX, 4), (4, u64::MAX), (u64::MAX, u64::MAX)] {
+            let data = make_nro(AssetSection { offset, size });
+            let nro = Nro::parse(&data).unwrap();
+            assert_eq!(nro.get_icon_region(), None);
+            let rc = nro.get_icon_data(&data).unwrap_err();
+            assert!(rc::ResultAssetNotFound::matches(rc));
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! NSO format utils
+
+use super::rc;
+use crate::result::*;
+use crate::util;
+use alloc::vec::Vec;
+use core::mem;
+
+define_bit_set! {
+    /// Represents the NSO header flags
+    Flags (u32) {
+        TextCompressed = bit!(0),
+        RoCompressed = bit!(1),
+        DataCompressed = bit!(2),
+        TextHashCheck = bit!(3),
+        RoHashCheck = bit!(4),
+        DataHashCheck = bit!(5)
+    }
+}
+
+/// Represents the NSO segments.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[allow(missing_docs)]
+pub enum Segment {
+    Text,
+    Ro,
+    Data,
+}
+
+/// Represents the location of a NSO segment, both in the NSO file and in memory.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct SegmentHeader {
+    /// The (compressed) segment file offset
+    pub file_offset: u32,
+    /// The segment offset in memory, relative to the load address
+    pub memory_offset: u32,
+    /// The (decompressed) segment size
+    pub size: u32,
+}
+const_assert!(mem::size_of::<SegmentHeader>() == 0xC);
+
+/// Represents a region inside the `.rodata` segment.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct RoRegion {
+    /// The region offset, relative to the start of `.rodata`
+    pub offset: u32,
+    /// The region size
+    pub size: u32,
+}
+const_assert!(mem::size_of::<RoRegion>() == 0x8);
+
+/// Represents the NSO header.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct Header {
+    /// The magic, whose expected value is [`MAGIC`][`Header::MAGIC`]
+    pub magic: u32,
+    /// The format version
+    pub version: u32,
+    /// Reserved
+    pub reserved: u32,
+    /// The flags
+    pub flags: Flags,
+    /// The `.text` segment
+    pub text_segment: SegmentHeader,
+    /// The module name offset
+    pub module_name_offset: u32,
+    /// The `.rodata` segment
+    pub ro_segment: SegmentHeader,
+    /// The module name size
+    pub module_name_size: u32,
+    /// The `.data` segment
+    pub data_segment: SegmentHeader,
+    /// The BSS size
+    pub bss_size: u32,
+    /// The module ID (build ID)
+    pub module_id: [u8; 0x20],
+    /// The (compressed) `.text` file size
+    pub text_file_size: u32,
+    /// The (compressed) `.rodata` file size
+    pub ro_file_size: u32,
+    /// The (compressed) `.data` file size
+    pub data_file_size: u32,
+    /// Reserved
+    pub reserved_2: [u8; 0x1C],
+    /// The API info region
+    pub api_info: RoRegion,
+    /// The `.dynstr` region
+    pub dynstr: RoRegion,
+    /// The `.dynsym` region
+    pub dynsym: RoRegion,
+    /// The SHA-256 hashes of the (decompressed) `.text`, `.rodata` and `.data` segments
+    pub segment_hashes: [[u8; 0x20]; 3],
+}
+const_assert!(mem::size_of::<Header>() == 0x100);
+
+impl Header {
+    /// The header magic value (`NSO0`)
+    pub const MAGIC: u32 = u32::from_le_bytes(*b"NSO0");
+
+    /// Gets the [`SegmentHeader`] of a segment
+    ///
+    /// # Arguments
+    ///
+    /// * `segment`: The [`Segment`]
+    pub const fn get_segment_header(&self, segment: Segment) -> &SegmentHeader {
+        match segment {
+            Segment::Text => &self.text_segment,
+            Segment::Ro => &self.ro_segment,
+            Segment::Data => &self.data_segment,
+        }
+    }
+
+    /// Gets the size of a segment inside the NSO file
+    ///
+    /// # Arguments
+    ///
+    /// * `segment`: The [`Segment`]
+    pub const fn get_segment_file_size(&self, segment: Segment) -> usize {
+        (match segment {
+            Segment::Text => self.text_file_size,
+            Segment::Ro => self.ro_file_size,
+            Segment::Data => self.data_file_size,
+        }) as usize
+    }
+
+    /// Gets whether a segment is LZ4-compressed
+    ///
+    /// # Arguments
+    ///
+    /// * `segment`: The [`Segment`]
+    pub const fn is_segment_compressed(&self, segment: Segment) -> bool {
+        self.flags.contains(match segment {
+            Segment::Text => Flags::TextCompressed(),
+            Segment::Ro => Flags::RoCompressed(),
+            Segment::Data => Flags::DataCompressed(),
+        })
+    }
+
+    /// Gets the size of the whole module image in memory, including the BSS
+    pub const fn get_image_size(&self) -> usize {
+        self.data_segment.memory_offset as usize
+            + self.data_segment.size as usize
+            + self.bss_size as usize
+    }
+}
+
+/// Represents a parsed NSO
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Nso {
+    header: Header,
+}
+
+impl Nso {
+    /// Parses the NSO header from the given data
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The NSO data, which must be at least [`Header`]-sized
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        // SAFETY: the header only has integer/array fields
+        let header: Header = unsafe { util::read_unaligned_at(data, 0) }
+            .ok_or(rc::ResultInvalidNsoSegment::make())?;
+        result_return_unless!(header.magic == Header::MAGIC, rc::ResultInvalidNsoMagic);
+        Ok(Self { header })
+    }
+
+    /// Gets the NSO [`Header`]
+    #[inline]
+    pub fn get_header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Reads (and decompresses, if needed) a segment from the whole NSO data
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The whole NSO data
+    /// * `segment`: The [`Segment`] to read
+    pub fn read_segment(&self, data: &[u8], segment: Segment) -> Result<Vec<u8>> {
+        let segment_header = self.header.get_segment_header(segment);
+        let file_offset = segment_header.file_offset as usize;
+        let file_data = data
+            .get(file_offset..file_offset + self.header.get_segment_file_size(segment))
+            .ok_or(rc::ResultInvalidNsoSegment::make())?;
+
+        let size = segment_header.size as usize;
+        if self.header.is_segment_compressed(segment) {
+            decompress_lz4_block(file_data, size)
+        } else {
+            result_return_unless!(file_data.len() == size, rc::ResultInvalidNsoSegment);
+            Ok(file_data.to_vec())
+        }
+    }
+
+    /// Builds the module image, as it would be laid out in memory (all segments at their memory offsets, followed by the zeroed BSS)
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The whole NSO data
+    pub fn load_image(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut image = vec![0u8; self.header.get_image_size()];
+        for segment in [Segment::Text, Segment::Ro, Segment::Data] {
+            let segment_data = self.read_segment(data, segment)?;
+            let memory_offset = self.header.get_segment_header(segment).memory_offset as usize;
+            image
+                .get_mut(memory_offset..memory_offset + segment_data.len())
+                .ok_or(rc::ResultInvalidNsoSegment::make())?
+                .copy_from_slice(&segment_data);
+        }
+
+        Ok(image)
+    }
+}
+
+fn read_lz4_length(data: &[u8], offset: &mut usize, base_length: usize) -> Result<usize> {
+    let mut length = base_length;
+    if base_length == 0xF {
+        loop {
+            let byte = *data
+                .get(*offset)
+                .ok_or(rc::ResultInvalidNsoSegment::make())?;
+            *offset += 1;
+            length += byte as usize;
+            if byte != 0xFF {
+                break;
+            }
+        }
+    }
+
+    Ok(length)
+}
+
+/// Decompresses a raw LZ4 block (no frame format), as used by NSO segments
+///
+/// # Arguments
+///
+/// * `data`: The compressed data
+/// * `decompressed_size`: The expected decompressed size
+pub fn decompress_lz4_block(data: &[u8], decompressed_size: usize) -> Result<Vec<u8>> {
+    // The expected size is untrusted, so don't preallocate more than the data could possibly decompress to
+    const MAX_COMPRESSION_RATIO: usize = 0xFF;
+    let mut out =
+        Vec::with_capacity(decompressed_size.min(data.len().saturating_mul(MAX_COMPRESSION_RATIO)));
+    let mut offset = 0;
+    while offset < data.len() {
+        let token = data[offset];
+        offset += 1;
+
+        let literal_length = read_lz4_length(data, &mut offset, (token >> 4) as usize)?;
+        let literals = offset
+            .checked_add(literal_length)
+            .and_then(|end| data.get(offset..end))
+            .ok_or(rc::ResultInvalidNsoSegment::make())?;
+        result_return_if!(
+            out.len() + literal_length > decompressed_size,
+            rc::ResultInvalidNsoSegment
+        );
+        out.extend_from_slice(literals);
+        offset += literal_length;
+
+        // The last sequence only contains literals
+        if offset == data.len() {
+            break;
+        }
+
+        let match_offset = data
+            .get(offset..offset + 2)
+            .map(|raw| u16::from_le_bytes([raw[0], raw[1]]) as usize)
+            .ok_or(rc::ResultInvalidNsoSegment::make())?;
+        offset += 2;
+        result_return_if!(
+            match_offset == 0 || match_offset > out.len(),
+            rc::ResultInvalidNsoSegment
+        );
+
+        // The minimum match length is 4
+        let match_length = read_lz4_length(data, &mut offset, (token & 0xF) as usize)? + 4;
+        result_return_if!(
+            out.len() + match_length > decompressed_size,
+            rc::ResultInvalidNsoSegment
+        );
+
+        // Matches may overlap with the bytes they produce, thus copy them one by one
+        let match_start = out.len() - match_offset;
+        for i in 0..match_length {
+            out.push(out[match_start + i]);
+        }
+    }
+
+    result_return_unless!(out.len() == decompressed_size, rc::ResultInvalidNsoSegment);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lz4_literals_and_matches() {
+        // "abcd" as literals, then a 6-byte match at offset 4, then "xyz" as the last literals
+        let data = [
+            0x42, b'a', b'b', b'c', b'd', 0x04, 0x00, 0x30, b'x', b'y', b'z',
+        ];
+        assert_eq!(decompress_lz4_block(&data, 13).unwrap(), b"abcdabcdabxyz");
+    }
+
+    #[test]
+    fn lz4_size_mismatch() {
+        let data = [
+            0x42, b'a', b'b', b'c', b'd', 0x04, 0x00, 0x30, b'x', b'y', b'z',
+        ];
+        let rc = decompress_lz4_block(&data, 12).unwrap_err();
+        assert!(rc::ResultInvalidNsoSegment::matches(rc));
+        let rc = decompress_lz4_block(&data, 14).unwrap_err();
+        assert!(rc::ResultInvalidNsoSegment::matches(rc));
+
+        // The expected size isn't allocated upfront
+        let rc = decompress_lz4_block(&data, usize::MAX).unwrap_err();
+        assert!(rc::ResultInvalidNsoSegment::matches(rc));
+    }
+
+    #[test]
+    fn lz4_invalid_blocks() {
+        // Literal length past the end of the data
+        let rc = decompress_lz4_block(&[0xF0, 0xFF, 0xFF, 0x10], 4).unwrap_err();
+        assert!(rc::ResultInvalidNsoSegment::matches(rc));
+
+        // Match offset before the start of the output
+        let rc = decompress_lz4_block(&[0x10, b'a', 0x02, 0x00, 0x00], 5).unwrap_err();
+        assert!(rc::ResultInvalidNsoSegment::matches(rc));
+    }
+}
@@ -9,5 +9,12 @@ result_define_subgroup!(rc::RESULT_MODULE, RESULT_SUBMODULE => {
     RelaSizeMismatch: 1,
     InvalidModuleMagic: 2,
     DuplicatedDtEntry: 3,
-    MissingDtEntry: 4
+    MissingDtEntry: 4,
+    InvalidNroMagic: 5,
+    InvalidAssetMagic: 6,
+    AssetNotFound: 7,
+    InvalidNsoMagic: 8,
+    InvalidNsoSegment: 9,
+    InvalidNacpSize: 10,
+    InvalidNacpString: 11
 });
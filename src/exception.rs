@@ -1,18 +1,598 @@
-//! Exception handling support (stubbed for now).
+//! Exception handling support
+//!
+//! When a CPU exception (data abort, undefined instruction, etc.) happens in a thread of the process, the kernel jumps back to the process entrypoint with the exception type and a pointer to the exception information it saved (see `rrt0.s`).
+//!
+//! The exception entry saves the full thread context (including FPU registers and the ESR/FAR values) into an [`ExceptionContext`], switches to a dedicated exception stack and calls the registered [`ExceptionHandlerFn`] (see [`set_exception_handler`]), which decides what to do with the exception:
+//!
+//! * Resume execution with the (possibly modified) context, for instance skipping the faulting instruction
+//!
+//! * Return from the exception with an error result, leaving the kernel to treat it as an unhandled exception (which usually means a crash report is generated and the process is terminated)
+//!
+//! The handler may also just log a crash report (see the [`Display`][`fmt::Display`] implementation of [`ExceptionContext`] and [`ExceptionContext::get_backtrace`]) and exit/abort the process.
+//!
+//! If no handler is registered, the process is exited as soon as an exception is received.
+//!
+//! Note that the kernel only dispatches one exception at a time (threads faulting meanwhile wait until the current one is handled), and exceptions happening inside the handler itself aren't dispatched again but are treated as unhandled.
 
+use crate::arm;
 use crate::diag::abort::{AbortLevel, abort};
-use crate::result::ResultCode;
+use crate::result::*;
 use crate::svc;
+use core::fmt;
+use core::mem::offset_of;
+use core::sync::atomic::AtomicPtr;
+use core::sync::atomic::Ordering;
 
+/// The size of the stack exception handlers run on
+pub const EXCEPTION_STACK_SIZE: usize = 0x4000;
+
+/// Represents the context of a thread when an exception happened
+///
+/// Note that the kernel overwrites `x0` and `x1` before dispatching the exception, thus their values are always zero here, and `x0` will be zero when execution is resumed
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct ExceptionContext {
+    /// The raw exception type (see [`get_exception_type`][`ExceptionContext::get_exception_type`])
+    pub exception_type: u32,
+    /// The AFSR0 value
+    pub afsr0: u32,
+    /// The AFSR1 value
+    pub afsr1: u32,
+    /// The ESR value (exception syndrome)
+    pub esr: u32,
+    /// The FAR value (faulting address, for aborts)
+    pub far: u64,
+    /// The thread context, which is written back when execution is resumed
+    pub thread_context: arm::ThreadContext,
+}
+// Offsets hardcoded in the exception entry below
+const_assert!(offset_of!(ExceptionContext, afsr0) == 0x4);
+const_assert!(offset_of!(ExceptionContext, esr) == 0xC);
+const_assert!(offset_of!(ExceptionContext, far) == 0x10);
+const_assert!(offset_of!(ExceptionContext, thread_context) == 0x20);
+const_assert!(offset_of!(arm::ThreadContext, fp) == 0xE8);
+const_assert!(offset_of!(arm::ThreadContext, lr) == 0xF0);
+const_assert!(offset_of!(arm::ThreadContext, sp) == 0xF8);
+const_assert!(offset_of!(arm::ThreadContext, pc) == 0x100);
+const_assert!(offset_of!(arm::ThreadContext, psr) == 0x108);
+const_assert!(offset_of!(arm::ThreadContext, fpu_gprs) == 0x110);
+const_assert!(offset_of!(arm::ThreadContext, fpcr) == 0x310);
+const_assert!(offset_of!(arm::ThreadContext, fpsr) == 0x314);
+const_assert!(offset_of!(arm::ThreadContext, tpidr) == 0x318);
+const_assert!(core::mem::size_of::<ExceptionContext>() == 0x340);
+
+/// Represents the exception info the kernel saves at the top of the stack it provides to the exception entry
+///
+/// This mirrors libnx's `ThreadExceptionFrameA64`: the kernel only saves the registers it clobbers while dispatching (`x9`-`x17` at offset 0, then `lr`, `sp`, `pc` and `pstate`) and restores them from here when returning from the exception successfully
+#[allow(dead_code)] // Only its layout is used
+#[repr(C)]
+struct ExceptionFrame {
+    /// The `x9`-`x17` values
+    gprs_9_17: [u64; 9],
+    lr: u64,
+    sp: u64,
+    /// The `ELR_EL1` value, that is, the faulting PC
+    pc: u64,
+    pstate: u32,
+    afsr0: u32,
+    afsr1: u32,
+    esr: u32,
+    far: u64,
+}
+// Offsets used by the exception entry below, matching `ThreadExceptionFrameA64`
+const_assert!(offset_of!(ExceptionFrame, gprs_9_17) == 0x0);
+const_assert!(offset_of!(ExceptionFrame, lr) == 0x48);
+const_assert!(offset_of!(ExceptionFrame, sp) == 0x50);
+const_assert!(offset_of!(ExceptionFrame, pc) == 0x58);
+const_assert!(offset_of!(ExceptionFrame, pstate) == 0x60);
+const_assert!(offset_of!(ExceptionFrame, afsr0) == 0x64);
+const_assert!(offset_of!(ExceptionFrame, afsr1) == 0x68);
+const_assert!(offset_of!(ExceptionFrame, esr) == 0x6C);
+const_assert!(offset_of!(ExceptionFrame, far) == 0x70);
+const_assert!(core::mem::size_of::<ExceptionFrame>() == 0x78);
+
+impl ExceptionContext {
+    const fn zeroed() -> Self {
+        // SAFETY: all the fields are plain integers
+        unsafe { core::mem::zeroed() }
+    }
+
+    /// Gets the [`ExceptionType`][`svc::ExceptionType`], if it's a known one
+    pub const fn get_exception_type(&self) -> Option<svc::ExceptionType> {
+        match self.exception_type {
+            0x000 => Some(svc::ExceptionType::Init),
+            0x100 => Some(svc::ExceptionType::InstructionAbort),
+            0x101 => Some(svc::ExceptionType::DataAbort),
+            0x102 => Some(svc::ExceptionType::UnalignedInstruction),
+            0x103 => Some(svc::ExceptionType::UnalignedData),
+            0x104 => Some(svc::ExceptionType::UndefinedInstruction),
+            0x105 => Some(svc::ExceptionType::ExceptionInstruction),
+            0x106 => Some(svc::ExceptionType::MemorySystemError),
+            0x200 => Some(svc::ExceptionType::FpuException),
+            0x301 => Some(svc::ExceptionType::InvalidSystemCall),
+            0x302 => Some(svc::ExceptionType::SystemCallBreak),
+            0xFFE => Some(svc::ExceptionType::AtmosphereStdAbort),
+            _ => None,
+        }
+    }
+
+    /// Walks the call stack of the faulting thread, starting at the faulting instruction, using the unwind information from the module's `eh_frame_hdr` section
+    ///
+    /// This must be called from inside the exception handler, since the unwinder walks through it to reach the faulting code. Frames without unwind information (or unwind info not being emitted at all, i.e. `panic = "abort"` builds without debug info) end the backtrace early
+    ///
+    /// Returns the number of frames written, which is always at least one (the faulting PC itself)
+    ///
+    /// # Arguments
+    ///
+    /// * `out_frames`: The array to write the frame addresses to
+    pub fn get_backtrace(&self, out_frames: &mut [usize]) -> usize {
+        if out_frames.is_empty() {
+            return 0;
+        }
+
+        let pc = self.thread_context.pc.get_x() as usize;
+        let mut state = BacktraceState {
+            pc,
+            frames: out_frames,
+            count: 0,
+            skipped_count: 0,
+            last_frame: (0, 0),
+        };
+        unwinding::abi::_Unwind_Backtrace(
+            backtrace_trace_fn,
+            &raw mut state as *mut core::ffi::c_void,
+        );
+
+        if state.count == 0 {
+            // The unwinder couldn't get past the handler, so just provide the faulting PC
+            state.frames[0] = pc;
+            state.count = 1;
+        }
+        state.count
+    }
+}
+
+impl fmt::Display for ExceptionContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get_exception_type() {
+            Some(exception_type) => writeln!(f, "Exception: {exception_type:?}")?,
+            None => writeln!(f, "Exception: {:#X}", self.exception_type)?,
+        };
+        writeln!(
+            f,
+            "ESR: {:#010X}, FAR: {:#018X}, AFSR0: {:#010X}, AFSR1: {:#010X}",
+            self.esr, self.far, self.afsr0, self.afsr1
+        )?;
+
+        let ctx = &self.thread_context;
+        for (i, gpr) in ctx.gpu_gprs.iter().enumerate() {
+            write!(f, "X{i:<2}: {:#018X}", gpr.get_x())?;
+            if (i % 4) == 3 {
+                writeln!(f)?;
+            } else {
+                write!(f, "  ")?;
+            }
+        }
+        writeln!(f, "FP : {:#018X}", ctx.fp)?;
+        writeln!(
+            f,
+            "LR : {:#018X}  SP : {:#018X}  PC : {:#018X}",
+            ctx.lr,
+            ctx.sp,
+            ctx.pc.get_x()
+        )?;
+        write!(
+            f,
+            "PSR: {:#010X}  FPCR: {:#010X}  FPSR: {:#010X}",
+            ctx.psr, ctx.fpcr, ctx.fpsr
+        )
+    }
+}
+
+/// Represents what to do after an exception was handled
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ExceptionAction {
+    /// Resumes execution with the [`ExceptionContext`]'s thread context (which the handler may have modified)
+    Resume,
+    /// Returns from the exception with the given (error) result, thus the kernel treats it as unhandled
+    ReturnFromException(ResultCode),
+}
+
+/// Represents an exception handler
+///
+/// It runs on the faulting thread, but on a separate stack of [`EXCEPTION_STACK_SIZE`] bytes
+pub type ExceptionHandlerFn = fn(&mut ExceptionContext) -> ExceptionAction;
+
+static G_EXCEPTION_HANDLER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Registers the exception handler to use from now on
+///
+/// # Arguments
+///
+/// * `handler`: The handler to register
+pub fn set_exception_handler(handler: ExceptionHandlerFn) {
+    G_EXCEPTION_HANDLER.store(handler as *mut (), Ordering::Release);
+}
+
+/// Unregisters the exception handler (if any), making the process exit on exceptions
+pub fn reset_exception_handler() {
+    G_EXCEPTION_HANDLER.store(core::ptr::null_mut(), Ordering::Release);
+}
+
+/// Gets the registered exception handler, if any
+pub fn get_exception_handler() -> Option<ExceptionHandlerFn> {
+    let handler = G_EXCEPTION_HANDLER.load(Ordering::Acquire);
+    // SAFETY: only `ExceptionHandlerFn`s are stored (see `set_exception_handler`)
+    (!handler.is_null())
+        .then(|| unsafe { core::mem::transmute::<*mut (), ExceptionHandlerFn>(handler) })
+}
+
+/// Maximum amount of frames (from the handler itself, the entry, etc.) skipped before reaching the faulting PC
+const MAX_SKIPPED_BACKTRACE_FRAMES: usize = 32;
+
+struct BacktraceState<'a> {
+    pc: usize,
+    frames: &'a mut [usize],
+    count: usize,
+    skipped_count: usize,
+    last_frame: (usize, usize),
+}
+
+extern "C" fn backtrace_trace_fn(
+    ctx: &unwinding::abi::UnwindContext<'_>,
+    arg: *mut core::ffi::c_void,
+) -> unwinding::abi::UnwindReasonCode {
+    use unwinding::abi::{_Unwind_GetCFA, _Unwind_GetIP, UnwindReasonCode};
+
+    // SAFETY: we're the only ones using the state, see `ExceptionContext::get_backtrace`
+    let state = unsafe { &mut *(arg as *mut BacktraceState) };
+    let ip = _Unwind_GetIP(ctx);
+    let frame = (ip, _Unwind_GetCFA(ctx));
+
+    if state.count == 0 {
+        if ip != state.pc {
+            state.skipped_count += 1;
+            if state.skipped_count > MAX_SKIPPED_BACKTRACE_FRAMES {
+                return UnwindReasonCode::END_OF_STACK;
+            }
+            return UnwindReasonCode::NO_REASON;
+        }
+    } else if frame == state.last_frame {
+        // The faulting function was a leaf one (its return address was still in LR, which the faulting PC replaces when unwinding), don't loop over it
+        return UnwindReasonCode::END_OF_STACK;
+    }
+
+    state.frames[state.count] = ip;
+    state.count += 1;
+    state.last_frame = frame;
+    if state.count == state.frames.len() {
+        UnwindReasonCode::END_OF_STACK
+    } else {
+        UnwindReasonCode::NO_REASON
+    }
+}
+
+#[repr(C, align(16))]
+struct ExceptionStack([u8; EXCEPTION_STACK_SIZE]);
+
+// Only accessed from the exception entry, and the kernel only dispatches one exception at a time
+static mut G_EXCEPTION_CONTEXT: ExceptionContext = ExceptionContext::zeroed();
+static mut G_EXCEPTION_STACK: ExceptionStack = ExceptionStack([0; EXCEPTION_STACK_SIZE]);
+
+extern "C" fn exception_dispatch(ctx: &mut ExceptionContext) -> ResultCode {
+    match get_exception_handler() {
+        Some(handler) => match handler(ctx) {
+            ExceptionAction::Resume => ResultSuccess::make(),
+            ExceptionAction::ReturnFromException(rc) => rc,
+        },
+        // Immediately exit if a crate consumer hasn't defined their own exception handler
+        None => abort(
+            AbortLevel::ProcessExit(),
+            ResultCode::new(0x6C01 /* StopProcessingException */),
+        ),
+    }
+}
+
+/// Exception entrypoint, jumped to from `rrt0.s` (see its notes)
+///
+/// This is a weak symbol, thus crate consumers may still define their own `__nx_exception_dispatch` (with this same signature) to handle exceptions at the lowest level, bypassing the handler system in this module altogether.
+///
+/// On entry, `x0` contains the exception type and `x1` (the top of the stack the kernel provides) points to the exception info saved by the kernel, laid out as an `ExceptionFrame` (libnx's `ThreadExceptionFrameA64`):
+///
+/// * `0x00`: `x9`-`x17` (the kernel has already saved them, so they're free to use here)
+/// * `0x48`: `lr`
+/// * `0x50`: `sp`
+/// * `0x58`: `pc`
+/// * `0x60`: `pstate` (32-bit)
+/// * `0x64`: `afsr0` (32-bit)
+/// * `0x68`: `afsr1` (32-bit)
+/// * `0x6C`: `esr` (32-bit)
+/// * `0x70`: `far`
+///
+/// The kernel restores `x9`-`x17`, `lr`, `sp`, `pc` and `pstate` from the exception info when returning from the exception successfully, the rest of registers are restored here.
+///
+/// While the dispatcher runs, the CFI describes the saved context as the caller frame, thus unwinding from the handler continues into the faulting code.
+#[unsafe(naked)]
 #[unsafe(no_mangle)]
 #[linkage = "weak"]
 pub(crate) unsafe extern "C" fn __nx_exception_dispatch(
     _reason: svc::ExceptionType,
     _stack_top: *mut u8,
 ) -> ! {
-    // immediately exit if a crate consumer hasn't definite their own exception handler.
-    abort(
-        AbortLevel::ProcessExit(),
-        ResultCode::new(0x6C01 /* StopProcessingException */),
+    core::arch::naked_asm!(
+        maybe_cfi!(".cfi_startproc"),
+        maybe_cfi!(".cfi_signal_frame"),
+        "adrp x9, {context}",
+        "add x9, x9, :lo12:{context}",
+        // Exception type and syndrome values
+        "str w0, [x9, #0x0]",
+        "ldp w10, w11, [x1, #{frame_afsr0}]",
+        "stp w10, w11, [x9, #0x4]",
+        "ldr w10, [x1, #{frame_esr}]",
+        "str w10, [x9, #0xC]",
+        "ldr x10, [x1, #{frame_far}]",
+        "str x10, [x9, #0x10]",
+        // CPU registers (x0 and x1 were overwritten by the kernel)
+        "add x9, x9, #0x20",
+        "stp xzr, xzr, [x9, #0x0]",
+        "stp x2, x3, [x9, #0x10]",
+        "stp x4, x5, [x9, #0x20]",
+        "stp x6, x7, [x9, #0x30]",
+        "str x8, [x9, #0x40]",
+        "ldp x10, x11, [x1, #0x0]",
+        "stp x10, x11, [x9, #0x48]",
+        "ldp x10, x11, [x1, #0x10]",
+        "stp x10, x11, [x9, #0x58]",
+        "ldp x10, x11, [x1, #0x20]",
+        "stp x10, x11, [x9, #0x68]",
+        "ldp x10, x11, [x1, #0x30]",
+        "stp x10, x11, [x9, #0x78]",
+        "ldr x10, [x1, #0x40]",
+        "str x10, [x9, #0x88]",
+        "stp x18, x19, [x9, #0x90]",
+        "stp x20, x21, [x9, #0xA0]",
+        "stp x22, x23, [x9, #0xB0]",
+        "stp x24, x25, [x9, #0xC0]",
+        "stp x26, x27, [x9, #0xD0]",
+        "stp x28, x29, [x9, #0xE0]",
+        "ldp x10, x11, [x1, #{frame_lr}]",
+        "stp x10, x11, [x9, #0xF0]",
+        "ldr x10, [x1, #{frame_pc}]",
+        "str x10, [x9, #0x100]",
+        "ldr w10, [x1, #{frame_pstate}]",
+        "str w10, [x9, #0x108]",
+        // FPU registers
+        "add x10, x9, #0x110",
+        "stp q0, q1, [x10, #0x0]",
+        "stp q2, q3, [x10, #0x20]",
+        "stp q4, q5, [x10, #0x40]",
+        "stp q6, q7, [x10, #0x60]",
+        "stp q8, q9, [x10, #0x80]",
+        "stp q10, q11, [x10, #0xA0]",
+        "stp q12, q13, [x10, #0xC0]",
+        "stp q14, q15, [x10, #0xE0]",
+        "stp q16, q17, [x10, #0x100]",
+        "stp q18, q19, [x10, #0x120]",
+        "stp q20, q21, [x10, #0x140]",
+        "stp q22, q23, [x10, #0x160]",
+        "stp q24, q25, [x10, #0x180]",
+        "stp q26, q27, [x10, #0x1A0]",
+        "stp q28, q29, [x10, #0x1C0]",
+        "stp q30, q31, [x10, #0x1E0]",
+        "mrs x10, fpcr",
+        "str w10, [x9, #0x310]",
+        "mrs x10, fpsr",
+        "str w10, [x9, #0x314]",
+        "mrs x10, tpidr_el0",
+        "str x10, [x9, #0x318]",
+        // Switch to our own stack, the one provided by the kernel is pretty small
+        "adrp x10, {stack}",
+        "add x10, x10, :lo12:{stack}",
+        "mov x11, #{stack_size}",
+        "add x10, x10, x11",
+        "mov sp, x10",
+        "mov x19, x9",
+        "mov x20, x1",
+        "mov x29, xzr",
+        // Describe the saved context as the caller frame, returning to the faulting PC
+        maybe_cfi!(".cfi_def_cfa x19, 0"),
+        maybe_cfi!(".cfi_offset x2, 0x10"),
+        maybe_cfi!(".cfi_offset x3, 0x18"),
+        maybe_cfi!(".cfi_offset x4, 0x20"),
+        maybe_cfi!(".cfi_offset x5, 0x28"),
+        maybe_cfi!(".cfi_offset x6, 0x30"),
+        maybe_cfi!(".cfi_offset x7, 0x38"),
+        maybe_cfi!(".cfi_offset x8, 0x40"),
+        maybe_cfi!(".cfi_offset x9, 0x48"),
+        maybe_cfi!(".cfi_offset x10, 0x50"),
+        maybe_cfi!(".cfi_offset x11, 0x58"),
+        maybe_cfi!(".cfi_offset x12, 0x60"),
+        maybe_cfi!(".cfi_offset x13, 0x68"),
+        maybe_cfi!(".cfi_offset x14, 0x70"),
+        maybe_cfi!(".cfi_offset x15, 0x78"),
+        maybe_cfi!(".cfi_offset x16, 0x80"),
+        maybe_cfi!(".cfi_offset x17, 0x88"),
+        maybe_cfi!(".cfi_offset x18, 0x90"),
+        maybe_cfi!(".cfi_offset x19, 0x98"),
+        maybe_cfi!(".cfi_offset x20, 0xA0"),
+        maybe_cfi!(".cfi_offset x21, 0xA8"),
+        maybe_cfi!(".cfi_offset x22, 0xB0"),
+        maybe_cfi!(".cfi_offset x23, 0xB8"),
+        maybe_cfi!(".cfi_offset x24, 0xC0"),
+        maybe_cfi!(".cfi_offset x25, 0xC8"),
+        maybe_cfi!(".cfi_offset x26, 0xD0"),
+        maybe_cfi!(".cfi_offset x27, 0xD8"),
+        maybe_cfi!(".cfi_offset x28, 0xE0"),
+        maybe_cfi!(".cfi_offset x29, 0xE8"),
+        maybe_cfi!(".cfi_offset x30, 0x100"),
+        maybe_cfi!(".cfi_offset 31, 0xF8"),
+        "sub x0, x9, #0x20",
+        "bl {dispatch}",
+        "cbnz w0, 2f",
+        // Resuming: write the kernel-restored registers back to the exception info, and restore the rest here
+        "ldp x10, x11, [x19, #0x48]",
+        "stp x10, x11, [x20, #0x0]",
+        "ldp x10, x11, [x19, #0x58]",
+        "stp x10, x11, [x20, #0x10]",
+        "ldp x10, x11, [x19, #0x68]",
+        "stp x10, x11, [x20, #0x20]",
+        "ldp x10, x11, [x19, #0x78]",
+        "stp x10, x11, [x20, #0x30]",
+        "ldr x10, [x19, #0x88]",
+        "str x10, [x20, #0x40]",
+        "ldp x10, x11, [x19, #0xF0]",
+        "stp x10, x11, [x20, #{frame_lr}]",
+        "ldr x10, [x19, #0x100]",
+        "str x10, [x20, #{frame_pc}]",
+        "ldr w10, [x19, #0x108]",
+        "str w10, [x20, #{frame_pstate}]",
+        "add x10, x19, #0x110",
+        "ldp q0, q1, [x10, #0x0]",
+        "ldp q2, q3, [x10, #0x20]",
+        "ldp q4, q5, [x10, #0x40]",
+        "ldp q6, q7, [x10, #0x60]",
+        "ldp q8, q9, [x10, #0x80]",
+        "ldp q10, q11, [x10, #0xA0]",
+        "ldp q12, q13, [x10, #0xC0]",
+        "ldp q14, q15, [x10, #0xE0]",
+        "ldp q16, q17, [x10, #0x100]",
+        "ldp q18, q19, [x10, #0x120]",
+        "ldp q20, q21, [x10, #0x140]",
+        "ldp q22, q23, [x10, #0x160]",
+        "ldp q24, q25, [x10, #0x180]",
+        "ldp q26, q27, [x10, #0x1A0]",
+        "ldp q28, q29, [x10, #0x1C0]",
+        "ldp q30, q31, [x10, #0x1E0]",
+        "ldr w10, [x19, #0x310]",
+        "msr fpcr, x10",
+        "ldr w10, [x19, #0x314]",
+        "msr fpsr, x10",
+        "ldr x10, [x19, #0x318]",
+        "msr tpidr_el0, x10",
+        "mov x9, x19",
+        "ldr x1, [x9, #0x8]",
+        "ldp x2, x3, [x9, #0x10]",
+        "ldp x4, x5, [x9, #0x20]",
+        "ldp x6, x7, [x9, #0x30]",
+        "ldr x8, [x9, #0x40]",
+        "ldp x18, x19, [x9, #0x90]",
+        "ldp x20, x21, [x9, #0xA0]",
+        "ldp x22, x23, [x9, #0xB0]",
+        "ldp x24, x25, [x9, #0xC0]",
+        "ldp x26, x27, [x9, #0xD0]",
+        "ldp x28, x29, [x9, #0xE0]",
+        "mov w0, wzr",
+        "2:",
+        "svc 0x28",
+        "brk #0",
+        maybe_cfi!(".cfi_endproc"),
+        context = sym G_EXCEPTION_CONTEXT,
+        stack = sym G_EXCEPTION_STACK,
+        stack_size = const EXCEPTION_STACK_SIZE,
+        frame_lr = const offset_of!(ExceptionFrame, lr),
+        frame_pc = const offset_of!(ExceptionFrame, pc),
+        frame_pstate = const offset_of!(ExceptionFrame, pstate),
+        frame_afsr0 = const offset_of!(ExceptionFrame, afsr0),
+        frame_esr = const offset_of!(ExceptionFrame, esr),
+        frame_far = const offset_of!(ExceptionFrame, far),
+        dispatch = sym exception_dispatch
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_context(exception_type: u32) -> ExceptionContext {
+        let mut ctx = ExceptionContext::zeroed();
+        ctx.exception_type = exception_type;
+        ctx.afsr0 = 0x1;
+        ctx.afsr1 = 0x2;
+        ctx.esr = 0x92000046;
+        ctx.far = 0xDEAD_BEEF;
+        for (i, gpr) in ctx.thread_context.gpu_gprs.iter_mut().enumerate() {
+            gpr.set_x(i as u64);
+        }
+        ctx.thread_context.fp = 0x1D;
+        ctx.thread_context.lr = 0x8000_1000;
+        ctx.thread_context.sp = 0x1_0000;
+        ctx.thread_context.pc.set_x(0x8000_2000);
+        ctx.thread_context.psr = 0x6000_0000;
+        ctx
+    }
+
+    #[test]
+    fn exception_types() {
+        let get_type = |exception_type| make_context(exception_type).get_exception_type();
+        assert_eq!(get_type(0x000), Some(svc::ExceptionType::Init));
+        assert_eq!(get_type(0x101), Some(svc::ExceptionType::DataAbort));
+        assert_eq!(
+            get_type(0x104),
+            Some(svc::ExceptionType::UndefinedInstruction)
+        );
+        assert_eq!(get_type(0x200), Some(svc::ExceptionType::FpuException));
+        assert_eq!(get_type(0x302), Some(svc::ExceptionType::SystemCallBreak));
+        assert_eq!(
+            get_type(0xFFE),
+            Some(svc::ExceptionType::AtmosphereStdAbort)
+        );
+        assert_eq!(get_type(0x107), None);
+        assert_eq!(get_type(0x300), None);
+    }
+
+    #[test]
+    fn display_context() {
+        let report = make_context(0x101).to_string();
+        let lines: Vec<_> = report.lines().collect();
+        assert_eq!(lines.len(), 12);
+        assert_eq!(lines[0], "Exception: DataAbort");
+        assert_eq!(
+            lines[1],
+            "ESR: 0x92000046, FAR: 0x00000000DEADBEEF, AFSR0: 0x00000001, AFSR1: 0x00000002"
+        );
+        assert_eq!(
+            lines[2],
+            "X0 : 0x0000000000000000  X1 : 0x0000000000000001  X2 : 0x0000000000000002  X3 : 0x0000000000000003"
+        );
+        assert!(lines[9].starts_with("X28: 0x000000000000001C"));
+        assert!(lines[9].ends_with("FP : 0x000000000000001D"));
+        assert_eq!(
+            lines[10],
+            "LR : 0x0000000080001000  SP : 0x0000000000010000  PC : 0x0000000080002000"
+        );
+        assert_eq!(
+            lines[11],
+            "PSR: 0x60000000  FPCR: 0x00000000  FPSR: 0x00000000"
+        );
+
+        let report = make_context(0x123).to_string();
+        assert!(report.starts_with("Exception: 0x123\n"));
+    }
+
+    #[test]
+    fn dispatch_to_handler() {
+        fn skip_instruction(ctx: &mut ExceptionContext) -> ExceptionAction {
+            let pc = ctx.thread_context.pc.get_x();
+            ctx.thread_context.pc.set_x(pc + 4);
+            ExceptionAction::Resume
+        }
+
+        fn unhandled(_ctx: &mut ExceptionContext) -> ExceptionAction {
+            ExceptionAction::ReturnFromException(ResultCode::new(0xCAFE))
+        }
+
+        set_exception_handler(skip_instruction);
+        assert!(get_exception_handler().is_some());
+        let mut ctx = make_context(0x104);
+        assert!(exception_dispatch(&mut ctx).is_success());
+        assert_eq!(ctx.thread_context.pc.get_x(), 0x8000_2004);
+
+        set_exception_handler(unhandled);
+        let mut ctx = make_context(0x104);
+        assert_eq!(exception_dispatch(&mut ctx), ResultCode::new(0xCAFE));
+        assert_eq!(ctx, make_context(0x104));
+
+        reset_exception_handler();
+        assert!(get_exception_handler().is_none());
+    }
+}
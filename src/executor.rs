@@ -0,0 +1,817 @@
+//! Lightweight single-threaded async executor
+//!
+//! The [`Executor`] polls its tasks until all of them are blocked, and then waits (via [`wait::wait_handles`]) on the kernel handles they are waiting for, or until the nearest timer expires.
+//!
+//! Futures for kernel events and timers (and socket readiness, with the `socket` feature) are provided by the executor's [`Reactor`]:
+//!
+//! ```ignore
+//! let mut executor = Executor::new()?;
+//!
+//! let reactor = executor.get_reactor();
+//! executor.spawn(async move {
+//!     loop {
+//!         reactor.wait_remote_event(&vsync_event).await?;
+//!         // Render...
+//!     }
+//! });
+//!
+//! let reactor = executor.get_reactor();
+//! executor.spawn(async move {
+//!     reactor.sleep(1_000_000_000).await;
+//!     // Do something a second later...
+//! });
+//!
+//! executor.run()?;
+//! ```
+//!
+//! Tasks may also be woken from other threads: the executor waits on an internal event alongside the task handles, which is signaled when a sleeping executor's task is woken.
+
+use crate::arm;
+use crate::result::*;
+use crate::svc;
+use crate::sync::Mutex;
+use crate::wait;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::pin::pin;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+use core::task::Context;
+use core::task::Poll;
+use core::task::Waker;
+
+#[cfg(not(test))]
+use crate::arm::get_system_tick;
+#[cfg(feature = "socket")]
+use crate::socket;
+#[cfg(feature = "socket")]
+use crate::socket::net;
+
+/// The ID used for the future being driven by [`Executor::block_on`]
+const MAIN_TASK_ID: usize = usize::MAX;
+
+/// The interval in nanoseconds at which socket readiness is polled, since sockets can't be waited on like kernel handles
+#[cfg(feature = "socket")]
+pub const SOCKET_POLL_INTERVAL: i64 = 1_000_000;
+
+/// The maximum time in nanoseconds spent waiting on some of the handles when tasks wait for more than [`wait::MAX_OBJECT_COUNT`] of them, before waiting on the next ones
+pub const HANDLE_ROTATION_INTERVAL: i64 = 1_000_000;
+
+// Host unit tests can't read the system counter, so they drive a fake (per-thread) one instead
+#[cfg(test)]
+std::thread_local! {
+    static G_TEST_SYSTEM_TICK: core::cell::Cell<u64> = const { core::cell::Cell::new(0) };
+}
+
+#[cfg(test)]
+fn get_system_tick() -> u64 {
+    G_TEST_SYSTEM_TICK.with(|tick| tick.get())
+}
+
+#[cfg(not(test))]
+type WakeEvent = wait::SystemEvent;
+
+// Host unit tests can't create kernel events either, nor do they ever wait for wakes
+#[cfg(test)]
+struct WakeEvent {
+    client_handle: svc::Handle,
+}
+
+#[cfg(test)]
+impl WakeEvent {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            client_handle: svc::INVALID_HANDLE,
+        })
+    }
+
+    fn signal(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct WakeQueue {
+    ready_ids: Mutex<VecDeque<usize>>,
+    sleeping: AtomicBool,
+    event: WakeEvent,
+}
+
+impl WakeQueue {
+    fn push(&self, id: usize) {
+        self.ready_ids.lock().push_back(id);
+        if self.sleeping.load(Ordering::Acquire) {
+            let _ = self.event.signal();
+        }
+    }
+
+    fn pop(&self) -> Option<usize> {
+        self.ready_ids.lock().pop_front()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ready_ids.lock().is_empty()
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    queue: Arc<WakeQueue>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.queue.push(self.id);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.queue.push(self.id);
+    }
+}
+
+struct Task {
+    future: Pin<Box<dyn Future<Output = ()>>>,
+    waker: Waker,
+}
+
+/// Represents the reactor of an [`Executor`], which keeps track of what its tasks are waiting for
+///
+/// All the futures it provides must be awaited inside tasks of the same [`Executor`]
+pub struct Reactor {
+    handle_waiters: RefCell<Vec<(svc::Handle, Waker)>>,
+    timers: RefCell<Vec<(u64, Waker)>>,
+}
+
+impl Reactor {
+    const fn new() -> Self {
+        Self {
+            handle_waiters: RefCell::new(Vec::new()),
+            timers: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn register_handle(&self, handle: svc::Handle, waker: &Waker) {
+        let mut handle_waiters = self.handle_waiters.borrow_mut();
+        if !handle_waiters
+            .iter()
+            .any(|(waiter_handle, waiter)| *waiter_handle == handle && waiter.will_wake(waker))
+        {
+            handle_waiters.push((handle, waker.clone()));
+        }
+    }
+
+    fn register_timer(&self, deadline: u64, waker: &Waker) {
+        let mut timers = self.timers.borrow_mut();
+        if !timers
+            .iter()
+            .any(|(timer_deadline, timer)| *timer_deadline == deadline && timer.will_wake(waker))
+        {
+            timers.push((deadline, waker.clone()));
+        }
+    }
+
+    fn unregister_handle(&self, handle: svc::Handle, waker: &Waker) {
+        self.handle_waiters
+            .borrow_mut()
+            .retain(|(waiter_handle, waiter)| {
+                !(*waiter_handle == handle && waiter.will_wake(waker))
+            });
+    }
+
+    fn unregister_timer(&self, deadline: u64, waker: &Waker) {
+        self.timers.borrow_mut().retain(|(timer_deadline, timer)| {
+            !(*timer_deadline == deadline && timer.will_wake(waker))
+        });
+    }
+
+    fn get_next_deadline(&self) -> Option<u64> {
+        self.timers
+            .borrow()
+            .iter()
+            .map(|(deadline, _)| *deadline)
+            .min()
+    }
+
+    fn wake_handle(&self, handle: svc::Handle) {
+        self.handle_waiters
+            .borrow_mut()
+            .retain(|(waiter_handle, waiter)| {
+                if *waiter_handle == handle {
+                    waiter.wake_by_ref();
+                    false
+                } else {
+                    true
+                }
+            });
+    }
+
+    fn wake_expired_timers(&self) {
+        let now = get_system_tick();
+        self.timers.borrow_mut().retain(|(deadline, timer)| {
+            if *deadline <= now {
+                timer.wake_by_ref();
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Gets a future which completes once the given handle is signaled
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The handle to wait for
+    #[inline]
+    pub fn wait_handle(&self, handle: svc::Handle) -> WaitHandle<'_> {
+        WaitHandle {
+            reactor: self,
+            handle,
+            waker: None,
+        }
+    }
+
+    /// Waits for a [`RemoteEvent`][`wait::RemoteEvent`], then resets it (like [`RemoteEvent::wait`][`wait::RemoteEvent::wait`])
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to wait for
+    pub async fn wait_remote_event(&self, event: &wait::RemoteEvent) -> Result<()> {
+        self.wait_handle(event.handle).await?;
+        event.reset()
+    }
+
+    /// Waits for a [`SystemEvent`][`wait::SystemEvent`] to be signaled, then resets it
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to wait for
+    pub async fn wait_system_event(&self, event: &wait::SystemEvent) -> Result<()> {
+        self.wait_handle(event.client_handle).await?;
+        svc::reset_signal(event.client_handle)
+    }
+
+    /// Gets a future which completes once the given system tick is reached
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - The system tick to wait for (see [`arm::get_system_tick`])
+    #[inline]
+    pub fn sleep_until(&self, deadline: u64) -> Timer<'_> {
+        Timer {
+            reactor: self,
+            deadline,
+            waker: None,
+        }
+    }
+
+    /// Gets a future which completes after the given timeout
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The timeout in nanoseconds
+    #[inline]
+    pub fn sleep(&self, timeout: i64) -> Timer<'_> {
+        self.sleep_until(
+            get_system_tick().saturating_add(arm::nanoseconds_to_ticks(timeout.max(0) as u64)),
+        )
+    }
+
+    /// Waits for a socket to be ready for any of the given events, returning the events it's ready for
+    ///
+    /// Sockets are polled every [`SOCKET_POLL_INTERVAL`] nanoseconds while the task is waiting
+    ///
+    /// # Arguments
+    ///
+    /// * `socket` - The socket to wait for
+    /// * `flags` - The events to wait for
+    #[cfg(feature = "socket")]
+    pub async fn wait_socket<P: net::traits::Pollable>(
+        &self,
+        socket: &P,
+        flags: socket::PollFlags,
+    ) -> Result<socket::PollFlags> {
+        let poll_fd = [(RawPollFd(socket.get_poll_fd()), flags)];
+        loop {
+            if let Some((_, revents)) = net::poll(&poll_fd, Some(0))?.next() {
+                return Ok(revents);
+            }
+
+            self.sleep(SOCKET_POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(feature = "socket")]
+struct RawPollFd(i32);
+
+#[cfg(feature = "socket")]
+impl net::traits::Pollable for RawPollFd {
+    fn get_poll_fd(&self) -> i32 {
+        self.0
+    }
+}
+
+/// Represents a future which completes once a handle is signaled (see [`Reactor::wait_handle`])
+pub struct WaitHandle<'a> {
+    reactor: &'a Reactor,
+    handle: svc::Handle,
+    waker: Option<Waker>,
+}
+
+impl Future for WaitHandle<'_> {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match svc::wait_synchronization_one(self.handle, 0) {
+            Ok(()) => Poll::Ready(Ok(())),
+            Err(rc) if svc::rc::ResultTimedOut::matches(rc) => {
+                self.reactor.register_handle(self.handle, cx.waker());
+                self.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            Err(rc) => Poll::Ready(Err(rc)),
+        }
+    }
+}
+
+impl Drop for WaitHandle<'_> {
+    /// Stops waiting for the handle, since it might get closed once the future is dropped
+    fn drop(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            self.reactor.unregister_handle(self.handle, &waker);
+        }
+    }
+}
+
+/// Represents a future which completes once a system tick is reached (see [`Reactor::sleep_until`])
+pub struct Timer<'a> {
+    reactor: &'a Reactor,
+    deadline: u64,
+    waker: Option<Waker>,
+}
+
+impl Future for Timer<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if get_system_tick() >= self.deadline {
+            Poll::Ready(())
+        } else {
+            if let Some(waker) = self.waker.as_ref()
+                && !waker.will_wake(cx.waker())
+            {
+                self.reactor.unregister_timer(self.deadline, waker);
+            }
+            self.reactor.register_timer(self.deadline, cx.waker());
+            self.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Timer<'_> {
+    /// Stops waiting for the deadline, since timers dropped before firing (like timeouts) would otherwise pile up and cause spurious wakeups
+    fn drop(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            self.reactor.unregister_timer(self.deadline, &waker);
+        }
+    }
+}
+
+/// Represents a single-threaded executor, driven by kernel waits
+pub struct Executor {
+    tasks: Vec<Option<Task>>,
+    queue: Arc<WakeQueue>,
+    reactor: Rc<Reactor>,
+    /// The index of the handle to start waiting at, when not all of them can be waited on at once
+    next_handle_index: usize,
+}
+
+impl Executor {
+    /// Creates a new [`Executor`] with no tasks
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            tasks: Vec::new(),
+            queue: Arc::new(WakeQueue {
+                ready_ids: Mutex::new(VecDeque::new()),
+                sleeping: AtomicBool::new(false),
+                event: WakeEvent::new()?,
+            }),
+            reactor: Rc::new(Reactor::new()),
+            next_handle_index: 0,
+        })
+    }
+
+    /// Gets the [`Reactor`] of this [`Executor`], which provides the futures its tasks may wait on
+    #[inline]
+    pub fn get_reactor(&self) -> Rc<Reactor> {
+        self.reactor.clone()
+    }
+
+    fn make_waker(&self, id: usize) -> Waker {
+        Waker::from(Arc::new(TaskWaker {
+            id,
+            queue: self.queue.clone(),
+        }))
+    }
+
+    /// Spawns a task, which will be run on the next [`run`][`Executor::run`] or [`block_on`][`Executor::block_on`] call
+    ///
+    /// # Arguments
+    ///
+    /// * `future` - The task future
+    pub fn spawn(&mut self, future: impl Future<Output = ()> + 'static) {
+        let id = match self.tasks.iter().position(Option::is_none) {
+            Some(free_id) => free_id,
+            None => {
+                self.tasks.push(None);
+                self.tasks.len() - 1
+            }
+        };
+
+        self.tasks[id] = Some(Task {
+            future: Box::pin(future),
+            waker: self.make_waker(id),
+        });
+        self.queue.push(id);
+    }
+
+    /// Gets the number of tasks which didn't complete yet
+    pub fn get_task_count(&self) -> usize {
+        self.tasks.iter().filter(|task| task.is_some()).count()
+    }
+
+    /// Polls all woken tasks, returning whether the main future was woken
+    fn poll_woken_tasks(&mut self) -> bool {
+        let mut main_woken = false;
+        while let Some(id) = self.queue.pop() {
+            if id == MAIN_TASK_ID {
+                main_woken = true;
+                continue;
+            }
+
+            // Wakes may come after the task completed
+            if let Some(Some(task)) = self.tasks.get_mut(id) {
+                let mut cx = Context::from_waker(&task.waker);
+                if task.future.as_mut().poll(&mut cx).is_ready() {
+                    self.tasks[id] = None;
+                }
+            }
+        }
+
+        main_woken
+    }
+
+    /// Gets the handles to wait on (the internal event's and those tasks are waiting for), also returning whether all of them fit
+    fn get_wait_handles(&mut self) -> (Vec<svc::Handle>, bool) {
+        let mut handles: Vec<svc::Handle> = vec![self.queue.event.client_handle];
+        let mut waiter_handles: Vec<svc::Handle> = Vec::new();
+        for (handle, _) in self.reactor.handle_waiters.borrow().iter() {
+            if !waiter_handles.contains(handle) {
+                waiter_handles.push(*handle);
+            }
+        }
+
+        // Only so many handles can be waited on at once, thus rotate through them across waits so that none of them is starved
+        let max_count = wait::MAX_OBJECT_COUNT as usize - handles.len();
+        let all_fit = waiter_handles.len() <= max_count;
+        if !all_fit {
+            let start_index = self.next_handle_index % waiter_handles.len();
+            waiter_handles.rotate_left(start_index);
+            waiter_handles.truncate(max_count);
+            self.next_handle_index = start_index + max_count;
+        }
+        handles.extend(waiter_handles);
+        (handles, all_fit)
+    }
+
+    /// Waits until some task is woken, either by a handle being signaled, a timer expiring or a wake from another thread
+    fn wait_for_wakes(&mut self) -> Result<()> {
+        let (handles, all_fit) = self.get_wait_handles();
+
+        let mut timeout = match self.reactor.get_next_deadline() {
+            Some(deadline) => {
+                arm::ticks_to_nanoseconds(deadline.saturating_sub(get_system_tick())) as i64
+            }
+            None => -1,
+        };
+        if !all_fit && !(0..HANDLE_ROTATION_INTERVAL).contains(&timeout) {
+            timeout = HANDLE_ROTATION_INTERVAL;
+        }
+
+        // Tasks woken before we got here (from other threads) wouldn't signal the event
+        self.queue.sleeping.store(true, Ordering::Release);
+        if !self.queue.is_empty() {
+            self.queue.sleeping.store(false, Ordering::Release);
+            return Ok(());
+        }
+
+        let wait_rc = wait::wait_handles(&handles, timeout);
+        self.queue.sleeping.store(false, Ordering::Release);
+        match wait_rc {
+            Ok(0) => svc::reset_signal(self.queue.event.client_handle)?,
+            Ok(index) => self.reactor.wake_handle(handles[index]),
+            Err(rc) if svc::rc::ResultTimedOut::matches(rc) => {}
+            Err(rc) => return Err(rc),
+        };
+
+        self.reactor.wake_expired_timers();
+        Ok(())
+    }
+
+    /// Runs the spawned tasks until all of them complete
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            self.poll_woken_tasks();
+            if self.get_task_count() == 0 {
+                return Ok(());
+            }
+
+            self.wait_for_wakes()?;
+        }
+    }
+
+    /// Runs a future (along with the spawned tasks) until it completes, returning its output
+    ///
+    /// Spawned tasks which didn't complete by then are kept for later [`run`][`Executor::run`] or [`block_on`][`Executor::block_on`] calls
+    ///
+    /// # Arguments
+    ///
+    /// * `future` - The future to run
+    pub fn block_on<F: Future>(&mut self, future: F) -> Result<F::Output> {
+        let mut future = pin!(future);
+        let waker = self.make_waker(MAIN_TASK_ID);
+        let mut cx = Context::from_waker(&waker);
+
+        self.queue.push(MAIN_TASK_ID);
+        loop {
+            if self.poll_woken_tasks()
+                && let Poll::Ready(output) = future.as_mut().poll(&mut cx)
+            {
+                return Ok(output);
+            }
+
+            // Polling the main future may have woken tasks (or itself)
+            if self.queue.is_empty() {
+                self.wait_for_wakes()?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+    use core::cell::Cell;
+
+    fn set_system_tick(tick: u64) {
+        G_TEST_SYSTEM_TICK.with(|cur_tick| cur_tick.set(tick));
+    }
+
+    /// Future which completes once [`Signal::set`] is called, counting how many times it was polled
+    #[derive(Clone, Default)]
+    struct Signal(Rc<RefCell<(bool, Option<Waker>, usize)>>);
+
+    impl Signal {
+        fn set(&self) {
+            let mut state = self.0.borrow_mut();
+            state.0 = true;
+            if let Some(waker) = state.1.take() {
+                waker.wake();
+            }
+        }
+
+        fn get_poll_count(&self) -> usize {
+            self.0.borrow().2
+        }
+    }
+
+    impl Future for Signal {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let mut state = self.0.borrow_mut();
+            state.2 += 1;
+            if state.0 {
+                Poll::Ready(())
+            } else {
+                state.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Polls a future once, returning whether it completed
+    async fn poll_once<F: Future + Unpin>(future: &mut F) -> bool {
+        core::future::poll_fn(|cx| Poll::Ready(Pin::new(&mut *future).poll(cx).is_ready())).await
+    }
+
+    #[test]
+    fn spawn_and_run() {
+        let mut executor = Executor::new().unwrap();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        for i in 0..3 {
+            let log = log.clone();
+            executor.spawn(async move { log.borrow_mut().push(i) });
+        }
+        assert_eq!(executor.get_task_count(), 3);
+
+        executor.run().unwrap();
+        assert_eq!(*log.borrow(), [0, 1, 2]);
+        assert_eq!(executor.get_task_count(), 0);
+
+        // Completed task slots are reused
+        let log_2 = log.clone();
+        executor.spawn(async move { log_2.borrow_mut().push(3) });
+        assert_eq!(executor.tasks.len(), 3);
+        executor.run().unwrap();
+        assert_eq!(*log.borrow(), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn join_tasks() {
+        let mut executor = Executor::new().unwrap();
+        let signal = Signal::default();
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        let (waiter_signal, waiter_log) = (signal.clone(), log.clone());
+        executor.spawn(async move {
+            waiter_log.borrow_mut().push("waiting");
+            waiter_signal.await;
+            waiter_log.borrow_mut().push("woken");
+        });
+        let (setter_signal, setter_log) = (signal.clone(), log.clone());
+        executor.spawn(async move {
+            setter_log.borrow_mut().push("setting");
+            setter_signal.set();
+        });
+
+        executor.run().unwrap();
+        assert_eq!(*log.borrow(), ["waiting", "setting", "woken"]);
+        // Tasks are only polled again once woken
+        assert_eq!(signal.get_poll_count(), 2);
+    }
+
+    #[test]
+    fn block_on_output() {
+        let mut executor = Executor::new().unwrap();
+        let signal = Signal::default();
+
+        let task_signal = signal.clone();
+        executor.spawn(async move { task_signal.set() });
+        let pending_signal = Signal::default();
+        executor.spawn(pending_signal.clone());
+
+        let output = executor
+            .block_on(async {
+                signal.clone().await;
+                String::from("done")
+            })
+            .unwrap();
+        assert_eq!(output, "done");
+
+        // Unfinished tasks are kept for later
+        assert_eq!(executor.get_task_count(), 1);
+        pending_signal.set();
+        executor.run().unwrap();
+        assert_eq!(executor.get_task_count(), 0);
+    }
+
+    #[test]
+    fn wake_from_other_thread() {
+        let mut executor = Executor::new().unwrap();
+        let poll_count = Rc::new(Cell::new(0));
+
+        let task_poll_count = poll_count.clone();
+        executor.spawn(core::future::poll_fn(move |cx| {
+            task_poll_count.set(task_poll_count.get() + 1);
+            if task_poll_count.get() > 1 {
+                return Poll::Ready(());
+            }
+            let waker = cx.waker().clone();
+            std::thread::spawn(move || waker.wake()).join().unwrap();
+            Poll::Pending
+        }));
+
+        executor.run().unwrap();
+        assert_eq!(poll_count.get(), 2);
+    }
+
+    #[test]
+    fn timer_wakes() {
+        set_system_tick(0);
+        let mut executor = Executor::new().unwrap();
+        let reactor = executor.get_reactor();
+
+        let task_reactor = reactor.clone();
+        executor.spawn(async move {
+            task_reactor.sleep_until(100).await;
+            task_reactor.sleep_until(50).await;
+        });
+        executor.poll_woken_tasks();
+        assert_eq!(executor.get_task_count(), 1);
+        assert_eq!(reactor.get_next_deadline(), Some(100));
+
+        set_system_tick(99);
+        reactor.wake_expired_timers();
+        assert!(executor.queue.is_empty());
+
+        // Expired timers are removed once woken, and the next one is already expired
+        set_system_tick(100);
+        reactor.wake_expired_timers();
+        assert_eq!(reactor.get_next_deadline(), None);
+        executor.poll_woken_tasks();
+        assert_eq!(executor.get_task_count(), 0);
+    }
+
+    #[test]
+    fn drop_pending_timer() {
+        set_system_tick(0);
+        let mut executor = Executor::new().unwrap();
+        let reactor = executor.get_reactor();
+
+        // Timeout pattern: the timer is dropped once the other future completes first
+        let task_reactor = reactor.clone();
+        let signal = Signal::default();
+        let task_signal = signal.clone();
+        executor.spawn(async move {
+            let mut timeout = pin!(task_reactor.sleep_until(1000));
+            assert!(!poll_once(&mut timeout).await);
+            task_signal.await;
+        });
+        executor.poll_woken_tasks();
+        assert_eq!(reactor.get_next_deadline(), Some(1000));
+
+        signal.set();
+        executor.poll_woken_tasks();
+        assert_eq!(executor.get_task_count(), 0);
+        assert_eq!(reactor.get_next_deadline(), None);
+
+        // Polling again with the same waker doesn't register it twice
+        let mut timer = reactor.sleep_until(2000);
+        let waker = executor.make_waker(1);
+        let mut cx = Context::from_waker(&waker);
+        for _ in 0..3 {
+            assert!(Pin::new(&mut timer).poll(&mut cx).is_pending());
+        }
+        assert_eq!(reactor.timers.borrow().len(), 1);
+
+        // Polling with another waker replaces the previous one
+        let other_waker = executor.make_waker(2);
+        assert!(
+            Pin::new(&mut timer)
+                .poll(&mut Context::from_waker(&other_waker))
+                .is_pending()
+        );
+        assert_eq!(reactor.timers.borrow().len(), 1);
+        assert!(reactor.timers.borrow()[0].1.will_wake(&other_waker));
+        drop(timer);
+        assert!(reactor.timers.borrow().is_empty());
+    }
+
+    #[test]
+    fn rotate_wait_handles() {
+        let mut executor = Executor::new().unwrap();
+        let reactor = executor.get_reactor();
+        let event_handle = executor.queue.event.client_handle;
+
+        let max_count = wait::MAX_OBJECT_COUNT as usize - 1;
+        for handle in 1..=10 {
+            reactor.register_handle(handle, Waker::noop());
+        }
+        // Duplicates are only waited on once
+        reactor.register_handle(1, &executor.make_waker(0));
+        let (handles, all_fit) = executor.get_wait_handles();
+        assert!(all_fit);
+        assert_eq!(handles[0], event_handle);
+        assert_eq!(handles[1..], (1..=10).collect::<Vec<_>>());
+
+        let handle_count = max_count * 2 + 10;
+        reactor.handle_waiters.borrow_mut().clear();
+        for handle in 1..=handle_count as svc::Handle {
+            reactor.register_handle(handle, Waker::noop());
+        }
+
+        // Every handle is waited on after enough waits
+        let mut waited_counts = alloc::vec![0; handle_count];
+        for _ in 0..3 {
+            let (handles, all_fit) = executor.get_wait_handles();
+            assert!(!all_fit);
+            assert_eq!(handles.len(), wait::MAX_OBJECT_COUNT as usize);
+            assert_eq!(handles[0], event_handle);
+            for handle in &handles[1..] {
+                waited_counts[*handle as usize - 1] += 1;
+            }
+        }
+        assert!(waited_counts.iter().all(|&count| count >= 1));
+        assert_eq!(waited_counts.iter().sum::<usize>(), max_count * 3);
+    }
+}
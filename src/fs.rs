@@ -1,13 +1,23 @@
 //! FileSystem support
 
+use crate::hbl;
 use crate::ipc::sf as ipc_sf;
+use crate::ipc::sf::account::Uid;
 use crate::ipc::sf::fsp::IDirectoryClient;
 use crate::ipc::sf::fsp::IFileClient;
 use crate::ipc::sf::fsp::IFileSystemClient;
+use crate::ipc::sf::fsp::ISaveDataInfoReaderClient;
+use crate::ipc::sf::ncm::ApplicationId;
+use crate::ipc::sf::ncm::{ContentType, ProgramId, StorageId};
 use crate::result::*;
+use crate::rrt0;
 use crate::service;
 use crate::service::fsp;
 use crate::service::fsp::srv::IFileSystemProxyClient;
+use crate::service::ncm;
+use crate::service::ncm::{
+    IContentManagerClient, IContentMetaDatabaseClient, IContentStorageClient,
+};
 use crate::sync::RwLock;
 use alloc::boxed::Box;
 use alloc::string::String;
@@ -23,6 +33,7 @@ pub mod rc;
 
 // TODO: define this types here and alias them in fsp-srv?
 
+pub use fsp::fsp_sf::ContentStorageId;
 pub use fsp::fsp_sf::DirectoryEntry;
 pub use fsp::fsp_sf::DirectoryEntryType;
 pub use fsp::fsp_sf::DirectoryOpenMode;
@@ -30,10 +41,16 @@ pub use fsp::fsp_sf::FileAttribute;
 pub use fsp::fsp_sf::FileOpenMode;
 pub use fsp::fsp_sf::FileQueryRangeInfo;
 pub use fsp::fsp_sf::FileReadOption;
+pub use fsp::fsp_sf::FileSystemType;
 pub use fsp::fsp_sf::FileTimeStampRaw;
 pub use fsp::fsp_sf::FileWriteOption;
 pub use fsp::fsp_sf::OperationId;
 pub use fsp::fsp_sf::QueryId;
+pub use fsp::fsp_sf::SaveDataAttribute;
+pub use fsp::fsp_sf::SaveDataInfo;
+pub use fsp::fsp_sf::SaveDataRank;
+pub use fsp::fsp_sf::SaveDataSpaceId;
+pub use fsp::fsp_sf::SaveDataType;
 
 /// Represents a file, abstracted from the IPC client API.
 pub trait File: Sync {
@@ -538,6 +555,11 @@ impl FileAccessor {
         self.file.get_size()
     }
 
+    /// Consumes the [`FileAccessor`], returning the underlying [`File`] object.
+    pub fn into_file(self) -> Box<dyn File> {
+        self.file
+    }
+
     /// Seeks in the file to a certain offset.
     ///
     /// # Arguments:
@@ -721,6 +743,27 @@ impl DirectoryAccessor {
     }
 }
 
+/// Reads exactly `out_buf.len()` bytes from a [`File`] at a given offset.
+///
+/// This will fail with [`ResultUnexpectedEndOfFile`][`rc::ResultUnexpectedEndOfFile`] if the file ends before the buffer is filled.
+pub(crate) fn read_file_exact(
+    file: &mut dyn File,
+    offset: usize,
+    out_buf: &mut [u8],
+) -> Result<()> {
+    let mut read_size = 0;
+    while read_size < out_buf.len() {
+        let cur_read_size = file.read(
+            offset + read_size,
+            &mut out_buf[read_size..],
+            FileReadOption::None(),
+        )?;
+        result_return_if!(cur_read_size == 0, rc::ResultUnexpectedEndOfFile);
+        read_size += cur_read_size;
+    }
+    Ok(())
+}
+
 pub(crate) struct FileSystemDevice {
     mount_name: String,
     fs: Arc<dyn FileSystem>,
@@ -833,6 +876,434 @@ pub fn mount_sd_card(name: &str) -> Result<()> {
     Ok(())
 }
 
+/// Opens a save data filesystem using `fsp-srv` support
+///
+/// Note that changes made to save data filesystems aren't persisted until they're explicitly committed (see [`FileSystem::commit`], or [`commit`] if mounted)
+///
+/// This will fail with [`ResultNotInitialized`][`super::rc::ResultNotInitialized`] if `fsp-srv` support isn't initialized
+///
+/// # Arguments
+///
+/// * `space_id`: The space the save data is in
+/// * `attribute`: The attribute identifying the save data
+pub fn open_save_data(
+    space_id: SaveDataSpaceId,
+    attribute: SaveDataAttribute,
+) -> Result<ProxyFileSystem> {
+    let fs_obj = match attribute.save_data_type {
+        SaveDataType::System | SaveDataType::SystemBcat => get_fspsrv_session()?
+            .open_save_data_file_system_by_system_save_data_id(space_id, attribute)?,
+        _ => get_fspsrv_session()?.open_save_data_file_system(space_id, attribute)?,
+    };
+    Ok(ProxyFileSystem::new(Arc::new(fs_obj)))
+}
+
+/// Mounts an application's account save data using `fsp-srv` support
+///
+/// Changes must be explicitly committed with [`commit`] (for instance, `commit("save:/")`) to be persisted
+///
+/// # Arguments
+///
+/// * `name`: The mount name
+/// * `application_id`: The application ID, where `0` means the current application
+/// * `uid`: The user account the save data belongs to
+pub fn mount_save_data(name: &str, application_id: ApplicationId, uid: Uid) -> Result<()> {
+    let save_fs = open_save_data(
+        SaveDataSpaceId::User,
+        SaveDataAttribute {
+            application_id,
+            uid,
+            save_data_type: SaveDataType::Account,
+            ..Default::default()
+        },
+    )?;
+    mount(name, Arc::new(save_fs));
+    Ok(())
+}
+
+/// Mounts an application's device save data using `fsp-srv` support
+///
+/// Changes must be explicitly committed with [`commit`] to be persisted
+///
+/// # Arguments
+///
+/// * `name`: The mount name
+/// * `application_id`: The application ID, where `0` means the current application
+pub fn mount_device_save_data(name: &str, application_id: ApplicationId) -> Result<()> {
+    let save_fs = open_save_data(
+        SaveDataSpaceId::User,
+        SaveDataAttribute {
+            application_id,
+            save_data_type: SaveDataType::Device,
+            ..Default::default()
+        },
+    )?;
+    mount(name, Arc::new(save_fs));
+    Ok(())
+}
+
+/// Mounts an application's BCAT save data using `fsp-srv` support
+///
+/// # Arguments
+///
+/// * `name`: The mount name
+/// * `application_id`: The application ID, where `0` means the current application
+pub fn mount_bcat_save_data(name: &str, application_id: ApplicationId) -> Result<()> {
+    let save_fs = open_save_data(
+        SaveDataSpaceId::User,
+        SaveDataAttribute {
+            application_id,
+            save_data_type: SaveDataType::Bcat,
+            ..Default::default()
+        },
+    )?;
+    mount(name, Arc::new(save_fs));
+    Ok(())
+}
+
+/// Mounts a system save data using `fsp-srv` support
+///
+/// Changes must be explicitly committed with [`commit`] to be persisted
+///
+/// # Arguments
+///
+/// * `name`: The mount name
+/// * `space_id`: The space the save data is in
+/// * `system_save_data_id`: The system save data ID
+/// * `uid`: The user account the save data belongs to, if any (otherwise the default/zero [`Uid`])
+pub fn mount_system_save_data(
+    name: &str,
+    space_id: SaveDataSpaceId,
+    system_save_data_id: u64,
+    uid: Uid,
+) -> Result<()> {
+    let save_fs = open_save_data(
+        space_id,
+        SaveDataAttribute {
+            uid,
+            system_save_data_id,
+            save_data_type: SaveDataType::System,
+            ..Default::default()
+        },
+    )?;
+    mount(name, Arc::new(save_fs));
+    Ok(())
+}
+
+/// Represents a filter for save data enumeration (see [`iter_save_data`])
+///
+/// Every set field must match for a save data to be yielded
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct SaveDataFilter {
+    /// The application ID to match
+    pub application_id: Option<ApplicationId>,
+    /// The user account to match
+    pub uid: Option<Uid>,
+    /// The save data type to match
+    pub save_data_type: Option<SaveDataType>,
+}
+
+impl SaveDataFilter {
+    /// Creates a new [`SaveDataFilter`] matching all save data
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            application_id: None,
+            uid: None,
+            save_data_type: None,
+        }
+    }
+
+    /// Creates a new [`SaveDataFilter`] matching the save data of an application
+    ///
+    /// # Arguments
+    ///
+    /// * `application_id`: The application ID to match
+    #[inline]
+    pub const fn by_application_id(application_id: ApplicationId) -> Self {
+        Self {
+            application_id: Some(application_id),
+            uid: None,
+            save_data_type: None,
+        }
+    }
+
+    /// Creates a new [`SaveDataFilter`] matching the save data of a user account
+    ///
+    /// # Arguments
+    ///
+    /// * `uid`: The user account to match
+    #[inline]
+    pub const fn by_uid(uid: Uid) -> Self {
+        Self {
+            application_id: None,
+            uid: Some(uid),
+            save_data_type: None,
+        }
+    }
+
+    /// Gets whether a save data matches this filter
+    ///
+    /// # Arguments
+    ///
+    /// * `info`: The save data info to check
+    pub fn matches(&self, info: &SaveDataInfo) -> bool {
+        self.application_id
+            .is_none_or(|application_id| application_id == info.application_id)
+            && self.uid.is_none_or(|uid| uid == info.uid)
+            && self
+                .save_data_type
+                .is_none_or(|save_data_type| info.get_save_data_type() == Some(save_data_type))
+    }
+}
+
+/// Represents an iterator over save data infos, reading them from `fsp-srv` in batches
+///
+/// Reading errors are yielded as `Err` items, after which the iteration ends
+pub struct SaveDataInfoIterator {
+    reader: fsp::fsp_sf::SaveDataInfoReader,
+    filter: SaveDataFilter,
+    infos: Vec<SaveDataInfo>,
+    info_count: usize,
+    index: usize,
+    done: bool,
+}
+
+impl SaveDataInfoIterator {
+    /// The amount of save data infos read at once
+    const BATCH_COUNT: usize = 0x40;
+
+    fn read_batch(&mut self) -> Result<()> {
+        let read_count = self
+            .reader
+            .read_save_data_info(ipc_sf::Buffer::from_mut_array(&mut self.infos))?;
+        self.info_count = (read_count as usize).min(self.infos.len());
+        self.index = 0;
+        self.done = self.info_count == 0;
+        Ok(())
+    }
+}
+
+impl Iterator for SaveDataInfoIterator {
+    type Item = Result<SaveDataInfo>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            if self.index == self.info_count {
+                if let Err(rc) = self.read_batch() {
+                    self.done = true;
+                    return Some(Err(rc));
+                }
+                continue;
+            }
+
+            let info = self.infos[self.index];
+            self.index += 1;
+            if self.filter.matches(&info) {
+                return Some(Ok(info));
+            }
+        }
+
+        None
+    }
+}
+
+/// Enumerates the save data in a given space using `fsp-srv` support
+///
+/// This will fail with [`ResultNotInitialized`][`super::rc::ResultNotInitialized`] if `fsp-srv` support isn't initialized
+///
+/// # Arguments
+///
+/// * `space_id`: The space to enumerate
+/// * `filter`: The filter the yielded save data must match
+pub fn iter_save_data(
+    space_id: SaveDataSpaceId,
+    filter: SaveDataFilter,
+) -> Result<SaveDataInfoIterator> {
+    let reader =
+        get_fspsrv_session()?.open_save_data_info_reader_by_save_data_space_id(space_id)?;
+    Ok(SaveDataInfoIterator {
+        reader,
+        filter,
+        infos: alloc::vec![SaveDataInfo::default(); SaveDataInfoIterator::BATCH_COUNT],
+        info_count: 0,
+        index: 0,
+        done: false,
+    })
+}
+
+/// Opens the filesystem of a content (NCA) from its path using `fsp-srv` support
+///
+/// This will fail with [`ResultNotInitialized`][`super::rc::ResultNotInitialized`] if `fsp-srv` support isn't initialized
+///
+/// # Arguments
+///
+/// * `path`: The content path, as obtained from [`IContentStorageClient::get_path`]
+/// * `fs_type`: The kind of filesystem to open from the content
+/// * `program_id`: The program ID the content belongs to
+pub fn open_content_file_system(
+    path: &str,
+    fs_type: FileSystemType,
+    program_id: ProgramId,
+) -> Result<ProxyFileSystem> {
+    let sf_path = fsp::fsp_sf::Path::from_str(path);
+    let fs_obj = get_fspsrv_session()?.open_file_system_with_id(
+        ipc_sf::Buffer::from_var(&sf_path),
+        fs_type,
+        program_id.0,
+    )?;
+    Ok(ProxyFileSystem::new(Arc::new(fs_obj)))
+}
+
+/// Opens the filesystem of an installed title's content using `ncm` and `fsp-srv` support
+///
+/// The content is looked up in the latest content meta installed for the title in the given storage
+///
+/// This will fail with [`ResultNotInitialized`][`super::rc::ResultNotInitialized`] if `fsp-srv` support isn't initialized
+///
+/// # Arguments
+///
+/// * `program_id`: The program ID of the title
+/// * `storage_id`: The storage the title is installed in
+/// * `content_type`: The type of the content to look up (for instance, [`ContentType::Control`])
+/// * `fs_type`: The kind of filesystem to open from the content (for instance, [`FileSystemType::ContentControl`])
+pub fn open_program_content(
+    program_id: ProgramId,
+    storage_id: StorageId,
+    content_type: ContentType,
+    fs_type: FileSystemType,
+) -> Result<ProxyFileSystem> {
+    let ncm_srv = service::new_service_object::<ncm::ContentManagerService>()?;
+
+    let meta_db = ncm_srv.open_content_meta_database(storage_id)?;
+    let meta_key = meta_db.get_latest_content_meta_key(program_id)?;
+    let content_id = meta_db.get_content_id_by_type(meta_key, content_type)?;
+
+    let content_storage = ncm_srv.open_content_storage(storage_id)?;
+    let mut path = ncm::ContentPath::new();
+    content_storage.get_path(ipc_sf::Buffer::from_mut_var(&mut path), content_id)?;
+
+    open_content_file_system(path.get_str()?, fs_type, program_id)
+}
+
+/// Mounts the ExeFS of an installed title using `ncm` and `fsp-srv` support
+///
+/// # Arguments
+///
+/// * `name`: The mount name
+/// * `program_id`: The program ID of the title
+/// * `storage_id`: The storage the title is installed in
+pub fn mount_program_exefs(name: &str, program_id: ProgramId, storage_id: StorageId) -> Result<()> {
+    let exefs = open_program_content(
+        program_id,
+        storage_id,
+        ContentType::Program,
+        FileSystemType::Code,
+    )?;
+    mount(name, Arc::new(exefs));
+    Ok(())
+}
+
+/// Mounts the RomFS of an installed title using `ncm` and `fsp-srv` support
+///
+/// # Arguments
+///
+/// * `name`: The mount name
+/// * `program_id`: The program ID of the title
+/// * `storage_id`: The storage the title is installed in
+pub fn mount_program_romfs(name: &str, program_id: ProgramId, storage_id: StorageId) -> Result<()> {
+    let romfs = open_program_content(
+        program_id,
+        storage_id,
+        ContentType::Program,
+        FileSystemType::ContentData,
+    )?;
+    mount(name, Arc::new(romfs));
+    Ok(())
+}
+
+/// Mounts the control data (NACP, icons...) of an installed title using `ncm` and `fsp-srv` support
+///
+/// # Arguments
+///
+/// * `name`: The mount name
+/// * `program_id`: The program ID of the title
+/// * `storage_id`: The storage the title is installed in
+pub fn mount_program_control(
+    name: &str,
+    program_id: ProgramId,
+    storage_id: StorageId,
+) -> Result<()> {
+    let control_fs = open_program_content(
+        program_id,
+        storage_id,
+        ContentType::Control,
+        FileSystemType::ContentControl,
+    )?;
+    mount(name, Arc::new(control_fs));
+    Ok(())
+}
+
+/// Mounts a content storage filesystem using `fsp-srv` support
+///
+/// This will fail with [`ResultNotInitialized`][`super::rc::ResultNotInitialized`] if `fsp-srv` support isn't initialized
+///
+/// # Arguments
+///
+/// * `name`: The mount name
+/// * `storage_id`: The content storage to mount
+pub fn mount_content_storage(name: &str, storage_id: ContentStorageId) -> Result<()> {
+    let fs_obj = get_fspsrv_session()?.open_content_storage_file_system(storage_id)?;
+    mount_fsp_filesystem(name, Arc::new(fs_obj));
+    Ok(())
+}
+
+/// Mounts a RomFS image
+///
+/// # Arguments
+///
+/// * `name`: The mount name
+/// * `file`: The [`File`] containing the RomFS image
+pub fn mount_romfs(name: &str, file: Box<dyn File>) -> Result<()> {
+    let romfs = romfs::RomFsFileSystem::new(file)?;
+    mount(name, Arc::new(romfs));
+    Ok(())
+}
+
+/// Mounts the RomFS image embedded in the currently running homebrew NRO
+///
+/// The NRO is opened through its path (see [`get_executable_path`][`crate::hbl::get_executable_path`]). If the device of that path (usually `sdmc`) isn't mounted, the SD card is accessed directly, which requires `fsp-srv` support to be initialized
+///
+/// This will fail with [`ResultNotSupported`][`super::rc::ResultNotSupported`] if the current executable isn't a NRO
+///
+/// # Arguments
+///
+/// * `name`: The mount name
+pub fn mount_romfs_self(name: &str) -> Result<()> {
+    result_return_unless!(
+        rrt0::get_executable_type() == rrt0::ExecutableType::Nro,
+        super::rc::ResultNotSupported
+    );
+    let nro_path = hbl::get_executable_path().ok_or(super::rc::ResultNotSupported::make())?;
+
+    let nro_file = match open_file(nro_path, FileOpenOption::Read()) {
+        Ok(nro_file) => nro_file.into_file(),
+        Err(rc) if rc::ResultDeviceNotFound::matches(rc) => {
+            let (_, sd_path) = nro_path
+                .split_once(':')
+                .ok_or(rc::ResultInvalidPath::make())?;
+            let sd_fs =
+                ProxyFileSystem::new(Arc::new(get_fspsrv_session()?.open_sd_card_filesystem()?));
+            sd_fs.open_file(sd_path, FileOpenMode::Read())?
+        }
+        Err(rc) => return Err(rc),
+    };
+
+    let romfs = romfs::RomFsFileSystem::from_nro(nro_file)?;
+    mount(name, Arc::new(romfs));
+    Ok(())
+}
+
 /// Unmounts a mounted filesystem
 ///
 /// Note that this does nothing if there is no mounted filesystem with the given name
@@ -1176,6 +1647,9 @@ pub fn set_concatenation_file_attribute(path: &str) -> Result<()> {
 
 pub mod subdir;
 
-//pub mod pfs0;
+pub mod pfs0;
+
+pub mod hfs0;
+pub mod romfs;
 
-//pub mod nca;
+pub mod nca;
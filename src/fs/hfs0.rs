@@ -0,0 +1,635 @@
+//! HFS0 (hashed partition filesystem) support
+//!
+//! HFS0 images are PFS0-like archives whose file entries also contain the SHA-256 hash of a leading "hashed region" of each file.
+//!
+//! Gamecard images (XCI) contain a root HFS0 partition whose files are themselves HFS0 partitions (see [`GamecardPartition`]):
+//!
+//! ```ignore
+//! let xci_file = nx::fs::open_file("sdmc:/game.xci", nx::fs::FileOpenOption::Read())?;
+//! let root_fs = Hfs0FileSystem::open_gamecard(xci_file.into_file(), true)?;
+//! let secure_fs = root_fs.open_partition(GamecardPartition::Secure.get_name())?;
+//! nx::fs::mount("secure", Arc::new(secure_fs));
+//! ```
+
+use crate::fs;
+use crate::fs::FileSystem;
+use crate::fs::pfs0;
+use crate::fs::rc;
+use crate::result::*;
+use crate::service::fsp::fsp_sf;
+use crate::sync::Mutex;
+use crate::util;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem;
+use core::sync::atomic::{AtomicBool, Ordering};
+use sha2::Digest;
+
+/// Represents a SHA-256 hash
+pub type Hash = [u8; 0x20];
+
+/// Computes the SHA-256 hash of the given data
+///
+/// # Arguments
+///
+/// * `data`: The data to hash
+pub fn compute_hash(data: &[u8]) -> Hash {
+    sha2::Sha256::digest(data).into()
+}
+
+/// Represents the HFS0 header
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct Header {
+    /// The magic, whose expected value is [`MAGIC`][`Header::MAGIC`]
+    pub magic: u32,
+    /// The number of [`FileEntry`]s following the header
+    pub file_count: u32,
+    /// The size of the string table following the file entries
+    pub string_table_size: u32,
+    /// Reserved
+    pub reserved: u32,
+}
+const_assert!(mem::size_of::<Header>() == 0x10);
+
+impl Header {
+    /// The header magic value (`HFS0`)
+    pub const MAGIC: u32 = u32::from_le_bytes(*b"HFS0");
+
+    /// Gets the size of the whole metadata block (header, file entry table and string table)
+    ///
+    /// File data offsets are relative to the end of this block
+    pub const fn get_metadata_size(&self) -> usize {
+        pfs0::get_partition_metadata_size::<Self, FileEntry>(
+            self.file_count,
+            self.string_table_size,
+        )
+    }
+}
+
+/// Represents a raw HFS0 file entry
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct FileEntry {
+    /// The file data offset, relative to the end of the metadata block
+    pub offset: u64,
+    /// The file data size
+    pub size: u64,
+    /// The offset of the (NUL-terminated) file name in the string table
+    pub string_table_offset: u32,
+    /// The size of the region (at the start of the file) covered by `hash`
+    pub hashed_region_size: u32,
+    /// Reserved
+    pub reserved: u64,
+    /// The SHA-256 hash of the hashed region
+    pub hash: Hash,
+}
+const_assert!(mem::size_of::<FileEntry>() == 0x40);
+
+/// Represents a parsed file entry
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Entry {
+    /// The file name
+    pub name: String,
+    /// The absolute offset of the file data inside the image
+    pub offset: usize,
+    /// The file data size
+    pub size: usize,
+    /// The size of the region (at the start of the file) covered by `hash`
+    pub hashed_region_size: usize,
+    /// The SHA-256 hash of the hashed region
+    pub hash: Hash,
+}
+
+/// Represents the parsed metadata of a HFS0 image
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Hfs0 {
+    entries: Vec<Entry>,
+    metadata_size: usize,
+}
+
+impl Hfs0 {
+    /// Reads and validates the [`Header`] at the start of the given data
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The image data, which must be at least [`Header`]-sized
+    pub fn read_header(data: &[u8]) -> Result<Header> {
+        // SAFETY: the header only has integer fields
+        let header: Header = unsafe { util::read_unaligned_at(data, 0) }
+            .ok_or(rc::ResultUnexpectedEndOfFile::make())?;
+        result_return_unless!(header.magic == Header::MAGIC, rc::ResultInvalidHfs0Magic);
+        Ok(header)
+    }
+
+    /// Parses the HFS0 metadata from the given data
+    ///
+    /// The data must contain at least the whole metadata block (see [`Header::get_metadata_size`]), the file data itself isn't required.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The image data
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let header = Self::read_header(data)?;
+        // SAFETY: the file entries only have integer/hash fields
+        let (entries, metadata_size) = unsafe {
+            pfs0::parse_partition_entries::<Header, FileEntry, _>(
+                data,
+                header.file_count,
+                header.string_table_size,
+                Self::make_entry,
+            )
+        }?;
+
+        Ok(Self {
+            entries,
+            metadata_size,
+        })
+    }
+
+    fn make_entry(raw_entry: &FileEntry, string_table: &[u8], data_offset: usize) -> Result<Entry> {
+        let offset = usize::try_from(raw_entry.offset)
+            .ok()
+            .and_then(|offset| offset.checked_add(data_offset))
+            .ok_or(rc::ResultInvalidHfs0Header::make())?;
+        let size =
+            usize::try_from(raw_entry.size).map_err(|_| rc::ResultInvalidHfs0Header::make())?;
+        let hashed_region_size = raw_entry.hashed_region_size as usize;
+        result_return_if!(
+            offset.checked_add(size).is_none() || hashed_region_size > size,
+            rc::ResultInvalidHfs0Header
+        );
+
+        Ok(Entry {
+            name: pfs0::read_partition_entry_name::<rc::ResultInvalidHfs0Header>(
+                string_table,
+                raw_entry.string_table_offset as usize,
+            )?,
+            offset,
+            size,
+            hashed_region_size,
+            hash: raw_entry.hash,
+        })
+    }
+
+    /// Gets the parsed file entries
+    #[inline]
+    pub fn get_entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Gets the size of the metadata block, which is also where file data starts
+    #[inline]
+    pub fn get_metadata_size(&self) -> usize {
+        self.metadata_size
+    }
+
+    /// Finds a file entry by its name
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The file name
+    pub fn find_entry(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Gets the data of a file entry from the whole image data
+    ///
+    /// Returns [`None`] if the image data is too small to contain the file
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The whole image data
+    /// * `entry`: The file entry
+    pub fn get_file_data<'a>(&self, data: &'a [u8], entry: &Entry) -> Option<&'a [u8]> {
+        data.get(entry.offset..entry.offset + entry.size)
+    }
+
+    /// Verifies the hashed region of a file entry against the whole image data
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The whole image data
+    /// * `entry`: The file entry
+    pub fn verify_file_data(&self, data: &[u8], entry: &Entry) -> Result<()> {
+        let file_data = self
+            .get_file_data(data, entry)
+            .ok_or(rc::ResultUnexpectedEndOfFile::make())?;
+        result_return_unless!(
+            compute_hash(&file_data[..entry.hashed_region_size]) == entry.hash,
+            rc::ResultHfs0HashMismatch
+        );
+        Ok(())
+    }
+
+    fn to_pfs0(&self) -> pfs0::Pfs0 {
+        let entries = self
+            .entries
+            .iter()
+            .map(|entry| pfs0::Entry {
+                name: entry.name.clone(),
+                offset: entry.offset,
+                size: entry.size,
+            })
+            .collect();
+        pfs0::Pfs0::from_entries(entries, self.metadata_size)
+    }
+}
+
+/// Represents the gamecard (XCI) header
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct GamecardHeader {
+    /// The RSA-2048 signature of the header
+    pub signature: [u8; 0x100],
+    /// The magic, whose expected value is [`MAGIC`][`GamecardHeader::MAGIC`]
+    pub magic: u32,
+    /// The secure area start address, in pages
+    pub secure_area_start_page: u32,
+    /// The backup area start address, in pages
+    pub backup_area_start_page: u32,
+    /// The title key decryption key index
+    pub key_index: u8,
+    /// The gamecard ROM size identifier
+    pub rom_size: u8,
+    /// The header version
+    pub version: u8,
+    /// The gamecard flags
+    pub flags: u8,
+    /// The package ID
+    pub package_id: u64,
+    /// The end of the valid data, in pages
+    pub valid_data_end_page: u32,
+    /// Reserved
+    pub reserved: u32,
+    /// The IV used for the encrypted part of the header
+    pub iv: [u8; 0x10],
+    /// The absolute offset of the root HFS0 partition
+    pub partition_fs_header_address: u64,
+    /// The size of the root HFS0 partition metadata
+    pub partition_fs_header_size: u64,
+    /// The SHA-256 hash of the root HFS0 partition metadata
+    pub partition_fs_header_hash: Hash,
+    /// The SHA-256 hash of the initial data
+    pub initial_data_hash: Hash,
+    /// The secure mode selection value
+    pub sel_sec: u32,
+    /// The T1 key selection value
+    pub sel_t1_key: u32,
+    /// The key selection value
+    pub sel_key: u32,
+    /// The limit area, in pages
+    pub lim_area_page: u32,
+    /// The encrypted part of the header
+    pub encrypted_data: [u8; 0x70],
+}
+const_assert!(mem::size_of::<GamecardHeader>() == 0x200);
+
+impl GamecardHeader {
+    /// The header magic value (`HEAD`)
+    pub const MAGIC: u32 = u32::from_le_bytes(*b"HEAD");
+}
+
+/// Represents the partitions contained in the root HFS0 partition of a gamecard
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GamecardPartition {
+    /// The system update partition
+    Update,
+    /// The normal area partition
+    Normal,
+    /// The secure area partition, containing the actual game contents
+    Secure,
+    /// The logo partition (only present on newer gamecards)
+    Logo,
+}
+
+impl GamecardPartition {
+    /// Gets the partition's file name inside the root HFS0 partition
+    pub const fn get_name(&self) -> &'static str {
+        match self {
+            Self::Update => "update",
+            Self::Normal => "normal",
+            Self::Secure => "secure",
+            Self::Logo => "logo",
+        }
+    }
+}
+
+/// Represents a read-only [`FileSystem`][`fs::FileSystem`] over a HFS0 image
+///
+/// If hash verification is enabled, the hashed region of every file is checked the first time the file is opened
+pub struct Hfs0FileSystem {
+    inner: pfs0::Pfs0FileSystem,
+    hfs0: Arc<Hfs0>,
+    verify_hashes: bool,
+    verified_entries: Vec<AtomicBool>,
+}
+
+impl Hfs0FileSystem {
+    const VERIFY_CHUNK_SIZE: usize = 0x10000;
+
+    /// Creates a new [`Hfs0FileSystem`], parsing the HFS0 metadata from the given [`File`][`fs::File`]
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: The [`File`][`fs::File`] containing the HFS0 image
+    /// * `verify_hashes`: Whether to verify the hashed region of files when opening them
+    pub fn new(mut file: Box<dyn fs::File>, verify_hashes: bool) -> Result<Self> {
+        let hfs0 = Self::read_metadata(file.as_mut())?;
+        let inner = pfs0::Pfs0FileSystem::from_parts(Arc::new(Mutex::new(file)), hfs0.to_pfs0());
+        let verified_entries = hfs0
+            .get_entries()
+            .iter()
+            .map(|_| AtomicBool::new(false))
+            .collect();
+        Ok(Self {
+            inner,
+            hfs0: Arc::new(hfs0),
+            verify_hashes,
+            verified_entries,
+        })
+    }
+
+    /// Creates a new [`Hfs0FileSystem`] over the root HFS0 partition of a gamecard image
+    ///
+    /// If hash verification is enabled, the root partition metadata is also checked against the hash in the [`GamecardHeader`]
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: The [`File`][`fs::File`] containing the gamecard image
+    /// * `verify_hashes`: Whether to verify hashes
+    pub fn open_gamecard(mut file: Box<dyn fs::File>, verify_hashes: bool) -> Result<Self> {
+        let mut header_data = [0u8; mem::size_of::<GamecardHeader>()];
+        fs::read_file_exact(file.as_mut(), 0, &mut header_data)?;
+        // SAFETY: the gamecard header only has integer/byte array fields
+        let header: GamecardHeader = unsafe { util::read_unaligned_at(&header_data, 0) }
+            .ok_or(rc::ResultUnexpectedEndOfFile::make())?;
+        result_return_unless!(
+            header.magic == GamecardHeader::MAGIC,
+            rc::ResultInvalidGamecardMagic
+        );
+
+        let file_size = file.get_size()?;
+        let root_offset = usize::try_from(header.partition_fs_header_address)
+            .map_err(|_| rc::ResultUnexpectedEndOfFile::make())?;
+        result_return_if!(root_offset > file_size, rc::ResultUnexpectedEndOfFile);
+
+        if verify_hashes {
+            // The header size is untrusted, so it must fit in the file before being allocated
+            let root_header_size = usize::try_from(header.partition_fs_header_size)
+                .ok()
+                .filter(|&size| size <= file_size - root_offset)
+                .ok_or(rc::ResultUnexpectedEndOfFile::make())?;
+            let mut root_header_data = vec![0u8; root_header_size];
+            fs::read_file_exact(file.as_mut(), root_offset, &mut root_header_data)?;
+            result_return_unless!(
+                compute_hash(&root_header_data) == header.partition_fs_header_hash,
+                rc::ResultHfs0HashMismatch
+            );
+        }
+        let root_file = pfs0::Pfs0File::new(
+            Arc::new(Mutex::new(file)),
+            root_offset,
+            file_size - root_offset,
+        );
+        Self::new(Box::new(root_file), verify_hashes)
+    }
+
+    fn read_metadata(file: &mut dyn fs::File) -> Result<Hfs0> {
+        let mut header_data = [0u8; mem::size_of::<Header>()];
+        fs::read_file_exact(file, 0, &mut header_data)?;
+        let header = Hfs0::read_header(&header_data)?;
+
+        let metadata = pfs0::read_partition_metadata(file, header.get_metadata_size())?;
+        Hfs0::parse(&metadata)
+    }
+
+    /// Gets the parsed HFS0 metadata
+    #[inline]
+    pub fn get_hfs0(&self) -> &Hfs0 {
+        &self.hfs0
+    }
+
+    /// Opens a file inside this partition as a nested HFS0 partition
+    ///
+    /// This is meant to be used on the root partition of gamecards, see [`GamecardPartition`]
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The partition file name
+    pub fn open_partition(&self, name: &str) -> Result<Self> {
+        let file = self.open_file(name, fs::FileOpenMode::Read())?;
+        Self::new(file, self.verify_hashes)
+    }
+
+    /// Verifies the hashed region of a file entry
+    ///
+    /// # Arguments
+    ///
+    /// * `entry`: The file entry
+    pub fn verify_entry(&self, entry: &Entry) -> Result<()> {
+        let mut file = self.inner.open_entry(&pfs0::Entry {
+            name: String::new(),
+            offset: entry.offset,
+            size: entry.size,
+        });
+
+        let mut hasher = sha2::Sha256::new();
+        let mut chunk = vec![0u8; Self::VERIFY_CHUNK_SIZE.min(entry.hashed_region_size)];
+        let mut offset = 0;
+        while offset < entry.hashed_region_size {
+            let chunk_size = chunk.len().min(entry.hashed_region_size - offset);
+            fs::read_file_exact(&mut file, offset, &mut chunk[..chunk_size])?;
+            hasher.update(&chunk[..chunk_size]);
+            offset += chunk_size;
+        }
+
+        let hash: Hash = hasher.finalize().into();
+        result_return_unless!(hash == entry.hash, rc::ResultHfs0HashMismatch);
+        Ok(())
+    }
+}
+
+impl fs::FileSystem for Hfs0FileSystem {
+    fn create_file(&self, path: &str, attribute: fs::FileAttribute, size: usize) -> Result<()> {
+        self.inner.create_file(path, attribute, size)
+    }
+
+    fn remove_file(&self, path: &str) -> Result<()> {
+        self.inner.remove_file(path)
+    }
+
+    fn create_directory(&self, path: &str) -> Result<()> {
+        self.inner.create_directory(path)
+    }
+
+    fn remove_dir(&self, path: &str) -> Result<()> {
+        self.inner.remove_dir(path)
+    }
+
+    fn remove_dir_all(&self, path: &str) -> Result<()> {
+        self.inner.remove_dir_all(path)
+    }
+
+    fn rename_file(&self, old_path: &str, new_path: &str) -> Result<()> {
+        self.inner.rename_file(old_path, new_path)
+    }
+
+    fn rename_directory(&self, old_path: &str, new_path: &str) -> Result<()> {
+        self.inner.rename_directory(old_path, new_path)
+    }
+
+    fn get_entry_type(&self, path: &str) -> Result<fs::DirectoryEntryType> {
+        self.inner.get_entry_type(path)
+    }
+
+    fn open_file(&self, path: &str, mode: fs::FileOpenMode) -> Result<Box<dyn fs::File>> {
+        if self.verify_hashes {
+            let name = pfs0::get_entry_name(path);
+            let index = self
+                .hfs0
+                .get_entries()
+                .iter()
+                .position(|entry| entry.name == name)
+                .ok_or(fsp_sf::rc::ResultPathNotFound::make())?;
+
+            // Files are read-only, so their hashes only need to be checked once
+            let verified = &self.verified_entries[index];
+            if !verified.load(Ordering::Acquire) {
+                self.verify_entry(&self.hfs0.get_entries()[index])?;
+                verified.store(true, Ordering::Release);
+            }
+        }
+
+        self.inner.open_file(path, mode)
+    }
+
+    fn open_directory(
+        &self,
+        path: &str,
+        mode: fs::DirectoryOpenMode,
+    ) -> Result<Box<dyn fs::Directory>> {
+        self.inner.open_directory(path, mode)
+    }
+
+    fn commit(&self) -> Result<()> {
+        self.inner.commit()
+    }
+
+    fn get_free_space_size(&self, path: &str) -> Result<usize> {
+        self.inner.get_free_space_size(path)
+    }
+
+    fn get_total_space_size(&self, path: &str) -> Result<usize> {
+        self.inner.get_total_space_size(path)
+    }
+
+    fn remove_children_all(&self, path: &str) -> Result<()> {
+        self.inner.remove_children_all(path)
+    }
+
+    fn get_file_time_stamp_raw(&self, path: &str) -> Result<fs::FileTimeStampRaw> {
+        self.inner.get_file_time_stamp_raw(path)
+    }
+
+    fn query_entry(
+        &self,
+        path: &str,
+        query_id: fs::QueryId,
+        in_buf: &[u8],
+        out_buf: &mut [u8],
+    ) -> Result<()> {
+        self.inner.query_entry(path, query_id, in_buf, out_buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::pfs0::tests::{MemoryFile, make_partition_image, push_raw};
+
+    /// Builds an HFS0 image containing the given files, each one fully hashed
+    fn make_image(files: &[(&str, &[u8])]) -> Vec<u8> {
+        make_partition_image(
+            Header::MAGIC,
+            files,
+            |offset, string_table_offset, contents| FileEntry {
+                offset,
+                size: contents.len() as u64,
+                string_table_offset,
+                hashed_region_size: contents.len() as u32,
+                reserved: 0,
+                hash: compute_hash(contents),
+            },
+        )
+    }
+
+    #[test]
+    fn parse_and_verify_entries() {
+        let image = make_image(&[("normal", b"partition"), ("secure", b"contents")]);
+        let hfs0 = Hfs0::parse(&image).unwrap();
+        assert_eq!(hfs0.get_metadata_size(), 0x10 + 2 * 0x40 + 14);
+
+        let entry = hfs0.find_entry("secure").unwrap();
+        assert_eq!(hfs0.get_file_data(&image, entry), Some(&b"contents"[..]));
+        hfs0.verify_file_data(&image, entry).unwrap();
+
+        let mut corrupted = image.clone();
+        *corrupted.last_mut().unwrap() ^= 0xFF;
+        let rc = hfs0.verify_file_data(&corrupted, entry).unwrap_err();
+        assert!(rc::ResultHfs0HashMismatch::matches(rc));
+    }
+
+    #[test]
+    fn parse_invalid_entries() {
+        let mut image = make_image(&[("secure", b"contents")]);
+        image[0] = b'P';
+        let rc = Hfs0::parse(&image).unwrap_err();
+        assert!(rc::ResultInvalidHfs0Magic::matches(rc));
+
+        // Name offset past the end of the string table
+        let mut image = make_image(&[("secure", b"contents")]);
+        image[0x20] = 0x40;
+        let rc = Hfs0::parse(&image).unwrap_err();
+        assert!(rc::ResultInvalidHfs0Header::matches(rc));
+
+        // Hashed region bigger than the file
+        let mut image = make_image(&[("secure", b"contents")]);
+        image[0x24] = 0x40;
+        let rc = Hfs0::parse(&image).unwrap_err();
+        assert!(rc::ResultInvalidHfs0Header::matches(rc));
+    }
+
+    #[test]
+    fn parse_huge_file_count() {
+        let mut image = make_image(&[]);
+        image[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let rc = Hfs0::parse(&image).unwrap_err();
+        assert!(rc::ResultUnexpectedEndOfFile::matches(rc));
+
+        let rc = Hfs0FileSystem::new(Box::new(MemoryFile(image)), true)
+            .err()
+            .unwrap();
+        assert!(rc::ResultUnexpectedEndOfFile::matches(rc));
+    }
+
+    #[test]
+    fn open_gamecard_huge_root_header() {
+        let mut header: GamecardHeader = unsafe { mem::zeroed() };
+        header.magic = GamecardHeader::MAGIC;
+        header.partition_fs_header_address = 0x200;
+        header.partition_fs_header_size = u64::MAX;
+
+        let mut image = Vec::new();
+        push_raw(&mut image, &header);
+        image.extend_from_slice(&make_image(&[("secure", b"contents")]));
+
+        // The root header size must be checked against the file before being allocated
+        let rc = Hfs0FileSystem::open_gamecard(Box::new(MemoryFile(image)), true)
+            .err()
+            .unwrap();
+        assert!(rc::ResultUnexpectedEndOfFile::matches(rc));
+    }
+}
@@ -1 +1,1359 @@
-// TODO: NCA filesystem/file/dir
+//! NCA (Nintendo content archive) support
+//!
+//! NCAs are the containers of all installed contents: a [`Header`] followed by up to [`FS_HEADER_COUNT`] [`FsHeader`]s, each one describing a section which holds a PFS0 or RomFS image.
+//!
+//! Only NCA3 archives are supported. The header is encrypted with AES-128-XTS and sections are usually encrypted with AES-128-CTR. All cryptography goes through a [`Decryptor`], and all keys must be supplied by the caller through a [`KeySet`]:
+//!
+//! ```ignore
+//! let mut keys = KeySet::new(header_key);
+//! keys.key_area_keys[key_generation][KeyAreaEncryptionKeyIndex::Application as usize] = Some(key_area_key);
+//!
+//! let nca_file = nx::fs::open_file("sdmc:/program.nca", nx::fs::FileOpenOption::Read())?;
+//! let nca = Nca::new(nca_file.into_file(), &keys, Arc::new(SoftwareDecryptor))?;
+//! nx::fs::mount("exefs", nca.open_section_filesystem(0)?);
+//! ```
+
+use crate::fs;
+use crate::fs::pfs0;
+use crate::fs::rc;
+use crate::fs::romfs;
+use crate::ipc::sf::ncm;
+use crate::result::*;
+use crate::sync::Mutex;
+use crate::util;
+use aes::Aes128;
+use aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::mem;
+use sha2::Digest;
+
+/// Represents an AES-128 key
+pub type AesKey = [u8; 0x10];
+
+/// Represents an AES-128-XTS key pair (data key followed by tweak key)
+pub type AesXtsKey = [u8; 0x20];
+
+/// Represents an AES-128-CTR counter
+pub type AesCounter = [u8; 0x10];
+
+/// The AES block size
+pub const AES_BLOCK_SIZE: usize = 0x10;
+
+/// The size of a media unit, in which NCA section offsets are expressed
+pub const MEDIA_UNIT_SIZE: usize = 0x200;
+
+/// The XTS sector size used to encrypt NCA headers
+pub const HEADER_SECTOR_SIZE: usize = 0x200;
+
+/// The (maximum) number of sections in a NCA
+pub const FS_HEADER_COUNT: usize = 4;
+
+/// The number of entries in the key area
+pub const KEY_AREA_KEY_COUNT: usize = 4;
+
+/// The maximum supported key generation
+pub const KEY_GENERATION_COUNT: usize = 0x20;
+
+/// The size of the whole NCA header block (the [`Header`] and all [`FsHeader`]s)
+pub const HEADER_BLOCK_SIZE: usize =
+    mem::size_of::<Header>() + FS_HEADER_COUNT * mem::size_of::<FsHeader>();
+
+/// The index of the key area entry used for AES-CTR sections
+pub const KEY_AREA_AES_CTR_KEY_INDEX: usize = 2;
+
+/// Represents the distribution type of a NCA
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum DistributionType {
+    Download = 0,
+    GameCard = 1,
+}
+
+/// Represents the content type of a NCA
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum ContentType {
+    Program = 0,
+    Meta = 1,
+    Control = 2,
+    Manual = 3,
+    Data = 4,
+    PublicData = 5,
+}
+
+/// Represents which key area encryption key is used to decrypt the key area of a NCA
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum KeyAreaEncryptionKeyIndex {
+    Application = 0,
+    Ocean = 1,
+    System = 2,
+}
+
+impl KeyAreaEncryptionKeyIndex {
+    /// The number of key area encryption key indexes
+    pub const COUNT: usize = 3;
+}
+
+/// Represents the filesystem type of a NCA section
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum FsType {
+    RomFs = 0,
+    PartitionFs = 1,
+}
+
+/// Represents the hash type of a NCA section
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum HashType {
+    Auto = 0,
+    None = 1,
+    HierarchicalSha256 = 2,
+    HierarchicalIntegrity = 3,
+    AutoSha3 = 4,
+    HierarchicalSha3256 = 5,
+    HierarchicalIntegritySha3 = 6,
+}
+
+/// Represents the encryption type of a NCA section
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum EncryptionType {
+    Auto = 0,
+    None = 1,
+    AesXts = 2,
+    AesCtr = 3,
+    AesCtrEx = 4,
+    AesCtrSkipLayerHash = 5,
+    AesCtrExSkipLayerHash = 6,
+}
+
+macro_rules! impl_from_raw {
+    ($enum:ident { $($variant:ident),* }) => {
+        impl $enum {
+            /// Converts a raw value into this type, returning [`None`] for unknown values
+            ///
+            /// # Arguments
+            ///
+            /// * `raw`: The raw value
+            pub const fn from_raw(raw: u8) -> Option<Self> {
+                $(
+                    if raw == Self::$variant as u8 {
+                        return Some(Self::$variant);
+                    }
+                )*
+                None
+            }
+        }
+    };
+}
+
+impl_from_raw!(DistributionType { Download, GameCard });
+impl_from_raw!(ContentType {
+    Program,
+    Meta,
+    Control,
+    Manual,
+    Data,
+    PublicData
+});
+impl_from_raw!(KeyAreaEncryptionKeyIndex {
+    Application,
+    Ocean,
+    System
+});
+impl_from_raw!(FsType { RomFs, PartitionFs });
+impl_from_raw!(HashType {
+    Auto,
+    None,
+    HierarchicalSha256,
+    HierarchicalIntegrity,
+    AutoSha3,
+    HierarchicalSha3256,
+    HierarchicalIntegritySha3
+});
+impl_from_raw!(EncryptionType {
+    Auto,
+    None,
+    AesXts,
+    AesCtr,
+    AesCtrEx,
+    AesCtrSkipLayerHash,
+    AesCtrExSkipLayerHash
+});
+
+/// Represents the location of a section inside a NCA
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct FsEntry {
+    /// The section start offset, in media units
+    pub start_offset: u32,
+    /// The section end offset, in media units
+    pub end_offset: u32,
+    /// Reserved
+    pub reserved: u64,
+}
+const_assert!(mem::size_of::<FsEntry>() == 0x10);
+
+impl FsEntry {
+    /// Gets whether this entry describes an actual section
+    #[inline]
+    pub const fn is_present(&self) -> bool {
+        self.end_offset > self.start_offset
+    }
+
+    /// Gets the absolute section offset
+    #[inline]
+    pub const fn get_offset(&self) -> usize {
+        self.start_offset as usize * MEDIA_UNIT_SIZE
+    }
+
+    /// Gets the section size
+    #[inline]
+    pub const fn get_size(&self) -> usize {
+        (self.end_offset as usize).saturating_sub(self.start_offset as usize) * MEDIA_UNIT_SIZE
+    }
+}
+
+/// Represents the (decrypted) NCA header
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct Header {
+    /// The RSA-2048 signature over the header, using a fixed key
+    pub fixed_key_signature: [u8; 0x100],
+    /// The RSA-2048 signature over the header, using the key in the program's NPDM
+    pub npdm_signature: [u8; 0x100],
+    /// The magic, whose expected value is [`MAGIC`][`Header::MAGIC`]
+    pub magic: u32,
+    /// The raw [`DistributionType`]
+    pub distribution_type: u8,
+    /// The raw [`ContentType`]
+    pub content_type: u8,
+    /// The key generation, as used by older system versions
+    pub key_generation_old: u8,
+    /// The raw [`KeyAreaEncryptionKeyIndex`]
+    pub key_area_encryption_key_index: u8,
+    /// The size of the whole NCA
+    pub content_size: u64,
+    /// The program ID this content belongs to
+    pub program_id: ncm::ProgramId,
+    /// The content index
+    pub content_index: u32,
+    /// The SDK addon version
+    pub sdk_addon_version: u32,
+    /// The key generation
+    pub key_generation: u8,
+    /// The generation of the key used for the fixed-key signature
+    pub signature_key_generation: u8,
+    /// Reserved
+    pub reserved: [u8; 0xE],
+    /// The rights ID, all zeros if the content uses standard crypto
+    pub rights_id: [u8; 0x10],
+    /// The section locations
+    pub fs_entries: [FsEntry; FS_HEADER_COUNT],
+    /// The SHA-256 hashes of each [`FsHeader`]
+    pub fs_header_hashes: [[u8; 0x20]; FS_HEADER_COUNT],
+    /// The (encrypted) key area
+    pub encrypted_key_area: [AesKey; KEY_AREA_KEY_COUNT],
+    /// Reserved
+    pub reserved_2: [u8; 0xC0],
+}
+const_assert!(mem::size_of::<Header>() == 0x400);
+
+impl Header {
+    /// The header magic value (`NCA3`)
+    pub const MAGIC: u32 = u32::from_le_bytes(*b"NCA3");
+
+    /// Gets the [`DistributionType`], if valid
+    #[inline]
+    pub const fn get_distribution_type(&self) -> Option<DistributionType> {
+        DistributionType::from_raw(self.distribution_type)
+    }
+
+    /// Gets the [`ContentType`], if valid
+    #[inline]
+    pub const fn get_content_type(&self) -> Option<ContentType> {
+        ContentType::from_raw(self.content_type)
+    }
+
+    /// Gets the [`KeyAreaEncryptionKeyIndex`], if valid
+    #[inline]
+    pub const fn get_key_area_encryption_key_index(&self) -> Option<KeyAreaEncryptionKeyIndex> {
+        KeyAreaEncryptionKeyIndex::from_raw(self.key_area_encryption_key_index)
+    }
+
+    /// Gets the effective key generation, which is the index to use in [`KeySet::key_area_keys`]
+    pub const fn get_key_generation(&self) -> usize {
+        let key_generation = if self.key_generation > self.key_generation_old {
+            self.key_generation
+        } else {
+            self.key_generation_old
+        };
+
+        // Both 0 and 1 map to the first key generation
+        (key_generation as usize).saturating_sub(1)
+    }
+
+    /// Gets whether this content uses titlekey crypto (a non-zero rights ID) instead of the key area
+    #[inline]
+    pub fn has_rights_id(&self) -> bool {
+        self.rights_id.iter().any(|&b| b != 0)
+    }
+}
+
+/// Represents a hash layer region of a NCA section
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct Region {
+    /// The region offset, relative to the section start
+    pub offset: u64,
+    /// The region size
+    pub size: u64,
+}
+const_assert!(mem::size_of::<Region>() == 0x10);
+
+/// Represents the hash data of [`HashType::HierarchicalSha256`] sections (PFS0)
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct HierarchicalSha256Data {
+    /// The SHA-256 hash of the hash table
+    pub master_hash: [u8; 0x20],
+    /// The size of each hashed block
+    pub hash_block_size: u32,
+    /// The number of valid `hash_layer_regions`
+    pub hash_layer_count: u32,
+    /// The layer regions, the last valid one being the actual data
+    pub hash_layer_regions: [Region; 5],
+    /// Reserved
+    pub reserved: [u8; 0x80],
+}
+const_assert!(mem::size_of::<HierarchicalSha256Data>() == 0xF8);
+
+/// Represents a single level of [`HierarchicalIntegrityData`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct HierarchicalIntegrityLevel {
+    /// The level offset, relative to the section start
+    pub offset: u64,
+    /// The level size
+    pub size: u64,
+    /// The hashed block size, as a power of two
+    pub block_order: u32,
+    /// Reserved
+    pub reserved: u32,
+}
+const_assert!(mem::size_of::<HierarchicalIntegrityLevel>() == 0x18);
+
+/// Represents the hash data of [`HashType::HierarchicalIntegrity`] sections (RomFS), also known as IVFC
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct HierarchicalIntegrityData {
+    /// The magic, whose expected value is [`MAGIC`][`HierarchicalIntegrityData::MAGIC`]
+    pub magic: u32,
+    /// The version
+    pub version: u32,
+    /// The size of `master_hash`
+    pub master_hash_size: u32,
+    /// The number of layers, including the master hash
+    pub max_layers: u32,
+    /// The levels, the last valid one being the actual data
+    pub levels: [HierarchicalIntegrityLevel; 6],
+    /// The signature salt
+    pub signature_salt: [u8; 0x20],
+    /// The master hash
+    pub master_hash: [u8; 0x20],
+    /// Reserved
+    pub reserved: [u8; 0x18],
+}
+const_assert!(mem::size_of::<HierarchicalIntegrityData>() == 0xF8);
+
+impl HierarchicalIntegrityData {
+    /// The IVFC magic value (`IVFC`)
+    pub const MAGIC: u32 = u32::from_le_bytes(*b"IVFC");
+}
+
+/// Represents the (decrypted) header of a NCA section
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct FsHeader {
+    /// The version
+    pub version: u16,
+    /// The raw [`FsType`]
+    pub fs_type: u8,
+    /// The raw [`HashType`]
+    pub hash_type: u8,
+    /// The raw [`EncryptionType`]
+    pub encryption_type: u8,
+    /// The raw metadata hash type
+    pub metadata_hash_type: u8,
+    /// Reserved
+    pub reserved: [u8; 0x2],
+    /// The raw hash data, see [`get_sha256_data`][`FsHeader::get_sha256_data`] and [`get_integrity_data`][`FsHeader::get_integrity_data`]
+    pub hash_data: [u8; 0xF8],
+    /// The patch info (for update contents)
+    pub patch_info: [u8; 0x40],
+    /// The upper 64 bits of the AES-CTR counter
+    pub upper_counter: u64,
+    /// The sparse info
+    pub sparse_info: [u8; 0x30],
+    /// The compression info
+    pub compression_info: [u8; 0x28],
+    /// The metadata hash data info
+    pub metadata_hash_data_info: [u8; 0x30],
+    /// Reserved
+    pub reserved_2: [u8; 0x30],
+}
+const_assert!(mem::size_of::<FsHeader>() == 0x200);
+
+impl FsHeader {
+    /// Gets the [`FsType`], if valid
+    #[inline]
+    pub const fn get_fs_type(&self) -> Option<FsType> {
+        FsType::from_raw(self.fs_type)
+    }
+
+    /// Gets the [`HashType`], if valid
+    #[inline]
+    pub const fn get_hash_type(&self) -> Option<HashType> {
+        HashType::from_raw(self.hash_type)
+    }
+
+    /// Gets the [`EncryptionType`], if valid
+    #[inline]
+    pub const fn get_encryption_type(&self) -> Option<EncryptionType> {
+        EncryptionType::from_raw(self.encryption_type)
+    }
+
+    /// Gets the hash data as [`HierarchicalSha256Data`]
+    ///
+    /// This is only meaningful for [`HashType::HierarchicalSha256`] sections
+    pub fn get_sha256_data(&self) -> HierarchicalSha256Data {
+        // The hash data is exactly as large as the type, thus this can't fail
+        // SAFETY: the hash data only has integer/array fields
+        unsafe { util::read_unaligned_at(&self.hash_data, 0) }.unwrap()
+    }
+
+    /// Gets the hash data as [`HierarchicalIntegrityData`]
+    ///
+    /// This is only meaningful for [`HashType::HierarchicalIntegrity`] sections
+    pub fn get_integrity_data(&self) -> HierarchicalIntegrityData {
+        // The hash data is exactly as large as the type, thus this can't fail
+        // SAFETY: the hash data only has integer/array fields
+        unsafe { util::read_unaligned_at(&self.hash_data, 0) }.unwrap()
+    }
+
+    /// Gets the [`Region`] of the actual filesystem image, relative to the section start
+    ///
+    /// This is the last layer of the section's hash data, the previous ones being hash tables
+    pub fn get_data_region(&self) -> Result<Region> {
+        match self.get_hash_type() {
+            Some(HashType::HierarchicalSha256) => {
+                let data = self.get_sha256_data();
+                let layer_count = data.hash_layer_count as usize;
+                result_return_unless!(
+                    (1..=data.hash_layer_regions.len()).contains(&layer_count),
+                    rc::ResultInvalidNcaHeader
+                );
+                Ok(data.hash_layer_regions[layer_count - 1])
+            }
+            Some(HashType::HierarchicalIntegrity) => {
+                let data = self.get_integrity_data();
+                result_return_unless!(
+                    data.magic == HierarchicalIntegrityData::MAGIC,
+                    rc::ResultInvalidNcaHeader
+                );
+                // The master hash counts as a layer, but has no level entry
+                let level_count = (data.max_layers as usize).saturating_sub(1);
+                result_return_unless!(
+                    (1..=data.levels.len()).contains(&level_count),
+                    rc::ResultInvalidNcaHeader
+                );
+                let level = data.levels[level_count - 1];
+                Ok(Region {
+                    offset: level.offset,
+                    size: level.size,
+                })
+            }
+            _ => crate::rc::ResultNotSupported::make_err(),
+        }
+    }
+}
+
+/// Represents the keys needed to decrypt NCAs
+///
+/// These are console/firmware secrets and are never embedded in this library: they must always be supplied by the caller
+#[derive(Clone, Default)]
+pub struct KeySet {
+    /// The header key
+    pub header_key: AesXtsKey,
+    /// The key area encryption keys, indexed by key generation (see [`Header::get_key_generation`]) and [`KeyAreaEncryptionKeyIndex`]
+    pub key_area_keys: [[Option<AesKey>; KeyAreaEncryptionKeyIndex::COUNT]; KEY_GENERATION_COUNT],
+    /// The (already decrypted) titlekey, used for contents with a rights ID
+    pub title_key: Option<AesKey>,
+}
+
+impl KeySet {
+    /// Creates a new [`KeySet`] with only the header key set
+    ///
+    /// # Arguments
+    ///
+    /// * `header_key`: The header key
+    pub fn new(header_key: AesXtsKey) -> Self {
+        Self {
+            header_key,
+            ..Default::default()
+        }
+    }
+}
+
+/// Represents a type providing the cryptography needed to read NCAs
+///
+/// All operations work in-place. [`SoftwareDecryptor`] is a plain software implementation, but custom implementations (like ones backed by hardware or a crypto service) may be used instead
+pub trait Decryptor: Send + Sync {
+    /// Decrypts AES-128-XTS data, with Nintendo's tweak format (the sector number in big-endian)
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: The XTS key pair
+    /// * `sector`: The number of the first sector
+    /// * `sector_size`: The sector size, the data size must be a multiple of it
+    /// * `data`: The data to decrypt
+    fn decrypt_xts(
+        &self,
+        key: &AesXtsKey,
+        sector: usize,
+        sector_size: usize,
+        data: &mut [u8],
+    ) -> Result<()>;
+
+    /// Decrypts (or encrypts, since it's the same operation) AES-128-CTR data
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: The key
+    /// * `counter`: The counter of the first block
+    /// * `data`: The data to decrypt
+    fn crypt_ctr(&self, key: &AesKey, counter: &AesCounter, data: &mut [u8]) -> Result<()>;
+
+    /// Decrypts AES-128-ECB data
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: The key
+    /// * `data`: The data to decrypt, whose size must be a multiple of [`AES_BLOCK_SIZE`]
+    fn decrypt_ecb(&self, key: &AesKey, data: &mut [u8]) -> Result<()>;
+}
+
+/// Represents a software [`Decryptor`] implementation
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct SoftwareDecryptor;
+
+impl SoftwareDecryptor {
+    fn xor_block(block: &mut [u8], other: &[u8]) {
+        block.iter_mut().zip(other).for_each(|(b, o)| *b ^= o);
+    }
+
+    // Multiplies the tweak by x in GF(2^128), as defined by XTS
+    fn next_xts_tweak(tweak: &mut [u8]) {
+        let mut carry = 0;
+        for b in tweak.iter_mut() {
+            let next_carry = *b >> 7;
+            *b = (*b << 1) | carry;
+            carry = next_carry;
+        }
+
+        if carry != 0 {
+            tweak[0] ^= 0x87;
+        }
+    }
+}
+
+impl Decryptor for SoftwareDecryptor {
+    fn decrypt_xts(
+        &self,
+        key: &AesXtsKey,
+        sector: usize,
+        sector_size: usize,
+        data: &mut [u8],
+    ) -> Result<()> {
+        result_return_unless!(
+            sector_size > 0
+                && sector_size.is_multiple_of(AES_BLOCK_SIZE)
+                && data.len().is_multiple_of(sector_size),
+            rc::ResultInvalidAesDataSize
+        );
+
+        let data_cipher = Aes128::new(key[..0x10].into());
+        let tweak_cipher = Aes128::new(key[0x10..].into());
+        for (i, sector_data) in data.chunks_exact_mut(sector_size).enumerate() {
+            let mut tweak = aes::Block::from(((sector + i) as u128).to_be_bytes());
+            tweak_cipher.encrypt_block(&mut tweak);
+
+            for block in sector_data.chunks_exact_mut(AES_BLOCK_SIZE) {
+                Self::xor_block(block, &tweak);
+                data_cipher.decrypt_block(block.into());
+                Self::xor_block(block, &tweak);
+                Self::next_xts_tweak(&mut tweak);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn crypt_ctr(&self, key: &AesKey, counter: &AesCounter, data: &mut [u8]) -> Result<()> {
+        let cipher = Aes128::new(key.into());
+        let mut counter = u128::from_be_bytes(*counter);
+        for block in data.chunks_mut(AES_BLOCK_SIZE) {
+            let mut key_stream = aes::Block::from(counter.to_be_bytes());
+            cipher.encrypt_block(&mut key_stream);
+            Self::xor_block(block, &key_stream);
+            counter = counter.wrapping_add(1);
+        }
+
+        Ok(())
+    }
+
+    fn decrypt_ecb(&self, key: &AesKey, data: &mut [u8]) -> Result<()> {
+        result_return_unless!(
+            data.len().is_multiple_of(AES_BLOCK_SIZE),
+            rc::ResultInvalidAesDataSize
+        );
+
+        let cipher = Aes128::new(key.into());
+        for block in data.chunks_exact_mut(AES_BLOCK_SIZE) {
+            cipher.decrypt_block(block.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Computes the AES-CTR counter for a given absolute offset inside a NCA section
+///
+/// # Arguments
+///
+/// * `upper_counter`: The section's [`FsHeader::upper_counter`]
+/// * `offset`: The absolute offset inside the NCA, which should be [`AES_BLOCK_SIZE`]-aligned
+pub const fn make_section_counter(upper_counter: u64, offset: usize) -> AesCounter {
+    ((upper_counter as u128) << 64 | (offset / AES_BLOCK_SIZE) as u128).to_be_bytes()
+}
+
+/// Represents a (decrypted) [`File`][`fs::File`] view over a region of a NCA section
+pub struct NcaSectionFile {
+    base: Arc<Mutex<Box<dyn fs::File>>>,
+    decryptor: Arc<dyn Decryptor>,
+    offset: usize,
+    size: usize,
+    key: Option<AesKey>,
+    upper_counter: u64,
+}
+
+impl NcaSectionFile {
+    fn read_decrypted(&self, offset: usize, out_buf: &mut [u8]) -> Result<()> {
+        let abs_offset = self.offset + offset;
+        let Some(key) = self.key else {
+            return fs::read_file_exact(self.base.lock().as_mut(), abs_offset, out_buf);
+        };
+
+        let aligned_offset = abs_offset & !(AES_BLOCK_SIZE - 1);
+        let aligned_end = (abs_offset + out_buf.len()).next_multiple_of(AES_BLOCK_SIZE);
+        let mut data = vec![0u8; aligned_end - aligned_offset];
+        fs::read_file_exact(self.base.lock().as_mut(), aligned_offset, &mut data)?;
+
+        let counter = make_section_counter(self.upper_counter, aligned_offset);
+        self.decryptor.crypt_ctr(&key, &counter, &mut data)?;
+
+        let data_start = abs_offset - aligned_offset;
+        out_buf.copy_from_slice(&data[data_start..data_start + out_buf.len()]);
+        Ok(())
+    }
+}
+
+impl fs::File for NcaSectionFile {
+    fn read(
+        &mut self,
+        offset: usize,
+        out_buf: &mut [u8],
+        _option: fs::FileReadOption,
+    ) -> Result<usize> {
+        if offset >= self.size {
+            return Ok(0);
+        }
+
+        let read_size = out_buf.len().min(self.size - offset);
+        self.read_decrypted(offset, &mut out_buf[..read_size])?;
+        Ok(read_size)
+    }
+
+    fn write(&mut self, _offset: usize, _buf: &[u8], _option: fs::FileWriteOption) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_size(&mut self, _size: usize) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn get_size(&mut self) -> Result<usize> {
+        Ok(self.size)
+    }
+
+    fn operate_range(
+        &mut self,
+        _operation_id: fs::OperationId,
+        _offset: usize,
+        _size: usize,
+    ) -> Result<fs::FileQueryRangeInfo> {
+        crate::rc::ResultNotSupported::make_err()
+    }
+
+    fn operate_range_with_buffer(
+        &mut self,
+        _operation_id: fs::OperationId,
+        _offset: usize,
+        _size: usize,
+        _in_buf: &[u8],
+        _out_buf: &mut [u8],
+    ) -> Result<()> {
+        crate::rc::ResultNotSupported::make_err()
+    }
+}
+
+/// Represents a parsed NCA
+pub struct Nca {
+    base: Arc<Mutex<Box<dyn fs::File>>>,
+    decryptor: Arc<dyn Decryptor>,
+    header: Header,
+    fs_headers: [Option<FsHeader>; FS_HEADER_COUNT],
+    content_key: Option<AesKey>,
+}
+
+impl Nca {
+    /// Creates a new [`Nca`], reading and decrypting the headers from the given [`File`][`fs::File`]
+    ///
+    /// Already decrypted headers are detected and accepted. A missing key area key (or titlekey) isn't an error here, but opening any encrypted section will fail
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: The [`File`][`fs::File`] containing the NCA
+    /// * `keys`: The keys to use
+    /// * `decryptor`: The [`Decryptor`] to use
+    pub fn new(
+        mut file: Box<dyn fs::File>,
+        keys: &KeySet,
+        decryptor: Arc<dyn Decryptor>,
+    ) -> Result<Self> {
+        let mut header_data = vec![0u8; HEADER_BLOCK_SIZE];
+        fs::read_file_exact(file.as_mut(), 0, &mut header_data)?;
+
+        // SAFETY: any bit pattern is a valid `u32`
+        let is_plaintext =
+            unsafe { util::read_unaligned_at::<u32>(&header_data, mem::offset_of!(Header, magic)) }
+                == Some(Header::MAGIC);
+        if !is_plaintext {
+            decryptor.decrypt_xts(&keys.header_key, 0, HEADER_SECTOR_SIZE, &mut header_data)?;
+        }
+
+        // SAFETY: the header only has integer/array fields (the enum-like ones are kept raw)
+        let header: Header = unsafe { util::read_unaligned_at(&header_data, 0) }
+            .ok_or(rc::ResultUnexpectedEndOfFile::make())?;
+        result_return_unless!(header.magic == Header::MAGIC, rc::ResultInvalidNcaMagic);
+
+        let mut fs_headers = [None; FS_HEADER_COUNT];
+        for (i, fs_header) in fs_headers.iter_mut().enumerate() {
+            if !header.fs_entries[i].is_present() {
+                continue;
+            }
+
+            let fs_header_offset = mem::size_of::<Header>() + i * mem::size_of::<FsHeader>();
+            let fs_header_data =
+                &header_data[fs_header_offset..fs_header_offset + mem::size_of::<FsHeader>()];
+            let fs_header_hash: [u8; 0x20] = sha2::Sha256::digest(fs_header_data).into();
+            result_return_unless!(
+                fs_header_hash == header.fs_header_hashes[i],
+                rc::ResultNcaFsHeaderHashMismatch
+            );
+
+            // SAFETY: same as above, for the FS headers
+            *fs_header = unsafe { util::read_unaligned_at(fs_header_data, 0) };
+        }
+
+        let content_key = Self::get_content_key(&header, keys, decryptor.as_ref())?;
+        Ok(Self {
+            base: Arc::new(Mutex::new(file)),
+            decryptor,
+            header,
+            fs_headers,
+            content_key,
+        })
+    }
+
+    fn get_content_key(
+        header: &Header,
+        keys: &KeySet,
+        decryptor: &dyn Decryptor,
+    ) -> Result<Option<AesKey>> {
+        if header.has_rights_id() {
+            return Ok(keys.title_key);
+        }
+
+        let key_area_key = header
+            .get_key_area_encryption_key_index()
+            .and_then(|index| {
+                keys.key_area_keys
+                    .get(header.get_key_generation())
+                    .and_then(|gen_keys| gen_keys[index as usize])
+            });
+        let Some(key_area_key) = key_area_key else {
+            return Ok(None);
+        };
+
+        let mut content_key = header.encrypted_key_area[KEY_AREA_AES_CTR_KEY_INDEX];
+        decryptor.decrypt_ecb(&key_area_key, &mut content_key)?;
+        Ok(Some(content_key))
+    }
+
+    /// Gets the NCA [`Header`]
+    #[inline]
+    pub fn get_header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Gets the [`FsHeader`] of a section, if present
+    ///
+    /// # Arguments
+    ///
+    /// * `index`: The section index
+    #[inline]
+    pub fn get_fs_header(&self, index: usize) -> Option<&FsHeader> {
+        self.fs_headers.get(index).and_then(Option::as_ref)
+    }
+
+    /// Opens a (decrypted) [`File`][`fs::File`] over the filesystem image of a section
+    ///
+    /// # Arguments
+    ///
+    /// * `index`: The section index
+    pub fn open_section_file(&self, index: usize) -> Result<NcaSectionFile> {
+        let fs_header = self
+            .get_fs_header(index)
+            .ok_or(rc::ResultInvalidNcaSectionIndex::make())?;
+        let fs_entry = &self.header.fs_entries[index];
+
+        let key = match fs_header.get_encryption_type() {
+            Some(EncryptionType::None) => None,
+            Some(EncryptionType::AesCtr | EncryptionType::AesCtrSkipLayerHash) => {
+                Some(self.content_key.ok_or(rc::ResultNcaKeyNotFound::make())?)
+            }
+            _ => return rc::ResultUnsupportedNcaEncryptionType::make_err(),
+        };
+
+        let data_region = fs_header.get_data_region()?;
+        let data_offset =
+            usize::try_from(data_region.offset).map_err(|_| rc::ResultInvalidNcaHeader::make())?;
+        let data_size =
+            usize::try_from(data_region.size).map_err(|_| rc::ResultInvalidNcaHeader::make())?;
+        result_return_unless!(
+            data_offset
+                .checked_add(data_size)
+                .is_some_and(|end| end <= fs_entry.get_size()),
+            rc::ResultInvalidNcaHeader
+        );
+
+        Ok(NcaSectionFile {
+            base: self.base.clone(),
+            decryptor: self.decryptor.clone(),
+            offset: fs_entry.get_offset() + data_offset,
+            size: data_size,
+            key,
+            upper_counter: fs_header.upper_counter,
+        })
+    }
+
+    /// Opens the [`FileSystem`][`fs::FileSystem`] of a section, which can then be [`mount`][`fs::mount`]ed
+    ///
+    /// # Arguments
+    ///
+    /// * `index`: The section index
+    pub fn open_section_filesystem(&self, index: usize) -> Result<Arc<dyn fs::FileSystem>> {
+        let fs_type = self
+            .get_fs_header(index)
+            .ok_or(rc::ResultInvalidNcaSectionIndex::make())?
+            .get_fs_type();
+        let section_file = Box::new(self.open_section_file(index)?);
+
+        match fs_type {
+            Some(FsType::PartitionFs) => Ok(Arc::new(pfs0::Pfs0FileSystem::new(section_file)?)),
+            Some(FsType::RomFs) => Ok(Arc::new(romfs::RomFsFileSystem::new(section_file)?)),
+            None => crate::rc::ResultNotSupported::make_err(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::pfs0::tests::{MemoryFile, push_raw};
+    use alloc::vec::Vec;
+
+    // Test-only keys, these have nothing to do with the actual console ones
+    const HEADER_KEY: AesXtsKey = *b"test header key, data and tweak!";
+    const KEY_AREA_KEY: AesKey = *b"test key area k!";
+    const CONTENT_KEY: AesKey = *b"test content key";
+
+    const KEY_GENERATION: u8 = 3;
+    const UPPER_COUNTER: u64 = 0x0011_2233_4455_6677;
+
+    /// The offset of the PFS0 image inside the test section, right after a (dummy) hash table
+    const SECTION_DATA_OFFSET: usize = 0x200;
+
+    fn encrypt_xts(key: &AesXtsKey, sector: usize, sector_size: usize, data: &mut [u8]) {
+        let data_cipher = Aes128::new(key[..0x10].into());
+        let tweak_cipher = Aes128::new(key[0x10..].into());
+        for (i, sector_data) in data.chunks_exact_mut(sector_size).enumerate() {
+            let mut tweak = aes::Block::from(((sector + i) as u128).to_be_bytes());
+            tweak_cipher.encrypt_block(&mut tweak);
+
+            for block in sector_data.chunks_exact_mut(AES_BLOCK_SIZE) {
+                SoftwareDecryptor::xor_block(block, &tweak);
+                data_cipher.encrypt_block(block.into());
+                SoftwareDecryptor::xor_block(block, &tweak);
+                SoftwareDecryptor::next_xts_tweak(&mut tweak);
+            }
+        }
+    }
+
+    fn make_keys() -> KeySet {
+        let mut keys = KeySet::new(HEADER_KEY);
+        keys.key_area_keys[KEY_GENERATION as usize - 1]
+            [KeyAreaEncryptionKeyIndex::Application as usize] = Some(KEY_AREA_KEY);
+        keys
+    }
+
+    /// Builds the header of a PFS0 section whose image is at `data_region`
+    fn make_fs_header(encryption_type: EncryptionType, data_region: Region) -> FsHeader {
+        let mut hash_data: HierarchicalSha256Data = unsafe { mem::zeroed() };
+        hash_data.hash_block_size = 0x1000;
+        hash_data.hash_layer_count = 2;
+        hash_data.hash_layer_regions[0] = Region {
+            offset: 0,
+            size: 0x20,
+        };
+        hash_data.hash_layer_regions[1] = data_region;
+
+        let mut fs_header: FsHeader = unsafe { mem::zeroed() };
+        fs_header.version = 2;
+        fs_header.fs_type = FsType::PartitionFs as u8;
+        fs_header.hash_type = HashType::HierarchicalSha256 as u8;
+        fs_header.encryption_type = encryption_type as u8;
+        fs_header.upper_counter = UPPER_COUNTER;
+        let mut raw_hash_data = Vec::new();
+        push_raw(&mut raw_hash_data, &hash_data);
+        fs_header.hash_data.copy_from_slice(&raw_hash_data);
+        fs_header
+    }
+
+    /// Builds a (plaintext) NCA with a single section, placed right after the header block
+    fn make_nca(fs_header: &FsHeader, section: &[u8]) -> Vec<u8> {
+        let section_size = section.len().next_multiple_of(MEDIA_UNIT_SIZE);
+        let section_start = (HEADER_BLOCK_SIZE / MEDIA_UNIT_SIZE) as u32;
+
+        let mut raw_fs_header = Vec::new();
+        push_raw(&mut raw_fs_header, fs_header);
+
+        let mut header: Header = unsafe { mem::zeroed() };
+        header.magic = Header::MAGIC;
+        header.content_type = ContentType::Program as u8;
+        header.key_area_encryption_key_index = KeyAreaEncryptionKeyIndex::Application as u8;
+        header.content_size = (HEADER_BLOCK_SIZE + section_size) as u64;
+        header.key_generation = KEY_GENERATION;
+        header.fs_entries[0] = FsEntry {
+            start_offset: section_start,
+            end_offset: section_start + (section_size / MEDIA_UNIT_SIZE) as u32,
+            reserved: 0,
+        };
+        header.fs_header_hashes[0] = sha2::Sha256::digest(&raw_fs_header).into();
+        let key_area_cipher = Aes128::new((&KEY_AREA_KEY).into());
+        let mut encrypted_content_key = aes::Block::from(CONTENT_KEY);
+        key_area_cipher.encrypt_block(&mut encrypted_content_key);
+        header.encrypted_key_area[KEY_AREA_AES_CTR_KEY_INDEX] = encrypted_content_key.into();
+
+        let mut nca = Vec::new();
+        push_raw(&mut nca, &header);
+        nca.extend_from_slice(&raw_fs_header);
+        nca.resize(HEADER_BLOCK_SIZE, 0);
+        nca.extend_from_slice(section);
+        nca.resize(HEADER_BLOCK_SIZE + section_size, 0);
+        nca
+    }
+
+    /// Encrypts the header block, and the section too if it's an AES-CTR one
+    fn encrypt_nca(nca: &mut [u8], fs_header: &FsHeader) {
+        if fs_header.get_encryption_type() == Some(EncryptionType::AesCtr) {
+            let counter = make_section_counter(UPPER_COUNTER, HEADER_BLOCK_SIZE);
+            SoftwareDecryptor
+                .crypt_ctr(&CONTENT_KEY, &counter, &mut nca[HEADER_BLOCK_SIZE..])
+                .unwrap();
+        }
+        encrypt_xts(
+            &HEADER_KEY,
+            0,
+            HEADER_SECTOR_SIZE,
+            &mut nca[..HEADER_BLOCK_SIZE],
+        );
+    }
+
+    /// Builds a section containing a PFS0 with the given files, returning it and its data region
+    fn make_section(files: &[(&str, &[u8])]) -> (Vec<u8>, Region) {
+        let image = pfs0::tests::make_image(files);
+        let mut section = vec![0xAA; SECTION_DATA_OFFSET];
+        section.extend_from_slice(&image);
+        let data_region = Region {
+            offset: SECTION_DATA_OFFSET as u64,
+            size: image.len() as u64,
+        };
+        (section, data_region)
+    }
+
+    fn open_nca(nca: Vec<u8>, keys: &KeySet) -> Result<Nca> {
+        Nca::new(Box::new(MemoryFile(nca)), keys, Arc::new(SoftwareDecryptor))
+    }
+
+    fn read_section_file(nca: &Nca, index: usize, name: &str) -> Vec<u8> {
+        let section_fs = nca.open_section_filesystem(index).unwrap();
+        let mut file = section_fs
+            .open_file(name, fs::FileOpenMode::Read())
+            .unwrap();
+        let mut data = vec![0u8; file.get_size().unwrap()];
+        fs::read_file_exact(file.as_mut(), 0, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn xts_ieee_vector() {
+        // IEEE P1619 XTS-AES-128 vector 1: all-zero keys and plaintext, data unit 0
+        let mut data = [
+            0x91, 0x7C, 0xF6, 0x9E, 0xBD, 0x68, 0xB2, 0xEC, 0x9B, 0x9F, 0xE9, 0xA3, 0xEA, 0xDD,
+            0xA6, 0x92, 0xCD, 0x43, 0xD2, 0xF5, 0x95, 0x98, 0xED, 0x85, 0x8C, 0x02, 0xC2, 0x65,
+            0x2F, 0xBF, 0x92, 0x2E,
+        ];
+        SoftwareDecryptor
+            .decrypt_xts(&[0; 0x20], 0, data.len(), &mut data)
+            .unwrap();
+        assert_eq!(data, [0; 0x20]);
+    }
+
+    #[test]
+    fn xts_nintendo_tweak() {
+        // IEEE P1619 vector 2 keys and plaintext, data unit 0x3333333333
+        let mut key = [0x11; 0x20];
+        key[0x10..].fill(0x22);
+        let sector = 0x33_3333_3333;
+
+        // Nintendo encodes the sector number as a big-endian tweak...
+        let mut data = [
+            0x44, 0xBE, 0xC8, 0x2F, 0xFB, 0x76, 0xAE, 0xFD, 0xFB, 0xC9, 0x6D, 0xFE, 0x61, 0xE1,
+            0x92, 0xCC, 0xFA, 0x22, 0x13, 0x67, 0x7C, 0x8F, 0x4F, 0xD6, 0xE4, 0xF1, 0x8F, 0x7E,
+            0xBB, 0x69, 0x38, 0x2F,
+        ];
+        SoftwareDecryptor
+            .decrypt_xts(&key, sector, data.len(), &mut data)
+            .unwrap();
+        assert_eq!(data, [0x44; 0x20]);
+
+        // ...thus the standard (little-endian tweak) ciphertext doesn't decrypt with the same sector number
+        let mut data = [
+            0xC4, 0x54, 0x18, 0x5E, 0x6A, 0x16, 0x93, 0x6E, 0x39, 0x33, 0x40, 0x38, 0xAC, 0xEF,
+            0x83, 0x8B, 0xFB, 0x18, 0x6F, 0xFF, 0x74, 0x80, 0xAD, 0xC4, 0x28, 0x93, 0x82, 0xEC,
+            0xD6, 0xD3, 0x94, 0xF0,
+        ];
+        SoftwareDecryptor
+            .decrypt_xts(&key, sector, data.len(), &mut data)
+            .unwrap();
+        assert_ne!(data, [0x44; 0x20]);
+    }
+
+    #[test]
+    fn xts_multiple_sectors() {
+        let plaintext: Vec<u8> = (0..3 * HEADER_SECTOR_SIZE).map(|i| i as u8).collect();
+        let mut data = plaintext.clone();
+        encrypt_xts(&HEADER_KEY, 5, HEADER_SECTOR_SIZE, &mut data);
+
+        // Each sector is decrypted with the next sector number
+        for (i, sector_data) in data
+            .clone()
+            .chunks_exact_mut(HEADER_SECTOR_SIZE)
+            .enumerate()
+        {
+            SoftwareDecryptor
+                .decrypt_xts(&HEADER_KEY, 5 + i, HEADER_SECTOR_SIZE, sector_data)
+                .unwrap();
+            assert_eq!(
+                sector_data,
+                &plaintext[i * HEADER_SECTOR_SIZE..(i + 1) * HEADER_SECTOR_SIZE]
+            );
+        }
+
+        SoftwareDecryptor
+            .decrypt_xts(&HEADER_KEY, 5, HEADER_SECTOR_SIZE, &mut data)
+            .unwrap();
+        assert_eq!(data, plaintext);
+    }
+
+    #[test]
+    fn xts_invalid_size() {
+        let mut data = [0u8; 0x30];
+        for (sector_size, data_size) in [(0, 0x30), (0x18, 0x30), (0x20, 0x30)] {
+            let rc = SoftwareDecryptor
+                .decrypt_xts(&HEADER_KEY, 0, sector_size, &mut data[..data_size])
+                .unwrap_err();
+            assert!(rc::ResultInvalidAesDataSize::matches(rc));
+        }
+    }
+
+    #[test]
+    fn ctr_nist_vector() {
+        // NIST SP 800-38A F.5.2 (CTR-AES128.Decrypt), whose counter carries over its lowest byte
+        let key = [
+            0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7, 0x15, 0x88, 0x09, 0xCF,
+            0x4F, 0x3C,
+        ];
+        let counter = [
+            0xF0, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8, 0xF9, 0xFA, 0xFB, 0xFC, 0xFD,
+            0xFE, 0xFF,
+        ];
+        let ciphertext = [
+            0x87, 0x4D, 0x61, 0x91, 0xB6, 0x20, 0xE3, 0x26, 0x1B, 0xEF, 0x68, 0x64, 0x99, 0x0D,
+            0xB6, 0xCE, 0x98, 0x06, 0xF6, 0x6B, 0x79, 0x70, 0xFD, 0xFF, 0x86, 0x17, 0x18, 0x7B,
+            0xB9, 0xFF, 0xFD, 0xFF, 0x5A, 0xE4, 0xDF, 0x3E, 0xDB, 0xD5, 0xD3, 0x5E, 0x5B, 0x4F,
+            0x09, 0x02, 0x0D, 0xB0, 0x3E, 0xAB, 0x1E, 0x03, 0x1D, 0xDA, 0x2F, 0xBE, 0x03, 0xD1,
+            0x79, 0x21, 0x70, 0xA0, 0xF3, 0x00, 0x9C, 0xEE,
+        ];
+        let plaintext = [
+            0x6B, 0xC1, 0xBE, 0xE2, 0x2E, 0x40, 0x9F, 0x96, 0xE9, 0x3D, 0x7E, 0x11, 0x73, 0x93,
+            0x17, 0x2A, 0xAE, 0x2D, 0x8A, 0x57, 0x1E, 0x03, 0xAC, 0x9C, 0x9E, 0xB7, 0x6F, 0xAC,
+            0x45, 0xAF, 0x8E, 0x51, 0x30, 0xC8, 0x1C, 0x46, 0xA3, 0x5C, 0xE4, 0x11, 0xE5, 0xFB,
+            0xC1, 0x19, 0x1A, 0x0A, 0x52, 0xEF, 0xF6, 0x9F, 0x24, 0x45, 0xDF, 0x4F, 0x9B, 0x17,
+            0xAD, 0x2B, 0x41, 0x7B, 0xE6, 0x6C, 0x37, 0x10,
+        ];
+
+        let mut data = ciphertext;
+        SoftwareDecryptor
+            .crypt_ctr(&key, &counter, &mut data)
+            .unwrap();
+        assert_eq!(data, plaintext);
+
+        // The last block may be a partial one
+        let mut data = ciphertext[..0x25].to_vec();
+        SoftwareDecryptor
+            .crypt_ctr(&key, &counter, &mut data)
+            .unwrap();
+        assert_eq!(data, plaintext[..0x25]);
+    }
+
+    #[test]
+    fn ecb_nist_vector() {
+        // NIST SP 800-38A F.1.2 (ECB-AES128.Decrypt), first block
+        let key = [
+            0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7, 0x15, 0x88, 0x09, 0xCF,
+            0x4F, 0x3C,
+        ];
+        let mut data = [
+            0x3A, 0xD7, 0x7B, 0xB4, 0x0D, 0x7A, 0x36, 0x60, 0xA8, 0x9E, 0xCA, 0xF3, 0x24, 0x66,
+            0xEF, 0x97,
+        ];
+        SoftwareDecryptor.decrypt_ecb(&key, &mut data).unwrap();
+        assert_eq!(
+            data,
+            [
+                0x6B, 0xC1, 0xBE, 0xE2, 0x2E, 0x40, 0x9F, 0x96, 0xE9, 0x3D, 0x7E, 0x11, 0x73, 0x93,
+                0x17, 0x2A
+            ]
+        );
+
+        let rc = SoftwareDecryptor
+            .decrypt_ecb(&key, &mut data[..0xF])
+            .unwrap_err();
+        assert!(rc::ResultInvalidAesDataSize::matches(rc));
+    }
+
+    #[test]
+    fn section_counter() {
+        assert_eq!(
+            make_section_counter(UPPER_COUNTER, 0xC10),
+            [
+                0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0, 0, 0, 0, 0, 0, 0, 0xC1
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_plain_nca() {
+        let (section, data_region) = make_section(&[("main", b"code"), ("main.npdm", b"meta!")]);
+        let fs_header = make_fs_header(EncryptionType::None, data_region);
+        let nca = open_nca(make_nca(&fs_header, &section), &KeySet::default()).unwrap();
+
+        let header = nca.get_header();
+        assert_eq!(header.get_content_type(), Some(ContentType::Program));
+        assert_eq!(header.get_key_generation(), KEY_GENERATION as usize - 1);
+        assert!(!header.has_rights_id());
+        assert_eq!(nca.get_fs_header(0), Some(&fs_header));
+        assert!(nca.get_fs_header(1).is_none());
+        assert_eq!(
+            nca.get_fs_header(0).unwrap().get_data_region(),
+            Ok(data_region)
+        );
+
+        // No key area key was given, which only matters for encrypted sections
+        assert!(nca.content_key.is_none());
+        let section_file = nca.open_section_file(0).unwrap();
+        assert_eq!(section_file.offset, HEADER_BLOCK_SIZE + SECTION_DATA_OFFSET);
+        assert_eq!(section_file.size, data_region.size as usize);
+        assert!(section_file.key.is_none());
+
+        assert_eq!(read_section_file(&nca, 0, "/main.npdm"), b"meta!");
+    }
+
+    #[test]
+    fn parse_encrypted_nca() {
+        let (section, data_region) = make_section(&[("main", b"code"), ("main.npdm", b"meta!")]);
+        let fs_header = make_fs_header(EncryptionType::AesCtr, data_region);
+        let mut nca_data = make_nca(&fs_header, &section);
+        encrypt_nca(&mut nca_data, &fs_header);
+        assert_ne!(nca_data[0x200..0x204], *b"NCA3");
+
+        let nca = open_nca(nca_data.clone(), &make_keys()).unwrap();
+        assert_eq!(nca.get_header().magic, Header::MAGIC);
+        assert_eq!(nca.get_fs_header(0), Some(&fs_header));
+        assert_eq!(nca.content_key, Some(CONTENT_KEY));
+        assert_eq!(read_section_file(&nca, 0, "main"), b"code");
+
+        // Reads which aren't block-aligned
+        let mut section_file = nca.open_section_file(0).unwrap();
+        let mut data = [0u8; 0x15];
+        fs::read_file_exact(&mut section_file, 3, &mut data).unwrap();
+        assert_eq!(
+            data,
+            section[SECTION_DATA_OFFSET + 3..SECTION_DATA_OFFSET + 0x18]
+        );
+
+        // The section can't be opened without the key area key
+        let nca = open_nca(nca_data.clone(), &KeySet::new(HEADER_KEY)).unwrap();
+        let rc = nca.open_section_file(0).err().unwrap();
+        assert!(rc::ResultNcaKeyNotFound::matches(rc));
+
+        // Nor can the header be decrypted with the wrong key
+        let rc = open_nca(nca_data, &KeySet::new([0xFF; 0x20]))
+            .err()
+            .unwrap();
+        assert!(rc::ResultInvalidNcaMagic::matches(rc));
+    }
+
+    #[test]
+    fn fs_header_hash_mismatch() {
+        let (section, data_region) = make_section(&[("main", b"code")]);
+        let fs_header = make_fs_header(EncryptionType::AesCtr, data_region);
+        let mut nca_data = make_nca(&fs_header, &section);
+        // Tamper with the upper counter, after the hash was computed
+        let upper_counter_offset =
+            mem::size_of::<Header>() + mem::offset_of!(FsHeader, upper_counter);
+        nca_data[upper_counter_offset] ^= 1;
+
+        let rc = open_nca(nca_data.clone(), &KeySet::default())
+            .err()
+            .unwrap();
+        assert!(rc::ResultNcaFsHeaderHashMismatch::matches(rc));
+
+        encrypt_nca(&mut nca_data, &fs_header);
+        let rc = open_nca(nca_data, &make_keys()).err().unwrap();
+        assert!(rc::ResultNcaFsHeaderHashMismatch::matches(rc));
+    }
+
+    #[test]
+    fn parse_truncated_nca() {
+        let (section, data_region) = make_section(&[("main", b"code")]);
+        let mut nca_data = make_nca(&make_fs_header(EncryptionType::None, data_region), &section);
+        nca_data.truncate(HEADER_BLOCK_SIZE - 1);
+        let rc = open_nca(nca_data, &KeySet::default()).err().unwrap();
+        assert!(rc::ResultUnexpectedEndOfFile::matches(rc));
+    }
+
+    #[test]
+    fn section_bounds() {
+        let (section, data_region) = make_section(&[("main", b"code")]);
+        let section_size = section.len().next_multiple_of(MEDIA_UNIT_SIZE) as u64;
+        let open_section = |encryption_type, data_region| {
+            let fs_header = make_fs_header(encryption_type, data_region);
+            let nca = open_nca(make_nca(&fs_header, &section), &make_keys()).unwrap();
+            nca.open_section_file(0)
+                .map(|section_file| section_file.size)
+        };
+
+        // The data may end right at the end of the section...
+        let whole_region = Region {
+            offset: SECTION_DATA_OFFSET as u64,
+            size: section_size - SECTION_DATA_OFFSET as u64,
+        };
+        assert_eq!(
+            open_section(EncryptionType::None, whole_region),
+            Ok(whole_region.size as usize)
+        );
+
+        // ...but not past it, nor overflow
+        for bad_region in [
+            Region {
+                offset: SECTION_DATA_OFFSET as u64,
+                size: whole_region.size + 1,
+            },
+            Region {
+                offset: section_size,
+                size: 1,
+            },
+            Region {
+                offset: u64::MAX,
+                size: data_region.size,
+            },
+            Region {
+                offset: data_region.offset,
+                size: u64::MAX,
+            },
+        ] {
+            let rc = open_section(EncryptionType::None, bad_region).unwrap_err();
+            assert!(rc::ResultInvalidNcaHeader::matches(rc));
+        }
+
+        let rc = open_section(EncryptionType::AesXts, data_region).unwrap_err();
+        assert!(rc::ResultUnsupportedNcaEncryptionType::matches(rc));
+
+        let nca = open_nca(
+            make_nca(&make_fs_header(EncryptionType::None, data_region), &section),
+            &KeySet::default(),
+        )
+        .unwrap();
+        for index in [1, FS_HEADER_COUNT] {
+            let rc = nca.open_section_file(index).err().unwrap();
+            assert!(rc::ResultInvalidNcaSectionIndex::matches(rc));
+        }
+    }
+
+    #[test]
+    fn invalid_hash_layer_count() {
+        for hash_layer_count in [0u32, 6] {
+            let mut fs_header = make_fs_header(EncryptionType::None, Region::default());
+            let offset = mem::offset_of!(HierarchicalSha256Data, hash_layer_count);
+            fs_header.hash_data[offset..offset + 4]
+                .copy_from_slice(&hash_layer_count.to_le_bytes());
+            assert_eq!(
+                fs_header.get_sha256_data().hash_layer_count,
+                hash_layer_count
+            );
+            let rc = fs_header.get_data_region().unwrap_err();
+            assert!(rc::ResultInvalidNcaHeader::matches(rc));
+        }
+    }
+}
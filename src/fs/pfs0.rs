@@ -1 +1,753 @@
-// TODO: PFS0 filesystem/file/dir
+//! PFS0 (partition filesystem) support
+//!
+//! PFS0 images are flat, read-only archives: a [`Header`], a [`FileEntry`] table and a string table, followed by the actual file data.
+//! They are used (among other places) as the NSP container format and as the ExeFS section of program NCAs.
+//!
+//! The metadata parsing ([`Pfs0::parse`]) works on plain byte slices, while [`Pfs0FileSystem`] exposes a PFS0 image stored in any [`File`][`fs::File`] as a mountable [`FileSystem`][`fs::FileSystem`]:
+//!
+//! ```ignore
+//! let nsp_file = nx::fs::open_file("sdmc:/game.nsp", nx::fs::FileOpenOption::Read())?;
+//! let nsp_fs = Pfs0FileSystem::new(nsp_file.into_file())?;
+//! nx::fs::mount("nsp", Arc::new(nsp_fs));
+//! ```
+
+use crate::fs;
+use crate::fs::rc;
+use crate::result::*;
+use crate::service::fsp::fsp_sf;
+use crate::sync::Mutex;
+use crate::util;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem;
+
+/// Represents the PFS0 header
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct Header {
+    /// The magic, whose expected value is [`MAGIC`][`Header::MAGIC`]
+    pub magic: u32,
+    /// The number of [`FileEntry`]s following the header
+    pub file_count: u32,
+    /// The size of the string table following the file entries
+    pub string_table_size: u32,
+    /// Reserved
+    pub reserved: u32,
+}
+const_assert!(mem::size_of::<Header>() == 0x10);
+
+impl Header {
+    /// The header magic value (`PFS0`)
+    pub const MAGIC: u32 = u32::from_le_bytes(*b"PFS0");
+
+    /// Gets the size of the whole metadata block (header, file entry table and string table)
+    ///
+    /// File data offsets are relative to the end of this block
+    pub const fn get_metadata_size(&self) -> usize {
+        get_partition_metadata_size::<Self, FileEntry>(self.file_count, self.string_table_size)
+    }
+}
+
+/// Represents a raw PFS0 file entry
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct FileEntry {
+    /// The file data offset, relative to the end of the metadata block
+    pub offset: u64,
+    /// The file data size
+    pub size: u64,
+    /// The offset of the (NUL-terminated) file name in the string table
+    pub string_table_offset: u32,
+    /// Reserved
+    pub reserved: u32,
+}
+const_assert!(mem::size_of::<FileEntry>() == 0x18);
+
+/// Represents a parsed file entry
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Entry {
+    /// The file name
+    pub name: String,
+    /// The absolute offset of the file data inside the image
+    pub offset: usize,
+    /// The file data size
+    pub size: usize,
+}
+
+/// Represents the parsed metadata of a PFS0 image
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Pfs0 {
+    entries: Vec<Entry>,
+    metadata_size: usize,
+}
+
+impl Pfs0 {
+    /// Reads and validates the [`Header`] at the start of the given data
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The image data, which must be at least [`Header`]-sized
+    pub fn read_header(data: &[u8]) -> Result<Header> {
+        // SAFETY: the header only has integer fields
+        let header: Header = unsafe { util::read_unaligned_at(data, 0) }
+            .ok_or(rc::ResultUnexpectedEndOfFile::make())?;
+        result_return_unless!(header.magic == Header::MAGIC, rc::ResultInvalidPfs0Magic);
+        Ok(header)
+    }
+
+    /// Parses the PFS0 metadata from the given data
+    ///
+    /// The data must contain at least the whole metadata block (see [`Header::get_metadata_size`]), the file data itself isn't required.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The image data
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let header = Self::read_header(data)?;
+        // SAFETY: the file entries only have integer fields
+        let (entries, metadata_size) = unsafe {
+            parse_partition_entries::<Header, FileEntry, _>(
+                data,
+                header.file_count,
+                header.string_table_size,
+                Self::make_entry,
+            )
+        }?;
+
+        Ok(Self {
+            entries,
+            metadata_size,
+        })
+    }
+
+    pub(crate) fn read_entry_name(string_table: &[u8], name_offset: usize) -> Result<String> {
+        read_partition_entry_name::<rc::ResultInvalidPfs0Header>(string_table, name_offset)
+    }
+
+    fn make_entry(raw_entry: &FileEntry, string_table: &[u8], data_offset: usize) -> Result<Entry> {
+        let offset = usize::try_from(raw_entry.offset)
+            .ok()
+            .and_then(|offset| offset.checked_add(data_offset))
+            .ok_or(rc::ResultInvalidPfs0Header::make())?;
+        let size =
+            usize::try_from(raw_entry.size).map_err(|_| rc::ResultInvalidPfs0Header::make())?;
+        result_return_if!(
+            offset.checked_add(size).is_none(),
+            rc::ResultInvalidPfs0Header
+        );
+
+        Ok(Entry {
+            name: Self::read_entry_name(string_table, raw_entry.string_table_offset as usize)?,
+            offset,
+            size,
+        })
+    }
+
+    pub(crate) fn from_entries(entries: Vec<Entry>, metadata_size: usize) -> Self {
+        Self {
+            entries,
+            metadata_size,
+        }
+    }
+
+    /// Gets the parsed file entries
+    #[inline]
+    pub fn get_entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Gets the size of the metadata block, which is also where file data starts
+    #[inline]
+    pub fn get_metadata_size(&self) -> usize {
+        self.metadata_size
+    }
+
+    /// Gets the total image size, as described by the metadata
+    pub fn get_image_size(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|entry| entry.offset + entry.size)
+            .fold(self.metadata_size, usize::max)
+    }
+
+    /// Finds a file entry by its name
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: The file name
+    pub fn find_entry(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Gets the data of a file entry from the whole image data
+    ///
+    /// Returns [`None`] if the image data is too small to contain the file
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The whole image data
+    /// * `entry`: The file entry
+    pub fn get_file_data<'a>(&self, data: &'a [u8], entry: &Entry) -> Option<&'a [u8]> {
+        data.get(entry.offset..entry.offset + entry.size)
+    }
+}
+
+/// Gets the metadata size of a partition image (PFS0 or HFS0), given its header and file entry types
+pub(crate) const fn get_partition_metadata_size<H, E>(
+    file_count: u32,
+    string_table_size: u32,
+) -> usize {
+    mem::size_of::<H>() + file_count as usize * mem::size_of::<E>() + string_table_size as usize
+}
+
+/// Parses the file entry table of a partition image (PFS0 or HFS0), returning the entries and the metadata size
+///
+/// The entry and string tables are checked to be within the data before anything is allocated
+///
+/// # Safety
+///
+/// `E` must be valid for any bit pattern, see [`util::read_unaligned_at`]
+pub(crate) unsafe fn parse_partition_entries<H, E: Copy, T>(
+    data: &[u8],
+    file_count: u32,
+    string_table_size: u32,
+    make_entry: impl Fn(&E, &[u8], usize) -> Result<T>,
+) -> Result<(Vec<T>, usize)> {
+    let metadata_size = get_partition_metadata_size::<H, E>(file_count, string_table_size);
+    result_return_unless!(data.len() >= metadata_size, rc::ResultUnexpectedEndOfFile);
+
+    let entries_offset = mem::size_of::<H>();
+    let string_table_offset = entries_offset + file_count as usize * mem::size_of::<E>();
+    let string_table = &data[string_table_offset..metadata_size];
+
+    let mut entries = Vec::with_capacity(file_count as usize);
+    for i in 0..file_count as usize {
+        // SAFETY: the caller guarantees any bit pattern is a valid `E`
+        let raw_entry: E =
+            unsafe { util::read_unaligned_at(data, entries_offset + i * mem::size_of::<E>()) }
+                .ok_or(rc::ResultUnexpectedEndOfFile::make())?;
+        entries.push(make_entry(&raw_entry, string_table, metadata_size)?);
+    }
+
+    Ok((entries, metadata_size))
+}
+
+/// Reads a (NUL-terminated) file name from the string table of a partition image, failing with `R` if it's not within the table
+pub(crate) fn read_partition_entry_name<R: ResultBase>(
+    string_table: &[u8],
+    name_offset: usize,
+) -> Result<String> {
+    let name_data = string_table.get(name_offset..).ok_or(R::make())?;
+    let name = core::ffi::CStr::from_bytes_until_nul(name_data)
+        .map_err(|_| R::make())?
+        .to_str()
+        .map_err(|_| util::rc::ResultInvalidUtf8Conversion::make())?;
+    Ok(String::from(name))
+}
+
+/// Reads the whole metadata block of a partition image (PFS0 or HFS0) from a [`File`][`fs::File`]
+///
+/// The metadata size comes from the (untrusted) header, so it's checked against the file size before allocating
+pub(crate) fn read_partition_metadata(
+    file: &mut dyn fs::File,
+    metadata_size: usize,
+) -> Result<Vec<u8>> {
+    result_return_if!(
+        metadata_size > file.get_size()?,
+        rc::ResultUnexpectedEndOfFile
+    );
+
+    let mut metadata = vec![0u8; metadata_size];
+    fs::read_file_exact(file, 0, &mut metadata)?;
+    Ok(metadata)
+}
+
+#[inline]
+pub(crate) fn get_entry_name(path: &str) -> &str {
+    path.trim_start_matches('/')
+}
+
+pub(crate) fn make_directory_entry(
+    entry: &Entry,
+    mode: fs::DirectoryOpenMode,
+) -> fs::DirectoryEntry {
+    fs::DirectoryEntry {
+        name: fsp_sf::Path::from_str(&entry.name),
+        entry_type: fs::DirectoryEntryType::File,
+        file_size: match mode.contains(fs::DirectoryOpenMode::NoFileSizes()) {
+            true => 0,
+            false => entry.size,
+        },
+        ..Default::default()
+    }
+}
+
+/// Represents a [`File`][`fs::File`] inside a PFS0 image
+pub struct Pfs0File {
+    base: Arc<Mutex<Box<dyn fs::File>>>,
+    offset: usize,
+    size: usize,
+}
+
+impl Pfs0File {
+    pub(crate) fn new(base: Arc<Mutex<Box<dyn fs::File>>>, offset: usize, size: usize) -> Self {
+        Self { base, offset, size }
+    }
+}
+
+impl fs::File for Pfs0File {
+    fn read(
+        &mut self,
+        offset: usize,
+        out_buf: &mut [u8],
+        option: fs::FileReadOption,
+    ) -> Result<usize> {
+        if offset >= self.size {
+            return Ok(0);
+        }
+
+        let read_size = out_buf.len().min(self.size - offset);
+        self.base
+            .lock()
+            .read(self.offset + offset, &mut out_buf[..read_size], option)
+    }
+
+    fn write(&mut self, _offset: usize, _buf: &[u8], _option: fs::FileWriteOption) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_size(&mut self, _size: usize) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn get_size(&mut self) -> Result<usize> {
+        Ok(self.size)
+    }
+
+    fn operate_range(
+        &mut self,
+        operation_id: fs::OperationId,
+        offset: usize,
+        size: usize,
+    ) -> Result<fs::FileQueryRangeInfo> {
+        result_return_unless!(
+            matches!(
+                operation_id,
+                fs::OperationId::Invalidate | fs::OperationId::QueryRange
+            ),
+            crate::rc::ResultNotSupported
+        );
+        result_return_if!(offset > self.size, rc::ResultUnexpectedEndOfFile);
+
+        let size = size.min(self.size - offset);
+        self.base
+            .lock()
+            .operate_range(operation_id, self.offset + offset, size)
+    }
+
+    fn operate_range_with_buffer(
+        &mut self,
+        _operation_id: fs::OperationId,
+        _offset: usize,
+        _size: usize,
+        _in_buf: &[u8],
+        _out_buf: &mut [u8],
+    ) -> Result<()> {
+        crate::rc::ResultNotSupported::make_err()
+    }
+}
+
+/// Represents the (root, and only) [`Directory`][`fs::Directory`] of a PFS0 image
+pub struct Pfs0Directory {
+    pfs0: Arc<Pfs0>,
+    mode: fs::DirectoryOpenMode,
+    cur_index: Mutex<usize>,
+}
+
+impl Pfs0Directory {
+    pub(crate) fn new(pfs0: Arc<Pfs0>, mode: fs::DirectoryOpenMode) -> Self {
+        Self {
+            pfs0,
+            mode,
+            cur_index: Mutex::new(0),
+        }
+    }
+}
+
+impl fs::Directory for Pfs0Directory {
+    fn read(&self, out_entries: &mut [fs::DirectoryEntry]) -> Result<usize> {
+        // There are no subdirectories in PFS0 images, only files
+        if !self.mode.contains(fs::DirectoryOpenMode::ReadFiles()) {
+            return Ok(0);
+        }
+
+        let mut cur_index = self.cur_index.lock();
+        let entries = &self.pfs0.get_entries()[*cur_index..];
+        let read_count = out_entries.len().min(entries.len());
+        for (out_entry, entry) in out_entries.iter_mut().zip(entries) {
+            *out_entry = make_directory_entry(entry, self.mode);
+        }
+
+        *cur_index += read_count;
+        Ok(read_count)
+    }
+
+    fn get_entry_count(&self) -> Result<u64> {
+        match self.mode.contains(fs::DirectoryOpenMode::ReadFiles()) {
+            true => Ok(self.pfs0.get_entries().len() as u64),
+            false => Ok(0),
+        }
+    }
+}
+
+/// Represents a read-only [`FileSystem`][`fs::FileSystem`] over a PFS0 image
+pub struct Pfs0FileSystem {
+    base: Arc<Mutex<Box<dyn fs::File>>>,
+    pfs0: Arc<Pfs0>,
+}
+
+impl Pfs0FileSystem {
+    /// Creates a new [`Pfs0FileSystem`], parsing the PFS0 metadata from the given [`File`][`fs::File`]
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: The [`File`][`fs::File`] containing the PFS0 image
+    pub fn new(mut file: Box<dyn fs::File>) -> Result<Self> {
+        let pfs0 = Self::read_metadata(file.as_mut())?;
+        Ok(Self::from_parts(Arc::new(Mutex::new(file)), pfs0))
+    }
+
+    pub(crate) fn from_parts(base: Arc<Mutex<Box<dyn fs::File>>>, pfs0: Pfs0) -> Self {
+        Self {
+            base,
+            pfs0: Arc::new(pfs0),
+        }
+    }
+
+    pub(crate) fn open_entry(&self, entry: &Entry) -> Pfs0File {
+        Pfs0File::new(self.base.clone(), entry.offset, entry.size)
+    }
+
+    fn read_metadata(file: &mut dyn fs::File) -> Result<Pfs0> {
+        let mut header_data = [0u8; mem::size_of::<Header>()];
+        fs::read_file_exact(file, 0, &mut header_data)?;
+        let header = Pfs0::read_header(&header_data)?;
+
+        let metadata = read_partition_metadata(file, header.get_metadata_size())?;
+        Pfs0::parse(&metadata)
+    }
+
+    /// Gets the parsed PFS0 metadata
+    #[inline]
+    pub fn get_pfs0(&self) -> &Pfs0 {
+        &self.pfs0
+    }
+}
+
+impl fs::FileSystem for Pfs0FileSystem {
+    fn create_file(&self, _path: &str, _attribute: fs::FileAttribute, _size: usize) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn remove_file(&self, _path: &str) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn create_directory(&self, _path: &str) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn remove_dir(&self, _path: &str) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn remove_dir_all(&self, _path: &str) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn rename_file(&self, _old_path: &str, _new_path: &str) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn rename_directory(&self, _old_path: &str, _new_path: &str) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn get_entry_type(&self, path: &str) -> Result<fs::DirectoryEntryType> {
+        let name = get_entry_name(path);
+        if name.is_empty() {
+            Ok(fs::DirectoryEntryType::Directory)
+        } else if self.pfs0.find_entry(name).is_some() {
+            Ok(fs::DirectoryEntryType::File)
+        } else {
+            fsp_sf::rc::ResultPathNotFound::make_err()
+        }
+    }
+
+    fn open_file(&self, path: &str, mode: fs::FileOpenMode) -> Result<Box<dyn fs::File>> {
+        result_return_if!(
+            mode.contains(fs::FileOpenMode::Write()) || mode.contains(fs::FileOpenMode::Append()),
+            rc::ResultReadOnlyFileSystem
+        );
+
+        let entry = self
+            .pfs0
+            .find_entry(get_entry_name(path))
+            .ok_or(fsp_sf::rc::ResultPathNotFound::make())?;
+        Ok(Box::new(self.open_entry(entry)))
+    }
+
+    fn open_directory(
+        &self,
+        path: &str,
+        mode: fs::DirectoryOpenMode,
+    ) -> Result<Box<dyn fs::Directory>> {
+        result_return_unless!(
+            get_entry_name(path).is_empty(),
+            fsp_sf::rc::ResultPathNotFound
+        );
+        Ok(Box::new(Pfs0Directory::new(self.pfs0.clone(), mode)))
+    }
+
+    fn commit(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_free_space_size(&self, _path: &str) -> Result<usize> {
+        Ok(0)
+    }
+
+    fn get_total_space_size(&self, _path: &str) -> Result<usize> {
+        Ok(self.pfs0.get_image_size())
+    }
+
+    fn remove_children_all(&self, _path: &str) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn get_file_time_stamp_raw(&self, _path: &str) -> Result<fs::FileTimeStampRaw> {
+        crate::rc::ResultNotSupported::make_err()
+    }
+
+    fn query_entry(
+        &self,
+        _path: &str,
+        _query_id: fs::QueryId,
+        _in_buf: &[u8],
+        _out_buf: &mut [u8],
+    ) -> Result<()> {
+        crate::rc::ResultNotSupported::make_err()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::fs::File;
+
+    /// A [`File`][`fs::File`] over an in-memory image
+    pub(crate) struct MemoryFile(pub Vec<u8>);
+
+    impl fs::File for MemoryFile {
+        fn read(
+            &mut self,
+            offset: usize,
+            out_buf: &mut [u8],
+            _option: fs::FileReadOption,
+        ) -> Result<usize> {
+            let data = self.0.get(offset..).unwrap_or_default();
+            let read_size = out_buf.len().min(data.len());
+            out_buf[..read_size].copy_from_slice(&data[..read_size]);
+            Ok(read_size)
+        }
+
+        fn write(
+            &mut self,
+            _offset: usize,
+            _buf: &[u8],
+            _option: fs::FileWriteOption,
+        ) -> Result<()> {
+            rc::ResultReadOnlyFileSystem::make_err()
+        }
+
+        fn flush(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn set_size(&mut self, _size: usize) -> Result<()> {
+            rc::ResultReadOnlyFileSystem::make_err()
+        }
+
+        fn get_size(&mut self) -> Result<usize> {
+            Ok(self.0.len())
+        }
+
+        fn operate_range(
+            &mut self,
+            _operation_id: fs::OperationId,
+            _offset: usize,
+            _size: usize,
+        ) -> Result<fs::FileQueryRangeInfo> {
+            crate::rc::ResultNotSupported::make_err()
+        }
+
+        fn operate_range_with_buffer(
+            &mut self,
+            _operation_id: fs::OperationId,
+            _offset: usize,
+            _size: usize,
+            _in_buf: &[u8],
+            _out_buf: &mut [u8],
+        ) -> Result<()> {
+            crate::rc::ResultNotSupported::make_err()
+        }
+    }
+
+    pub(crate) fn push_raw<T: Copy>(data: &mut Vec<u8>, value: &T) {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>())
+        };
+        data.extend_from_slice(bytes);
+    }
+
+    /// Builds a partition image (PFS0 or HFS0) containing the given files
+    ///
+    /// The raw file entries (of any size) are created by `make_entry` from each file's data offset, name offset and contents.
+    pub(crate) fn make_partition_image<E: Copy>(
+        magic: u32,
+        files: &[(&str, &[u8])],
+        make_entry: impl Fn(u64, u32, &[u8]) -> E,
+    ) -> Vec<u8> {
+        let mut string_table = Vec::new();
+        let mut file_entries = Vec::new();
+        let mut file_data = Vec::new();
+        for (name, contents) in files {
+            file_entries.push(make_entry(
+                file_data.len() as u64,
+                string_table.len() as u32,
+                contents,
+            ));
+            string_table.extend_from_slice(name.as_bytes());
+            string_table.push(0);
+            file_data.extend_from_slice(contents);
+        }
+
+        // Both formats share the same header layout
+        let mut image = Vec::new();
+        push_raw(
+            &mut image,
+            &Header {
+                magic,
+                file_count: files.len() as u32,
+                string_table_size: string_table.len() as u32,
+                reserved: 0,
+            },
+        );
+        for file_entry in &file_entries {
+            push_raw(&mut image, file_entry);
+        }
+        image.extend_from_slice(&string_table);
+        image.extend_from_slice(&file_data);
+        image
+    }
+
+    /// Builds a PFS0 image containing the given files
+    pub(crate) fn make_image(files: &[(&str, &[u8])]) -> Vec<u8> {
+        make_partition_image(
+            Header::MAGIC,
+            files,
+            |offset, string_table_offset, contents| FileEntry {
+                offset,
+                size: contents.len() as u64,
+                string_table_offset,
+                reserved: 0,
+            },
+        )
+    }
+
+    #[test]
+    fn parse_entries() {
+        let image = make_image(&[("main", b"code"), ("main.npdm", b"meta!")]);
+        let pfs0 = Pfs0::parse(&image).unwrap();
+        let metadata_size = 0x10 + 2 * 0x18 + 15;
+        assert_eq!(pfs0.get_metadata_size(), metadata_size);
+        assert_eq!(pfs0.get_image_size(), image.len());
+
+        let entries = pfs0.get_entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "main");
+        assert_eq!(entries[0].offset, metadata_size);
+        assert_eq!(entries[1].name, "main.npdm");
+        assert_eq!(entries[1].offset, metadata_size + 4);
+
+        let entry = pfs0.find_entry("main.npdm").unwrap();
+        assert_eq!(pfs0.get_file_data(&image, entry), Some(&b"meta!"[..]));
+        assert!(pfs0.find_entry("missing").is_none());
+    }
+
+    #[test]
+    fn parse_invalid_magic() {
+        let mut image = make_image(&[("main", b"code")]);
+        image[0] = b'H';
+        let rc = Pfs0::parse(&image).unwrap_err();
+        assert!(rc::ResultInvalidPfs0Magic::matches(rc));
+    }
+
+    #[test]
+    fn parse_truncated_metadata() {
+        let image = make_image(&[("main", b"code")]);
+        let rc = Pfs0::parse(&image[..0x20]).unwrap_err();
+        assert!(rc::ResultUnexpectedEndOfFile::matches(rc));
+    }
+
+    #[test]
+    fn parse_invalid_name_offset() {
+        let mut image = make_image(&[("main", b"code")]);
+        // Point the name past the end of the string table
+        image[0x20] = 0x40;
+        let rc = Pfs0::parse(&image).unwrap_err();
+        assert!(rc::ResultInvalidPfs0Header::matches(rc));
+    }
+
+    #[test]
+    fn parse_invalid_name_utf8() {
+        let mut image = make_image(&[("main", b"code")]);
+        image[0x10 + 0x18] = 0xFF;
+        let rc = Pfs0::parse(&image).unwrap_err();
+        assert!(util::rc::ResultInvalidUtf8Conversion::matches(rc));
+    }
+
+    #[test]
+    fn parse_huge_file_count() {
+        let mut image = make_image(&[]);
+        image[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        image[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        let rc = Pfs0::parse(&image).unwrap_err();
+        assert!(rc::ResultUnexpectedEndOfFile::matches(rc));
+
+        // The metadata size must be checked against the file before being allocated
+        let rc = Pfs0FileSystem::new(Box::new(MemoryFile(image)))
+            .err()
+            .unwrap();
+        assert!(rc::ResultUnexpectedEndOfFile::matches(rc));
+    }
+
+    #[test]
+    fn filesystem_metadata() {
+        let image = make_image(&[("main", b"code")]);
+        let image_size = image.len();
+        let mut file = MemoryFile(image);
+        assert_eq!(file.get_size().unwrap(), image_size);
+
+        let pfs0_fs = Pfs0FileSystem::new(Box::new(file)).unwrap();
+        let pfs0 = pfs0_fs.get_pfs0();
+        assert_eq!(pfs0.get_entries().len(), 1);
+        assert_eq!(pfs0.get_image_size(), image_size);
+    }
+}
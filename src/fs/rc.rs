@@ -8,5 +8,21 @@ pub const RESULT_SUBMODULE: u32 = 700;
 result_define_subgroup!(rc::RESULT_MODULE, RESULT_SUBMODULE => {
     DeviceNotFound: 1,
     InvalidPath: 2,
-    NotInSameFileSystem: 3
+    NotInSameFileSystem: 3,
+    UnexpectedEndOfFile: 4,
+    ReadOnlyFileSystem: 5,
+    InvalidPfs0Magic: 6,
+    InvalidPfs0Header: 7,
+    InvalidHfs0Magic: 8,
+    InvalidHfs0Header: 9,
+    Hfs0HashMismatch: 10,
+    InvalidGamecardMagic: 11,
+    InvalidNcaMagic: 12,
+    InvalidNcaHeader: 13,
+    NcaFsHeaderHashMismatch: 14,
+    InvalidNcaSectionIndex: 15,
+    NcaKeyNotFound: 16,
+    UnsupportedNcaEncryptionType: 17,
+    InvalidAesDataSize: 18,
+    InvalidRomFsHeader: 19
 });
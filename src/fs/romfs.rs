@@ -0,0 +1,1128 @@
+//! RomFS (read-only filesystem) support
+//!
+//! RomFS images contain a [`Header`], the file data and four metadata tables: directory and file hash tables (buckets of entry offsets) and directory and file metadata tables ([`DirectoryMeta`]/[`FileMeta`] entries followed by their names).
+//!
+//! Path lookups go through the hash tables (see [`compute_hash`]), thus they don't need to scan the whole metadata tables.
+//!
+//! The RomFS of the currently running homebrew NRO, if present, can be mounted with [`mount_romfs_self`][`fs::mount_romfs_self`], while any other RomFS image can be mounted with [`mount_romfs`][`fs::mount_romfs`]:
+//!
+//! ```ignore
+//! nx::fs::mount_romfs_self("romfs")?;
+//! let asset_file = nx::fs::open_file("romfs:/assets/logo.png", nx::fs::FileOpenOption::Read())?;
+//! ```
+
+use crate::elf::nro;
+use crate::fs;
+use crate::fs::pfs0;
+use crate::fs::rc;
+use crate::result::*;
+use crate::service::fsp::fsp_sf;
+use crate::sync::Mutex;
+use crate::util;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem;
+
+/// Represents the RomFS header
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct Header {
+    /// The header size, whose expected value is the size of this type
+    pub header_size: u64,
+    /// The offset of the directory hash table
+    pub dir_hash_table_offset: u64,
+    /// The size of the directory hash table
+    pub dir_hash_table_size: u64,
+    /// The offset of the directory metadata table
+    pub dir_meta_table_offset: u64,
+    /// The size of the directory metadata table
+    pub dir_meta_table_size: u64,
+    /// The offset of the file hash table
+    pub file_hash_table_offset: u64,
+    /// The size of the file hash table
+    pub file_hash_table_size: u64,
+    /// The offset of the file metadata table
+    pub file_meta_table_offset: u64,
+    /// The size of the file metadata table
+    pub file_meta_table_size: u64,
+    /// The offset of the file data, which file offsets are relative to
+    pub file_data_offset: u64,
+}
+const_assert!(mem::size_of::<Header>() == 0x50);
+
+/// Represents a raw directory metadata entry, followed by its name in the directory metadata table
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct DirectoryMeta {
+    /// The offset of the parent directory
+    pub parent: u32,
+    /// The offset of the next sibling directory, or [`EMPTY_ENTRY`]
+    pub sibling: u32,
+    /// The offset of the first child directory, or [`EMPTY_ENTRY`]
+    pub child_dir: u32,
+    /// The offset of the first child file, or [`EMPTY_ENTRY`]
+    pub child_file: u32,
+    /// The offset of the next directory in the same hash table bucket, or [`EMPTY_ENTRY`]
+    pub next_hash: u32,
+    /// The name length
+    pub name_len: u32,
+}
+const_assert!(mem::size_of::<DirectoryMeta>() == 0x18);
+
+/// Represents a raw file metadata entry, followed by its name in the file metadata table
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct FileMeta {
+    /// The offset of the parent directory
+    pub parent: u32,
+    /// The offset of the next sibling file, or [`EMPTY_ENTRY`]
+    pub sibling: u32,
+    /// The file data offset, relative to [`Header::file_data_offset`]
+    pub data_offset: u64,
+    /// The file data size
+    pub data_size: u64,
+    /// The offset of the next file in the same hash table bucket, or [`EMPTY_ENTRY`]
+    pub next_hash: u32,
+    /// The name length
+    pub name_len: u32,
+}
+const_assert!(mem::size_of::<FileMeta>() == 0x20);
+
+/// The value used for empty entry offsets (no sibling, no children, empty hash bucket...)
+pub const EMPTY_ENTRY: u32 = u32::MAX;
+
+/// The offset of the root directory in the directory metadata table
+pub const ROOT_DIRECTORY_OFFSET: u32 = 0;
+
+/// Computes the hash table bucket of an entry
+///
+/// # Arguments
+///
+/// * `parent`: The offset of the parent directory
+/// * `name`: The entry name
+/// * `bucket_count`: The number of buckets in the hash table
+pub fn compute_hash(parent: u32, name: &[u8], bucket_count: usize) -> usize {
+    let hash = name.iter().fold(parent ^ 123456789, |hash, &c| {
+        hash.rotate_right(5) ^ c as u32
+    });
+    hash as usize % bucket_count
+}
+
+/// Represents the parsed metadata of a RomFS image
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RomFs {
+    header: Header,
+    dir_hash_table: Vec<u32>,
+    dir_meta_table: Vec<u8>,
+    file_hash_table: Vec<u32>,
+    file_meta_table: Vec<u8>,
+}
+
+impl RomFs {
+    /// Reads and validates the [`Header`] at the start of the given data
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The image data, which must be at least [`Header`]-sized
+    pub fn read_header(data: &[u8]) -> Result<Header> {
+        // SAFETY: the header only has integer fields
+        let header: Header = unsafe { util::read_unaligned_at(data, 0) }
+            .ok_or(rc::ResultUnexpectedEndOfFile::make())?;
+        result_return_unless!(
+            header.header_size == mem::size_of::<Header>() as u64,
+            rc::ResultInvalidRomFsHeader
+        );
+        Ok(header)
+    }
+
+    /// Parses the RomFS metadata from the given data
+    ///
+    /// The data must contain (at least) all the metadata tables, which are usually placed after the file data
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The image data
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let header = Self::read_header(data)?;
+        let get_table = |offset: u64, size: u64| -> Result<&[u8]> {
+            let offset =
+                usize::try_from(offset).map_err(|_| rc::ResultInvalidRomFsHeader::make())?;
+            let size = usize::try_from(size).map_err(|_| rc::ResultInvalidRomFsHeader::make())?;
+            data.get(offset..offset.saturating_add(size))
+                .ok_or(rc::ResultUnexpectedEndOfFile::make())
+        };
+
+        Self::from_tables(
+            header,
+            get_table(header.dir_hash_table_offset, header.dir_hash_table_size)?,
+            get_table(header.dir_meta_table_offset, header.dir_meta_table_size)?.to_vec(),
+            get_table(header.file_hash_table_offset, header.file_hash_table_size)?,
+            get_table(header.file_meta_table_offset, header.file_meta_table_size)?.to_vec(),
+        )
+    }
+
+    /// Reads the RomFS metadata from the given [`File`][`fs::File`]
+    ///
+    /// Only the header and the metadata tables are read, not the file data
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: The [`File`][`fs::File`] containing the RomFS image
+    pub fn read(file: &mut dyn fs::File) -> Result<Self> {
+        let mut header_data = [0u8; mem::size_of::<Header>()];
+        fs::read_file_exact(file, 0, &mut header_data)?;
+        let header = Self::read_header(&header_data)?;
+
+        let file_size = file.get_size()?;
+        let mut read_table = |offset: u64, size: u64| -> Result<Vec<u8>> {
+            let offset =
+                usize::try_from(offset).map_err(|_| rc::ResultInvalidRomFsHeader::make())?;
+            let size = usize::try_from(size).map_err(|_| rc::ResultInvalidRomFsHeader::make())?;
+            // The table sizes are untrusted, so they must fit in the file before being allocated
+            result_return_unless!(
+                offset.checked_add(size).is_some_and(|end| end <= file_size),
+                rc::ResultUnexpectedEndOfFile
+            );
+            let mut table = vec![0u8; size];
+            fs::read_file_exact(file, offset, &mut table)?;
+            Ok(table)
+        };
+
+        let dir_hash_table = read_table(header.dir_hash_table_offset, header.dir_hash_table_size)?;
+        let dir_meta_table = read_table(header.dir_meta_table_offset, header.dir_meta_table_size)?;
+        let file_hash_table =
+            read_table(header.file_hash_table_offset, header.file_hash_table_size)?;
+        let file_meta_table =
+            read_table(header.file_meta_table_offset, header.file_meta_table_size)?;
+        Self::from_tables(
+            header,
+            &dir_hash_table,
+            dir_meta_table,
+            &file_hash_table,
+            file_meta_table,
+        )
+    }
+
+    fn from_tables(
+        header: Header,
+        dir_hash_table: &[u8],
+        dir_meta_table: Vec<u8>,
+        file_hash_table: &[u8],
+        file_meta_table: Vec<u8>,
+    ) -> Result<Self> {
+        let to_hash_table = |data: &[u8]| -> Vec<u32> {
+            data.chunks_exact(mem::size_of::<u32>())
+                .map(|bucket| u32::from_le_bytes([bucket[0], bucket[1], bucket[2], bucket[3]]))
+                .collect()
+        };
+
+        result_return_if!(
+            dir_hash_table.len() < mem::size_of::<u32>()
+                || file_hash_table.len() < mem::size_of::<u32>(),
+            rc::ResultInvalidRomFsHeader
+        );
+
+        let romfs = Self {
+            header,
+            dir_hash_table: to_hash_table(dir_hash_table),
+            dir_meta_table,
+            file_hash_table: to_hash_table(file_hash_table),
+            file_meta_table,
+        };
+        result_return_unless!(
+            romfs.get_directory(ROOT_DIRECTORY_OFFSET).is_some(),
+            rc::ResultInvalidRomFsHeader
+        );
+        Ok(romfs)
+    }
+
+    /// Gets the RomFS [`Header`]
+    #[inline]
+    pub fn get_header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Gets the size of the image, assuming the usual layout where the metadata tables come last
+    ///
+    /// Returns [`None`] if the header offsets/sizes overflow
+    pub fn get_image_size(&self) -> Option<usize> {
+        let header = &self.header;
+        let image_size = [
+            Some(header.file_data_offset),
+            header
+                .dir_hash_table_offset
+                .checked_add(header.dir_hash_table_size),
+            header
+                .dir_meta_table_offset
+                .checked_add(header.dir_meta_table_size),
+            header
+                .file_hash_table_offset
+                .checked_add(header.file_hash_table_size),
+            header
+                .file_meta_table_offset
+                .checked_add(header.file_meta_table_size),
+        ]
+        .into_iter()
+        .try_fold(0, |image_size, end| end.map(|end| image_size.max(end)))?;
+        usize::try_from(image_size).ok()
+    }
+
+    /// Gets the maximum amount of directories the directory metadata table could hold, which bounds any walk over directory entries
+    #[inline]
+    fn get_max_directory_count(&self) -> usize {
+        self.dir_meta_table.len() / mem::size_of::<DirectoryMeta>()
+    }
+
+    /// Gets the maximum amount of files the file metadata table could hold, which bounds any walk over file entries
+    #[inline]
+    fn get_max_file_count(&self) -> usize {
+        self.file_meta_table.len() / mem::size_of::<FileMeta>()
+    }
+
+    fn get_entry_name(table: &[u8], name_offset: usize, name_len: u32) -> Option<&str> {
+        let name_data = table.get(name_offset..name_offset.checked_add(name_len as usize)?)?;
+        core::str::from_utf8(name_data).ok()
+    }
+
+    /// Gets a directory metadata entry and its name
+    ///
+    /// # Arguments
+    ///
+    /// * `offset`: The offset of the entry in the directory metadata table
+    pub fn get_directory(&self, offset: u32) -> Option<(DirectoryMeta, &str)> {
+        let offset = offset as usize;
+        // SAFETY: the metadata only has integer fields
+        let meta: DirectoryMeta = unsafe { util::read_unaligned_at(&self.dir_meta_table, offset) }?;
+        let name = Self::get_entry_name(
+            &self.dir_meta_table,
+            offset + mem::size_of::<DirectoryMeta>(),
+            meta.name_len,
+        )?;
+        Some((meta, name))
+    }
+
+    /// Gets a file metadata entry and its name
+    ///
+    /// # Arguments
+    ///
+    /// * `offset`: The offset of the entry in the file metadata table
+    pub fn get_file(&self, offset: u32) -> Option<(FileMeta, &str)> {
+        let offset = offset as usize;
+        // SAFETY: the metadata only has integer fields
+        let meta: FileMeta = unsafe { util::read_unaligned_at(&self.file_meta_table, offset) }?;
+        let name = Self::get_entry_name(
+            &self.file_meta_table,
+            offset + mem::size_of::<FileMeta>(),
+            meta.name_len,
+        )?;
+        Some((meta, name))
+    }
+
+    /// Finds a directory by its parent directory and name, returning its offset
+    ///
+    /// # Arguments
+    ///
+    /// * `parent`: The offset of the parent directory
+    /// * `name`: The directory name
+    pub fn find_directory(&self, parent: u32, name: &str) -> Option<u32> {
+        let bucket = compute_hash(parent, name.as_bytes(), self.dir_hash_table.len());
+        let mut cur_offset = self.dir_hash_table[bucket];
+        // Malformed images could have cyclic hash chains
+        for _ in 0..self.get_max_directory_count() {
+            if cur_offset == EMPTY_ENTRY {
+                break;
+            }
+
+            let (meta, cur_name) = self.get_directory(cur_offset)?;
+            if meta.parent == parent && cur_name == name {
+                return Some(cur_offset);
+            }
+            cur_offset = meta.next_hash;
+        }
+
+        None
+    }
+
+    /// Finds a file by its parent directory and name, returning its offset
+    ///
+    /// # Arguments
+    ///
+    /// * `parent`: The offset of the parent directory
+    /// * `name`: The file name
+    pub fn find_file(&self, parent: u32, name: &str) -> Option<u32> {
+        let bucket = compute_hash(parent, name.as_bytes(), self.file_hash_table.len());
+        let mut cur_offset = self.file_hash_table[bucket];
+        // Malformed images could have cyclic hash chains
+        for _ in 0..self.get_max_file_count() {
+            if cur_offset == EMPTY_ENTRY {
+                break;
+            }
+
+            let (meta, cur_name) = self.get_file(cur_offset)?;
+            if meta.parent == parent && cur_name == name {
+                return Some(cur_offset);
+            }
+            cur_offset = meta.next_hash;
+        }
+
+        None
+    }
+
+    /// Resolves a directory path (relative to the root directory), returning the directory offset
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The directory path
+    pub fn resolve_directory(&self, path: &str) -> Option<u32> {
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .try_fold(ROOT_DIRECTORY_OFFSET, |dir, name| {
+                self.find_directory(dir, name)
+            })
+    }
+
+    /// Resolves a file path (relative to the root directory), returning the file offset
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The file path
+    pub fn resolve_file(&self, path: &str) -> Option<u32> {
+        let (parent_path, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let parent = self.resolve_directory(parent_path)?;
+        self.find_file(parent, name)
+    }
+
+    /// Gets the absolute offset and the size of a file's data inside the image
+    ///
+    /// # Arguments
+    ///
+    /// * `meta`: The file metadata entry
+    pub fn get_file_data_region(&self, meta: &FileMeta) -> Option<(usize, usize)> {
+        let offset =
+            usize::try_from(self.header.file_data_offset.checked_add(meta.data_offset)?).ok()?;
+        let size = usize::try_from(meta.data_size).ok()?;
+        offset.checked_add(size)?;
+        Some((offset, size))
+    }
+}
+
+/// Represents a [`Directory`][`fs::Directory`] inside a RomFS image
+pub struct RomFsDirectory {
+    romfs: Arc<RomFs>,
+    mode: fs::DirectoryOpenMode,
+    dir_meta: DirectoryMeta,
+    // The next child directory and file offsets to read, and the amount of entries read so far
+    cur_offsets: Mutex<(u32, u32, usize)>,
+}
+
+impl RomFsDirectory {
+    fn new(romfs: Arc<RomFs>, mode: fs::DirectoryOpenMode, dir_meta: DirectoryMeta) -> Self {
+        let cur_offsets = (
+            match mode.contains(fs::DirectoryOpenMode::ReadDirectories()) {
+                true => dir_meta.child_dir,
+                false => EMPTY_ENTRY,
+            },
+            match mode.contains(fs::DirectoryOpenMode::ReadFiles()) {
+                true => dir_meta.child_file,
+                false => EMPTY_ENTRY,
+            },
+            0,
+        );
+
+        Self {
+            romfs,
+            mode,
+            dir_meta,
+            cur_offsets: Mutex::new(cur_offsets),
+        }
+    }
+
+    fn read_next_entry(
+        &self,
+        cur_offsets: &mut (u32, u32, usize),
+    ) -> Result<Option<fs::DirectoryEntry>> {
+        let (cur_dir, cur_file, read_count) = cur_offsets;
+        if *cur_dir == EMPTY_ENTRY && *cur_file == EMPTY_ENTRY {
+            return Ok(None);
+        }
+
+        // Malformed images could have cyclic sibling chains
+        result_return_if!(
+            *read_count >= self.romfs.get_max_directory_count() + self.romfs.get_max_file_count(),
+            rc::ResultInvalidRomFsHeader
+        );
+        *read_count += 1;
+
+        if *cur_dir != EMPTY_ENTRY {
+            let (meta, name) = self
+                .romfs
+                .get_directory(*cur_dir)
+                .ok_or(rc::ResultInvalidRomFsHeader::make())?;
+            *cur_dir = meta.sibling;
+            return Ok(Some(fs::DirectoryEntry {
+                name: fsp_sf::Path::from_str(name),
+                entry_type: fs::DirectoryEntryType::Directory,
+                ..Default::default()
+            }));
+        }
+
+        if *cur_file != EMPTY_ENTRY {
+            let (meta, name) = self
+                .romfs
+                .get_file(*cur_file)
+                .ok_or(rc::ResultInvalidRomFsHeader::make())?;
+            *cur_file = meta.sibling;
+            return Ok(Some(fs::DirectoryEntry {
+                name: fsp_sf::Path::from_str(name),
+                entry_type: fs::DirectoryEntryType::File,
+                file_size: match self.mode.contains(fs::DirectoryOpenMode::NoFileSizes()) {
+                    true => 0,
+                    false => meta.data_size as usize,
+                },
+                ..Default::default()
+            }));
+        }
+
+        Ok(None)
+    }
+}
+
+impl fs::Directory for RomFsDirectory {
+    fn read(&self, out_entries: &mut [fs::DirectoryEntry]) -> Result<usize> {
+        let mut cur_offsets = self.cur_offsets.lock();
+        let mut read_count = 0;
+        for out_entry in out_entries.iter_mut() {
+            match self.read_next_entry(&mut cur_offsets)? {
+                Some(entry) => *out_entry = entry,
+                None => break,
+            }
+            read_count += 1;
+        }
+
+        Ok(read_count)
+    }
+
+    fn get_entry_count(&self) -> Result<u64> {
+        let mut entry_count = 0;
+
+        if self.mode.contains(fs::DirectoryOpenMode::ReadDirectories()) {
+            let mut cur_dir = self.dir_meta.child_dir;
+            while cur_dir != EMPTY_ENTRY {
+                // Malformed images could have cyclic sibling chains
+                result_return_if!(
+                    entry_count >= self.romfs.get_max_directory_count(),
+                    rc::ResultInvalidRomFsHeader
+                );
+                let (meta, _) = self
+                    .romfs
+                    .get_directory(cur_dir)
+                    .ok_or(rc::ResultInvalidRomFsHeader::make())?;
+                cur_dir = meta.sibling;
+                entry_count += 1;
+            }
+        }
+
+        if self.mode.contains(fs::DirectoryOpenMode::ReadFiles()) {
+            let mut file_count = 0;
+            let mut cur_file = self.dir_meta.child_file;
+            while cur_file != EMPTY_ENTRY {
+                result_return_if!(
+                    file_count >= self.romfs.get_max_file_count(),
+                    rc::ResultInvalidRomFsHeader
+                );
+                let (meta, _) = self
+                    .romfs
+                    .get_file(cur_file)
+                    .ok_or(rc::ResultInvalidRomFsHeader::make())?;
+                cur_file = meta.sibling;
+                file_count += 1;
+            }
+            entry_count += file_count;
+        }
+
+        Ok(entry_count as u64)
+    }
+}
+
+/// Represents a read-only [`FileSystem`][`fs::FileSystem`] over a RomFS image
+pub struct RomFsFileSystem {
+    base: Arc<Mutex<Box<dyn fs::File>>>,
+    romfs: Arc<RomFs>,
+}
+
+impl RomFsFileSystem {
+    /// Creates a new [`RomFsFileSystem`], reading the RomFS metadata from the given [`File`][`fs::File`]
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: The [`File`][`fs::File`] containing the RomFS image
+    pub fn new(mut file: Box<dyn fs::File>) -> Result<Self> {
+        let romfs = RomFs::read(file.as_mut())?;
+        Ok(Self {
+            base: Arc::new(Mutex::new(file)),
+            romfs: Arc::new(romfs),
+        })
+    }
+
+    /// Creates a new [`RomFsFileSystem`] from the RomFS image in the asset section of a NRO
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: The [`File`][`fs::File`] containing the NRO
+    pub fn from_nro(mut file: Box<dyn fs::File>) -> Result<Self> {
+        let nro = nro::Nro::read(file.as_mut())?;
+        let (romfs_offset, romfs_size) = nro
+            .get_romfs_region()
+            .ok_or(crate::elf::rc::ResultAssetNotFound::make())?;
+
+        let romfs_file = pfs0::Pfs0File::new(Arc::new(Mutex::new(file)), romfs_offset, romfs_size);
+        Self::new(Box::new(romfs_file))
+    }
+
+    /// Gets the parsed RomFS metadata
+    #[inline]
+    pub fn get_romfs(&self) -> &RomFs {
+        &self.romfs
+    }
+}
+
+impl fs::FileSystem for RomFsFileSystem {
+    fn create_file(&self, _path: &str, _attribute: fs::FileAttribute, _size: usize) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn remove_file(&self, _path: &str) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn create_directory(&self, _path: &str) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn remove_dir(&self, _path: &str) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn remove_dir_all(&self, _path: &str) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn rename_file(&self, _old_path: &str, _new_path: &str) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn rename_directory(&self, _old_path: &str, _new_path: &str) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn get_entry_type(&self, path: &str) -> Result<fs::DirectoryEntryType> {
+        if self.romfs.resolve_directory(path).is_some() {
+            Ok(fs::DirectoryEntryType::Directory)
+        } else if self.romfs.resolve_file(path).is_some() {
+            Ok(fs::DirectoryEntryType::File)
+        } else {
+            fsp_sf::rc::ResultPathNotFound::make_err()
+        }
+    }
+
+    fn open_file(&self, path: &str, mode: fs::FileOpenMode) -> Result<Box<dyn fs::File>> {
+        result_return_if!(
+            mode.contains(fs::FileOpenMode::Write()) || mode.contains(fs::FileOpenMode::Append()),
+            rc::ResultReadOnlyFileSystem
+        );
+
+        let (meta, _) = self
+            .romfs
+            .resolve_file(path)
+            .and_then(|offset| self.romfs.get_file(offset))
+            .ok_or(fsp_sf::rc::ResultPathNotFound::make())?;
+        let (offset, size) = self
+            .romfs
+            .get_file_data_region(&meta)
+            .ok_or(rc::ResultInvalidRomFsHeader::make())?;
+        Ok(Box::new(pfs0::Pfs0File::new(
+            self.base.clone(),
+            offset,
+            size,
+        )))
+    }
+
+    fn open_directory(
+        &self,
+        path: &str,
+        mode: fs::DirectoryOpenMode,
+    ) -> Result<Box<dyn fs::Directory>> {
+        let (meta, _) = self
+            .romfs
+            .resolve_directory(path)
+            .and_then(|offset| self.romfs.get_directory(offset))
+            .ok_or(fsp_sf::rc::ResultPathNotFound::make())?;
+        Ok(Box::new(RomFsDirectory::new(
+            self.romfs.clone(),
+            mode,
+            meta,
+        )))
+    }
+
+    fn commit(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_free_space_size(&self, _path: &str) -> Result<usize> {
+        Ok(0)
+    }
+
+    fn get_total_space_size(&self, _path: &str) -> Result<usize> {
+        self.romfs
+            .get_image_size()
+            .ok_or(rc::ResultInvalidRomFsHeader::make())
+    }
+
+    fn remove_children_all(&self, _path: &str) -> Result<()> {
+        rc::ResultReadOnlyFileSystem::make_err()
+    }
+
+    fn get_file_time_stamp_raw(&self, _path: &str) -> Result<fs::FileTimeStampRaw> {
+        crate::rc::ResultNotSupported::make_err()
+    }
+
+    fn query_entry(
+        &self,
+        _path: &str,
+        _query_id: fs::QueryId,
+        _in_buf: &[u8],
+        _out_buf: &mut [u8],
+    ) -> Result<()> {
+        crate::rc::ResultNotSupported::make_err()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::pfs0::tests::{MemoryFile, push_raw};
+    use alloc::string::String;
+
+    /// Builds a RomFS image with a root directory containing a single file `a`, whose hash and sibling chains point back to itself
+    fn make_cyclic_image() -> Vec<u8> {
+        let dir_meta_table_offset = mem::size_of::<Header>() + 4;
+        let file_meta_table_offset = dir_meta_table_offset + mem::size_of::<DirectoryMeta>() + 4;
+        let file_meta_table_size = mem::size_of::<FileMeta>() + 4;
+
+        let mut image = Vec::new();
+        push_raw(
+            &mut image,
+            &Header {
+                header_size: mem::size_of::<Header>() as u64,
+                dir_hash_table_offset: mem::size_of::<Header>() as u64,
+                dir_hash_table_size: 4,
+                dir_meta_table_offset: dir_meta_table_offset as u64,
+                dir_meta_table_size: mem::size_of::<DirectoryMeta>() as u64,
+                file_hash_table_offset: (file_meta_table_offset - 4) as u64,
+                file_hash_table_size: 4,
+                file_meta_table_offset: file_meta_table_offset as u64,
+                file_meta_table_size: file_meta_table_size as u64,
+                file_data_offset: (file_meta_table_offset + file_meta_table_size) as u64,
+            },
+        );
+        image.extend_from_slice(&ROOT_DIRECTORY_OFFSET.to_le_bytes());
+        push_raw(
+            &mut image,
+            &DirectoryMeta {
+                parent: ROOT_DIRECTORY_OFFSET,
+                sibling: EMPTY_ENTRY,
+                child_dir: EMPTY_ENTRY,
+                child_file: 0,
+                next_hash: EMPTY_ENTRY,
+                name_len: 0,
+            },
+        );
+        image.extend_from_slice(&0u32.to_le_bytes());
+        push_raw(
+            &mut image,
+            &FileMeta {
+                parent: ROOT_DIRECTORY_OFFSET,
+                sibling: 0,
+                data_offset: 0,
+                data_size: 0,
+                next_hash: 0,
+                name_len: 1,
+            },
+        );
+        image.extend_from_slice(b"a\0\0\0");
+        image
+    }
+
+    /// Builds a RomFS image with the given directories (listed after their parents) and files, using small hash tables so that buckets get chained
+    fn make_image(dirs: &[&str], files: &[(&str, &[u8])]) -> Vec<u8> {
+        const DIR_BUCKET_COUNT: usize = 3;
+        const FILE_BUCKET_COUNT: usize = 5;
+        fn split_path(path: &str) -> (&str, &str) {
+            path.rsplit_once('/').unwrap_or(("", path))
+        }
+        let entry_size = |meta_size: usize, name: &str| meta_size + name.len().next_multiple_of(4);
+
+        let mut dir_paths = vec![""];
+        dir_paths.extend_from_slice(dirs);
+        let dir_index = |path: &str| dir_paths.iter().position(|dir| *dir == path).unwrap();
+
+        let mut dir_offsets = Vec::new();
+        let mut dir_meta_table_size = 0;
+        for dir in &dir_paths {
+            dir_offsets.push(dir_meta_table_size as u32);
+            dir_meta_table_size += entry_size(mem::size_of::<DirectoryMeta>(), split_path(dir).1);
+        }
+        let mut file_offsets = Vec::new();
+        let mut file_meta_table_size = 0;
+        for (path, _) in files {
+            file_offsets.push(file_meta_table_size as u32);
+            file_meta_table_size += entry_size(mem::size_of::<FileMeta>(), split_path(path).1);
+        }
+
+        let mut dir_metas: Vec<_> = dir_paths
+            .iter()
+            .map(|dir| DirectoryMeta {
+                parent: dir_offsets[dir_index(split_path(dir).0)],
+                sibling: EMPTY_ENTRY,
+                child_dir: EMPTY_ENTRY,
+                child_file: EMPTY_ENTRY,
+                next_hash: EMPTY_ENTRY,
+                name_len: split_path(dir).1.len() as u32,
+            })
+            .collect();
+        let mut file_data = Vec::new();
+        let mut file_metas: Vec<_> = files
+            .iter()
+            .map(|(path, contents)| {
+                let data_offset = file_data.len() as u64;
+                file_data.extend_from_slice(contents);
+                file_data.resize(file_data.len().next_multiple_of(0x10), 0);
+                FileMeta {
+                    parent: dir_offsets[dir_index(split_path(path).0)],
+                    sibling: EMPTY_ENTRY,
+                    data_offset,
+                    data_size: contents.len() as u64,
+                    next_hash: EMPTY_ENTRY,
+                    name_len: split_path(path).1.len() as u32,
+                }
+            })
+            .collect();
+
+        // Children are linked in reverse so that the sibling chains keep the given order
+        for i in (1..dir_paths.len()).rev() {
+            let parent = dir_index(split_path(dir_paths[i]).0);
+            dir_metas[i].sibling = dir_metas[parent].child_dir;
+            dir_metas[parent].child_dir = dir_offsets[i];
+        }
+        for i in (0..files.len()).rev() {
+            let parent = dir_index(split_path(files[i].0).0);
+            file_metas[i].sibling = dir_metas[parent].child_file;
+            dir_metas[parent].child_file = file_offsets[i];
+        }
+
+        let mut dir_hash_table = [EMPTY_ENTRY; DIR_BUCKET_COUNT];
+        for (i, meta) in dir_metas.iter_mut().enumerate() {
+            let bucket = compute_hash(
+                meta.parent,
+                split_path(dir_paths[i]).1.as_bytes(),
+                DIR_BUCKET_COUNT,
+            );
+            meta.next_hash = dir_hash_table[bucket];
+            dir_hash_table[bucket] = dir_offsets[i];
+        }
+        let mut file_hash_table = [EMPTY_ENTRY; FILE_BUCKET_COUNT];
+        for (i, meta) in file_metas.iter_mut().enumerate() {
+            let bucket = compute_hash(
+                meta.parent,
+                split_path(files[i].0).1.as_bytes(),
+                FILE_BUCKET_COUNT,
+            );
+            meta.next_hash = file_hash_table[bucket];
+            file_hash_table[bucket] = file_offsets[i];
+        }
+
+        // Usual layout: header, file data and then the metadata tables
+        let file_data_offset = mem::size_of::<Header>().next_multiple_of(0x10);
+        let dir_hash_table_offset = file_data_offset + file_data.len();
+        let dir_meta_table_offset = dir_hash_table_offset + DIR_BUCKET_COUNT * 4;
+        let file_hash_table_offset = dir_meta_table_offset + dir_meta_table_size;
+        let file_meta_table_offset = file_hash_table_offset + FILE_BUCKET_COUNT * 4;
+
+        let mut image = Vec::new();
+        push_raw(
+            &mut image,
+            &Header {
+                header_size: mem::size_of::<Header>() as u64,
+                dir_hash_table_offset: dir_hash_table_offset as u64,
+                dir_hash_table_size: (DIR_BUCKET_COUNT * 4) as u64,
+                dir_meta_table_offset: dir_meta_table_offset as u64,
+                dir_meta_table_size: dir_meta_table_size as u64,
+                file_hash_table_offset: file_hash_table_offset as u64,
+                file_hash_table_size: (FILE_BUCKET_COUNT * 4) as u64,
+                file_meta_table_offset: file_meta_table_offset as u64,
+                file_meta_table_size: file_meta_table_size as u64,
+                file_data_offset: file_data_offset as u64,
+            },
+        );
+        image.resize(file_data_offset, 0);
+        image.extend_from_slice(&file_data);
+        for bucket in dir_hash_table {
+            image.extend_from_slice(&bucket.to_le_bytes());
+        }
+        for (meta, dir) in dir_metas.iter().zip(&dir_paths) {
+            push_raw(&mut image, meta);
+            image.extend_from_slice(split_path(dir).1.as_bytes());
+            image.resize(image.len().next_multiple_of(4), 0);
+        }
+        for bucket in file_hash_table {
+            image.extend_from_slice(&bucket.to_le_bytes());
+        }
+        for (meta, (path, _)) in file_metas.iter().zip(files) {
+            push_raw(&mut image, meta);
+            image.extend_from_slice(split_path(path).1.as_bytes());
+            image.resize(image.len().next_multiple_of(4), 0);
+        }
+        image
+    }
+
+    fn make_nested_filesystem() -> RomFsFileSystem {
+        let image = make_image(
+            &["assets", "data", "assets/fonts", "assets/images"],
+            &[
+                ("readme.txt", b"hello romfs"),
+                ("assets/fonts/main.ttf", b"not really a font"),
+                ("assets/images/logo.png", b"logo"),
+                ("assets/images/icon.png", b"icon data"),
+                ("data/empty", b""),
+            ],
+        );
+        RomFsFileSystem::new(Box::new(MemoryFile(image))).unwrap()
+    }
+
+    fn read_all(fs: &RomFsFileSystem, path: &str) -> Vec<u8> {
+        let mut file = fs::FileSystem::open_file(fs, path, fs::FileOpenMode::Read()).unwrap();
+        let mut data = vec![0u8; file.get_size().unwrap()];
+        fs::read_file_exact(file.as_mut(), 0, &mut data).unwrap();
+        data
+    }
+
+    fn read_directory(
+        fs: &RomFsFileSystem,
+        path: &str,
+        mode: fs::DirectoryOpenMode,
+    ) -> Vec<(String, fs::DirectoryEntryType, usize)> {
+        let dir = fs::FileSystem::open_directory(fs, path, mode).unwrap();
+        let entry_count = dir.get_entry_count().unwrap() as usize;
+
+        // Read in small chunks to check that reading resumes where it stopped
+        let mut entries = Vec::new();
+        let mut chunk = [fs::DirectoryEntry::default(); 2];
+        loop {
+            let read_count = dir.read(&mut chunk).unwrap();
+            if read_count == 0 {
+                break;
+            }
+            entries.extend(chunk[..read_count].iter().map(|entry| {
+                (
+                    String::from(entry.name.get_str().unwrap()),
+                    entry.entry_type,
+                    entry.file_size,
+                )
+            }));
+        }
+        assert_eq!(entries.len(), entry_count);
+        entries
+    }
+
+    #[test]
+    fn open_nested_files() {
+        let fs = make_nested_filesystem();
+        assert_eq!(read_all(&fs, "/readme.txt"), b"hello romfs");
+        assert_eq!(read_all(&fs, "assets/fonts/main.ttf"), b"not really a font");
+        assert_eq!(read_all(&fs, "/assets/images/logo.png"), b"logo");
+        assert_eq!(read_all(&fs, "/assets/images/icon.png"), b"icon data");
+        assert_eq!(read_all(&fs, "/data/empty"), b"");
+
+        // Reads are bounded by the file data region
+        let mut file =
+            fs::FileSystem::open_file(&fs, "/assets/images/logo.png", fs::FileOpenMode::Read())
+                .unwrap();
+        let mut data = [0u8; 0x10];
+        let read_size = file.read(1, &mut data, fs::FileReadOption::None()).unwrap();
+        assert_eq!(&data[..read_size], b"ogo");
+
+        for path in [
+            "/missing",
+            "/assets/main.ttf",
+            "/assets/fonts",
+            "/data/empty/file",
+        ] {
+            let rc = fs::FileSystem::open_file(&fs, path, fs::FileOpenMode::Read())
+                .err()
+                .unwrap();
+            assert!(fsp_sf::rc::ResultPathNotFound::matches(rc));
+        }
+        let rc = fs::FileSystem::open_file(&fs, "/readme.txt", fs::FileOpenMode::Write())
+            .err()
+            .unwrap();
+        assert!(rc::ResultReadOnlyFileSystem::matches(rc));
+
+        assert_eq!(
+            fs::FileSystem::get_entry_type(&fs, "/assets/fonts").unwrap(),
+            fs::DirectoryEntryType::Directory
+        );
+        assert_eq!(
+            fs::FileSystem::get_entry_type(&fs, "/assets/fonts/main.ttf").unwrap(),
+            fs::DirectoryEntryType::File
+        );
+        let rc = fs::FileSystem::get_entry_type(&fs, "/assets/sounds").unwrap_err();
+        assert!(fsp_sf::rc::ResultPathNotFound::matches(rc));
+    }
+
+    #[test]
+    fn read_nested_directories() {
+        use fs::DirectoryEntryType::{Directory, File};
+        let fs = make_nested_filesystem();
+        let all = fs::DirectoryOpenMode::ReadDirectories() | fs::DirectoryOpenMode::ReadFiles();
+
+        assert_eq!(
+            read_directory(&fs, "/", all),
+            [
+                ("assets".into(), Directory, 0),
+                ("data".into(), Directory, 0),
+                ("readme.txt".into(), File, 11),
+            ]
+        );
+        assert_eq!(
+            read_directory(&fs, "/assets", all),
+            [
+                ("fonts".into(), Directory, 0),
+                ("images".into(), Directory, 0)
+            ]
+        );
+        assert_eq!(
+            read_directory(&fs, "/assets/images", all),
+            [("logo.png".into(), File, 4), ("icon.png".into(), File, 9)]
+        );
+        assert_eq!(
+            read_directory(&fs, "/data", all),
+            [("empty".into(), File, 0)]
+        );
+
+        // Open mode filters
+        assert_eq!(
+            read_directory(&fs, "/", fs::DirectoryOpenMode::ReadDirectories()),
+            [
+                ("assets".into(), Directory, 0),
+                ("data".into(), Directory, 0)
+            ]
+        );
+        assert_eq!(
+            read_directory(&fs, "/", fs::DirectoryOpenMode::ReadFiles()),
+            [("readme.txt".into(), File, 11)]
+        );
+        assert_eq!(
+            read_directory(
+                &fs,
+                "/assets/images",
+                fs::DirectoryOpenMode::ReadFiles() | fs::DirectoryOpenMode::NoFileSizes()
+            ),
+            [("logo.png".into(), File, 0), ("icon.png".into(), File, 0)]
+        );
+
+        let rc = fs::FileSystem::open_directory(&fs, "/readme.txt", all)
+            .err()
+            .unwrap();
+        assert!(fsp_sf::rc::ResultPathNotFound::matches(rc));
+    }
+
+    #[test]
+    fn walk_nested_directories() {
+        fn walk(fs: &RomFsFileSystem, path: &str, out_files: &mut Vec<(String, Vec<u8>)>) {
+            let mode =
+                fs::DirectoryOpenMode::ReadDirectories() | fs::DirectoryOpenMode::ReadFiles();
+            for (name, entry_type, file_size) in read_directory(fs, path, mode) {
+                let entry_path = format!("{}/{}", path.trim_end_matches('/'), name);
+                match entry_type {
+                    fs::DirectoryEntryType::Directory => walk(fs, &entry_path, out_files),
+                    fs::DirectoryEntryType::File => {
+                        let data = read_all(fs, &entry_path);
+                        assert_eq!(data.len(), file_size);
+                        out_files.push((entry_path, data));
+                    }
+                }
+            }
+        }
+
+        let fs = make_nested_filesystem();
+        let mut files = Vec::new();
+        walk(&fs, "/", &mut files);
+        assert_eq!(
+            files,
+            [
+                (
+                    "/assets/fonts/main.ttf".into(),
+                    b"not really a font".to_vec()
+                ),
+                ("/assets/images/logo.png".into(), b"logo".to_vec()),
+                ("/assets/images/icon.png".into(), b"icon data".to_vec()),
+                ("/data/empty".into(), Vec::new()),
+                ("/readme.txt".into(), b"hello romfs".to_vec()),
+            ]
+        );
+        assert_eq!(
+            fs::FileSystem::get_total_space_size(&fs, "/").unwrap(),
+            fs.get_romfs().get_image_size().unwrap()
+        );
+    }
+
+    #[test]
+    fn find_file_cyclic_hash_chain() {
+        let romfs = RomFs::parse(&make_cyclic_image()).unwrap();
+        assert_eq!(romfs.resolve_file("a"), Some(0));
+        assert_eq!(romfs.resolve_file("/a"), Some(0));
+        assert_eq!(romfs.resolve_file("b"), None);
+        assert_eq!(romfs.resolve_directory("dir"), None);
+    }
+
+    #[test]
+    fn read_directory_cyclic_sibling_chain() {
+        let romfs = Arc::new(RomFs::parse(&make_cyclic_image()).unwrap());
+        let (root_meta, _) = romfs.get_directory(ROOT_DIRECTORY_OFFSET).unwrap();
+        let dir = RomFsDirectory::new(romfs, fs::DirectoryOpenMode::ReadFiles(), root_meta);
+
+        let rc = fs::Directory::get_entry_count(&dir).unwrap_err();
+        assert!(rc::ResultInvalidRomFsHeader::matches(rc));
+
+        // Reading stops once more entries than the tables could hold are read
+        let mut cur_offsets = (EMPTY_ENTRY, root_meta.child_file, 0);
+        let max_entry_count = 2;
+        for _ in 0..max_entry_count {
+            assert!(dir.read_next_entry(&mut cur_offsets).unwrap().is_some());
+        }
+        let rc = dir.read_next_entry(&mut cur_offsets).unwrap_err();
+        assert!(rc::ResultInvalidRomFsHeader::matches(rc));
+    }
+
+    #[test]
+    fn image_size_overflow() {
+        let mut image = make_cyclic_image();
+        let image_size = image.len();
+        let romfs = RomFs::parse(&image).unwrap();
+        assert_eq!(romfs.get_image_size(), Some(image_size));
+
+        // Overflowing file data offset
+        image[0x48..0x50].copy_from_slice(&u64::MAX.to_le_bytes());
+        let romfs = RomFs::parse(&image).unwrap();
+        assert_eq!(romfs.get_image_size(), usize::try_from(u64::MAX).ok());
+
+        // Overflowing table offset + size
+        image[0x30..0x38].copy_from_slice(&u64::MAX.to_le_bytes());
+        let romfs = RomFs {
+            header: RomFs::read_header(&image).unwrap(),
+            ..romfs
+        };
+        assert_eq!(romfs.get_image_size(), None);
+    }
+}
@@ -2,6 +2,7 @@ use core::marker::PhantomData;
 use core::ops::Mul;
 
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use bitfield_struct::bitfield;
 //use num_traits::float::Float;
 use sealed::CanvasColorFormat;
@@ -16,9 +17,23 @@ use crate::{ipc::sf::AppletResourceUserId, service::vi::LayerFlags};
 use super::surface::{ScaleMode, Surface};
 use super::{BlockLinearHeights, ColorFormat, Context, LayerZ, MultiFence, PixelFormat};
 
+pub mod image;
+pub use image::Image;
+
+pub mod vector;
+pub use vector::{Paint, Path, Point, StrokeStyle};
+
 #[cfg(feature = "truetype")]
 pub type Font<'a> = ab_glyph::FontRef<'a>;
 
+#[cfg(feature = "truetype")]
+pub mod text;
+#[cfg(feature = "truetype")]
+pub use text::{FontCollection, GlyphCache, TextLayout, TextOptions};
+
+#[cfg(feature = "truetype")]
+pub mod shared_font;
+
 #[derive(Debug, Clone, Copy, Default)]
 pub enum AlphaBlend {
     None,
@@ -104,6 +119,17 @@ impl sealed::CanvasColorFormat for RGBA4 {
         let new_alpha = alpha * self.a() as f32;
         self.with_a(new_alpha as u8)
     }
+
+    /// Scales the 4-bit channels up to the range of 8-bit channels (`0xF` becomes `0xFF`)
+    #[inline]
+    fn to_scaled(self) -> (u8, u8, u8, u8) {
+        (
+            self.r() * 0x11,
+            self.g() * 0x11,
+            self.b() * 0x11,
+            self.a() * 0x11,
+        )
+    }
 }
 
 /// RGBA (8888) Pixel/Color Representation
@@ -179,6 +205,10 @@ impl sealed::CanvasColorFormat for RGBA8 {
         let new_alpha = alpha * self.a() as f32;
         self.with_a(new_alpha as u8)
     }
+
+    fn to_scaled(self) -> (u8, u8, u8, u8) {
+        (self.r(), self.g(), self.b(), self.a())
+    }
 }
 
 pub(crate) mod sealed {
@@ -210,6 +240,8 @@ pub(crate) mod sealed {
         ///
         /// `alpha` must be between 0 and 1, or the implementation should panic.
         fn scale_alpha(self, alpha: f32) -> Self;
+        /// Get the color channels (`(r, g, b, a)`) scaled to standard 8-bit values
+        fn to_scaled(self) -> (u8, u8, u8, u8);
     }
 }
 
@@ -442,9 +474,17 @@ pub trait Canvas {
         }
     }
 
-    /// Draw a straight line from the start co-ordinates to the end co-ordinates
+    /// Draw an anti-aliased straight line from the start co-ordinates to the end co-ordinates, with round ends
     ///
     /// Start/end co-ordinates may be outside the screen, but out-of-bounds pixel co-ordinates should be ignored by the implementation.
+    ///
+    /// # Arguments
+    ///
+    /// * `start`: The start co-ordinates (pixel centre)
+    /// * `end`: The end co-ordinates (pixel centre)
+    /// * `width`: The radius of the round brush the line is drawn with, in pixels (so the line is `2 * width` pixels thick)
+    /// * `color`: The line color
+    /// * `blend`: The blend mode
     fn draw_line(
         &mut self,
         start: (i32, i32),
@@ -453,14 +493,20 @@ pub trait Canvas {
         color: Self::ColorFormat,
         blend: AlphaBlend,
     ) {
-        for (x, y) in line_drawing::Bresenham::new(start, end) {
-            // TODO - fix this stupid algorithm
-            self.draw_circle_filled(x, y, width, color, blend)
-        }
+        let mut path = Path::new();
+        path.move_to(start.0 as f32 + 0.5, start.1 as f32 + 0.5)
+            .line_to(end.0 as f32 + 0.5, end.1 as f32 + 0.5);
+        self.stroke_path(
+            &path,
+            &StrokeStyle::new(2.0 * width as f32).with_cap(vector::LineCap::Round),
+            &Paint::Solid(color),
+            blend,
+        );
     }
 
-    /// Draw an empty circle from the centre co-ordinates and radius.
+    /// Draw an anti-aliased empty circle from the centre co-ordinates and radius.
     ///
+    /// The outline is centred on the radius, and is `2 * line_width` pixels thick (`line_width` being the radius of the brush it's drawn with).
     /// The shape does not need to be fully in-bounds, but out-of-bounds pixel co-ordinates should be ignored by the implementation.
     fn draw_circle(
         &mut self,
@@ -471,10 +517,168 @@ pub trait Canvas {
         color: Self::ColorFormat,
         blend: AlphaBlend,
     ) {
-        for (x, y) in line_drawing::BresenhamCircle::new(x, y, r as i32) {
-            // TODO - fix this stupid algorithm
-            self.draw_circle_filled(x, y, line_width, color, blend);
-        }
+        let mut path = Path::new();
+        path.circle(x as f32 + 0.5, y as f32 + 0.5, r as f32);
+        self.stroke_path(
+            &path,
+            &StrokeStyle::new(2.0 * line_width as f32),
+            &Paint::Solid(color),
+            blend,
+        );
+    }
+
+    /// Fill a path, using the given fill rule to decide which areas are inside it
+    ///
+    /// Subpaths are implicitly closed. Edges are anti-aliased, except with [`AlphaBlend::None`] (which can't blend partially covered pixels).
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path to fill
+    /// * `fill_rule`: The fill rule
+    /// * `paint`: The solid color or gradient to fill with
+    /// * `blend`: The blend mode
+    fn fill_path(
+        &mut self,
+        path: &Path,
+        fill_rule: vector::FillRule,
+        paint: &Paint<Self::ColorFormat>,
+        blend: AlphaBlend,
+    ) {
+        let mut rasterizer = vector::Rasterizer::new(self.width(), self.height());
+        rasterizer.add_path_fill(path);
+        vector::draw_rasterized(self, rasterizer, fill_rule, paint, blend);
+    }
+
+    /// Stroke the outline of a path
+    ///
+    /// Edges are anti-aliased, except with [`AlphaBlend::None`] (which can't blend partially covered pixels).
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: The path to stroke
+    /// * `style`: The line width, caps and joins
+    /// * `paint`: The solid color or gradient to stroke with
+    /// * `blend`: The blend mode
+    fn stroke_path(
+        &mut self,
+        path: &Path,
+        style: &StrokeStyle,
+        paint: &Paint<Self::ColorFormat>,
+        blend: AlphaBlend,
+    ) {
+        let mut rasterizer = vector::Rasterizer::new(self.width(), self.height());
+        rasterizer.add_path_stroke(path, style);
+        vector::draw_rasterized(self, rasterizer, vector::FillRule::NonZero, paint, blend);
+    }
+
+    /// Draw an anti-aliased filled polygon
+    ///
+    /// The polygon does not need to be convex or fully in-bounds, self-intersecting polygons are filled with the non-zero rule.
+    fn fill_polygon(&mut self, points: &[Point], color: Self::ColorFormat, blend: AlphaBlend) {
+        let mut path = Path::new();
+        path.polygon(points);
+        self.fill_path(
+            &path,
+            vector::FillRule::NonZero,
+            &Paint::Solid(color),
+            blend,
+        );
+    }
+
+    /// Draw the anti-aliased outline of a polygon, with mitered corners
+    fn draw_polygon(
+        &mut self,
+        points: &[Point],
+        line_width: f32,
+        color: Self::ColorFormat,
+        blend: AlphaBlend,
+    ) {
+        let mut path = Path::new();
+        path.polygon(points);
+        self.stroke_path(
+            &path,
+            &StrokeStyle::new(line_width),
+            &Paint::Solid(color),
+            blend,
+        );
+    }
+
+    /// Draw an anti-aliased filled rectangle with rounded corners
+    ///
+    /// The corner radius is limited to half the rectangle size.
+    fn fill_rounded_rect(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        radius: f32,
+        color: Self::ColorFormat,
+        blend: AlphaBlend,
+    ) {
+        let mut path = Path::new();
+        path.rounded_rect(x as f32, y as f32, width as f32, height as f32, radius);
+        self.fill_path(
+            &path,
+            vector::FillRule::NonZero,
+            &Paint::Solid(color),
+            blend,
+        );
+    }
+
+    /// Draw the anti-aliased outline of a rectangle with rounded corners
+    ///
+    /// The outline is drawn inside the rectangle bounds.
+    fn draw_rounded_rect(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        radius: f32,
+        line_width: f32,
+        color: Self::ColorFormat,
+        blend: AlphaBlend,
+    ) {
+        let inset = line_width / 2.0;
+        let mut path = Path::new();
+        path.rounded_rect(
+            x as f32 + inset,
+            y as f32 + inset,
+            width as f32 - line_width,
+            height as f32 - line_width,
+            radius - inset,
+        );
+        self.stroke_path(
+            &path,
+            &StrokeStyle::new(line_width),
+            &Paint::Solid(color),
+            blend,
+        );
+    }
+
+    /// Draw an anti-aliased circular arc from the centre co-ordinates and radius
+    ///
+    /// Angles are in radians, going clockwise from the positive X axis.
+    fn draw_arc(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        r: f32,
+        start_angle: f32,
+        end_angle: f32,
+        line_width: f32,
+        color: Self::ColorFormat,
+        blend: AlphaBlend,
+    ) {
+        let mut path = Path::new();
+        path.arc(cx, cy, r, start_angle, end_angle);
+        self.stroke_path(
+            &path,
+            &StrokeStyle::new(line_width),
+            &Paint::Solid(color),
+            blend,
+        );
     }
 
     /// Draw an filled circle from the centre co-ordinates and radius.
@@ -503,6 +707,110 @@ pub trait Canvas {
         }
     }
 
+    /// Draw an image to the canvas, with its top-left corner at the given co-ordinates.
+    ///
+    /// The options allow drawing a region of the image, scaling, flipping and choosing the blend mode.
+    /// The image does not need to be fully in-bounds, but out-of-bounds pixel co-ordinates should be ignored by the implementation.
+    fn draw_image(&mut self, image: &Image, x: i32, y: i32, options: &image::ImageDrawOptions) {
+        let source =
+            options
+                .source
+                .unwrap_or(image::ImageRect::new(0, 0, image.width(), image.height()));
+        // Clip the source region to the image bounds
+        let src_x = source.x.min(image.width());
+        let src_y = source.y.min(image.height());
+        let source = image::ImageRect::new(
+            src_x,
+            src_y,
+            source.width.min(image.width() - src_x),
+            source.height.min(image.height() - src_y),
+        );
+        let (width, height) = options.size.unwrap_or((source.width, source.height));
+        if source.width == 0 || source.height == 0 || width == 0 || height == 0 {
+            return;
+        }
+
+        let s_width = self.width() as i32;
+        let s_height = self.height() as i32;
+        let x0 = x.clamp(0, s_width);
+        let x1 = x.saturating_add_unsigned(width).clamp(0, s_width);
+        let y0 = y.clamp(0, s_height);
+        let y1 = y.saturating_add_unsigned(height).clamp(0, s_height);
+        for dst_y in y0..y1 {
+            let mut offset_y = (dst_y - y) as u64;
+            if options.flip_vertical {
+                offset_y = height as u64 - 1 - offset_y;
+            }
+            for dst_x in x0..x1 {
+                let mut offset_x = (dst_x - x) as u64;
+                if options.flip_horizontal {
+                    offset_x = width as u64 - 1 - offset_x;
+                }
+
+                let pixel = match options.filter {
+                    image::ScaleFilter::Nearest => {
+                        let src_x =
+                            source.x + (offset_x * source.width as u64 / width as u64) as u32;
+                        let src_y =
+                            source.y + (offset_y * source.height as u64 / height as u64) as u32;
+                        image.pixels()[(src_y * image.width() + src_x) as usize]
+                    }
+                    image::ScaleFilter::Bilinear => {
+                        // Map the destination pixel centre to the source region, in 16.16 fixed point
+                        let fx = (((2 * offset_x + 1) * source.width as u64) << 15) / width as u64;
+                        let fy =
+                            (((2 * offset_y + 1) * source.height as u64) << 15) / height as u64;
+                        image.sample_bilinear(&source, fx as i64, fy as i64)
+                    }
+                };
+
+                // Fully transparent pixels can't affect blended output
+                if pixel.a() == 0 && !matches!(options.blend, AlphaBlend::None) {
+                    continue;
+                }
+                self.draw_single(
+                    dst_x,
+                    dst_y,
+                    Self::ColorFormat::new_scaled(pixel.r(), pixel.g(), pixel.b(), pixel.a()),
+                    options.blend,
+                );
+            }
+        }
+    }
+
+    /// Draw the contents of an offscreen canvas to the canvas, with its top-left corner at the given co-ordinates.
+    ///
+    /// The source canvas may use a different color format. To scale or flip it, convert it with [`OffscreenCanvas::to_image`] and use [`Canvas::draw_image`] instead.
+    /// The canvas does not need to be fully in-bounds, but out-of-bounds pixel co-ordinates should be ignored by the implementation.
+    fn draw_canvas<SourceColorFormat: sealed::CanvasColorFormat>(
+        &mut self,
+        canvas: &OffscreenCanvas<SourceColorFormat>,
+        x: i32,
+        y: i32,
+        blend: AlphaBlend,
+    ) {
+        let s_width = self.width() as i32;
+        let s_height = self.height() as i32;
+        let x0 = x.clamp(0, s_width);
+        let x1 = x.saturating_add_unsigned(canvas.width()).clamp(0, s_width);
+        let y0 = y.clamp(0, s_height);
+        let y1 = y
+            .saturating_add_unsigned(canvas.height())
+            .clamp(0, s_height);
+        for dst_y in y0..y1 {
+            for dst_x in x0..x1 {
+                let index = ((dst_y - y) as u32 * canvas.width() + (dst_x - x) as u32) as usize;
+                let (r, g, b, a) = canvas.pixels()[index].to_scaled();
+                self.draw_single(
+                    dst_x,
+                    dst_y,
+                    Self::ColorFormat::new_scaled(r, g, b, a),
+                    blend,
+                );
+            }
+        }
+    }
+
     /// Draw text in a true-type font to the canvas.
     ///
     /// The text does not need to be fully in-bounds, but out-of-bounds pixel co-ordinates should be ignored by the implementation.
@@ -573,6 +881,56 @@ pub trait Canvas {
         }
     }
 
+    /// Draw laid out text to the canvas, with the layout's top-left corner at the given co-ordinates.
+    ///
+    /// Glyphs are rasterized once into the glyph cache and reused in later draws.
+    /// Since the cache identifies fonts by their data address, it must be cleared after a font it was used with is freed, before using it with other fonts.
+    /// The text does not need to be fully in-bounds, but out-of-bounds pixel co-ordinates should be ignored by the implementation.
+    ///
+    /// # Arguments
+    ///
+    /// * `layout`: The text layout
+    /// * `fonts`: The fonts the layout was created with
+    /// * `cache`: The glyph cache
+    /// * `x`: The left co-ordinate
+    /// * `y`: The top co-ordinate
+    /// * `color`: The text color
+    /// * `blend`: The blend mode
+    #[cfg(feature = "truetype")]
+    fn draw_text_layout(
+        &mut self,
+        layout: &TextLayout,
+        fonts: &FontCollection,
+        cache: &mut GlyphCache,
+        x: i32,
+        y: i32,
+        color: Self::ColorFormat,
+        blend: AlphaBlend,
+    ) {
+        text::draw_layout(self, layout, fonts, cache, x, y, color, blend);
+    }
+
+    /// Lay out and draw text to the canvas, with its top-left corner at the given co-ordinates.
+    ///
+    /// Text which is drawn repeatedly can be laid out once with [`TextLayout::new`] and drawn with [`Canvas::draw_text_layout`] instead.
+    /// The glyph cache has the same requirements as with [`Canvas::draw_text_layout`].
+    /// The text does not need to be fully in-bounds, but out-of-bounds pixel co-ordinates should be ignored by the implementation.
+    #[cfg(feature = "truetype")]
+    fn draw_text(
+        &mut self,
+        fonts: &FontCollection,
+        cache: &mut GlyphCache,
+        text: impl AsRef<str>,
+        options: &TextOptions,
+        x: i32,
+        y: i32,
+        color: Self::ColorFormat,
+        blend: AlphaBlend,
+    ) {
+        let layout = TextLayout::new(fonts, text, options);
+        self.draw_text_layout(&layout, fonts, cache, x, y, color, blend);
+    }
+
     /// Draw text in a 8x8 monospace font to the canvas.
     ///
     /// The text does not need to be fully in-bounds, but out-of-bounds pixel co-ordinates should be ignored by the implementation.
@@ -885,3 +1243,187 @@ impl<ColorFormat: CanvasColorFormat> Drop for UnbufferedCanvas<'_, ColorFormat>
         let _ = self.manager.surface.wait_buffer_event(-1);
     }
 }
+
+/// A Canvas backed by a plain pixel buffer in memory, which doesn't need a GPU context or surface.
+///
+/// This can be used to render content off-screen (and draw it later with [`Canvas::draw_canvas`] or [`Canvas::draw_image`]),
+/// or to test drawing code outside of the console. A screen-sized canvas can also be presented with [`CanvasManager::render_prepared_buffer`].
+pub struct OffscreenCanvas<ColorFormat: sealed::CanvasColorFormat> {
+    width: u32,
+    height: u32,
+    pixels: Vec<ColorFormat>,
+}
+
+impl<ColorFormat: sealed::CanvasColorFormat> OffscreenCanvas<ColorFormat> {
+    /// Creates a new [`OffscreenCanvas`], with all pixels set to the default (fully transparent) color
+    ///
+    /// # Arguments
+    ///
+    /// * `width`: The canvas width
+    /// * `height`: The canvas height
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: alloc::vec![ColorFormat::new(); width as usize * height as usize],
+        }
+    }
+
+    /// Gets the canvas pixels, in row-major order
+    #[inline]
+    pub fn pixels(&self) -> &[ColorFormat] {
+        &self.pixels
+    }
+
+    /// Gets the canvas pixels mutably, in row-major order
+    #[inline]
+    pub fn pixels_mut(&mut self) -> &mut [ColorFormat] {
+        &mut self.pixels
+    }
+
+    /// Gets a pixel, or [`None`] if the coordinates are out of bounds
+    ///
+    /// # Arguments
+    ///
+    /// * `x`: The X coordinate
+    /// * `y`: The Y coordinate
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<ColorFormat> {
+        if (x < self.width) && (y < self.height) {
+            Some(self.pixels[(y * self.width + x) as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Dumps the canvas contents as raw RGBA8 data (4 bytes per pixel, in `R`, `G`, `B`, `A` order), as accepted by [`Image::from_rgba8`]
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.pixels.len() * 4);
+        for pixel in &self.pixels {
+            let (r, g, b, a) = pixel.to_scaled();
+            data.extend_from_slice(&[r, g, b, a]);
+        }
+        data
+    }
+
+    /// Converts the canvas contents to an [`Image`], which can be drawn with scaling/flipping through [`Canvas::draw_image`]
+    ///
+    /// Empty canvases can't be converted.
+    pub fn to_image(&self) -> Result<Image> {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|pixel| {
+                let (r, g, b, a) = pixel.to_scaled();
+                RGBA8::new_scaled(r, g, b, a)
+            })
+            .collect();
+        Image::new(self.width, self.height, pixels)
+    }
+}
+
+impl<ColorFormat: sealed::CanvasColorFormat> Canvas for OffscreenCanvas<ColorFormat> {
+    type ColorFormat = ColorFormat;
+
+    fn draw_single(&mut self, x: i32, y: i32, color: Self::ColorFormat, blend: AlphaBlend) {
+        if !(0..self.width as i32).contains(&x) || !(0..self.height as i32).contains(&y) {
+            return;
+        }
+
+        let pixel = &mut self.pixels[(y as u32 * self.width + x as u32) as usize];
+        *pixel = color.blend_with(*pixel, blend);
+    }
+
+    fn clear(&mut self, color: Self::ColorFormat) {
+        self.pixels.fill(color);
+    }
+
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    const BLACK: RGBA8 = RGBA8::new_scaled(0, 0, 0, 0xFF);
+    const WHITE: RGBA8 = RGBA8::new_scaled(0xFF, 0xFF, 0xFF, 0xFF);
+
+    fn new_canvas(width: u32, height: u32) -> OffscreenCanvas<RGBA8> {
+        let mut canvas = OffscreenCanvas::new(width, height);
+        canvas.clear(BLACK);
+        canvas
+    }
+
+    /// Renders white-on-black canvas contents as text: `#` for fully covered pixels, `+` for partially covered ones and `.` for untouched ones
+    fn render_coverage(canvas: &OffscreenCanvas<RGBA8>) -> String {
+        let mut out = String::new();
+        for y in 0..canvas.height() {
+            for x in 0..canvas.width() {
+                out.push(match canvas.get_pixel(x, y).unwrap().r() {
+                    0 => '.',
+                    0xFF => '#',
+                    _ => '+',
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    #[test]
+    fn draw_circle_filled_golden() {
+        let mut canvas = new_canvas(9, 9);
+        canvas.draw_circle_filled(4, 4, 3, WHITE, AlphaBlend::Source);
+        let expected = concat!(
+            ".........\n",
+            ".........\n",
+            "..####...\n",
+            "..####...\n",
+            ".######..\n",
+            "..####...\n",
+            "..####...\n",
+            ".........\n",
+            ".........\n",
+        );
+        assert_eq!(render_coverage(&canvas), expected);
+    }
+
+    #[test]
+    fn draw_line_golden() {
+        let mut canvas = new_canvas(10, 7);
+        canvas.draw_line((2, 3), (7, 3), 1, WHITE, AlphaBlend::Source);
+        let expected = concat!(
+            "..........\n",
+            "..........\n",
+            ".++++++++.\n",
+            ".+######+.\n",
+            ".++++++++.\n",
+            "..........\n",
+            "..........\n",
+        );
+        assert_eq!(render_coverage(&canvas), expected);
+
+        let mut canvas = new_canvas(10, 10);
+        canvas.draw_line((1, 1), (8, 8), 1, WHITE, AlphaBlend::Source);
+        // The round caps don't add up with the line's partially covered pixels
+        let expected = concat!(
+            "+++.......\n",
+            "+#++......\n",
+            "++#++.....\n",
+            ".++#++....\n",
+            "..++#++...\n",
+            "...++#++..\n",
+            "....++#++.\n",
+            ".....++#++\n",
+            "......++#+\n",
+            ".......+++\n",
+        );
+        assert_eq!(render_coverage(&canvas), expected);
+    }
+}
@@ -0,0 +1,351 @@
+//! Image (sprite) support for canvases
+//!
+//! Images are stored as non-premultiplied [`RGBA8`] pixels, and can be drawn to any [`Canvas`][`super::Canvas`] with [`Canvas::draw_image`][`super::Canvas::draw_image`].
+//!
+//! The decoders only depend on `alloc`, so they can be used (and tested) outside of the console as well.
+
+use super::sealed::CanvasColorFormat;
+use super::{AlphaBlend, RGBA4, RGBA8};
+use crate::result::*;
+use alloc::vec::Vec;
+
+pub mod rc;
+
+mod inflate;
+
+pub mod bmp;
+
+pub mod jpeg;
+
+pub mod png;
+
+/// The maximum width/height of images
+///
+/// Decoders additionally check the dimensions against the size of the encoded data, so malformed data can't cause allocations way bigger than itself
+pub const MAX_DIMENSION: u32 = 0x4000;
+
+pub(crate) fn check_dimensions(width: u32, height: u32) -> Result<()> {
+    result_return_unless!(
+        (1..=MAX_DIMENSION).contains(&width) && (1..=MAX_DIMENSION).contains(&height),
+        rc::ResultInvalidImageSize
+    );
+    Ok(())
+}
+
+/// Represents an image format which can be decoded by [`Image::decode`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Bmp,
+}
+
+impl ImageFormat {
+    /// Detects the format of encoded image data from its signature
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The encoded image data
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&png::SIGNATURE) {
+            Some(Self::Png)
+        } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(Self::Jpeg)
+        } else if data.starts_with(&bmp::SIGNATURE) {
+            Some(Self::Bmp)
+        } else {
+            None
+        }
+    }
+}
+
+/// Represents an image (sprite), made of non-premultiplied [`RGBA8`] pixels in row-major order
+#[derive(Clone, Debug)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<RGBA8>,
+}
+
+impl Image {
+    /// Creates a new [`Image`] from its pixels
+    ///
+    /// # Arguments
+    ///
+    /// * `width`: The image width
+    /// * `height`: The image height
+    /// * `pixels`: The pixels, in row-major order (their amount must be `width * height`)
+    pub fn new(width: u32, height: u32, pixels: Vec<RGBA8>) -> Result<Self> {
+        check_dimensions(width, height)?;
+        result_return_unless!(
+            pixels.len() == width as usize * height as usize,
+            rc::ResultInvalidImageSize
+        );
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Creates a new [`Image`] filled with a single color
+    ///
+    /// # Arguments
+    ///
+    /// * `width`: The image width
+    /// * `height`: The image height
+    /// * `color`: The fill color
+    pub fn new_filled(width: u32, height: u32, color: RGBA8) -> Result<Self> {
+        check_dimensions(width, height)?;
+        Self::new(
+            width,
+            height,
+            alloc::vec![color; width as usize * height as usize],
+        )
+    }
+
+    /// Creates a new [`Image`] from raw RGBA8 data (4 bytes per pixel, in `R`, `G`, `B`, `A` order)
+    ///
+    /// # Arguments
+    ///
+    /// * `width`: The image width
+    /// * `height`: The image height
+    /// * `data`: The raw pixel data
+    pub fn from_rgba8(width: u32, height: u32, data: &[u8]) -> Result<Self> {
+        check_dimensions(width, height)?;
+        result_return_unless!(
+            data.len() == width as usize * height as usize * 4,
+            rc::ResultInvalidImageSize
+        );
+
+        let pixels = data
+            .chunks_exact(4)
+            .map(|px| RGBA8::new_scaled(px[0], px[1], px[2], px[3]))
+            .collect();
+        Self::new(width, height, pixels)
+    }
+
+    /// Creates a new [`Image`] from raw RGBA4 data (2 bytes per pixel)
+    ///
+    /// Each pixel is a little-endian `u16` with red in the lowest nibble, followed by green, blue and alpha, which is the layout [`RGBA4`] canvases use in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `width`: The image width
+    /// * `height`: The image height
+    /// * `data`: The raw pixel data
+    pub fn from_rgba4(width: u32, height: u32, data: &[u8]) -> Result<Self> {
+        check_dimensions(width, height)?;
+        result_return_unless!(
+            data.len() == width as usize * height as usize * 2,
+            rc::ResultInvalidImageSize
+        );
+
+        let pixels = data
+            .chunks_exact(2)
+            .map(|px| {
+                // Canvases store the bitfield byte-swapped (see `RGBA4::to_raw`)
+                let (r, g, b, a) = RGBA4::from_bits(u16::from_be_bytes([px[0], px[1]])).to_scaled();
+                RGBA8::new_scaled(r, g, b, a)
+            })
+            .collect();
+        Self::new(width, height, pixels)
+    }
+
+    /// Decodes an image, detecting its format (PNG, JPEG or BMP) from its signature
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The encoded image data
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        match ImageFormat::detect(data) {
+            Some(ImageFormat::Png) => png::decode(data),
+            Some(ImageFormat::Jpeg) => jpeg::decode(data),
+            Some(ImageFormat::Bmp) => bmp::decode(data),
+            None => rc::ResultUnsupportedImageFormat::make_err(),
+        }
+    }
+
+    /// Gets the image width
+    #[inline]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Gets the image height
+    #[inline]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Gets the image pixels, in row-major order
+    #[inline]
+    pub fn pixels(&self) -> &[RGBA8] {
+        &self.pixels
+    }
+
+    /// Gets the image pixels mutably, in row-major order
+    #[inline]
+    pub fn pixels_mut(&mut self) -> &mut [RGBA8] {
+        &mut self.pixels
+    }
+
+    /// Gets a pixel, or [`None`] if the coordinates are out of bounds
+    ///
+    /// # Arguments
+    ///
+    /// * `x`: The X coordinate
+    /// * `y`: The Y coordinate
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<RGBA8> {
+        if (x < self.width) && (y < self.height) {
+            Some(self.pixels[(y * self.width + x) as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Samples a region of the image with bilinear filtering, clamping at the region edges
+    ///
+    /// # Arguments
+    ///
+    /// * `area`: The region to sample (must be within the image bounds and non-empty)
+    /// * `fx`: The X position relative to the region, in 16.16 fixed point
+    /// * `fy`: The Y position relative to the region, in 16.16 fixed point
+    pub(crate) fn sample_bilinear(&self, area: &ImageRect, fx: i64, fy: i64) -> RGBA8 {
+        // Pixel centers are at +0.5
+        let fx = (fx - 0x8000).clamp(0, (area.width as i64 - 1) << 16);
+        let fy = (fy - 0x8000).clamp(0, (area.height as i64 - 1) << 16);
+        let (x0, y0) = (area.x + (fx >> 16) as u32, area.y + (fy >> 16) as u32);
+        let x1 = (x0 + 1).min(area.x + area.width - 1);
+        let y1 = (y0 + 1).min(area.y + area.height - 1);
+        let (wx, wy) = ((fx & 0xFFFF) as u64, (fy & 0xFFFF) as u64);
+
+        let weights = [
+            ((0x10000 - wx) * (0x10000 - wy)) >> 16,
+            (wx * (0x10000 - wy)) >> 16,
+            ((0x10000 - wx) * wy) >> 16,
+            (wx * wy) >> 16,
+        ];
+        let samples = [
+            self.pixels[(y0 * self.width + x0) as usize],
+            self.pixels[(y0 * self.width + x1) as usize],
+            self.pixels[(y1 * self.width + x0) as usize],
+            self.pixels[(y1 * self.width + x1) as usize],
+        ];
+
+        // Interpolate with premultiplied alpha, so transparent pixels don't bleed their color
+        let (mut r, mut g, mut b, mut a) = (0u64, 0u64, 0u64, 0u64);
+        for (sample, &weight) in samples.iter().zip(weights.iter()) {
+            let weight = weight * sample.a() as u64;
+            r += sample.r() as u64 * weight;
+            g += sample.g() as u64 * weight;
+            b += sample.b() as u64 * weight;
+            a += weight;
+        }
+        if a == 0 {
+            return RGBA8::new();
+        }
+        RGBA8::new_scaled(
+            ((r + a / 2) / a) as u8,
+            ((g + a / 2) / a) as u8,
+            ((b + a / 2) / a) as u8,
+            ((a + 0x8000) >> 16).min(0xFF) as u8,
+        )
+    }
+}
+
+/// Represents a rectangular region of an image
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct ImageRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageRect {
+    /// Creates a new [`ImageRect`]
+    ///
+    /// # Arguments
+    ///
+    /// * `x`: The left coordinate
+    /// * `y`: The top coordinate
+    /// * `width`: The region width
+    /// * `height`: The region height
+    #[inline]
+    pub const fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+/// Represents the filter used when drawing scaled images
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum ScaleFilter {
+    /// Nearest-neighbour sampling, keeps hard pixel edges
+    #[default]
+    Nearest,
+    /// Bilinear interpolation, smoother but blurrier
+    Bilinear,
+}
+
+/// Represents the options used by [`Canvas::draw_image`][`super::Canvas::draw_image`]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ImageDrawOptions {
+    /// The region of the image to draw, the whole image if [`None`]
+    ///
+    /// The region is clipped to the image bounds.
+    pub source: Option<ImageRect>,
+    /// The size (`(width, height)`) to scale the (source region of the) image to, its original size if [`None`]
+    pub size: Option<(u32, u32)>,
+    /// The filter used for scaling
+    pub filter: ScaleFilter,
+    /// Whether to mirror the image horizontally
+    pub flip_horizontal: bool,
+    /// Whether to mirror the image vertically
+    pub flip_vertical: bool,
+    /// The blend mode used to draw the image pixels
+    pub blend: AlphaBlend,
+}
+
+impl ImageDrawOptions {
+    /// Sets the source region
+    #[inline]
+    pub const fn with_source(mut self, source: ImageRect) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Sets the size to scale the image to
+    #[inline]
+    pub const fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some((width, height));
+        self
+    }
+
+    /// Sets the scaling filter
+    #[inline]
+    pub const fn with_filter(mut self, filter: ScaleFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets whether the image is mirrored horizontally and/or vertically
+    #[inline]
+    pub const fn with_flip(mut self, horizontal: bool, vertical: bool) -> Self {
+        self.flip_horizontal = horizontal;
+        self.flip_vertical = vertical;
+        self
+    }
+
+    /// Sets the blend mode
+    #[inline]
+    pub const fn with_blend(mut self, blend: AlphaBlend) -> Self {
+        self.blend = blend;
+        self
+    }
+}
@@ -0,0 +1,352 @@
+//! BMP image decoding
+//!
+//! Uncompressed 1/4/8-bit palettized and 16/24/32-bit images are supported (including `BI_BITFIELDS` masks), with `BITMAPCOREHEADER` to `BITMAPV5HEADER` headers.
+//! RLE-compressed images are not supported.
+
+use super::{Image, rc};
+use crate::gpu::canvas::RGBA8;
+use crate::result::*;
+use alloc::vec::Vec;
+
+/// The signature every BMP file starts with
+pub const SIGNATURE: [u8; 2] = *b"BM";
+
+const FILE_HEADER_SIZE: usize = 14;
+
+const CORE_HEADER_SIZE: usize = 12;
+const INFO_HEADER_SIZE: usize = 40;
+
+const COMPRESSION_RGB: u32 = 0;
+const COMPRESSION_BITFIELDS: u32 = 3;
+const COMPRESSION_ALPHA_BITFIELDS: u32 = 6;
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .ok_or(rc::ResultInvalidImageData::make())?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or(rc::ResultInvalidImageData::make())?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Channel bit mask, as used by 16/32-bit images
+#[derive(Copy, Clone)]
+struct ChannelMask {
+    mask: u32,
+    shift: u32,
+    max_value: u32,
+}
+
+impl ChannelMask {
+    fn new(mask: u32) -> Result<Self> {
+        if mask == 0 {
+            return Ok(Self {
+                mask,
+                shift: 0,
+                max_value: 0,
+            });
+        }
+
+        let shift = mask.trailing_zeros();
+        let bits = (mask >> shift).trailing_ones();
+        // Masks must be contiguous
+        result_return_unless!(bits == mask.count_ones(), rc::ResultInvalidImageData);
+        Ok(Self {
+            mask,
+            shift,
+            max_value: ((1u64 << bits) - 1) as u32,
+        })
+    }
+
+    /// Extracts the channel from a pixel and scales it to 8 bits
+    ///
+    /// # Arguments
+    ///
+    /// * `pixel`: The raw pixel value
+    /// * `default`: The value used for an empty mask
+    fn extract(&self, pixel: u32, default: u8) -> u8 {
+        if self.max_value == 0 {
+            default
+        } else {
+            (((pixel & self.mask) >> self.shift) as u64 * 0xFF / self.max_value as u64) as u8
+        }
+    }
+}
+
+/// Decodes a BMP image
+///
+/// # Arguments
+///
+/// * `data`: The BMP file data
+pub fn decode(data: &[u8]) -> Result<Image> {
+    result_return_unless!(data.starts_with(&SIGNATURE), rc::ResultInvalidImageData);
+    let pixel_data_offset = read_u32(data, 10)? as usize;
+
+    let header_size = read_u32(data, FILE_HEADER_SIZE)? as usize;
+    let header = data
+        .get(FILE_HEADER_SIZE..FILE_HEADER_SIZE + header_size)
+        .ok_or(rc::ResultInvalidImageData::make())?;
+    let is_core_header = header_size == CORE_HEADER_SIZE;
+
+    let (width, height, bits_per_pixel, compression, color_count) = if is_core_header {
+        (
+            read_u16(header, 4)? as i16 as i32,
+            read_u16(header, 6)? as i16 as i32,
+            read_u16(header, 10)?,
+            COMPRESSION_RGB,
+            0,
+        )
+    } else {
+        result_return_unless!(
+            header_size >= INFO_HEADER_SIZE,
+            rc::ResultUnsupportedImageFormat
+        );
+        (
+            read_u32(header, 4)? as i32,
+            read_u32(header, 8)? as i32,
+            read_u16(header, 14)?,
+            read_u32(header, 16)?,
+            read_u32(header, 32)? as usize,
+        )
+    };
+
+    // Positive heights mean bottom-up row order
+    let is_bottom_up = height > 0;
+    result_return_if!(width <= 0, rc::ResultInvalidImageSize);
+    let (width, height) = (width as u32, height.unsigned_abs());
+    super::check_dimensions(width, height)?;
+
+    let mut palette_offset = FILE_HEADER_SIZE + header_size;
+    let masks = match compression {
+        COMPRESSION_RGB => match bits_per_pixel {
+            16 => Some((0x7C00, 0x3E0, 0x1F, 0)),
+            32 => Some((0xFF0000, 0xFF00, 0xFF, 0)),
+            _ => None,
+        },
+        COMPRESSION_BITFIELDS | COMPRESSION_ALPHA_BITFIELDS => {
+            result_return_unless!(
+                matches!(bits_per_pixel, 16 | 32),
+                rc::ResultInvalidImageData
+            );
+            let has_alpha = compression == COMPRESSION_ALPHA_BITFIELDS;
+            // Plain info headers are followed by the masks, newer headers contain them
+            let mask_data = if header_size == INFO_HEADER_SIZE {
+                let mask_count = if has_alpha { 4 } else { 3 };
+                palette_offset += mask_count * 4;
+                &data[FILE_HEADER_SIZE + INFO_HEADER_SIZE..]
+            } else {
+                &header[INFO_HEADER_SIZE..]
+            };
+            // Headers since BITMAPV3INFOHEADER also contain an alpha mask
+            let alpha_mask = if has_alpha || (header_size >= INFO_HEADER_SIZE + 16) {
+                read_u32(mask_data, 12)?
+            } else {
+                0
+            };
+            Some((
+                read_u32(mask_data, 0)?,
+                read_u32(mask_data, 4)?,
+                read_u32(mask_data, 8)?,
+                alpha_mask,
+            ))
+        }
+        _ => return rc::ResultUnsupportedImageFormat::make_err(),
+    };
+
+    let mut palette: Vec<RGBA8> = Vec::new();
+    if bits_per_pixel <= 8 {
+        result_return_unless!(
+            matches!(bits_per_pixel, 1 | 4 | 8),
+            rc::ResultUnsupportedImageFormat
+        );
+        let entry_size = if is_core_header { 3 } else { 4 };
+        // Palettes may also be shorter than the header says (core headers don't specify their size), so they're limited to the space before the pixel data
+        let max_color_count = (1usize << bits_per_pixel)
+            .min(pixel_data_offset.saturating_sub(palette_offset) / entry_size);
+        let color_count = if color_count == 0 {
+            max_color_count
+        } else {
+            color_count.min(max_color_count)
+        };
+        let palette_data = data
+            .get(palette_offset..palette_offset + color_count * entry_size)
+            .ok_or(rc::ResultInvalidImageData::make())?;
+        palette.extend(
+            palette_data
+                .chunks_exact(entry_size)
+                .map(|entry| RGBA8::new_scaled(entry[2], entry[1], entry[0], 0xFF)),
+        );
+    } else {
+        result_return_unless!(
+            matches!(bits_per_pixel, 16 | 24 | 32),
+            rc::ResultUnsupportedImageFormat
+        );
+    }
+    let masks = match masks {
+        Some((r, g, b, a)) => Some((
+            ChannelMask::new(r)?,
+            ChannelMask::new(g)?,
+            ChannelMask::new(b)?,
+            ChannelMask::new(a)?,
+        )),
+        None => None,
+    };
+
+    // Rows are padded to 4 bytes
+    let row_size = (width as usize * bits_per_pixel as usize).div_ceil(32) * 4;
+    let pixel_data = data
+        .get(pixel_data_offset..)
+        .and_then(|rest| rest.get(..row_size * height as usize))
+        .ok_or(rc::ResultInvalidImageData::make())?;
+
+    // The pixel data was checked to be present above, so the dimensions are bounded by the file size
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height as usize {
+        let src_y = if is_bottom_up {
+            height as usize - 1 - y
+        } else {
+            y
+        };
+        let row = &pixel_data[src_y * row_size..(src_y + 1) * row_size];
+        for x in 0..width as usize {
+            let pixel = match bits_per_pixel {
+                1 | 4 | 8 => {
+                    let bit_offset = x * bits_per_pixel as usize;
+                    let shift = 8 - bits_per_pixel as usize - (bit_offset % 8);
+                    let index = (row[bit_offset / 8] >> shift) & ((1 << bits_per_pixel) - 1) as u8;
+                    *palette
+                        .get(index as usize)
+                        .ok_or(rc::ResultInvalidImageData::make())?
+                }
+                24 => RGBA8::new_scaled(row[x * 3 + 2], row[x * 3 + 1], row[x * 3], 0xFF),
+                _ => {
+                    let raw = if bits_per_pixel == 16 {
+                        u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]) as u32
+                    } else {
+                        u32::from_le_bytes([
+                            row[x * 4],
+                            row[x * 4 + 1],
+                            row[x * 4 + 2],
+                            row[x * 4 + 3],
+                        ])
+                    };
+                    // Both cases are guaranteed to have masks
+                    let (r, g, b, a) = masks.ok_or(rc::ResultInvalidImageData::make())?;
+                    RGBA8::new_scaled(
+                        r.extract(raw, 0),
+                        g.extract(raw, 0),
+                        b.extract(raw, 0),
+                        a.extract(raw, 0xFF),
+                    )
+                }
+            };
+            pixels.push(pixel);
+        }
+    }
+
+    Image::new(width, height, pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a BMP with a `BITMAPINFOHEADER`, followed by the masks/palette and the pixel data
+    fn make_bmp(
+        width: i32,
+        height: i32,
+        bits_per_pixel: u16,
+        compression: u32,
+        extra: &[u8],
+        pixel_data: &[u8],
+    ) -> Vec<u8> {
+        let pixel_data_offset = FILE_HEADER_SIZE + INFO_HEADER_SIZE + extra.len();
+        let mut bmp = SIGNATURE.to_vec();
+        bmp.extend_from_slice(&((pixel_data_offset + pixel_data.len()) as u32).to_le_bytes());
+        bmp.extend_from_slice(&[0; 4]);
+        bmp.extend_from_slice(&(pixel_data_offset as u32).to_le_bytes());
+
+        bmp.extend_from_slice(&(INFO_HEADER_SIZE as u32).to_le_bytes());
+        bmp.extend_from_slice(&width.to_le_bytes());
+        bmp.extend_from_slice(&height.to_le_bytes());
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&bits_per_pixel.to_le_bytes());
+        bmp.extend_from_slice(&compression.to_le_bytes());
+        bmp.extend_from_slice(&[0; 20]);
+
+        bmp.extend_from_slice(extra);
+        bmp.extend_from_slice(pixel_data);
+        bmp
+    }
+
+    fn get_rgba(image: &Image, x: u32, y: u32) -> (u8, u8, u8, u8) {
+        let pixel = image.get_pixel(x, y).unwrap();
+        (pixel.r(), pixel.g(), pixel.b(), pixel.a())
+    }
+
+    #[test]
+    fn decode_24bit_bottom_up() {
+        #[rustfmt::skip]
+        let pixel_data = [
+            // Bottom row (BGR), padded to 4 bytes
+            0xFF, 0, 0, 0, 0xFF, 0, 0, 0,
+            // Top row
+            0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0,
+        ];
+        let bmp = make_bmp(2, 2, 24, COMPRESSION_RGB, &[], &pixel_data);
+        let image = Image::decode(&bmp).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 2));
+        assert_eq!(get_rgba(&image, 0, 0), (0xFF, 0, 0, 0xFF));
+        assert_eq!(get_rgba(&image, 1, 0), (0xFF, 0xFF, 0xFF, 0xFF));
+        assert_eq!(get_rgba(&image, 0, 1), (0, 0, 0xFF, 0xFF));
+        assert_eq!(get_rgba(&image, 1, 1), (0, 0xFF, 0, 0xFF));
+    }
+
+    #[test]
+    fn decode_1bit_palette() {
+        let palette = [0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0];
+        // Top-down, 10 pixels in a row
+        let pixel_data = [0b1010_0000, 0b1100_0000, 0, 0];
+        let bmp = make_bmp(10, -1, 1, COMPRESSION_RGB, &palette, &pixel_data);
+        let image = Image::decode(&bmp).unwrap();
+        for x in 0..10 {
+            let value = if [0, 2, 8, 9].contains(&x) { 0xFF } else { 0 };
+            assert_eq!(get_rgba(&image, x, 0), (value, value, value, 0xFF));
+        }
+    }
+
+    #[test]
+    fn decode_32bit_alpha_bitfields() {
+        let mut masks = Vec::new();
+        for mask in [0xFF, 0xFF00, 0xFF0000, 0xFF000000u32] {
+            masks.extend_from_slice(&mask.to_le_bytes());
+        }
+        let pixel_data = [0x10, 0x20, 0x30, 0x40];
+        let bmp = make_bmp(1, 1, 32, COMPRESSION_ALPHA_BITFIELDS, &masks, &pixel_data);
+        let image = Image::decode(&bmp).unwrap();
+        assert_eq!(get_rgba(&image, 0, 0), (0x10, 0x20, 0x30, 0x40));
+    }
+
+    #[test]
+    fn decode_truncated_pixel_data() {
+        let bmp = make_bmp(2, 2, 24, COMPRESSION_RGB, &[], &[0; 15]);
+        let rc = Image::decode(&bmp).unwrap_err();
+        assert!(rc::ResultInvalidImageData::matches(rc));
+    }
+
+    #[test]
+    fn decode_oversized_dimensions() {
+        let bmp = make_bmp(0x4000, 0x4000, 32, COMPRESSION_RGB, &[], &[0; 64]);
+        let rc = Image::decode(&bmp).unwrap_err();
+        assert!(rc::ResultInvalidImageData::matches(rc));
+
+        let bmp = make_bmp(1, -0x4001, 32, COMPRESSION_RGB, &[], &[0; 64]);
+        let rc = Image::decode(&bmp).unwrap_err();
+        assert!(rc::ResultInvalidImageSize::matches(rc));
+    }
+}
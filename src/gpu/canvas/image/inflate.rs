@@ -0,0 +1,384 @@
+//! Minimal DEFLATE/zlib decompressor (RFC 1950/1951), used for PNG image data
+
+use super::rc;
+use crate::result::*;
+use alloc::vec::Vec;
+
+const MAX_CODE_LENGTH: usize = 15;
+
+/// The maximum DEFLATE compression ratio (258-byte matches encoded in a single bit each, plus block overhead)
+pub const MAX_COMPRESSION_RATIO: usize = 1032;
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order in which code length code lengths are stored in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// LSB-first bit reader over the compressed data
+struct BitReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    const fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            offset: 0,
+            bit_buf: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn read_bits(&mut self, count: u32) -> Result<u32> {
+        while self.bit_count < count {
+            let byte = *self
+                .data
+                .get(self.offset)
+                .ok_or(rc::ResultInvalidImageData::make())?;
+            self.offset += 1;
+            self.bit_buf |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+
+        let value = self.bit_buf & ((1u64 << count) - 1) as u32;
+        self.bit_buf = self.bit_buf.checked_shr(count).unwrap_or(0);
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    /// Discards the remaining bits of the current byte
+    fn align_to_byte(&mut self) {
+        self.bit_buf = 0;
+        self.bit_count = 0;
+    }
+
+    fn read_bytes(&mut self, size: usize) -> Result<&'a [u8]> {
+        let end = self
+            .offset
+            .checked_add(size)
+            .ok_or(rc::ResultInvalidImageData::make())?;
+        let bytes = self
+            .data
+            .get(self.offset..end)
+            .ok_or(rc::ResultInvalidImageData::make())?;
+        self.offset = end;
+        Ok(bytes)
+    }
+}
+
+/// Canonical Huffman decoding table
+struct Huffman {
+    /// Amount of codes of each length
+    counts: [u16; MAX_CODE_LENGTH + 1],
+    /// Symbols ordered by their codes
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self> {
+        let mut counts = [0u16; MAX_CODE_LENGTH + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // Reject over-subscribed code sets (incomplete ones are allowed)
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            result_return_if!(left < 0, rc::ResultInvalidImageData);
+        }
+
+        let mut offsets = [0u16; MAX_CODE_LENGTH + 1];
+        for length in 1..MAX_CODE_LENGTH {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = alloc::vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for &count in &self.counts[1..] {
+            code |= reader.read_bits(1)? as i32;
+            let count = count as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        rc::ResultInvalidImageData::make_err()
+    }
+}
+
+/// Checks that `size` more bytes can be output without exceeding the expected size
+fn check_output_size(out: &[u8], size: usize, max_size: usize) -> Result<()> {
+    result_return_unless!(size <= max_size - out.len(), rc::ResultInvalidImageData);
+    Ok(())
+}
+
+fn inflate_stored_block(reader: &mut BitReader, out: &mut Vec<u8>, max_size: usize) -> Result<()> {
+    reader.align_to_byte();
+    let header = reader.read_bytes(4)?;
+    let length = u16::from_le_bytes([header[0], header[1]]);
+    let length_complement = u16::from_le_bytes([header[2], header[3]]);
+    result_return_unless!(length == !length_complement, rc::ResultInvalidImageData);
+
+    check_output_size(out, length as usize, max_size)?;
+    out.extend_from_slice(reader.read_bytes(length as usize)?);
+    Ok(())
+}
+
+fn inflate_huffman_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    lengths: &Huffman,
+    distances: &Huffman,
+    max_size: usize,
+) -> Result<()> {
+    loop {
+        let symbol = lengths.decode(reader)? as usize;
+        match symbol {
+            0..=255 => {
+                check_output_size(out, 1, max_size)?;
+                out.push(symbol as u8);
+            }
+            256 => return Ok(()),
+            _ => {
+                let symbol = symbol - 257;
+                result_return_unless!(symbol < LENGTH_BASES.len(), rc::ResultInvalidImageData);
+                let length = LENGTH_BASES[symbol] as usize
+                    + reader.read_bits(LENGTH_EXTRA_BITS[symbol] as u32)? as usize;
+
+                let symbol = distances.decode(reader)? as usize;
+                result_return_unless!(symbol < DISTANCE_BASES.len(), rc::ResultInvalidImageData);
+                let distance = DISTANCE_BASES[symbol] as usize
+                    + reader.read_bits(DISTANCE_EXTRA_BITS[symbol] as u32)? as usize;
+                result_return_unless!(distance <= out.len(), rc::ResultInvalidImageData);
+                check_output_size(out, length, max_size)?;
+
+                // Copies may overlap with their own output, so copy byte by byte
+                let start = out.len() - distance;
+                for i in 0..length {
+                    let byte = out[start + i];
+                    out.push(byte);
+                }
+            }
+        }
+    }
+}
+
+fn create_fixed_tables() -> Result<(Huffman, Huffman)> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn read_dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman)> {
+    let length_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+    result_return_if!(
+        (length_count > 286) || (distance_count > 30),
+        rc::ResultInvalidImageData
+    );
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = reader.read_bits(3)? as u8;
+    }
+    let code_length_table = Huffman::new(&code_lengths)?;
+
+    let mut lengths = [0u8; 286 + 30];
+    let total_count = length_count + distance_count;
+    let mut index = 0;
+    while index < total_count {
+        let symbol = code_length_table.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                result_return_if!(index == 0, rc::ResultInvalidImageData);
+                (lengths[index - 1], 3 + reader.read_bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.read_bits(3)? as usize),
+            _ => (0, 11 + reader.read_bits(7)? as usize),
+        };
+        result_return_if!(index + repeat > total_count, rc::ResultInvalidImageData);
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+    result_return_if!(lengths[256] == 0, rc::ResultInvalidImageData);
+
+    Ok((
+        Huffman::new(&lengths[..length_count])?,
+        Huffman::new(&lengths[length_count..total_count])?,
+    ))
+}
+
+/// Decompresses raw DEFLATE data
+///
+/// Decompression fails as soon as the output exceeds `max_size`, so malformed data can't make it grow past the expected size
+///
+/// # Arguments
+///
+/// * `data`: The compressed data
+/// * `max_size`: The maximum (expected) decompressed size
+pub fn inflate(data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+    let mut reader = BitReader::new(data);
+    let mut out =
+        Vec::with_capacity(max_size.min(data.len().saturating_mul(MAX_COMPRESSION_RATIO)));
+
+    loop {
+        let is_last = reader.read_bits(1)? != 0;
+        match reader.read_bits(2)? {
+            0 => inflate_stored_block(&mut reader, &mut out, max_size)?,
+            1 => {
+                let (lengths, distances) = create_fixed_tables()?;
+                inflate_huffman_block(&mut reader, &mut out, &lengths, &distances, max_size)?;
+            }
+            2 => {
+                let (lengths, distances) = read_dynamic_tables(&mut reader)?;
+                inflate_huffman_block(&mut reader, &mut out, &lengths, &distances, max_size)?;
+            }
+            _ => return rc::ResultInvalidImageData::make_err(),
+        }
+
+        if is_last {
+            return Ok(out);
+        }
+    }
+}
+
+pub(super) fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // Sums can't overflow within chunks of this size
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+/// Decompresses zlib-wrapped DEFLATE data, verifying its checksum
+///
+/// # Arguments
+///
+/// * `data`: The compressed data
+/// * `max_size`: The maximum (expected) decompressed size, see [`inflate`]
+pub fn zlib_decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+    result_return_unless!(data.len() >= 6, rc::ResultInvalidImageData);
+    let (cmf, flg) = (data[0], data[1]);
+    result_return_unless!(
+        ((cmf & 0xF) == 8)
+            && (((cmf as u16) << 8) | flg as u16).is_multiple_of(31)
+            && ((flg & 0x20) == 0),
+        rc::ResultInvalidImageData
+    );
+
+    let out = inflate(&data[2..data.len() - 4], max_size)?;
+    let checksum = u32::from_be_bytes([
+        data[data.len() - 4],
+        data[data.len() - 3],
+        data[data.len() - 2],
+        data[data.len() - 1],
+    ]);
+    result_return_unless!(adler32(&out) == checksum, rc::ResultInvalidImageData);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `b"abracadabra "` repeated 20 times, compressed with a fixed Huffman block
+    const FIXED_DATA: [u8; 22] = [
+        0x78, 0xDA, 0x4B, 0x4C, 0x2A, 0x4A, 0x4C, 0x4E, 0x4C, 0x49, 0x04, 0x52, 0x0A, 0x89, 0x23,
+        0x80, 0x0D, 0x00, 0x0B, 0xA2, 0x59, 0x11,
+    ];
+
+    /// 31 `a`s, 23 `b`s and 10 `c`s repeated 3 times, compressed with a dynamic Huffman block
+    const DYNAMIC_DATA: [u8; 61] = [
+        0x78, 0x01, 0x05, 0xC1, 0x01, 0x01, 0x00, 0x00, 0x00, 0x82, 0xA0, 0xAD, 0xD8, 0xFF, 0x0F,
+        0x01, 0x00, 0x00, 0x00, 0xA0, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xDA, 0xB6, 0x6D, 0xDB, 0x00,
+        0x00, 0x00, 0x00, 0x55, 0x55, 0x55, 0x55, 0x55, 0xD5, 0xB6, 0x6D, 0xDB, 0x06, 0x00, 0x00,
+        0x00, 0xA8, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xB6, 0x6D, 0xDB, 0xB6, 0x03, 0x96, 0x2D, 0x49,
+        0x42,
+    ];
+
+    fn make_dynamic_expected() -> Vec<u8> {
+        let mut data = Vec::new();
+        for _ in 0..3 {
+            data.extend_from_slice(&[b'a'; 31]);
+            data.extend_from_slice(&[b'b'; 23]);
+            data.extend_from_slice(&[b'c'; 10]);
+        }
+        data
+    }
+
+    #[test]
+    fn decompress_fixed() {
+        let out = zlib_decompress(&FIXED_DATA, 240).unwrap();
+        assert_eq!(out, b"abracadabra ".repeat(20));
+    }
+
+    #[test]
+    fn decompress_dynamic() {
+        let out = zlib_decompress(&DYNAMIC_DATA, 192).unwrap();
+        assert_eq!(out, make_dynamic_expected());
+    }
+
+    #[test]
+    fn decompress_past_max_size() {
+        for max_size in [0, 1, 100, 239] {
+            let rc = zlib_decompress(&FIXED_DATA, max_size).unwrap_err();
+            assert!(rc::ResultInvalidImageData::matches(rc));
+        }
+        let rc = zlib_decompress(&DYNAMIC_DATA, 191).unwrap_err();
+        assert!(rc::ResultInvalidImageData::matches(rc));
+    }
+
+    #[test]
+    fn decompress_bad_checksum() {
+        let mut data = FIXED_DATA;
+        data[data.len() - 1] ^= 1;
+        let rc = zlib_decompress(&data, 240).unwrap_err();
+        assert!(rc::ResultInvalidImageData::matches(rc));
+    }
+}
@@ -0,0 +1,912 @@
+//! Baseline JPEG image decoding
+//!
+//! Baseline and extended sequential (Huffman-coded, 8-bit) images are supported, with any chroma subsampling and restart intervals.
+//! Progressive, lossless and arithmetic-coded images are not supported.
+
+use super::{Image, rc};
+use crate::gpu::canvas::RGBA8;
+use crate::result::*;
+use alloc::vec::Vec;
+
+/// Maps zigzag-ordered coefficient indices to their natural (row-major) indices
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+const MARKER_SOI: u8 = 0xD8;
+const MARKER_EOI: u8 = 0xD9;
+const MARKER_SOS: u8 = 0xDA;
+const MARKER_DQT: u8 = 0xDB;
+const MARKER_DNL: u8 = 0xDC;
+const MARKER_DRI: u8 = 0xDD;
+const MARKER_DHT: u8 = 0xC4;
+const MARKER_SOF0: u8 = 0xC0;
+const MARKER_SOF1: u8 = 0xC1;
+const MARKER_APP14: u8 = 0xEE;
+
+const fn is_restart_marker(marker: u8) -> bool {
+    (marker >= 0xD0) && (marker <= 0xD7)
+}
+
+const fn is_unsupported_frame_marker(marker: u8) -> bool {
+    // SOF2-SOF15, excluding DHT (0xC4), JPG (0xC8) and DAC (0xCC)
+    (marker >= 0xC2) && (marker <= 0xCF) && (marker != 0xC4) && (marker != 0xC8) && (marker != 0xCC)
+}
+
+/// Canonical Huffman table, as defined in a `DHT` segment
+#[derive(Clone)]
+struct HuffmanTable {
+    /// Largest code of each length (`-1` if there are no codes of that length)
+    max_codes: [i32; 17],
+    /// Offset to subtract from a code of each length to get its symbol index
+    value_offsets: [i32; 17],
+    symbols: Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(counts: &[u8], symbols: &[u8]) -> Result<Self> {
+        let mut max_codes = [-1i32; 17];
+        let mut value_offsets = [0i32; 17];
+        let mut code: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..=16 {
+            let count = counts[length - 1] as i32;
+            if count > 0 {
+                value_offsets[length] = index - code;
+                code += count;
+                index += count;
+                max_codes[length] = code - 1;
+            }
+            // Codes must fit in their length
+            result_return_if!(code > (1 << length), rc::ResultInvalidImageData);
+            code <<= 1;
+        }
+
+        Ok(Self {
+            max_codes,
+            value_offsets,
+            symbols: symbols.to_vec(),
+        })
+    }
+}
+
+#[derive(Clone, Default)]
+struct Component {
+    id: u8,
+    h_factor: usize,
+    v_factor: usize,
+    quant_table: usize,
+    dc_table: usize,
+    ac_table: usize,
+    dc_pred: i32,
+    /// Width of the sample plane, in (padded) samples
+    plane_width: usize,
+    /// Height of the sample plane, in (padded) samples
+    plane_height: usize,
+    plane: Vec<u8>,
+}
+
+/// Entropy-coded data reader, handling byte stuffing and markers
+struct BitReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    bit_buf: u32,
+    bit_count: u32,
+    /// The marker found while reading, after which zeros are read
+    marker: Option<u8>,
+}
+
+impl<'a> BitReader<'a> {
+    const fn new(data: &'a [u8], offset: usize) -> Self {
+        Self {
+            data,
+            offset,
+            bit_buf: 0,
+            bit_count: 0,
+            marker: None,
+        }
+    }
+
+    fn fill(&mut self) {
+        while self.bit_count <= 24 {
+            let mut byte = 0;
+            if self.marker.is_none() {
+                match self.data.get(self.offset) {
+                    Some(0xFF) => {
+                        let next = self
+                            .data
+                            .get(self.offset + 1)
+                            .copied()
+                            .unwrap_or(MARKER_EOI);
+                        if next == 0 {
+                            byte = 0xFF;
+                            self.offset += 2;
+                        } else {
+                            self.marker = Some(next);
+                        }
+                    }
+                    Some(&value) => {
+                        byte = value;
+                        self.offset += 1;
+                    }
+                    None => self.marker = Some(MARKER_EOI),
+                }
+            }
+            self.bit_buf |= (byte as u32) << (24 - self.bit_count);
+            self.bit_count += 8;
+        }
+    }
+
+    fn read_bit(&mut self) -> u32 {
+        self.fill();
+        let bit = self.bit_buf >> 31;
+        self.bit_buf <<= 1;
+        self.bit_count -= 1;
+        bit
+    }
+
+    fn read_bits(&mut self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        self.fill();
+        let value = self.bit_buf >> (32 - count);
+        self.bit_buf <<= count;
+        self.bit_count -= count;
+        value
+    }
+
+    /// Reads a `count`-bit value and sign-extends it as specified in F.2.2.1
+    fn receive_extend(&mut self, count: u32) -> i32 {
+        let value = self.read_bits(count) as i32;
+        if (count > 0) && (value < (1 << (count - 1))) {
+            value - (1 << count) + 1
+        } else {
+            value
+        }
+    }
+
+    fn decode_huffman(&mut self, table: &HuffmanTable) -> Result<u8> {
+        let mut code: i32 = 0;
+        for length in 1..=16 {
+            code = (code << 1) | self.read_bit() as i32;
+            if code <= table.max_codes[length] {
+                let index = (code + table.value_offsets[length]) as usize;
+                return table
+                    .symbols
+                    .get(index)
+                    .copied()
+                    .ok_or(rc::ResultInvalidImageData::make());
+            }
+        }
+        rc::ResultInvalidImageData::make_err()
+    }
+
+    /// Skips the expected restart marker and resets the reader state
+    fn handle_restart(&mut self) -> Result<()> {
+        self.bit_buf = 0;
+        self.bit_count = 0;
+        if self.marker.is_none() {
+            // Some encoders pad the entropy-coded data before the marker
+            while let Some(&byte) = self.data.get(self.offset) {
+                if (byte == 0xFF) && (self.data.get(self.offset + 1).copied() != Some(0)) {
+                    break;
+                }
+                self.offset += 1;
+            }
+            self.marker = self.data.get(self.offset + 1).copied();
+        }
+
+        match self.marker.take() {
+            Some(marker) if is_restart_marker(marker) => {
+                self.offset += 2;
+                Ok(())
+            }
+            _ => rc::ResultInvalidImageData::make_err(),
+        }
+    }
+}
+
+/// Dequantizes a coefficient, clamping it to the 16-bit range valid coefficients (of 8-bit images) are always within
+///
+/// Malformed data may otherwise produce values overflowing the IDCT.
+fn dequantize(value: i32, quant: u16) -> i32 {
+    (value as i64 * quant as i64).clamp(i16::MIN as i64, i16::MAX as i64) as i32
+}
+
+/// Integer inverse DCT, writing the clamped samples into the destination plane
+///
+/// This is the well-known fixed-point IDCT used by stb_image (derived from the IJG jidctint algorithm), computed with 64-bit intermediates so no (clamped) coefficients can overflow it.
+fn idct_block(coefs: &[i32; 64], out: &mut [u8], out_offset: usize, out_stride: usize) {
+    macro_rules! idct_1d {
+        ($s0:expr, $s1:expr, $s2:expr, $s3:expr, $s4:expr, $s5:expr, $s6:expr, $s7:expr) => {{
+            let p2 = $s2;
+            let p3 = $s6;
+            let p1 = (p2 + p3) * 2217;
+            let t2 = p1 + p3 * -7567;
+            let t3 = p1 + p2 * 3135;
+            let p2 = $s0;
+            let p3 = $s4;
+            let t0 = (p2 + p3) * 4096;
+            let t1 = (p2 - p3) * 4096;
+            let x0 = t0 + t3;
+            let x3 = t0 - t3;
+            let x1 = t1 + t2;
+            let x2 = t1 - t2;
+            let t0 = $s7;
+            let t1 = $s5;
+            let t2 = $s3;
+            let t3 = $s1;
+            let p3 = t0 + t2;
+            let p4 = t1 + t3;
+            let p1 = t0 + t3;
+            let p2 = t1 + t2;
+            let p5 = (p3 + p4) * 4816;
+            let t0 = t0 * 1223;
+            let t1 = t1 * 8410;
+            let t2 = t2 * 12586;
+            let t3 = t3 * 6149;
+            let p1 = p5 + p1 * -3685;
+            let p2 = p5 + p2 * -10497;
+            let p3 = p3 * -8034;
+            let p4 = p4 * -1597;
+            (
+                x0,
+                x1,
+                x2,
+                x3,
+                t0 + p1 + p3,
+                t1 + p2 + p4,
+                t2 + p2 + p3,
+                t3 + p1 + p4,
+            )
+        }};
+    }
+
+    let mut values = [0i64; 64];
+    for i in 0..8 {
+        let d = |row: usize| coefs[row * 8 + i] as i64;
+        if (1..8).all(|row| d(row) == 0) {
+            let dc_term = d(0) * 4;
+            for row in 0..8 {
+                values[row * 8 + i] = dc_term;
+            }
+        } else {
+            let (x0, x1, x2, x3, t0, t1, t2, t3) =
+                idct_1d!(d(0), d(1), d(2), d(3), d(4), d(5), d(6), d(7));
+            // Scaled up by 1 << 12 by the constants, keep 2 extra bits of precision
+            let (x0, x1, x2, x3) = (x0 + 512, x1 + 512, x2 + 512, x3 + 512);
+            values[i] = (x0 + t3) >> 10;
+            values[56 + i] = (x0 - t3) >> 10;
+            values[8 + i] = (x1 + t2) >> 10;
+            values[48 + i] = (x1 - t2) >> 10;
+            values[16 + i] = (x2 + t1) >> 10;
+            values[40 + i] = (x2 - t1) >> 10;
+            values[24 + i] = (x3 + t0) >> 10;
+            values[32 + i] = (x3 - t0) >> 10;
+        }
+    }
+
+    for (row, v) in values.chunks_exact(8).enumerate() {
+        let (x0, x1, x2, x3, t0, t1, t2, t3) =
+            idct_1d!(v[0], v[1], v[2], v[3], v[4], v[5], v[6], v[7]);
+        // Remove the remaining 1 << 17 scale (rounding), and add the 128 level shift
+        let bias = 65536 + (128 << 17);
+        let (x0, x1, x2, x3) = (x0 + bias, x1 + bias, x2 + bias, x3 + bias);
+        let clamp = |value: i64| (value >> 17).clamp(0, 0xFF) as u8;
+        let o = &mut out[out_offset + row * out_stride..][..8];
+        o[0] = clamp(x0 + t3);
+        o[7] = clamp(x0 - t3);
+        o[1] = clamp(x1 + t2);
+        o[6] = clamp(x1 - t2);
+        o[2] = clamp(x2 + t1);
+        o[5] = clamp(x2 - t1);
+        o[3] = clamp(x3 + t0);
+        o[4] = clamp(x3 - t0);
+    }
+}
+
+struct Decoder {
+    width: usize,
+    height: usize,
+    max_h_factor: usize,
+    max_v_factor: usize,
+    mcu_columns: usize,
+    mcu_rows: usize,
+    components: Vec<Component>,
+    quant_tables: [[u16; 64]; 4],
+    dc_tables: [Option<HuffmanTable>; 4],
+    ac_tables: [Option<HuffmanTable>; 4],
+    restart_interval: usize,
+    adobe_transform: Option<u8>,
+}
+
+impl Decoder {
+    fn new() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            max_h_factor: 1,
+            max_v_factor: 1,
+            mcu_columns: 0,
+            mcu_rows: 0,
+            components: Vec::new(),
+            quant_tables: [[0; 64]; 4],
+            dc_tables: Default::default(),
+            ac_tables: Default::default(),
+            restart_interval: 0,
+            adobe_transform: None,
+        }
+    }
+
+    fn read_quant_tables(&mut self, mut data: &[u8]) -> Result<()> {
+        while !data.is_empty() {
+            let precision = data[0] >> 4;
+            let index = (data[0] & 0xF) as usize;
+            result_return_unless!(index < 4, rc::ResultInvalidImageData);
+            let size = if precision == 0 { 64 } else { 128 };
+            let values = data
+                .get(1..1 + size)
+                .ok_or(rc::ResultInvalidImageData::make())?;
+            for (i, value) in self.quant_tables[index].iter_mut().enumerate() {
+                *value = if precision == 0 {
+                    values[i] as u16
+                } else {
+                    u16::from_be_bytes([values[i * 2], values[i * 2 + 1]])
+                };
+            }
+            data = &data[1 + size..];
+        }
+        Ok(())
+    }
+
+    fn read_huffman_tables(&mut self, mut data: &[u8]) -> Result<()> {
+        while !data.is_empty() {
+            let class = data[0] >> 4;
+            let index = (data[0] & 0xF) as usize;
+            result_return_unless!((class < 2) && (index < 4), rc::ResultInvalidImageData);
+            let counts = data.get(1..17).ok_or(rc::ResultInvalidImageData::make())?;
+            let symbol_count: usize = counts.iter().map(|&count| count as usize).sum();
+            let symbols = data
+                .get(17..17 + symbol_count)
+                .ok_or(rc::ResultInvalidImageData::make())?;
+
+            let table = HuffmanTable::new(counts, symbols)?;
+            if class == 0 {
+                self.dc_tables[index] = Some(table);
+            } else {
+                self.ac_tables[index] = Some(table);
+            }
+            data = &data[17 + symbol_count..];
+        }
+        Ok(())
+    }
+
+    fn read_frame(&mut self, data: &[u8], file_size: usize) -> Result<()> {
+        result_return_unless!(self.components.is_empty(), rc::ResultInvalidImageData);
+        result_return_unless!(data.len() >= 6, rc::ResultInvalidImageData);
+        result_return_unless!(data[0] == 8, rc::ResultUnsupportedImageFormat);
+        self.height = u16::from_be_bytes([data[1], data[2]]) as usize;
+        self.width = u16::from_be_bytes([data[3], data[4]]) as usize;
+        // A zero height would be defined later on by a DNL marker
+        result_return_if!(self.height == 0, rc::ResultUnsupportedImageFormat);
+        super::check_dimensions(self.width as u32, self.height as u32)?;
+
+        let component_count = data[5] as usize;
+        result_return_unless!(
+            matches!(component_count, 1 | 3),
+            rc::ResultUnsupportedImageFormat
+        );
+        let component_data = data
+            .get(6..6 + component_count * 3)
+            .ok_or(rc::ResultInvalidImageData::make())?;
+        for info in component_data.chunks_exact(3) {
+            let component = Component {
+                id: info[0],
+                h_factor: (info[1] >> 4) as usize,
+                v_factor: (info[1] & 0xF) as usize,
+                quant_table: info[2] as usize,
+                ..Default::default()
+            };
+            result_return_unless!(
+                (1..=4).contains(&component.h_factor)
+                    && (1..=4).contains(&component.v_factor)
+                    && (component.quant_table < 4),
+                rc::ResultInvalidImageData
+            );
+            self.components.push(component);
+        }
+
+        self.max_h_factor = self
+            .components
+            .iter()
+            .map(|c| c.h_factor)
+            .max()
+            .unwrap_or(1);
+        self.max_v_factor = self
+            .components
+            .iter()
+            .map(|c| c.v_factor)
+            .max()
+            .unwrap_or(1);
+        self.mcu_columns = self.width.div_ceil(8 * self.max_h_factor);
+        self.mcu_rows = self.height.div_ceil(8 * self.max_v_factor);
+
+        // Dimensions are untrusted: every block takes at least 2 bits (its DC and end-of-block codes), so the file must be big enough to hold them before anything is allocated for them
+        let block_count: usize = self
+            .components
+            .iter()
+            .map(|c| self.mcu_columns * c.h_factor * self.mcu_rows * c.v_factor)
+            .sum();
+        result_return_unless!(
+            block_count.div_ceil(4) <= file_size,
+            rc::ResultInvalidImageData
+        );
+
+        for component in self.components.iter_mut() {
+            component.plane_width = self.mcu_columns * component.h_factor * 8;
+            component.plane_height = self.mcu_rows * component.v_factor * 8;
+            component.plane = alloc::vec![0; component.plane_width * component.plane_height];
+        }
+        Ok(())
+    }
+
+    fn decode_block(
+        &mut self,
+        reader: &mut BitReader,
+        component_index: usize,
+        block_x: usize,
+        block_y: usize,
+    ) -> Result<()> {
+        let component = &self.components[component_index];
+        let dc_table = self.dc_tables[component.dc_table]
+            .as_ref()
+            .ok_or(rc::ResultInvalidImageData::make())?;
+        let ac_table = self.ac_tables[component.ac_table]
+            .as_ref()
+            .ok_or(rc::ResultInvalidImageData::make())?;
+        let quant_table = &self.quant_tables[component.quant_table];
+
+        let mut coefs = [0i32; 64];
+        let dc_size = reader.decode_huffman(dc_table)? as u32;
+        result_return_if!(dc_size > 16, rc::ResultInvalidImageData);
+        let dc_pred = component
+            .dc_pred
+            .saturating_add(reader.receive_extend(dc_size));
+        coefs[0] = dequantize(dc_pred, quant_table[0]);
+
+        let mut k = 1;
+        while k < 64 {
+            let symbol = reader.decode_huffman(ac_table)?;
+            let (run, size) = ((symbol >> 4) as usize, (symbol & 0xF) as u32);
+            if size == 0 {
+                if run != 0xF {
+                    // End of block
+                    break;
+                }
+                k += 16;
+                continue;
+            }
+            k += run;
+            result_return_if!(k >= 64, rc::ResultInvalidImageData);
+            coefs[ZIGZAG[k]] = dequantize(reader.receive_extend(size), quant_table[k]);
+            k += 1;
+        }
+
+        let component = &mut self.components[component_index];
+        component.dc_pred = dc_pred;
+        let offset = block_y * 8 * component.plane_width + block_x * 8;
+        idct_block(&coefs, &mut component.plane, offset, component.plane_width);
+        Ok(())
+    }
+
+    fn read_scan(&mut self, data: &[u8], header: &[u8], mut offset: usize) -> Result<usize> {
+        let scan_count = *header.first().ok_or(rc::ResultInvalidImageData::make())? as usize;
+        result_return_unless!(
+            (1..=self.components.len()).contains(&scan_count)
+                && (header.len() >= 1 + scan_count * 2 + 3),
+            rc::ResultInvalidImageData
+        );
+
+        let mut scan_components = Vec::with_capacity(scan_count);
+        for info in header[1..1 + scan_count * 2].chunks_exact(2) {
+            let index = self
+                .components
+                .iter()
+                .position(|c| c.id == info[0])
+                .ok_or(rc::ResultInvalidImageData::make())?;
+            let (dc_table, ac_table) = ((info[1] >> 4) as usize, (info[1] & 0xF) as usize);
+            result_return_unless!((dc_table < 4) && (ac_table < 4), rc::ResultInvalidImageData);
+            self.components[index].dc_table = dc_table;
+            self.components[index].ac_table = ac_table;
+            scan_components.push(index);
+        }
+
+        // Baseline scans always cover the whole spectrum without approximation
+        let spectral = &header[1 + scan_count * 2..];
+        result_return_unless!(
+            (spectral[0] == 0) && (spectral[1] == 63) && (spectral[2] == 0),
+            rc::ResultUnsupportedImageFormat
+        );
+
+        for component in self.components.iter_mut() {
+            component.dc_pred = 0;
+        }
+
+        let mut reader = BitReader::new(data, offset);
+        if let [index] = scan_components[..] {
+            // Non-interleaved scans contain the component's blocks covering the image, one block per MCU
+            let component = &self.components[index];
+            let block_columns = (self.width * component.h_factor)
+                .div_ceil(self.max_h_factor)
+                .div_ceil(8);
+            let block_rows = (self.height * component.v_factor)
+                .div_ceil(self.max_v_factor)
+                .div_ceil(8);
+            let total = block_columns * block_rows;
+            for i in 0..total {
+                self.decode_block(&mut reader, index, i % block_columns, i / block_columns)?;
+                self.process_restart(&mut reader, i, total)?;
+            }
+        } else {
+            let total = self.mcu_columns * self.mcu_rows;
+            for i in 0..total {
+                let (mcu_x, mcu_y) = (i % self.mcu_columns, i / self.mcu_columns);
+                for &index in &scan_components {
+                    let (h_factor, v_factor) = {
+                        let component = &self.components[index];
+                        (component.h_factor, component.v_factor)
+                    };
+                    for v in 0..v_factor {
+                        for h in 0..h_factor {
+                            self.decode_block(
+                                &mut reader,
+                                index,
+                                mcu_x * h_factor + h,
+                                mcu_y * v_factor + v,
+                            )?;
+                        }
+                    }
+                }
+                self.process_restart(&mut reader, i, total)?;
+            }
+        }
+
+        // Continue right at the marker following the scan data
+        offset = reader.offset;
+        while offset < data.len() {
+            if (data[offset] == 0xFF) && (data.get(offset + 1).copied().unwrap_or(0xFF) != 0) {
+                break;
+            }
+            offset += 1;
+        }
+        Ok(offset)
+    }
+
+    fn process_restart(&mut self, reader: &mut BitReader, mcu: usize, total: usize) -> Result<()> {
+        if (self.restart_interval != 0)
+            && (mcu + 1).is_multiple_of(self.restart_interval)
+            && (mcu + 1 < total)
+        {
+            reader.handle_restart()?;
+            for component in self.components.iter_mut() {
+                component.dc_pred = 0;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_rgb(&self) -> bool {
+        match self.adobe_transform {
+            Some(transform) => transform == 0,
+            None => self.components.iter().map(|c| c.id).eq(*b"RGB"),
+        }
+    }
+
+    fn to_pixels(&self) -> Vec<RGBA8> {
+        // The dimensions were checked against the file size when reading the frame
+        let mut pixels = Vec::with_capacity(self.width * self.height);
+        // Upsample each component with nearest-neighbour sampling
+        let sample = |component: &Component, x: usize, y: usize| {
+            let sx = x * component.h_factor / self.max_h_factor;
+            let sy = y * component.v_factor / self.max_v_factor;
+            component.plane[sy * component.plane_width + sx] as i32
+        };
+
+        let is_rgb = self.is_rgb();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let pixel = match &self.components[..] {
+                    [gray] => {
+                        let gray = sample(gray, x, y) as u8;
+                        RGBA8::new_scaled(gray, gray, gray, 0xFF)
+                    }
+                    [c0, c1, c2] if is_rgb => RGBA8::new_scaled(
+                        sample(c0, x, y) as u8,
+                        sample(c1, x, y) as u8,
+                        sample(c2, x, y) as u8,
+                        0xFF,
+                    ),
+                    [c0, c1, c2] => {
+                        // JFIF YCbCr -> RGB conversion, in 16.16 fixed point
+                        let luma = sample(c0, x, y) << 16;
+                        let cb = sample(c1, x, y) - 128;
+                        let cr = sample(c2, x, y) - 128;
+                        let convert = |value: i32| ((value + 0x8000) >> 16).clamp(0, 0xFF) as u8;
+                        RGBA8::new_scaled(
+                            convert(luma + 91881 * cr),
+                            convert(luma - 22554 * cb - 46802 * cr),
+                            convert(luma + 116130 * cb),
+                            0xFF,
+                        )
+                    }
+                    _ => unreachable!(),
+                };
+                pixels.push(pixel);
+            }
+        }
+        pixels
+    }
+}
+
+/// Decodes a baseline JPEG image
+///
+/// # Arguments
+///
+/// * `data`: The JPEG file data
+pub fn decode(data: &[u8]) -> Result<Image> {
+    result_return_unless!(
+        data.starts_with(&[0xFF, MARKER_SOI]),
+        rc::ResultInvalidImageData
+    );
+
+    let mut decoder = Decoder::new();
+    let mut decoded_scan = false;
+    let mut offset = 2;
+    loop {
+        // Markers may be preceded by any amount of fill bytes
+        result_return_unless!(
+            data.get(offset).copied() == Some(0xFF),
+            rc::ResultInvalidImageData
+        );
+        while data.get(offset).copied() == Some(0xFF) {
+            offset += 1;
+        }
+        let marker = *data.get(offset).ok_or(rc::ResultInvalidImageData::make())?;
+        offset += 1;
+
+        match marker {
+            MARKER_EOI => break,
+            MARKER_SOI | 0x01 => continue,
+            _ if is_restart_marker(marker) => continue,
+            _ => {}
+        }
+
+        let length_bytes = data
+            .get(offset..offset + 2)
+            .ok_or(rc::ResultInvalidImageData::make())?;
+        let length = u16::from_be_bytes([length_bytes[0], length_bytes[1]]) as usize;
+        result_return_if!(length < 2, rc::ResultInvalidImageData);
+        let segment = data
+            .get(offset + 2..offset + length)
+            .ok_or(rc::ResultInvalidImageData::make())?;
+        offset += length;
+
+        match marker {
+            MARKER_SOF0 | MARKER_SOF1 => decoder.read_frame(segment, data.len())?,
+            MARKER_DQT => decoder.read_quant_tables(segment)?,
+            MARKER_DHT => decoder.read_huffman_tables(segment)?,
+            MARKER_DRI => {
+                result_return_unless!(segment.len() >= 2, rc::ResultInvalidImageData);
+                decoder.restart_interval = u16::from_be_bytes([segment[0], segment[1]]) as usize;
+            }
+            MARKER_APP14 => {
+                if segment.starts_with(b"Adobe") && (segment.len() >= 12) {
+                    decoder.adobe_transform = Some(segment[11]);
+                }
+            }
+            MARKER_SOS => {
+                result_return_if!(decoder.components.is_empty(), rc::ResultInvalidImageData);
+                offset = decoder.read_scan(data, segment, offset)?;
+                decoded_scan = true;
+            }
+            MARKER_DNL => return rc::ResultUnsupportedImageFormat::make_err(),
+            _ if is_unsupported_frame_marker(marker) => {
+                return rc::ResultUnsupportedImageFormat::make_err();
+            }
+            // APPn, COM and other segments are skipped
+            _ => {}
+        }
+    }
+
+    result_return_unless!(decoded_scan, rc::ResultInvalidImageData);
+    let pixels = decoder.to_pixels();
+    Image::new(decoder.width as u32, decoder.height as u32, pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Entropy-coded data writer, stuffing `0xFF` bytes
+    struct BitWriter {
+        data: Vec<u8>,
+        bit_buf: u32,
+        bit_count: u32,
+    }
+
+    impl BitWriter {
+        fn write_bits(&mut self, value: u32, count: u32) {
+            for i in (0..count).rev() {
+                self.bit_buf = (self.bit_buf << 1) | ((value >> i) & 1);
+                self.bit_count += 1;
+                if self.bit_count == 8 {
+                    self.data.push(self.bit_buf as u8);
+                    if self.bit_buf == 0xFF {
+                        self.data.push(0);
+                    }
+                    self.bit_buf = 0;
+                    self.bit_count = 0;
+                }
+            }
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            // Pad with ones
+            while self.bit_count != 0 {
+                self.write_bits(1, 1);
+            }
+            self.data
+        }
+    }
+
+    fn push_segment(jpeg: &mut Vec<u8>, marker: u8, data: &[u8]) {
+        jpeg.extend_from_slice(&[0xFF, marker]);
+        jpeg.extend_from_slice(&(data.len() as u16 + 2).to_be_bytes());
+        jpeg.extend_from_slice(data);
+    }
+
+    /// Builds a baseline JPEG (no subsampling) whose blocks only have DC coefficients
+    ///
+    /// The DC table codes every size with 4 bits, and the AC table only has an end-of-block code (a single `0` bit)
+    ///
+    /// # Arguments
+    ///
+    /// * `width`: The image width
+    /// * `height`: The image height
+    /// * `dc_quant`: The DC quantization value (a 16-bit precision table is used for values over `0xFF`)
+    /// * `dc_values`: The (quantized) DC coefficient values of the blocks of each component, in MCU order
+    fn make_jpeg(width: u16, height: u16, dc_quant: u16, dc_values: &[&[i32]]) -> Vec<u8> {
+        let mut jpeg = alloc::vec![0xFF, MARKER_SOI];
+
+        let mut quant_data = Vec::new();
+        if dc_quant > 0xFF {
+            quant_data.push(0x10);
+            quant_data.extend_from_slice(&dc_quant.to_be_bytes());
+            quant_data.extend_from_slice(&[0, 1].repeat(63));
+        } else {
+            quant_data.push(0x00);
+            quant_data.push(dc_quant as u8);
+            quant_data.extend_from_slice(&[1; 63]);
+        }
+        push_segment(&mut jpeg, MARKER_DQT, &quant_data);
+
+        let mut frame = alloc::vec![8];
+        frame.extend_from_slice(&height.to_be_bytes());
+        frame.extend_from_slice(&width.to_be_bytes());
+        frame.push(dc_values.len() as u8);
+        for id in 1..=dc_values.len() as u8 {
+            frame.extend_from_slice(&[id, 0x11, 0]);
+        }
+        push_segment(&mut jpeg, MARKER_SOF0, &frame);
+
+        let mut dc_table = alloc::vec![0x00, 0, 0, 0, 16];
+        dc_table.extend_from_slice(&[0; 12]);
+        dc_table.extend(0..16u8);
+        push_segment(&mut jpeg, MARKER_DHT, &dc_table);
+        let mut ac_table = alloc::vec![0x10, 1];
+        ac_table.extend_from_slice(&[0; 15]);
+        ac_table.push(0x00);
+        push_segment(&mut jpeg, MARKER_DHT, &ac_table);
+
+        let mut scan = alloc::vec![dc_values.len() as u8];
+        for id in 1..=dc_values.len() as u8 {
+            scan.extend_from_slice(&[id, 0x00]);
+        }
+        scan.extend_from_slice(&[0, 63, 0]);
+        push_segment(&mut jpeg, MARKER_SOS, &scan);
+
+        let mut writer = BitWriter {
+            data: Vec::new(),
+            bit_buf: 0,
+            bit_count: 0,
+        };
+        let mut preds = alloc::vec![0; dc_values.len()];
+        for block in 0..dc_values[0].len() {
+            for (component, values) in dc_values.iter().enumerate() {
+                let diff = values[block] - preds[component];
+                preds[component] += diff;
+                let size = 32 - diff.unsigned_abs().leading_zeros();
+                writer.write_bits(size, 4);
+                let bits = if diff < 0 { diff - 1 } else { diff };
+                writer.write_bits(bits as u32 & ((1 << size) - 1), size);
+                // End of block
+                writer.write_bits(0, 1);
+            }
+        }
+        jpeg.extend(writer.finish());
+        jpeg.extend_from_slice(&[0xFF, MARKER_EOI]);
+        jpeg
+    }
+
+    fn get_rgba(image: &Image, x: u32, y: u32) -> (u8, u8, u8, u8) {
+        let pixel = image.get_pixel(x, y).unwrap();
+        (pixel.r(), pixel.g(), pixel.b(), pixel.a())
+    }
+
+    /// Gets the quantized DC coefficient value of a block filled with a single sample value
+    const fn dc_for(value: i32, dc_quant: i32) -> i32 {
+        (value - 128) * 8 / dc_quant
+    }
+
+    #[test]
+    fn decode_grayscale() {
+        let jpeg = make_jpeg(16, 8, 4, &[&[dc_for(200, 4), dc_for(50, 4)]]);
+        let image = Image::decode(&jpeg).unwrap();
+        assert_eq!((image.width(), image.height()), (16, 8));
+        assert_eq!(get_rgba(&image, 0, 0), (200, 200, 200, 0xFF));
+        assert_eq!(get_rgba(&image, 7, 7), (200, 200, 200, 0xFF));
+        assert_eq!(get_rgba(&image, 8, 0), (50, 50, 50, 0xFF));
+        assert_eq!(get_rgba(&image, 15, 7), (50, 50, 50, 0xFF));
+    }
+
+    #[test]
+    fn decode_ycbcr() {
+        // Y = 128, Cb = 128, Cr = 228 -> R = 128 + 1.402 * 100
+        let jpeg = make_jpeg(
+            4,
+            4,
+            1,
+            &[&[dc_for(128, 1)], &[dc_for(128, 1)], &[dc_for(228, 1)]],
+        );
+        let image = Image::decode(&jpeg).unwrap();
+        assert_eq!((image.width(), image.height()), (4, 4));
+        assert_eq!(get_rgba(&image, 3, 3), (0xFF, 57, 128, 0xFF));
+    }
+
+    #[test]
+    fn decode_huge_coefficients() {
+        // Accumulating huge DC differences with a huge quantization value overflows unless the coefficients are clamped
+        for (sign, sample) in [(1, 0xFF), (-1, 0)] {
+            let values: Vec<i32> = (1..=64).map(|i| sign * i * 0x7FFF).collect();
+            let jpeg = make_jpeg(8 * 64, 8, 0xFFFF, &[&values]);
+            let image = Image::decode(&jpeg).unwrap();
+            for x in [0, 8 * 63 + 7] {
+                assert_eq!(get_rgba(&image, x, 0), (sample, sample, sample, 0xFF));
+            }
+        }
+    }
+
+    #[test]
+    fn decode_oversized_dimensions() {
+        // The file is way too small to hold the blocks of a 0x4000x0x4000 frame
+        let mut jpeg = make_jpeg(8, 8, 1, &[&[0]]);
+        let frame_offset = jpeg
+            .windows(2)
+            .position(|m| m == [0xFF, MARKER_SOF0])
+            .unwrap();
+        jpeg[frame_offset + 5..frame_offset + 9].copy_from_slice(&[0x40, 0, 0x40, 0]);
+        let rc = Image::decode(&jpeg).unwrap_err();
+        assert!(rc::ResultInvalidImageData::matches(rc));
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let jpeg = make_jpeg(8, 8, 1, &[&[0]]);
+        for size in [2, 8, 30] {
+            assert!(Image::decode(&jpeg[..size]).is_err());
+        }
+    }
+}
@@ -0,0 +1,576 @@
+//! PNG image decoding
+//!
+//! All standard color types and bit depths are supported, including palettes, `tRNS` transparency and Adam7 interlacing.
+//! 16-bit samples are reduced to 8 bits, and ancillary chunks other than `tRNS` are ignored.
+
+use super::{Image, inflate, rc};
+use crate::gpu::canvas::RGBA8;
+use crate::result::*;
+use alloc::vec::Vec;
+
+/// The signature every PNG file starts with
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Adam7 pass layout: `(x_start, y_start, x_step, y_step)`
+const ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    fn from_raw(raw: u8, bit_depth: u8) -> Result<Self> {
+        let (color_type, valid_depth) = match raw {
+            0 => (Self::Grayscale, matches!(bit_depth, 1 | 2 | 4 | 8 | 16)),
+            2 => (Self::Rgb, matches!(bit_depth, 8 | 16)),
+            3 => (Self::Indexed, matches!(bit_depth, 1 | 2 | 4 | 8)),
+            4 => (Self::GrayscaleAlpha, matches!(bit_depth, 8 | 16)),
+            6 => (Self::Rgba, matches!(bit_depth, 8 | 16)),
+            _ => return rc::ResultInvalidImageData::make_err(),
+        };
+        result_return_unless!(valid_depth, rc::ResultInvalidImageData);
+        Ok(color_type)
+    }
+
+    const fn channel_count(self) -> usize {
+        match self {
+            Self::Grayscale | Self::Indexed => 1,
+            Self::GrayscaleAlpha => 2,
+            Self::Rgb => 3,
+            Self::Rgba => 4,
+        }
+    }
+}
+
+struct Header {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    interlaced: bool,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self> {
+        result_return_unless!(data.len() == 13, rc::ResultInvalidImageData);
+        let width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        let bit_depth = data[8];
+        let color_type = ColorType::from_raw(data[9], bit_depth)?;
+        // Only deflate compression and adaptive filtering are defined
+        result_return_unless!(
+            (data[10] == 0) && (data[11] == 0),
+            rc::ResultUnsupportedImageFormat
+        );
+        let interlaced = match data[12] {
+            0 => false,
+            1 => true,
+            _ => return rc::ResultUnsupportedImageFormat::make_err(),
+        };
+        super::check_dimensions(width, height)?;
+
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced,
+        })
+    }
+
+    /// Gets the size of a filtered row (without the filter type byte)
+    const fn get_row_size(&self, width: u32) -> usize {
+        (width as usize * self.color_type.channel_count() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    /// Gets the interlacing passes of the image, as `(x_start, y_start, x_step, y_step)`
+    fn get_passes(&self) -> &'static [(u32, u32, u32, u32)] {
+        if self.interlaced {
+            &ADAM7_PASSES
+        } else {
+            &[(0, 0, 1, 1)]
+        }
+    }
+
+    /// Gets the size (`(width, height)`) of a pass, or [`None`] if it's empty
+    ///
+    /// Passes may be empty for small images, in which case they have no rows at all
+    fn get_pass_size(
+        &self,
+        (x_start, y_start, x_step, y_step): (u32, u32, u32, u32),
+    ) -> Option<(u32, u32)> {
+        if (x_start >= self.width) || (y_start >= self.height) {
+            return None;
+        }
+        Some((
+            (self.width - x_start).div_ceil(x_step),
+            (self.height - y_start).div_ceil(y_step),
+        ))
+    }
+
+    /// Gets the exact size of the decompressed image data (all the filtered rows of all the passes)
+    fn get_data_size(&self) -> usize {
+        self.get_passes()
+            .iter()
+            .filter_map(|&pass| self.get_pass_size(pass))
+            .map(|(pass_width, pass_height)| {
+                (1 + self.get_row_size(pass_width)) * pass_height as usize
+            })
+            .sum()
+    }
+
+    /// Gets the byte distance between corresponding bytes of adjacent pixels, as used by filters
+    const fn get_filter_stride(&self) -> usize {
+        let bits = self.color_type.channel_count() * self.bit_depth as usize;
+        if bits < 8 { 1 } else { bits / 8 }
+    }
+}
+
+/// Transparency information from the `tRNS` chunk
+enum Transparency {
+    None,
+    /// Raw sample value (at the image bit depth) treated as fully transparent
+    Gray(u16),
+    /// Raw sample values (at the image bit depth) treated as fully transparent
+    Rgb(u16, u16, u16),
+}
+
+fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if (pa <= pb) && (pa <= pc) {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Reverses the filter of a row in place
+///
+/// # Arguments
+///
+/// * `filter_type`: The row filter type
+/// * `row`: The row to unfilter
+/// * `prev_row`: The (already unfiltered) previous row, all zeros for the first row of a pass
+/// * `stride`: The filter stride
+fn unfilter_row(filter_type: u8, row: &mut [u8], prev_row: &[u8], stride: usize) -> Result<()> {
+    match filter_type {
+        0 => {}
+        1 => {
+            for i in stride..row.len() {
+                row[i] = row[i].wrapping_add(row[i - stride]);
+            }
+        }
+        2 => {
+            for (cur, &up) in row.iter_mut().zip(prev_row) {
+                *cur = cur.wrapping_add(up);
+            }
+        }
+        3 => {
+            for i in 0..row.len() {
+                let left = if i >= stride { row[i - stride] } else { 0 };
+                row[i] = row[i].wrapping_add(((left as u16 + prev_row[i] as u16) / 2) as u8);
+            }
+        }
+        4 => {
+            for i in 0..row.len() {
+                let (left, up_left) = if i >= stride {
+                    (row[i - stride], prev_row[i - stride])
+                } else {
+                    (0, 0)
+                };
+                row[i] = row[i].wrapping_add(paeth_predictor(left, prev_row[i], up_left));
+            }
+        }
+        _ => return rc::ResultInvalidImageData::make_err(),
+    }
+    Ok(())
+}
+
+/// Reads the `index`-th sample of a row
+fn read_sample(row: &[u8], index: usize, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+        8 => row[index] as u16,
+        _ => {
+            let bit_offset = index * bit_depth as usize;
+            let shift = 8 - bit_depth as usize - (bit_offset % 8);
+            ((row[bit_offset / 8] >> shift) & ((1 << bit_depth) - 1)) as u16
+        }
+    }
+}
+
+/// Scales a sample of the given bit depth to 8 bits
+fn scale_sample(sample: u16, bit_depth: u8) -> u8 {
+    match bit_depth {
+        16 => (sample >> 8) as u8,
+        8 => sample as u8,
+        _ => (sample as u32 * 0xFF / ((1 << bit_depth) - 1)) as u8,
+    }
+}
+
+struct Decoder<'a> {
+    header: Header,
+    palette: &'a [u8],
+    palette_alpha: &'a [u8],
+    transparency: Transparency,
+}
+
+impl Decoder<'_> {
+    fn convert_pixel(&self, row: &[u8], x: usize) -> Result<RGBA8> {
+        let depth = self.header.bit_depth;
+        let pixel = match self.header.color_type {
+            ColorType::Grayscale => {
+                let sample = read_sample(row, x, depth);
+                let gray = scale_sample(sample, depth);
+                let alpha = match self.transparency {
+                    Transparency::Gray(transparent) if transparent == sample => 0,
+                    _ => 0xFF,
+                };
+                RGBA8::new_scaled(gray, gray, gray, alpha)
+            }
+            ColorType::GrayscaleAlpha => {
+                let gray = scale_sample(read_sample(row, x * 2, depth), depth);
+                let alpha = scale_sample(read_sample(row, x * 2 + 1, depth), depth);
+                RGBA8::new_scaled(gray, gray, gray, alpha)
+            }
+            ColorType::Rgb => {
+                let r = read_sample(row, x * 3, depth);
+                let g = read_sample(row, x * 3 + 1, depth);
+                let b = read_sample(row, x * 3 + 2, depth);
+                let alpha = match self.transparency {
+                    Transparency::Rgb(tr, tg, tb) if (tr, tg, tb) == (r, g, b) => 0,
+                    _ => 0xFF,
+                };
+                RGBA8::new_scaled(
+                    scale_sample(r, depth),
+                    scale_sample(g, depth),
+                    scale_sample(b, depth),
+                    alpha,
+                )
+            }
+            ColorType::Rgba => RGBA8::new_scaled(
+                scale_sample(read_sample(row, x * 4, depth), depth),
+                scale_sample(read_sample(row, x * 4 + 1, depth), depth),
+                scale_sample(read_sample(row, x * 4 + 2, depth), depth),
+                scale_sample(read_sample(row, x * 4 + 3, depth), depth),
+            ),
+            ColorType::Indexed => {
+                let index = read_sample(row, x, depth) as usize;
+                let color = self
+                    .palette
+                    .get(index * 3..index * 3 + 3)
+                    .ok_or(rc::ResultInvalidImageData::make())?;
+                let alpha = self.palette_alpha.get(index).copied().unwrap_or(0xFF);
+                RGBA8::new_scaled(color[0], color[1], color[2], alpha)
+            }
+        };
+        Ok(pixel)
+    }
+
+    fn decode_pixels(&self, data: &[u8]) -> Result<Vec<RGBA8>> {
+        let width = self.header.width;
+        let height = self.header.height;
+        let mut pixels = alloc::vec![RGBA8::new(); width as usize * height as usize];
+
+        let stride = self.header.get_filter_stride();
+        let mut offset = 0;
+        for &pass in self.header.get_passes() {
+            let Some((pass_width, pass_height)) = self.header.get_pass_size(pass) else {
+                continue;
+            };
+            let (x_start, y_start, x_step, y_step) = pass;
+            let row_size = self.header.get_row_size(pass_width);
+
+            let mut prev_row = alloc::vec![0u8; row_size];
+            let mut row = alloc::vec![0u8; row_size];
+            for pass_y in 0..pass_height {
+                let filtered = data
+                    .get(offset..offset + 1 + row_size)
+                    .ok_or(rc::ResultInvalidImageData::make())?;
+                offset += 1 + row_size;
+
+                row.copy_from_slice(&filtered[1..]);
+                unfilter_row(filtered[0], &mut row, &prev_row, stride)?;
+
+                let y = y_start + pass_y * y_step;
+                for pass_x in 0..pass_width {
+                    let x = x_start + pass_x * x_step;
+                    pixels[(y * width + x) as usize] = self.convert_pixel(&row, pass_x as usize)?;
+                }
+                core::mem::swap(&mut row, &mut prev_row);
+            }
+        }
+
+        Ok(pixels)
+    }
+}
+
+/// Decodes a PNG image
+///
+/// # Arguments
+///
+/// * `data`: The PNG file data
+pub fn decode(data: &[u8]) -> Result<Image> {
+    result_return_unless!(data.starts_with(&SIGNATURE), rc::ResultInvalidImageData);
+
+    let mut header: Option<Header> = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency_data: &[u8] = &[];
+    let mut compressed_data = Vec::new();
+    let mut offset = SIGNATURE.len();
+    loop {
+        let chunk_header = data
+            .get(offset..offset + 8)
+            .ok_or(rc::ResultInvalidImageData::make())?;
+        let chunk_size = u32::from_be_bytes([
+            chunk_header[0],
+            chunk_header[1],
+            chunk_header[2],
+            chunk_header[3],
+        ]) as usize;
+        let chunk_type = &chunk_header[4..8];
+        let chunk_data = data
+            .get(offset + 8..)
+            .and_then(|rest| rest.get(..chunk_size))
+            .ok_or(rc::ResultInvalidImageData::make())?;
+        // Skip the header, the data and the trailing CRC
+        offset += 8 + chunk_size + 4;
+
+        match chunk_type {
+            b"IHDR" => {
+                result_return_if!(header.is_some(), rc::ResultInvalidImageData);
+                header = Some(Header::parse(chunk_data)?);
+            }
+            b"PLTE" => {
+                result_return_unless!(
+                    !chunk_data.is_empty() && (chunk_data.len() % 3 == 0),
+                    rc::ResultInvalidImageData
+                );
+                palette = chunk_data;
+            }
+            b"tRNS" => transparency_data = chunk_data,
+            b"IDAT" => compressed_data.extend_from_slice(chunk_data),
+            b"IEND" => break,
+            _ => {
+                // Unknown critical chunks (uppercase first letter) can't be safely ignored
+                result_return_if!(
+                    chunk_type[0].is_ascii_uppercase(),
+                    rc::ResultUnsupportedImageFormat
+                );
+            }
+        }
+    }
+
+    let header = header.ok_or(rc::ResultInvalidImageData::make())?;
+    result_return_if!(
+        (header.color_type == ColorType::Indexed) && palette.is_empty(),
+        rc::ResultInvalidImageData
+    );
+
+    let mut palette_alpha: &[u8] = &[];
+    let transparency = match header.color_type {
+        ColorType::Indexed => {
+            palette_alpha = transparency_data;
+            Transparency::None
+        }
+        ColorType::Grayscale if transparency_data.len() >= 2 => {
+            Transparency::Gray(u16::from_be_bytes([
+                transparency_data[0],
+                transparency_data[1],
+            ]))
+        }
+        ColorType::Rgb if transparency_data.len() >= 6 => Transparency::Rgb(
+            u16::from_be_bytes([transparency_data[0], transparency_data[1]]),
+            u16::from_be_bytes([transparency_data[2], transparency_data[3]]),
+            u16::from_be_bytes([transparency_data[4], transparency_data[5]]),
+        ),
+        _ => Transparency::None,
+    };
+
+    // Dimensions are untrusted, the compressed data must be able to hold the image data they require
+    let data_size = header.get_data_size();
+    result_return_unless!(
+        data_size
+            <= compressed_data
+                .len()
+                .saturating_mul(inflate::MAX_COMPRESSION_RATIO),
+        rc::ResultInvalidImageData
+    );
+    let raw_data = inflate::zlib_decompress(&compressed_data, data_size)?;
+    result_return_unless!(raw_data.len() == data_size, rc::ResultInvalidImageData);
+
+    let (width, height) = (header.width, header.height);
+    let decoder = Decoder {
+        header,
+        palette,
+        palette_alpha,
+        transparency,
+    };
+    let pixels = decoder.decode_pixels(&raw_data)?;
+    Image::new(width, height, pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in data {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg());
+            }
+        }
+        !crc
+    }
+
+    fn push_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend_from_slice(chunk_type);
+        png.extend_from_slice(data);
+        let crc = crc32(&png[start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    /// Wraps data in a zlib stream made of a single stored block
+    fn make_stored_zlib(data: &[u8]) -> Vec<u8> {
+        let mut zlib = alloc::vec![0x78, 0x01, 0x01];
+        zlib.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(data.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(data);
+        zlib.extend_from_slice(&inflate::adler32(data).to_be_bytes());
+        zlib
+    }
+
+    fn make_png(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: u8,
+        interlaced: bool,
+        extra_chunks: &[(&[u8; 4], &[u8])],
+        zlib_data: &[u8],
+    ) -> Vec<u8> {
+        let mut png = SIGNATURE.to_vec();
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[bit_depth, color_type, 0, 0, interlaced as u8]);
+        push_chunk(&mut png, b"IHDR", &header);
+        for &(chunk_type, data) in extra_chunks {
+            push_chunk(&mut png, chunk_type, data);
+        }
+        push_chunk(&mut png, b"IDAT", zlib_data);
+        push_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    fn get_rgba(image: &Image, x: u32, y: u32) -> (u8, u8, u8, u8) {
+        let pixel = image.get_pixel(x, y).unwrap();
+        (pixel.r(), pixel.g(), pixel.b(), pixel.a())
+    }
+
+    #[test]
+    fn decode_rgba() {
+        #[rustfmt::skip]
+        let raw = [
+            // No filter
+            0, 0xFF, 0, 0, 0xFF, 0, 0xFF, 0, 0x80,
+            // Up filter
+            2, 0, 0, 0xFF, 0, 0x10, 0, 0x10, 0x7F,
+        ];
+        let png = make_png(2, 2, 8, 6, false, &[], &make_stored_zlib(&raw));
+        let image = Image::decode(&png).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 2));
+        assert_eq!(get_rgba(&image, 0, 0), (0xFF, 0, 0, 0xFF));
+        assert_eq!(get_rgba(&image, 1, 0), (0, 0xFF, 0, 0x80));
+        assert_eq!(get_rgba(&image, 0, 1), (0xFF, 0, 0xFF, 0xFF));
+        assert_eq!(get_rgba(&image, 1, 1), (0x10, 0xFF, 0x10, 0xFF));
+    }
+
+    #[test]
+    fn decode_indexed_transparency() {
+        // 3x2, 2-bit indices: 0 1 2 / 2 1 0
+        let raw = [0, 0b0001_1000, 0, 0b1001_0000];
+        let palette = [0xFF, 0, 0, 0, 0xFF, 0, 0, 0, 0xFF];
+        let alpha = [0x00, 0x80];
+        let png = make_png(
+            3,
+            2,
+            2,
+            3,
+            false,
+            &[(b"PLTE", &palette), (b"tRNS", &alpha)],
+            &make_stored_zlib(&raw),
+        );
+        let image = Image::decode(&png).unwrap();
+        assert_eq!(get_rgba(&image, 0, 0), (0xFF, 0, 0, 0));
+        assert_eq!(get_rgba(&image, 1, 0), (0, 0xFF, 0, 0x80));
+        assert_eq!(get_rgba(&image, 2, 0), (0, 0, 0xFF, 0xFF));
+        assert_eq!(get_rgba(&image, 0, 1), (0, 0, 0xFF, 0xFF));
+    }
+
+    #[test]
+    fn decode_interlaced() {
+        // 3x3 8-bit grayscale, every pixel set to its index: only passes 1, 4, 6 and 7 have rows
+        let raw = [0, 0, 0, 2, 0, 6, 8, 0, 1, 0, 7, 0, 3, 4, 5];
+        let png = make_png(3, 3, 8, 0, true, &[], &make_stored_zlib(&raw));
+        let image = Image::decode(&png).unwrap();
+        for y in 0..3 {
+            for x in 0..3 {
+                let value = (y * 3 + x) as u8;
+                assert_eq!(get_rgba(&image, x, y), (value, value, value, 0xFF));
+            }
+        }
+    }
+
+    #[test]
+    fn decode_size_mismatch() {
+        // 1x1 RGB needs exactly 4 bytes of image data
+        for raw in [&[0u8, 1, 2][..], &[0, 1, 2, 3, 4]] {
+            let png = make_png(1, 1, 8, 2, false, &[], &make_stored_zlib(raw));
+            let rc = Image::decode(&png).unwrap_err();
+            assert!(rc::ResultInvalidImageData::matches(rc));
+        }
+    }
+
+    #[test]
+    fn decode_oversized_dimensions() {
+        // The compressed data can't possibly hold the image data for these dimensions
+        let png = make_png(
+            0x4000,
+            0x4000,
+            16,
+            6,
+            false,
+            &[],
+            &make_stored_zlib(&[0; 16]),
+        );
+        let rc = Image::decode(&png).unwrap_err();
+        assert!(rc::ResultInvalidImageData::matches(rc));
+
+        let png = make_png(0x4001, 1, 8, 0, false, &[], &make_stored_zlib(&[0; 16]));
+        let rc = Image::decode(&png).unwrap_err();
+        assert!(rc::ResultInvalidImageSize::matches(rc));
+    }
+}
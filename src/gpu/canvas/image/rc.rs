@@ -0,0 +1,12 @@
+//! Image-related result definitions
+
+use crate::rc;
+
+/// Result Submodule ID for the parent module
+pub const RESULT_SUBMODULE: u32 = 1700;
+
+result_define_subgroup!(rc::RESULT_MODULE, RESULT_SUBMODULE => {
+    InvalidImageData: 1,
+    UnsupportedImageFormat: 2,
+    InvalidImageSize: 3
+});
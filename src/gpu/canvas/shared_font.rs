@@ -0,0 +1,177 @@
+//! System shared font support
+//!
+//! The system keeps its fonts (see [`SharedFontType`]) in a shared memory block provided by the `pl:u` service, so they can be used without bundling any font data.
+//! Fonts are borrowed straight from the mapped memory as [`Font`]s, usable with [`Canvas::draw_font_text`][`super::Canvas::draw_font_text`] or in a [`FontCollection`].
+
+use super::{Font, FontCollection};
+use crate::ipc::sf;
+use crate::result::*;
+use crate::service;
+use crate::service::pl::{
+    IPlatformServiceManagerClient, LoadState, PlatformServiceManagerService, SharedFontType,
+};
+use crate::svc;
+use crate::vmem;
+use alloc::vec::Vec;
+
+pub mod rc;
+
+pub mod bfttf;
+
+/// The size of the shared font memory
+pub const SHMEM_SIZE: usize = 0x1100000;
+
+/// The maximum amount of fonts returned by the service
+const MAX_FONT_COUNT: usize = SharedFontType::ALL.len();
+
+/// The time to wait between font load state checks, in nanoseconds
+const LOAD_POLL_INTERVAL: i64 = 1_000_000;
+
+/// The maximum amount of font load state checks before giving up
+const LOAD_POLL_COUNT: usize = 1000;
+
+/// Represents a mapping of the system shared fonts
+///
+/// Fonts borrow the mapped memory, so they can't outlive this type.
+pub struct SharedFontManager {
+    service: PlatformServiceManagerService,
+    shmem_handle: svc::Handle,
+    shmem_address: *mut u8,
+}
+
+impl SharedFontManager {
+    /// Creates a new [`SharedFontManager`], opening the `pl:u` service and mapping the shared font memory
+    pub fn new() -> Result<Self> {
+        let service = service::new_service_object::<PlatformServiceManagerService>()?;
+        let shmem_handle = service.get_shared_memory_native_handle()?.handle;
+        let map_shmem = || -> Result<*mut u8> {
+            let shmem_address = vmem::allocate(SHMEM_SIZE)?;
+            unsafe {
+                svc::map_shared_memory(
+                    shmem_handle,
+                    shmem_address,
+                    SHMEM_SIZE,
+                    svc::MemoryPermission::Read(),
+                )?
+            };
+            Ok(shmem_address)
+        };
+        let shmem_address = match map_shmem() {
+            Ok(shmem_address) => shmem_address,
+            Err(rc) => {
+                // The handle is only closed on drop once mapped, so it would be leaked otherwise
+                let _ = svc::close_handle(shmem_handle);
+                return Err(rc);
+            }
+        };
+
+        Ok(Self {
+            service,
+            shmem_handle,
+            shmem_address,
+        })
+    }
+
+    #[inline]
+    fn shmem(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.shmem_address, SHMEM_SIZE) }
+    }
+
+    /// Requests a shared font to be loaded, and waits until it is
+    ///
+    /// # Arguments
+    ///
+    /// * `font_type`: The font to load
+    pub fn load(&self, font_type: SharedFontType) -> Result<()> {
+        self.service.request_load(font_type)?;
+        for _ in 0..LOAD_POLL_COUNT {
+            if self.service.get_load_state(font_type)? == LoadState::Loaded {
+                return Ok(());
+            }
+            svc::sleep_thread(LOAD_POLL_INTERVAL)?;
+        }
+        rc::ResultFontLoadTimedOut::make_err()
+    }
+
+    /// Gets the TrueType data of a shared font, loading it if needed
+    ///
+    /// # Arguments
+    ///
+    /// * `font_type`: The font to get
+    pub fn get_font_data(&self, font_type: SharedFontType) -> Result<&[u8]> {
+        self.load(font_type)?;
+        let offset = self.service.get_shared_memory_address_offset(font_type)?;
+        bfttf::get_shared_font_data(self.shmem(), offset as usize)
+    }
+
+    /// Gets a shared font, loading it if needed
+    ///
+    /// # Arguments
+    ///
+    /// * `font_type`: The font to get
+    pub fn get_font(&self, font_type: SharedFontType) -> Result<Font<'_>> {
+        Font::try_from_slice(self.get_font_data(font_type)?)
+            .map_err(|_| rc::ResultInvalidFontData::make())
+    }
+
+    /// Gets a [`FontCollection`] of shared fonts, where the first one is the main font and the rest are fallbacks
+    ///
+    /// # Arguments
+    ///
+    /// * `font_types`: The fonts to use, in priority order (there must be at least one)
+    pub fn get_font_collection(&self, font_types: &[SharedFontType]) -> Result<FontCollection<'_>> {
+        let (&main_type, fallback_types) = font_types
+            .split_first()
+            .ok_or(rc::ResultInvalidFontData::make())?;
+        let mut fonts = FontCollection::new(self.get_font(main_type)?);
+        for &font_type in fallback_types {
+            fonts.push_fallback(self.get_font(font_type)?);
+        }
+        Ok(fonts)
+    }
+
+    /// Gets all the shared fonts, in the priority order the system uses for the given language
+    ///
+    /// # Arguments
+    ///
+    /// * `language_code`: The language code (as returned by the settings services)
+    pub fn get_fonts_in_order_of_priority(&self, language_code: u64) -> Result<Vec<Font<'_>>> {
+        let mut font_codes = [0u32; MAX_FONT_COUNT];
+        let mut offsets = [0u32; MAX_FONT_COUNT];
+        let mut sizes = [0u32; MAX_FONT_COUNT];
+        let mut get_order = || {
+            self.service.get_shared_font_in_order_of_priority(
+                language_code,
+                sf::Buffer::from_mut_array(&mut font_codes),
+                sf::Buffer::from_mut_array(&mut offsets),
+                sf::Buffer::from_mut_array(&mut sizes),
+            )
+        };
+
+        let (mut loaded, mut count) = get_order()?;
+        if !loaded {
+            // The offsets are only valid once every font is loaded
+            for font_type in SharedFontType::ALL {
+                self.load(font_type)?;
+            }
+            (loaded, count) = get_order()?;
+            result_return_unless!(loaded, rc::ResultFontLoadTimedOut);
+        }
+
+        let mut fonts = Vec::new();
+        for &offset in offsets.iter().take((count as usize).min(MAX_FONT_COUNT)) {
+            let data = bfttf::get_shared_font_data(self.shmem(), offset as usize)?;
+            fonts.push(Font::try_from_slice(data).map_err(|_| rc::ResultInvalidFontData::make())?);
+        }
+        Ok(fonts)
+    }
+}
+
+impl Drop for SharedFontManager {
+    /// Destroys the [`SharedFontManager`], un-mapping the shared memory and closing it
+    fn drop(&mut self) {
+        let _ =
+            unsafe { svc::unmap_shared_memory(self.shmem_handle, self.shmem_address, SHMEM_SIZE) };
+        let _ = svc::close_handle(self.shmem_handle);
+    }
+}
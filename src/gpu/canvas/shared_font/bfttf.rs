@@ -0,0 +1,183 @@
+//! BFTTF font (de)obfuscation
+//!
+//! BFTTF is the format the system stores its shared fonts in: an 8-byte header (magic and big-endian font size) followed by the TrueType data, all XORed with a repeating 4-byte key.
+//! The key is recovered from the (known) magic, so fonts obfuscated with any key can be decoded.
+//!
+//! These functions don't depend on any system services, so they can be used (and tested) outside of the console as well.
+
+use super::rc;
+use crate::result::*;
+use alloc::vec::Vec;
+
+/// The size of the BFTTF header
+pub const HEADER_SIZE: usize = 8;
+
+/// The de-obfuscated magic at the start of the header
+pub const MAGIC: [u8; 4] = [0x7F, 0x9A, 0x02, 0x18];
+
+/// The key the system's shared fonts are obfuscated with
+pub const SHARED_FONT_KEY: [u8; 4] = [0x49, 0x62, 0x18, 0x06];
+
+#[inline]
+fn xor_key(data: [u8; 4], key: [u8; 4]) -> [u8; 4] {
+    [
+        data[0] ^ key[0],
+        data[1] ^ key[1],
+        data[2] ^ key[2],
+        data[3] ^ key[3],
+    ]
+}
+
+/// XORs data with a repeating key, starting at the first key byte
+fn apply_key(data: &mut [u8], key: [u8; 4]) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= key[i % key.len()];
+    }
+}
+
+/// Gets the key and font size from an obfuscated BFTTF header
+///
+/// # Arguments
+///
+/// * `data`: The BFTTF data, starting with its header
+pub fn read_header(data: &[u8]) -> Result<([u8; 4], usize)> {
+    result_return_unless!(data.len() >= HEADER_SIZE, rc::ResultInvalidFontSize);
+    let key = xor_key([data[0], data[1], data[2], data[3]], MAGIC);
+    let size = u32::from_be_bytes(xor_key([data[4], data[5], data[6], data[7]], key)) as usize;
+    result_return_unless!(size <= data.len() - HEADER_SIZE, rc::ResultInvalidFontSize);
+    Ok((key, size))
+}
+
+/// De-obfuscates a BFTTF font, returning its TrueType data
+///
+/// # Arguments
+///
+/// * `data`: The BFTTF data
+pub fn decode(data: &[u8]) -> Result<Vec<u8>> {
+    let (key, size) = read_header(data)?;
+    let mut font = Vec::from(&data[HEADER_SIZE..HEADER_SIZE + size]);
+    apply_key(&mut font, key);
+    Ok(font)
+}
+
+/// Obfuscates TrueType data as a BFTTF font
+///
+/// # Arguments
+///
+/// * `font`: The TrueType data (its size must fit in 32 bits)
+/// * `key`: The key to obfuscate with
+pub fn encode(font: &[u8], key: [u8; 4]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(HEADER_SIZE + font.len());
+    data.extend_from_slice(&MAGIC);
+    data.extend_from_slice(&(font.len() as u32).to_be_bytes());
+    data.extend_from_slice(font);
+    apply_key(&mut data, key);
+    data
+}
+
+/// Gets the TrueType data of a font in the shared font memory
+///
+/// Fonts are already de-obfuscated in shared memory, but their header still is: it keeps the plain magic, followed by the size obfuscated with [`SHARED_FONT_KEY`].
+///
+/// # Arguments
+///
+/// * `shmem`: The shared font memory
+/// * `offset`: The offset of the font data (right after its header), as given by the service
+pub fn get_shared_font_data(shmem: &[u8], offset: usize) -> Result<&[u8]> {
+    result_return_unless!(
+        (HEADER_SIZE..=shmem.len()).contains(&offset),
+        rc::ResultInvalidFontSize
+    );
+    let header = &shmem[offset - HEADER_SIZE..offset];
+    result_return_unless!(header[..4] == MAGIC, rc::ResultInvalidFontMagic);
+
+    let size = u32::from_be_bytes(xor_key(
+        [header[4], header[5], header[6], header[7]],
+        SHARED_FONT_KEY,
+    )) as usize;
+    result_return_unless!(size <= shmem.len() - offset, rc::ResultInvalidFontSize);
+    Ok(&shmem[offset..offset + size])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONT: &[u8] = b"\x00\x01\x00\x00 not really a TrueType font";
+
+    /// Lays out a font the way it is in the shared font memory, returning the memory and the font offset
+    fn make_shmem(font: &[u8], magic: [u8; 4], size: u32) -> (Vec<u8>, usize) {
+        let mut shmem = vec![0xAA; 0x10];
+        shmem.extend_from_slice(&magic);
+        shmem.extend_from_slice(&xor_key(size.to_be_bytes(), SHARED_FONT_KEY));
+        let offset = shmem.len();
+        shmem.extend_from_slice(font);
+        shmem.extend_from_slice(&[0xAA; 0x10]);
+        (shmem, offset)
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        for key in [SHARED_FONT_KEY, [0; 4], [0xDE, 0xAD, 0xBE, 0xEF]] {
+            let data = encode(FONT, key);
+            assert_eq!(data.len(), HEADER_SIZE + FONT.len());
+            assert_eq!(read_header(&data).unwrap(), (key, FONT.len()));
+            assert_eq!(decode(&data).unwrap(), FONT);
+        }
+    }
+
+    #[test]
+    fn encode_shared_font_key() {
+        let data = encode(FONT, SHARED_FONT_KEY);
+        assert_eq!(data[..4], xor_key(MAGIC, SHARED_FONT_KEY));
+        assert_ne!(data[HEADER_SIZE..], *FONT);
+    }
+
+    #[test]
+    fn decode_ignores_trailing_data() {
+        let mut data = encode(FONT, SHARED_FONT_KEY);
+        data.extend_from_slice(&[0xFF; 3]);
+        assert_eq!(decode(&data).unwrap(), FONT);
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let data = encode(FONT, SHARED_FONT_KEY);
+        let rc = decode(&data[..data.len() - 1]).unwrap_err();
+        assert!(rc::ResultInvalidFontSize::matches(rc));
+        let rc = read_header(&data[..HEADER_SIZE - 1]).unwrap_err();
+        assert!(rc::ResultInvalidFontSize::matches(rc));
+    }
+
+    #[test]
+    fn shared_font_data() {
+        let (shmem, offset) = make_shmem(FONT, MAGIC, FONT.len() as u32);
+        assert_eq!(get_shared_font_data(&shmem, offset).unwrap(), FONT);
+    }
+
+    #[test]
+    fn shared_font_data_bad_offset() {
+        let (shmem, offset) = make_shmem(FONT, MAGIC, FONT.len() as u32);
+        for bad_offset in [0, HEADER_SIZE - 1, shmem.len() + 1, usize::MAX] {
+            let rc = get_shared_font_data(&shmem, bad_offset).unwrap_err();
+            assert!(rc::ResultInvalidFontSize::matches(rc));
+        }
+        // The header isn't where the offset says it is
+        let rc = get_shared_font_data(&shmem, offset + 1).unwrap_err();
+        assert!(rc::ResultInvalidFontMagic::matches(rc));
+    }
+
+    #[test]
+    fn shared_font_data_bad_magic() {
+        let (shmem, offset) = make_shmem(FONT, [0x7F, 0x9A, 0x02, 0x19], FONT.len() as u32);
+        let rc = get_shared_font_data(&shmem, offset).unwrap_err();
+        assert!(rc::ResultInvalidFontMagic::matches(rc));
+    }
+
+    #[test]
+    fn shared_font_data_oversized() {
+        let (shmem, offset) = make_shmem(FONT, MAGIC, u32::MAX);
+        let rc = get_shared_font_data(&shmem, offset).unwrap_err();
+        assert!(rc::ResultInvalidFontSize::matches(rc));
+    }
+}
@@ -0,0 +1,13 @@
+//! Shared font-related result definitions
+
+use crate::rc;
+
+/// Result Submodule ID for the parent module
+pub const RESULT_SUBMODULE: u32 = 1800;
+
+result_define_subgroup!(rc::RESULT_MODULE, RESULT_SUBMODULE => {
+    InvalidFontMagic: 1,
+    InvalidFontSize: 2,
+    InvalidFontData: 3,
+    FontLoadTimedOut: 4
+});
@@ -0,0 +1,1012 @@
+//! Text layout and cached glyph rendering for canvases
+//!
+//! Text is first laid out into a [`TextLayout`] (which can also be used just to measure it), using a [`FontCollection`] so that characters missing from the main font are taken from fallback fonts.
+//! Layouts are drawn with [`Canvas::draw_text_layout`][`super::Canvas::draw_text_layout`], which rasterizes each glyph once into a [`GlyphCache`] atlas and reuses it afterwards.
+
+use super::vector::draw_coverage;
+use super::{AlphaBlend, Canvas, Font};
+use ab_glyph::{Font as _, GlyphId, PxScale, PxScaleFont, ScaleFont};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+#[cfg(not(test))]
+use num_traits::float::Float;
+
+/// The amount of horizontal subpixel positions glyphs are rasterized at
+const SUBPIXEL_STEPS: u8 = 4;
+
+/// The default width/height of glyph cache atlases
+pub const DEFAULT_ATLAS_SIZE: u32 = 512;
+
+/// Represents a font with optional fallback fonts, used for characters the previous fonts don't have
+///
+/// Line metrics (ascent, descent and line gap) are always taken from the main font.
+#[derive(Clone, Debug)]
+pub struct FontCollection<'a> {
+    fonts: Vec<Font<'a>>,
+}
+
+impl<'a> FontCollection<'a> {
+    /// Creates a new [`FontCollection`] with the given main font
+    ///
+    /// # Arguments
+    ///
+    /// * `font`: The main font
+    pub fn new(font: Font<'a>) -> Self {
+        Self {
+            fonts: alloc::vec![font],
+        }
+    }
+
+    /// Adds a fallback font, with lower priority than the current ones
+    ///
+    /// # Arguments
+    ///
+    /// * `font`: The fallback font
+    pub fn with_fallback(mut self, font: Font<'a>) -> Self {
+        self.push_fallback(font);
+        self
+    }
+
+    /// Adds a fallback font, with lower priority than the current ones
+    ///
+    /// # Arguments
+    ///
+    /// * `font`: The fallback font
+    pub fn push_fallback(&mut self, font: Font<'a>) {
+        self.fonts.push(font);
+    }
+
+    /// Gets the fonts, in priority order
+    #[inline]
+    pub fn fonts(&self) -> &[Font<'a>] {
+        &self.fonts
+    }
+
+    /// Finds the first font with a glyph for the given character, returning its index and the glyph (the main font's missing glyph if none has it)
+    fn find_glyph(&self, c: char) -> (usize, GlyphId) {
+        self.fonts
+            .iter()
+            .enumerate()
+            .map(|(index, font)| (index, font.glyph_id(c)))
+            .find(|(_, id)| id.0 != 0)
+            .unwrap_or((0, GlyphId(0)))
+    }
+
+    /// Gets whether any font has a glyph for the given character
+    fn has_glyph(&self, c: char) -> bool {
+        self.find_glyph(c).1.0 != 0
+    }
+
+    /// Gets a font scaled to the given size in points
+    fn scaled(&self, index: usize, size: f32) -> PxScaleFont<&Font<'a>> {
+        let font = &self.fonts[index];
+        font.as_scaled(font_scale(font, size))
+    }
+}
+
+impl<'a> From<Font<'a>> for FontCollection<'a> {
+    fn from(font: Font<'a>) -> Self {
+        Self::new(font)
+    }
+}
+
+fn font_scale(font: &Font, size: f32) -> PxScale {
+    font.pt_to_px_scale(size).unwrap_or(PxScale::from(size))
+}
+
+/// Represents the horizontal alignment of text lines
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Represents how text is wrapped when it doesn't fit in the maximum width
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum TextWrap {
+    /// Lines are only broken at newlines
+    None,
+    /// Lines are broken between words (or CJK characters), and within words which don't fit on their own
+    #[default]
+    Word,
+}
+
+/// Represents the options used to lay out text
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TextOptions {
+    /// The font size, in points
+    pub size: f32,
+    /// The width of the box the text is laid out in, unbounded if [`None`]
+    pub max_width: Option<u32>,
+    /// The maximum amount of lines, unbounded if [`None`]
+    pub max_lines: Option<u32>,
+    /// How lines are wrapped when they exceed `max_width`
+    pub wrap: TextWrap,
+    /// The line alignment, within `max_width` (or the widest line if there's none)
+    pub align: TextAlign,
+    /// Whether truncated lines end with an ellipsis
+    ///
+    /// Lines are truncated when they exceed `max_width` without wrapping, or when the text is cut off by `max_lines`.
+    pub ellipsis: bool,
+    /// The line height, as a factor of the main font's line height
+    pub line_spacing: f32,
+}
+
+impl TextOptions {
+    /// Creates a new [`TextOptions`] with the given font size, with unbounded left-aligned text
+    ///
+    /// # Arguments
+    ///
+    /// * `size`: The font size, in points
+    #[inline]
+    pub const fn new(size: f32) -> Self {
+        Self {
+            size,
+            max_width: None,
+            max_lines: None,
+            wrap: TextWrap::Word,
+            align: TextAlign::Left,
+            ellipsis: false,
+            line_spacing: 1.0,
+        }
+    }
+
+    /// Sets the maximum width
+    #[inline]
+    pub const fn with_max_width(mut self, max_width: u32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    /// Sets the maximum amount of lines
+    #[inline]
+    pub const fn with_max_lines(mut self, max_lines: u32) -> Self {
+        self.max_lines = Some(max_lines);
+        self
+    }
+
+    /// Sets the wrap mode
+    #[inline]
+    pub const fn with_wrap(mut self, wrap: TextWrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Sets the alignment
+    #[inline]
+    pub const fn with_align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+
+    /// Sets whether truncated lines end with an ellipsis
+    #[inline]
+    pub const fn with_ellipsis(mut self, ellipsis: bool) -> Self {
+        self.ellipsis = ellipsis;
+        self
+    }
+
+    /// Sets the line spacing factor
+    #[inline]
+    pub const fn with_line_spacing(mut self, line_spacing: f32) -> Self {
+        self.line_spacing = line_spacing;
+        self
+    }
+}
+
+/// Represents a glyph positioned by a [`TextLayout`]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LayoutGlyph {
+    /// The index of the glyph's font in the [`FontCollection`]
+    pub font: usize,
+    /// The glyph's ID within its font
+    pub id: GlyphId,
+    /// The X coordinate of the glyph origin, relative to the layout's left edge
+    pub x: f32,
+    /// The Y coordinate of the glyph's baseline, relative to the layout's top edge
+    pub y: f32,
+}
+
+/// A glyph being placed on a line
+#[derive(Copy, Clone)]
+struct LineGlyph {
+    font: usize,
+    id: GlyphId,
+    x: f32,
+    advance: f32,
+    is_whitespace: bool,
+}
+
+/// Gets the width of a line, ignoring its trailing whitespace
+fn line_width(glyphs: &[LineGlyph]) -> f32 {
+    glyphs
+        .iter()
+        .rev()
+        .find(|glyph| !glyph.is_whitespace)
+        .map(|glyph| glyph.x + glyph.advance)
+        .unwrap_or(0.0)
+}
+
+/// Gets whether lines can be broken before and after a character even without whitespace (CJK text has no spaces between words)
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x2E80..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF | 0xFF00..=0xFFEF | 0x20000..=0x3FFFF)
+}
+
+/// Represents laid out text, ready to be measured or drawn
+#[derive(Clone, PartialEq, Debug)]
+pub struct TextLayout {
+    glyphs: Vec<LayoutGlyph>,
+    size: f32,
+    width: f32,
+    height: f32,
+    line_count: u32,
+}
+
+impl TextLayout {
+    /// Lays out text with the given fonts and options
+    ///
+    /// Newlines (`\n`) always start a new line, other control characters are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `fonts`: The fonts to use
+    /// * `text`: The text to lay out
+    /// * `options`: The layout options
+    pub fn new(fonts: &FontCollection, text: impl AsRef<str>, options: &TextOptions) -> Self {
+        let size = options.size;
+        let max_width = options.max_width.map(|width| width as f32);
+        let main_font = fonts.scaled(0, size);
+        let ascent = main_font.ascent();
+        let descent = main_font.descent();
+        let line_height = (main_font.height() + main_font.line_gap()) * options.line_spacing;
+
+        let mut lines: Vec<Vec<LineGlyph>> = Vec::new();
+        'paragraphs: for paragraph in text.as_ref().split('\n') {
+            let mut line: Vec<LineGlyph> = Vec::new();
+            let mut pen = 0.0f32;
+            // Index where the line can be broken (the first glyph of the next line)
+            let mut break_index: Option<usize> = None;
+            let mut previous: Option<(usize, GlyphId)> = None;
+
+            for c in paragraph.chars().filter(|c| !c.is_control()) {
+                let (font_index, id) = fonts.find_glyph(c);
+                let font = fonts.scaled(font_index, size);
+                if let Some((previous_font, previous_id)) = previous
+                    && (previous_font == font_index)
+                {
+                    pen += font.kern(previous_id, id);
+                }
+                let advance = font.h_advance(id);
+                let is_whitespace = c.is_whitespace();
+                if is_cjk(c) {
+                    break_index = Some(line.len());
+                }
+
+                if let Some(max_width) = max_width
+                    && (options.wrap == TextWrap::Word)
+                    && !is_whitespace
+                    && !line.is_empty()
+                    && (pen + advance > max_width)
+                {
+                    // Break at the last opportunity, or right here if the word doesn't fit on its own
+                    let split = break_index.filter(|&index| index > 0).unwrap_or(line.len());
+                    let mut rest = line.split_off(split);
+                    lines.push(core::mem::take(&mut line));
+                    if options
+                        .max_lines
+                        .is_some_and(|max| lines.len() > max as usize)
+                    {
+                        break 'paragraphs;
+                    }
+
+                    let offset = rest.first().map(|glyph| glyph.x).unwrap_or(pen);
+                    for glyph in rest.iter_mut() {
+                        glyph.x -= offset;
+                    }
+                    line = rest;
+                    pen -= offset;
+                    break_index = None;
+                }
+
+                line.push(LineGlyph {
+                    font: font_index,
+                    id,
+                    x: pen,
+                    advance,
+                    is_whitespace,
+                });
+                pen += advance;
+                if is_whitespace || is_cjk(c) {
+                    break_index = Some(line.len());
+                }
+                previous = Some((font_index, id));
+            }
+
+            lines.push(line);
+            if options
+                .max_lines
+                .is_some_and(|max| lines.len() > max as usize)
+            {
+                break;
+            }
+        }
+
+        // Text cut off by the line limit is truncated at the last visible line
+        let mut truncated_index = None;
+        if let Some(max_lines) = options.max_lines
+            && (lines.len() > max_lines as usize)
+        {
+            lines.truncate(max_lines as usize);
+            truncated_index = lines.len().checked_sub(1);
+        }
+
+        if options.ellipsis {
+            let ellipsis = Self::ellipsis_glyphs(fonts, size);
+            let ellipsis_width = line_width(&ellipsis);
+            for (index, line) in lines.iter_mut().enumerate() {
+                let overflows = max_width.is_some_and(|max_width| line_width(line) > max_width);
+                if overflows || (truncated_index == Some(index)) {
+                    Self::add_ellipsis(line, &ellipsis, ellipsis_width, max_width);
+                }
+            }
+        }
+
+        let widths: Vec<f32> = lines.iter().map(|line| line_width(line)).collect();
+        let width = widths.iter().fold(0.0f32, |max, &width| max.max(width));
+        let box_width = max_width.unwrap_or(width);
+
+        let mut glyphs = Vec::new();
+        for (index, (line, line_width)) in lines.iter().zip(widths).enumerate() {
+            let offset = match options.align {
+                TextAlign::Left => 0.0,
+                TextAlign::Center => (box_width - line_width) / 2.0,
+                TextAlign::Right => box_width - line_width,
+            };
+            let baseline = ascent + index as f32 * line_height;
+            glyphs.extend(
+                line.iter()
+                    .filter(|glyph| !glyph.is_whitespace)
+                    .map(|glyph| LayoutGlyph {
+                        font: glyph.font,
+                        id: glyph.id,
+                        x: offset + glyph.x,
+                        y: baseline,
+                    }),
+            );
+        }
+
+        let height = match lines.len() {
+            0 => 0.0,
+            count => ascent - descent + (count - 1) as f32 * line_height,
+        };
+        Self {
+            glyphs,
+            size,
+            width,
+            height,
+            line_count: lines.len() as u32,
+        }
+    }
+
+    /// Gets the glyphs for an ellipsis, using three periods if no font has an ellipsis character
+    fn ellipsis_glyphs(fonts: &FontCollection, size: f32) -> Vec<LineGlyph> {
+        let text = if fonts.has_glyph('…') { "…" } else { "..." };
+        let mut glyphs = Vec::new();
+        let mut pen = 0.0;
+        for c in text.chars() {
+            let (font_index, id) = fonts.find_glyph(c);
+            let advance = fonts.scaled(font_index, size).h_advance(id);
+            glyphs.push(LineGlyph {
+                font: font_index,
+                id,
+                x: pen,
+                advance,
+                is_whitespace: false,
+            });
+            pen += advance;
+        }
+        glyphs
+    }
+
+    /// Appends an ellipsis to a line, removing glyphs from its end until it fits in the maximum width
+    fn add_ellipsis(
+        line: &mut Vec<LineGlyph>,
+        ellipsis: &[LineGlyph],
+        ellipsis_width: f32,
+        max_width: Option<f32>,
+    ) {
+        loop {
+            while line.last().is_some_and(|glyph| glyph.is_whitespace) {
+                line.pop();
+            }
+            let fits =
+                max_width.is_none_or(|max_width| line_width(line) + ellipsis_width <= max_width);
+            if fits || line.pop().is_none() {
+                break;
+            }
+        }
+
+        let pen = line_width(line);
+        line.extend(ellipsis.iter().map(|glyph| LineGlyph {
+            x: pen + glyph.x,
+            ..*glyph
+        }));
+    }
+
+    /// Gets the positioned glyphs (whitespace has no glyphs)
+    #[inline]
+    pub fn glyphs(&self) -> &[LayoutGlyph] {
+        &self.glyphs
+    }
+
+    /// Gets the font size the text was laid out with, in points
+    #[inline]
+    pub const fn size(&self) -> f32 {
+        self.size
+    }
+
+    /// Gets the width of the widest line, in pixels
+    #[inline]
+    pub const fn width(&self) -> f32 {
+        self.width
+    }
+
+    /// Gets the height from the top of the first line to the bottom of the last one, in pixels
+    #[inline]
+    pub const fn height(&self) -> f32 {
+        self.height
+    }
+
+    /// Gets the amount of lines
+    #[inline]
+    pub const fn line_count(&self) -> u32 {
+        self.line_count
+    }
+}
+
+/// Measures text, returning the size (`(width, height)`) in pixels it takes when drawn
+///
+/// # Arguments
+///
+/// * `fonts`: The fonts to use
+/// * `text`: The text to measure
+/// * `options`: The layout options
+pub fn measure_text(
+    fonts: &FontCollection,
+    text: impl AsRef<str>,
+    options: &TextOptions,
+) -> (u32, u32) {
+    let layout = TextLayout::new(fonts, text, options);
+    (layout.width().ceil() as u32, layout.height().ceil() as u32)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct GlyphKey {
+    /// Fonts are identified by the address of their data, which is only unique while the font data is alive
+    font: usize,
+    scale_x: u32,
+    scale_y: u32,
+    glyph: u16,
+    subpixel: u8,
+}
+
+/// The location of a rasterized glyph in the atlas
+#[derive(Copy, Clone, Debug)]
+struct AtlasRegion {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    /// The offset of the glyph's top-left corner from its origin on the baseline
+    left: i32,
+    top: i32,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum CacheEntry {
+    /// The glyph has no outline (or it's empty)
+    Empty,
+    Atlas(AtlasRegion),
+    /// The glyph is too large for the atlas, so it's rasterized every time
+    Uncached,
+}
+
+/// Represents a cache of rasterized glyphs, stored as coverage values in a single atlas
+///
+/// Glyphs are keyed by font, size, glyph and subpixel position. When the atlas is full, it's cleared and filled again with the glyphs in use.
+/// Fonts are identified by the address of their data, so a cache shouldn't be used with different faces from the same font data.
+/// For the same reason, a cache must be [cleared][`GlyphCache::clear`] once a font it was used with is freed, otherwise a different font later loaded at the same address would be drawn with the stale glyphs.
+#[derive(Clone, Debug)]
+pub struct GlyphCache {
+    width: u32,
+    height: u32,
+    coverage: Vec<u8>,
+    entries: BTreeMap<GlyphKey, CacheEntry>,
+    shelf_x: u32,
+    shelf_y: u32,
+    shelf_height: u32,
+}
+
+impl GlyphCache {
+    /// Creates a new, empty [`GlyphCache`]
+    ///
+    /// # Arguments
+    ///
+    /// * `width`: The atlas width
+    /// * `height`: The atlas height
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            coverage: alloc::vec![0; width as usize * height as usize],
+            entries: BTreeMap::new(),
+            shelf_x: 0,
+            shelf_y: 0,
+            shelf_height: 0,
+        }
+    }
+
+    /// Removes all cached glyphs
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.shelf_x = 0;
+        self.shelf_y = 0;
+        self.shelf_height = 0;
+    }
+
+    /// Gets the amount of cached glyphs
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Gets whether no glyphs are cached
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Allocates an atlas region using shelf packing
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if self.shelf_x + width > self.width {
+            self.shelf_y += self.shelf_height;
+            self.shelf_x = 0;
+            self.shelf_height = 0;
+        }
+        if (width > self.width) || (self.shelf_y + height > self.height) {
+            return None;
+        }
+
+        let position = (self.shelf_x, self.shelf_y);
+        self.shelf_x += width;
+        self.shelf_height = self.shelf_height.max(height);
+        Some(position)
+    }
+
+    /// Gets a cached glyph, rasterizing it if it isn't cached yet
+    ///
+    /// Callers must make sure the cache doesn't hold glyphs from freed font data (see [`GlyphCache`]), as fonts are keyed by their data address.
+    fn get_or_insert(
+        &mut self,
+        font: &Font,
+        id: GlyphId,
+        scale: PxScale,
+        subpixel: u8,
+    ) -> CacheEntry {
+        let key = GlyphKey {
+            font: font.font_data().as_ptr() as usize,
+            scale_x: scale.x.to_bits(),
+            scale_y: scale.y.to_bits(),
+            glyph: id.0,
+            subpixel,
+        };
+        if let Some(entry) = self.entries.get(&key) {
+            return *entry;
+        }
+
+        let entry = self.rasterize(font, id, scale, subpixel);
+        self.entries.insert(key, entry);
+        entry
+    }
+
+    fn rasterize(&mut self, font: &Font, id: GlyphId, scale: PxScale, subpixel: u8) -> CacheEntry {
+        let position = ab_glyph::point(subpixel as f32 / SUBPIXEL_STEPS as f32, 0.0);
+        let Some(outline) = font.outline_glyph(id.with_scale_and_position(scale, position)) else {
+            return CacheEntry::Empty;
+        };
+        let bounds = outline.px_bounds();
+        let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+        if (width == 0) || (height == 0) {
+            return CacheEntry::Empty;
+        }
+
+        let (x, y) = match self.allocate(width, height) {
+            Some(position) => position,
+            None => {
+                // Start over with an empty atlas, unless the glyph wouldn't fit anyway
+                if (width > self.width) || (height > self.height) {
+                    return CacheEntry::Uncached;
+                }
+                self.clear();
+                match self.allocate(width, height) {
+                    Some(position) => position,
+                    None => return CacheEntry::Uncached,
+                }
+            }
+        };
+
+        let atlas_width = self.width as usize;
+        for row in y..y + height {
+            let start = row as usize * atlas_width + x as usize;
+            self.coverage[start..start + width as usize].fill(0);
+        }
+        outline.draw(|dx, dy, coverage| {
+            if (dx < width) && (dy < height) {
+                let index = (y + dy) as usize * atlas_width + (x + dx) as usize;
+                self.coverage[index] = (coverage.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+            }
+        });
+
+        CacheEntry::Atlas(AtlasRegion {
+            x,
+            y,
+            width,
+            height,
+            left: bounds.min.x as i32,
+            top: bounds.min.y as i32,
+        })
+    }
+}
+
+impl Default for GlyphCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_ATLAS_SIZE, DEFAULT_ATLAS_SIZE)
+    }
+}
+
+/// Draws a text layout onto a canvas, rasterizing glyphs through the cache
+///
+/// The cache must not hold glyphs from freed fonts whose data address may now belong to one of these fonts (see [`GlyphCache`]).
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn draw_layout<C: Canvas + ?Sized>(
+    canvas: &mut C,
+    layout: &TextLayout,
+    fonts: &FontCollection,
+    cache: &mut GlyphCache,
+    x: i32,
+    y: i32,
+    color: C::ColorFormat,
+    blend: AlphaBlend,
+) {
+    for glyph in layout.glyphs() {
+        let Some(font) = fonts.fonts().get(glyph.font) else {
+            continue;
+        };
+        let scale = font_scale(font, layout.size());
+
+        // Glyphs are placed at whole pixels vertically, and at a few subpixel positions horizontally
+        let pen_x = x as f32 + glyph.x;
+        let mut origin_x = pen_x.floor() as i32;
+        let mut subpixel = ((pen_x - pen_x.floor()) * SUBPIXEL_STEPS as f32).round() as u8;
+        if subpixel == SUBPIXEL_STEPS {
+            origin_x += 1;
+            subpixel = 0;
+        }
+        let origin_y = y + glyph.y.round() as i32;
+
+        match cache.get_or_insert(font, glyph.id, scale, subpixel) {
+            CacheEntry::Empty => {}
+            CacheEntry::Atlas(region) => {
+                for dy in 0..region.height {
+                    let row = (region.y + dy) as usize * cache.width as usize;
+                    for dx in 0..region.width {
+                        let coverage = cache.coverage[row + (region.x + dx) as usize];
+                        if coverage != 0 {
+                            draw_coverage(
+                                canvas,
+                                origin_x + region.left + dx as i32,
+                                origin_y + region.top + dy as i32,
+                                color,
+                                coverage as f32 / 255.0,
+                                blend,
+                            );
+                        }
+                    }
+                }
+            }
+            CacheEntry::Uncached => {
+                let position = ab_glyph::point(
+                    origin_x as f32 + subpixel as f32 / SUBPIXEL_STEPS as f32,
+                    origin_y as f32,
+                );
+                if let Some(outline) =
+                    font.outline_glyph(glyph.id.with_scale_and_position(scale, position))
+                {
+                    let bounds = outline.px_bounds();
+                    outline.draw(|dx, dy, coverage| {
+                        if coverage > 0.0 {
+                            draw_coverage(
+                                canvas,
+                                bounds.min.x as i32 + dx as i32,
+                                bounds.min.y as i32 + dy as i32,
+                                color,
+                                coverage.min(1.0),
+                                blend,
+                            );
+                        }
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tiny font (1000 units per em, 800 ascent, 200 descent) with box glyphs: lowercase letters are 500 units wide, spaces 250, and both `…` and `中` 1000
+    const TEST_FONT: &[u8] = include_bytes!("test_font.ttf");
+
+    /// The size at which the test font's em is 16 pixels, so letters are 8 pixels wide, spaces 4 and both `…` and `中` 16
+    const TEST_SIZE: f32 = 12.0;
+
+    fn test_fonts() -> FontCollection<'static> {
+        FontCollection::new(Font::try_from_slice(TEST_FONT).unwrap())
+    }
+
+    fn lay_out(text: &str, options: TextOptions) -> TextLayout {
+        TextLayout::new(&test_fonts(), text, &options)
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 0.001, "{actual} != {expected}");
+    }
+
+    /// Asserts the `(x, line)` positions of the laid out glyphs
+    fn assert_glyph_positions(layout: &TextLayout, expected: &[(f32, u32)]) {
+        assert_eq!(layout.glyphs().len(), expected.len());
+        for (glyph, &(x, line)) in layout.glyphs().iter().zip(expected) {
+            assert_close(glyph.x, x);
+            assert_close(glyph.y, 12.8 + line as f32 * 16.0);
+        }
+    }
+
+    #[test]
+    fn single_line() {
+        let layout = lay_out("ab c", TextOptions::new(TEST_SIZE));
+        assert_eq!(layout.line_count(), 1);
+        assert_close(layout.width(), 28.0);
+        assert_close(layout.height(), 16.0);
+        assert_glyph_positions(&layout, &[(0.0, 0), (8.0, 0), (20.0, 0)]);
+        assert_eq!(
+            measure_text(&test_fonts(), "ab c", &TextOptions::new(TEST_SIZE)),
+            (28, 16)
+        );
+
+        // Unknown characters use the missing glyph, and control characters are ignored
+        let layout = lay_out("a\u{7}?\n\nb", TextOptions::new(TEST_SIZE));
+        assert_eq!(layout.line_count(), 3);
+        assert_close(layout.height(), 48.0);
+        assert_eq!(layout.glyphs()[1].id, GlyphId(0));
+        assert_glyph_positions(&layout, &[(0.0, 0), (8.0, 0), (0.0, 2)]);
+    }
+
+    #[test]
+    fn wrap_words() {
+        let options = TextOptions::new(TEST_SIZE).with_max_width(40);
+        let layout = lay_out("ab cd ef", options);
+        assert_eq!(layout.line_count(), 2);
+        assert_close(layout.width(), 36.0);
+        assert_close(layout.height(), 32.0);
+        assert_glyph_positions(
+            &layout,
+            &[(0.0, 0), (8.0, 0), (20.0, 0), (28.0, 0), (0.0, 1), (8.0, 1)],
+        );
+
+        // Words which don't fit on their own are broken anywhere
+        let layout = lay_out("abcdefg hi", options.with_max_width(24));
+        assert_eq!(layout.line_count(), 4);
+        assert_close(layout.width(), 24.0);
+        assert_glyph_positions(
+            &layout,
+            &[
+                (0.0, 0),
+                (8.0, 0),
+                (16.0, 0),
+                (0.0, 1),
+                (8.0, 1),
+                (16.0, 1),
+                (0.0, 2),
+                (0.0, 3),
+                (8.0, 3),
+            ],
+        );
+
+        // Nothing is wrapped without a maximum width
+        let layout = lay_out("ab cd ef", options.with_wrap(TextWrap::None));
+        assert_eq!(layout.line_count(), 1);
+        assert_close(layout.width(), 56.0);
+    }
+
+    #[test]
+    fn wrap_cjk() {
+        let options = TextOptions::new(TEST_SIZE).with_max_width(24);
+        let layout = lay_out("ab中cd", options);
+        assert_eq!(layout.line_count(), 3);
+        assert_close(layout.width(), 16.0);
+        assert_glyph_positions(&layout, &[(0.0, 0), (8.0, 0), (0.0, 1), (0.0, 2), (8.0, 2)]);
+
+        let layout = lay_out("中中中", options.with_max_width(40));
+        assert_eq!(layout.line_count(), 2);
+        assert_close(layout.width(), 32.0);
+        assert_glyph_positions(&layout, &[(0.0, 0), (16.0, 0), (0.0, 1)]);
+    }
+
+    #[test]
+    fn max_lines_and_ellipsis() {
+        let ellipsis_id = test_fonts().fonts()[0].glyph_id('…');
+        let options = TextOptions::new(TEST_SIZE)
+            .with_max_width(40)
+            .with_max_lines(1);
+        let layout = lay_out("ab cd ef", options);
+        assert_eq!(layout.line_count(), 1);
+        assert_close(layout.width(), 36.0);
+        assert_close(layout.height(), 16.0);
+        assert_glyph_positions(&layout, &[(0.0, 0), (8.0, 0), (20.0, 0), (28.0, 0)]);
+
+        // The cut off line drops glyphs until the ellipsis fits
+        let layout = lay_out("ab cd ef", options.with_ellipsis(true));
+        assert_eq!(layout.line_count(), 1);
+        assert_close(layout.width(), 32.0);
+        assert_glyph_positions(&layout, &[(0.0, 0), (8.0, 0), (16.0, 0)]);
+        assert_eq!(layout.glyphs()[2].id, ellipsis_id);
+
+        // Lines are cut off at newlines too, even without a maximum width
+        let options = TextOptions::new(TEST_SIZE)
+            .with_max_lines(2)
+            .with_ellipsis(true);
+        let layout = lay_out("ab\ncd\nef", options);
+        assert_eq!(layout.line_count(), 2);
+        assert_close(layout.width(), 32.0);
+        assert_glyph_positions(
+            &layout,
+            &[(0.0, 0), (8.0, 0), (0.0, 1), (8.0, 1), (16.0, 1)],
+        );
+        assert_eq!(layout.glyphs()[4].id, ellipsis_id);
+
+        // Overflowing unwrapped lines get an ellipsis, while text which fits is kept as is
+        let options = TextOptions::new(TEST_SIZE)
+            .with_max_width(32)
+            .with_wrap(TextWrap::None)
+            .with_ellipsis(true);
+        let layout = lay_out("abcde\nabcd", options);
+        assert_eq!(layout.line_count(), 2);
+        assert_glyph_positions(
+            &layout,
+            &[
+                (0.0, 0),
+                (8.0, 0),
+                (16.0, 0),
+                (0.0, 1),
+                (8.0, 1),
+                (16.0, 1),
+                (24.0, 1),
+            ],
+        );
+        assert_eq!(layout.glyphs()[2].id, ellipsis_id);
+    }
+
+    #[test]
+    fn align_lines() {
+        let options = TextOptions::new(TEST_SIZE).with_max_width(40);
+        let layout = lay_out("ab cd ef", options.with_align(TextAlign::Center));
+        assert_close(layout.width(), 36.0);
+        assert_glyph_positions(
+            &layout,
+            &[
+                (2.0, 0),
+                (10.0, 0),
+                (22.0, 0),
+                (30.0, 0),
+                (12.0, 1),
+                (20.0, 1),
+            ],
+        );
+
+        let layout = lay_out("ab cd ef", options.with_align(TextAlign::Right));
+        assert_glyph_positions(
+            &layout,
+            &[
+                (4.0, 0),
+                (12.0, 0),
+                (24.0, 0),
+                (32.0, 0),
+                (24.0, 1),
+                (32.0, 1),
+            ],
+        );
+
+        // Without a maximum width, lines are aligned within the widest one
+        let options = TextOptions::new(TEST_SIZE).with_align(TextAlign::Center);
+        let layout = lay_out("abcd\nef", options);
+        assert_close(layout.width(), 32.0);
+        assert_glyph_positions(
+            &layout,
+            &[
+                (0.0, 0),
+                (8.0, 0),
+                (16.0, 0),
+                (24.0, 0),
+                (8.0, 1),
+                (16.0, 1),
+            ],
+        );
+    }
+
+    #[test]
+    fn glyph_cache_packing() {
+        let font = Font::try_from_slice(TEST_FONT).unwrap();
+        let scale = font_scale(&font, TEST_SIZE);
+        let get_region = |cache: &mut GlyphCache, c: char| match cache.get_or_insert(
+            &font,
+            font.glyph_id(c),
+            scale,
+            0,
+        ) {
+            CacheEntry::Atlas(region) => region,
+            entry => panic!("{c} isn't in the atlas: {entry:?}"),
+        };
+
+        // Letters take 8x12 pixels, so two of them fit in each shelf
+        let mut cache = GlyphCache::new(20, 30);
+        let region = get_region(&mut cache, 'a');
+        assert_eq!((region.width, region.height), (8, 12));
+        assert_eq!((region.left, region.top), (0, -12));
+        let positions: Vec<_> = "abcd"
+            .chars()
+            .map(|c| {
+                let region = get_region(&mut cache, c);
+                (region.x, region.y)
+            })
+            .collect();
+        assert_eq!(positions, [(0, 0), (8, 0), (0, 12), (8, 12)]);
+        assert_eq!(cache.len(), 4);
+
+        // Cached glyphs keep their coverage
+        let region = get_region(&mut cache, 'b');
+        assert_eq!((region.x, region.y), (8, 0));
+        let row = 6 * cache.width as usize;
+        assert_eq!(
+            cache.coverage[row + 8..row + 16],
+            [51, 255, 255, 255, 255, 255, 255, 51]
+        );
+
+        // The atlas is cleared once full
+        let region = get_region(&mut cache, 'e');
+        assert_eq!((region.x, region.y), (0, 0));
+        assert_eq!(cache.len(), 1);
+        let region = get_region(&mut cache, 'a');
+        assert_eq!((region.x, region.y), (8, 0));
+        assert_eq!(cache.len(), 2);
+
+        // Glyphs without outlines or too large for the atlas aren't stored in it
+        let mut cache = GlyphCache::new(12, 12);
+        assert!(matches!(
+            cache.get_or_insert(&font, font.glyph_id(' '), scale, 0),
+            CacheEntry::Empty
+        ));
+        assert!(matches!(
+            cache.get_or_insert(&font, font.glyph_id('中'), scale, 0),
+            CacheEntry::Uncached
+        ));
+        get_region(&mut cache, 'a');
+        assert_eq!(cache.len(), 3);
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,1520 @@
+//! Anti-aliased vector graphics for canvases
+//!
+//! Shapes are described as [`Path`]s, which are flattened into line segments and rendered by a coverage-accumulating scanline rasterizer.
+//! Everything is drawn through [`Canvas::draw_single`], so every canvas implementation supports these primitives.
+//!
+//! Coordinates are in pixels, with `(0, 0)` being the top-left corner of the top-left pixel (so its center is at `(0.5, 0.5)`).
+//! Angles are in radians, with positive angles going clockwise (since the Y axis points down).
+
+use super::sealed::CanvasColorFormat;
+use super::{AlphaBlend, Canvas};
+use alloc::vec::Vec;
+use core::f32::consts::{FRAC_PI_2, PI};
+// Float math comes from std (inherent methods) in host unit tests
+#[cfg(not(test))]
+use num_traits::float::Float;
+
+/// The maximum distance between flattened curves and the ideal ones, in pixels
+const FLATTEN_TOLERANCE: f32 = 0.25;
+
+/// The maximum amount of line segments a single curve is flattened into
+const MAX_CURVE_SEGMENTS: usize = 256;
+
+/// Coverage below this value doesn't affect 8-bit colors, so it isn't drawn
+const MIN_COVERAGE: f32 = 1.0 / 255.0;
+
+/// Represents a point in canvas coordinates
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Point {
+    /// Creates a new [`Point`]
+    ///
+    /// # Arguments
+    ///
+    /// * `x`: The X coordinate
+    /// * `y`: The Y coordinate
+    #[inline]
+    pub const fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        Self::new(self.x + other.x, self.y + other.y)
+    }
+
+    #[inline]
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.y - other.y)
+    }
+
+    #[inline]
+    fn scale(self, factor: f32) -> Self {
+        Self::new(self.x * factor, self.y * factor)
+    }
+
+    #[inline]
+    fn dot(self, other: Self) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    #[inline]
+    fn cross(self, other: Self) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
+
+    #[inline]
+    fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    /// Gets the unit vector perpendicular to this one (rotated 90 degrees counter-clockwise on screen)
+    #[inline]
+    fn normal(self) -> Self {
+        let length = self.length();
+        Self::new(self.y / length, -self.x / length)
+    }
+
+    /// Gets the point at the given angle and distance from this one
+    #[inline]
+    fn polar(self, radius: f32, angle: f32) -> Self {
+        Self::new(self.x + radius * angle.cos(), self.y + radius * angle.sin())
+    }
+}
+
+impl From<(f32, f32)> for Point {
+    #[inline]
+    fn from((x, y): (f32, f32)) -> Self {
+        Self::new(x, y)
+    }
+}
+
+impl From<(i32, i32)> for Point {
+    #[inline]
+    fn from((x, y): (i32, i32)) -> Self {
+        Self::new(x as f32, y as f32)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum PathCommand {
+    MoveTo(Point),
+    LineTo(Point),
+    QuadTo(Point, Point),
+    CubicTo(Point, Point, Point),
+    Close,
+}
+
+/// Represents a vector path, made of one or more subpaths of lines and curves
+///
+/// # Examples
+///
+/// ```
+/// let mut path = Path::new();
+/// path.move_to(10.0, 10.0)
+///     .line_to(100.0, 10.0)
+///     .quad_to(100.0, 100.0, 10.0, 100.0)
+///     .close();
+/// ```
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Path {
+    commands: Vec<PathCommand>,
+    start: Point,
+    current: Option<Point>,
+}
+
+impl Path {
+    /// Creates a new, empty [`Path`]
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            commands: Vec::new(),
+            start: Point::new(0.0, 0.0),
+            current: None,
+        }
+    }
+
+    /// Gets whether the path has no commands
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Starts a new subpath at the given point
+    pub fn move_to(&mut self, x: f32, y: f32) -> &mut Self {
+        let point = Point::new(x, y);
+        self.commands.push(PathCommand::MoveTo(point));
+        self.start = point;
+        self.current = Some(point);
+        self
+    }
+
+    /// Adds a line from the current point (starting a new subpath at the origin if there's none)
+    fn ensure_current(&mut self) {
+        if self.current.is_none() {
+            self.move_to(0.0, 0.0);
+        }
+    }
+
+    /// Adds a straight line to the given point
+    pub fn line_to(&mut self, x: f32, y: f32) -> &mut Self {
+        self.ensure_current();
+        let point = Point::new(x, y);
+        self.commands.push(PathCommand::LineTo(point));
+        self.current = Some(point);
+        self
+    }
+
+    /// Adds a quadratic Bézier curve to the given point
+    ///
+    /// # Arguments
+    ///
+    /// * `cx`, `cy`: The control point
+    /// * `x`, `y`: The end point
+    pub fn quad_to(&mut self, cx: f32, cy: f32, x: f32, y: f32) -> &mut Self {
+        self.ensure_current();
+        let point = Point::new(x, y);
+        self.commands
+            .push(PathCommand::QuadTo(Point::new(cx, cy), point));
+        self.current = Some(point);
+        self
+    }
+
+    /// Adds a cubic Bézier curve to the given point
+    ///
+    /// # Arguments
+    ///
+    /// * `c1x`, `c1y`: The first control point
+    /// * `c2x`, `c2y`: The second control point
+    /// * `x`, `y`: The end point
+    #[allow(clippy::too_many_arguments)]
+    pub fn cubic_to(
+        &mut self,
+        c1x: f32,
+        c1y: f32,
+        c2x: f32,
+        c2y: f32,
+        x: f32,
+        y: f32,
+    ) -> &mut Self {
+        self.ensure_current();
+        let point = Point::new(x, y);
+        self.commands.push(PathCommand::CubicTo(
+            Point::new(c1x, c1y),
+            Point::new(c2x, c2y),
+            point,
+        ));
+        self.current = Some(point);
+        self
+    }
+
+    /// Closes the current subpath with a straight line to its start point
+    pub fn close(&mut self) -> &mut Self {
+        if self.current.is_some() {
+            self.commands.push(PathCommand::Close);
+            self.current = Some(self.start);
+        }
+        self
+    }
+
+    /// Adds a circular arc, connected to the current point with a straight line (if there's one)
+    ///
+    /// # Arguments
+    ///
+    /// * `cx`, `cy`: The arc center
+    /// * `radius`: The arc radius
+    /// * `start_angle`: The angle where the arc starts
+    /// * `end_angle`: The angle where the arc ends (the arc goes counter-clockwise if it's smaller than `start_angle`)
+    pub fn arc(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+    ) -> &mut Self {
+        let center = Point::new(cx, cy);
+        let start = center.polar(radius, start_angle);
+        if self.current.is_some() {
+            self.line_to(start.x, start.y);
+        } else {
+            self.move_to(start.x, start.y);
+        }
+
+        // Approximate the arc with cubic curves spanning at most 90 degrees each
+        let sweep = (end_angle - start_angle).clamp(-2.0 * PI, 2.0 * PI);
+        let segment_count = (sweep.abs() / FRAC_PI_2).ceil().max(1.0) as usize;
+        let segment_sweep = sweep / segment_count as f32;
+        let k = 4.0 / 3.0 * (segment_sweep / 4.0).tan() * radius;
+        let mut angle = start_angle;
+        for _ in 0..segment_count {
+            let next_angle = angle + segment_sweep;
+            let p0 = center.polar(radius, angle);
+            let p3 = center.polar(radius, next_angle);
+            let c1 = p0.add(Point::new(-angle.sin(), angle.cos()).scale(k));
+            let c2 = p3.sub(Point::new(-next_angle.sin(), next_angle.cos()).scale(k));
+            self.cubic_to(c1.x, c1.y, c2.x, c2.y, p3.x, p3.y);
+            angle = next_angle;
+        }
+        self
+    }
+
+    /// Adds a closed polygon subpath
+    ///
+    /// # Arguments
+    ///
+    /// * `points`: The polygon vertices
+    pub fn polygon(&mut self, points: &[Point]) -> &mut Self {
+        if let Some((first, rest)) = points.split_first() {
+            self.move_to(first.x, first.y);
+            for point in rest {
+                self.line_to(point.x, point.y);
+            }
+            self.close();
+        }
+        self
+    }
+
+    /// Adds a closed rectangle subpath
+    ///
+    /// # Arguments
+    ///
+    /// * `x`, `y`: The top-left corner
+    /// * `width`, `height`: The rectangle size
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32) -> &mut Self {
+        self.move_to(x, y)
+            .line_to(x + width, y)
+            .line_to(x + width, y + height)
+            .line_to(x, y + height)
+            .close()
+    }
+
+    /// Adds a closed rectangle subpath with rounded corners
+    ///
+    /// # Arguments
+    ///
+    /// * `x`, `y`: The top-left corner
+    /// * `width`, `height`: The rectangle size
+    /// * `radius`: The corner radius (limited to half the rectangle size)
+    pub fn rounded_rect(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        radius: f32,
+    ) -> &mut Self {
+        let radius = radius
+            .min(width.abs() / 2.0)
+            .min(height.abs() / 2.0)
+            .max(0.0);
+        if radius == 0.0 {
+            return self.rect(x, y, width, height);
+        }
+
+        let (right, bottom) = (x + width, y + height);
+        self.move_to(x + radius, y);
+        self.arc(right - radius, y + radius, radius, -FRAC_PI_2, 0.0);
+        self.arc(right - radius, bottom - radius, radius, 0.0, FRAC_PI_2);
+        self.arc(x + radius, bottom - radius, radius, FRAC_PI_2, PI);
+        self.arc(x + radius, y + radius, radius, PI, PI + FRAC_PI_2);
+        self.close()
+    }
+
+    /// Adds a closed ellipse subpath
+    ///
+    /// # Arguments
+    ///
+    /// * `cx`, `cy`: The ellipse center
+    /// * `rx`, `ry`: The horizontal and vertical radii
+    pub fn ellipse(&mut self, cx: f32, cy: f32, rx: f32, ry: f32) -> &mut Self {
+        // Control point distance for a quarter circle approximation
+        const K: f32 = 0.552_284_8;
+        let (kx, ky) = (rx * K, ry * K);
+        self.move_to(cx + rx, cy)
+            .cubic_to(cx + rx, cy + ky, cx + kx, cy + ry, cx, cy + ry)
+            .cubic_to(cx - kx, cy + ry, cx - rx, cy + ky, cx - rx, cy)
+            .cubic_to(cx - rx, cy - ky, cx - kx, cy - ry, cx, cy - ry)
+            .cubic_to(cx + kx, cy - ry, cx + rx, cy - ky, cx + rx, cy)
+            .close()
+    }
+
+    /// Adds a closed circle subpath
+    ///
+    /// # Arguments
+    ///
+    /// * `cx`, `cy`: The circle center
+    /// * `radius`: The circle radius
+    #[inline]
+    pub fn circle(&mut self, cx: f32, cy: f32, radius: f32) -> &mut Self {
+        self.ellipse(cx, cy, radius, radius)
+    }
+
+    /// Flattens the path into polylines, returning each one with whether it's closed
+    fn flatten(&self) -> Vec<(Vec<Point>, bool)> {
+        let mut polylines = Vec::new();
+        let mut points: Vec<Point> = Vec::new();
+        let mut current = Point::default();
+
+        for command in &self.commands {
+            match *command {
+                PathCommand::MoveTo(point) => {
+                    // A lone point (like the start of a closed subpath) isn't a subpath on its own
+                    if points.len() > 1 {
+                        polylines.push((core::mem::take(&mut points), false));
+                    }
+                    points.clear();
+                    points.push(point);
+                    current = point;
+                }
+                PathCommand::LineTo(point) => {
+                    points.push(point);
+                    current = point;
+                }
+                PathCommand::QuadTo(control, point) => {
+                    let dd = current.sub(control.scale(2.0)).add(point).length();
+                    let count = segment_count(dd / (8.0 * FLATTEN_TOLERANCE));
+                    for i in 1..=count {
+                        let t = i as f32 / count as f32;
+                        let mt = 1.0 - t;
+                        points.push(
+                            current
+                                .scale(mt * mt)
+                                .add(control.scale(2.0 * mt * t))
+                                .add(point.scale(t * t)),
+                        );
+                    }
+                    current = point;
+                }
+                PathCommand::CubicTo(control_1, control_2, point) => {
+                    let dd = current
+                        .sub(control_1.scale(2.0))
+                        .add(control_2)
+                        .length()
+                        .max(control_1.sub(control_2.scale(2.0)).add(point).length());
+                    let count = segment_count(3.0 * dd / (4.0 * FLATTEN_TOLERANCE));
+                    for i in 1..=count {
+                        let t = i as f32 / count as f32;
+                        let mt = 1.0 - t;
+                        points.push(
+                            current
+                                .scale(mt * mt * mt)
+                                .add(control_1.scale(3.0 * mt * mt * t))
+                                .add(control_2.scale(3.0 * mt * t * t))
+                                .add(point.scale(t * t * t)),
+                        );
+                    }
+                    current = point;
+                }
+                PathCommand::Close => {
+                    if let Some(&first) = points.first() {
+                        polylines.push((core::mem::take(&mut points), true));
+                        // Following commands continue from the start of the closed subpath
+                        points.push(first);
+                        current = first;
+                    }
+                }
+            }
+        }
+        if points.len() > 1 {
+            polylines.push((points, false));
+        }
+
+        // Remove repeated points, which have no direction
+        for (points, _) in polylines.iter_mut() {
+            points.dedup_by(|a, b| a.sub(*b).length() < 1e-4);
+        }
+        polylines
+    }
+}
+
+/// Gets the amount of segments needed to flatten a curve, from its squared error factor
+fn segment_count(error_factor: f32) -> usize {
+    if error_factor.is_finite() {
+        (error_factor.sqrt().ceil() as usize).clamp(1, MAX_CURVE_SEGMENTS)
+    } else {
+        1
+    }
+}
+
+/// Represents the rule used to decide which areas are inside a path
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum FillRule {
+    /// Areas with a non-zero winding number are filled
+    #[default]
+    NonZero,
+    /// Areas with an odd winding number are filled (overlapping areas become holes)
+    EvenOdd,
+}
+
+/// Represents the shape at the ends of open stroked subpaths
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum LineCap {
+    /// The stroke ends exactly at the end point
+    #[default]
+    Butt,
+    /// The stroke is extended by half the line width past the end point
+    Square,
+    /// The stroke ends with a half circle
+    Round,
+}
+
+/// Represents the shape at the corners of stroked paths
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum LineJoin {
+    /// Sharp corners, which fall back to [`LineJoin::Bevel`] past the miter limit
+    #[default]
+    Miter,
+    /// Cut-off corners
+    Bevel,
+    /// Rounded corners
+    Round,
+}
+
+/// Represents the style used to stroke paths
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct StrokeStyle {
+    /// The line width, in pixels
+    pub width: f32,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    /// The maximum ratio between the miter length and the line width, past which miter joins are beveled
+    pub miter_limit: f32,
+}
+
+impl StrokeStyle {
+    /// Creates a new [`StrokeStyle`] with the given width, butt caps and miter joins
+    ///
+    /// # Arguments
+    ///
+    /// * `width`: The line width, in pixels
+    #[inline]
+    pub const fn new(width: f32) -> Self {
+        Self {
+            width,
+            cap: LineCap::Butt,
+            join: LineJoin::Miter,
+            miter_limit: 4.0,
+        }
+    }
+
+    /// Sets the line cap
+    #[inline]
+    pub const fn with_cap(mut self, cap: LineCap) -> Self {
+        self.cap = cap;
+        self
+    }
+
+    /// Sets the line join
+    #[inline]
+    pub const fn with_join(mut self, join: LineJoin) -> Self {
+        self.join = join;
+        self
+    }
+
+    /// Sets the miter limit
+    #[inline]
+    pub const fn with_miter_limit(mut self, miter_limit: f32) -> Self {
+        self.miter_limit = miter_limit;
+        self
+    }
+}
+
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+/// Represents a color stop in a gradient
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct GradientStop<ColorFormat: CanvasColorFormat> {
+    /// The stop position along the gradient, from `0.0` to `1.0`
+    pub offset: f32,
+    pub color: ColorFormat,
+}
+
+impl<ColorFormat: CanvasColorFormat> GradientStop<ColorFormat> {
+    /// Creates a new [`GradientStop`]
+    ///
+    /// # Arguments
+    ///
+    /// * `offset`: The stop position along the gradient, from `0.0` to `1.0`
+    /// * `color`: The stop color
+    #[inline]
+    pub const fn new(offset: f32, color: ColorFormat) -> Self {
+        Self { offset, color }
+    }
+}
+
+/// Represents how shapes are colored
+///
+/// Gradients are padded: positions before the first stop or after the last one take their colors.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Paint<ColorFormat: CanvasColorFormat> {
+    /// A single color
+    Solid(ColorFormat),
+    /// A gradient along the line from `start` to `end`
+    LinearGradient {
+        start: Point,
+        end: Point,
+        stops: Vec<GradientStop<ColorFormat>>,
+    },
+    /// A gradient going outwards from `center` to the circle of the given `radius`
+    RadialGradient {
+        center: Point,
+        radius: f32,
+        stops: Vec<GradientStop<ColorFormat>>,
+    },
+}
+
+impl<ColorFormat: CanvasColorFormat> From<ColorFormat> for Paint<ColorFormat> {
+    #[inline]
+    fn from(color: ColorFormat) -> Self {
+        Self::Solid(color)
+    }
+}
+
+/// The amount of precomputed colors for gradients
+const GRADIENT_LUT_SIZE: usize = 256;
+
+/// Precomputed colors for a paint
+enum PaintShader<ColorFormat: CanvasColorFormat> {
+    Solid(ColorFormat),
+    Linear {
+        start: Point,
+        /// Direction scaled by the inverse squared length, so the dot product gives the gradient position
+        direction: Point,
+        lut: Vec<ColorFormat>,
+    },
+    Radial {
+        center: Point,
+        inv_radius: f32,
+        lut: Vec<ColorFormat>,
+    },
+}
+
+fn build_gradient_lut<ColorFormat: CanvasColorFormat>(
+    stops: &[GradientStop<ColorFormat>],
+) -> Vec<ColorFormat> {
+    let mut lut = Vec::with_capacity(GRADIENT_LUT_SIZE);
+    for i in 0..GRADIENT_LUT_SIZE {
+        let t = i as f32 / (GRADIENT_LUT_SIZE - 1) as f32;
+        // Find the stops surrounding the position, stops are expected in increasing order
+        let next = stops.iter().position(|stop| stop.offset >= t);
+        let color = match next {
+            None => stops.last().map(|stop| stop.color).unwrap_or_default(),
+            Some(0) => stops[0].color,
+            Some(index) => {
+                let (prev, next) = (&stops[index - 1], &stops[index]);
+                let span = next.offset - prev.offset;
+                let factor = if span > 0.0 {
+                    (t - prev.offset) / span
+                } else {
+                    1.0
+                };
+                let (r0, g0, b0, a0) = prev.color.to_scaled();
+                let (r1, g1, b1, a1) = next.color.to_scaled();
+                let lerp =
+                    |c0: u8, c1: u8| (c0 as f32 + (c1 as f32 - c0 as f32) * factor + 0.5) as u8;
+                ColorFormat::new_scaled(lerp(r0, r1), lerp(g0, g1), lerp(b0, b1), lerp(a0, a1))
+            }
+        };
+        lut.push(color);
+    }
+    lut
+}
+
+impl<ColorFormat: CanvasColorFormat> PaintShader<ColorFormat> {
+    fn new(paint: &Paint<ColorFormat>) -> Self {
+        match paint {
+            Paint::Solid(color) => Self::Solid(*color),
+            Paint::LinearGradient { start, end, stops } => {
+                let direction = end.sub(*start);
+                let length_sq = direction.dot(direction);
+                Self::Linear {
+                    start: *start,
+                    direction: if length_sq > 0.0 {
+                        direction.scale(1.0 / length_sq)
+                    } else {
+                        Point::default()
+                    },
+                    lut: build_gradient_lut(stops),
+                }
+            }
+            Paint::RadialGradient {
+                center,
+                radius,
+                stops,
+            } => Self::Radial {
+                center: *center,
+                inv_radius: if *radius > 0.0 { 1.0 / radius } else { 0.0 },
+                lut: build_gradient_lut(stops),
+            },
+        }
+    }
+
+    fn lookup(lut: &[ColorFormat], t: f32) -> ColorFormat {
+        let index = (t.clamp(0.0, 1.0) * (GRADIENT_LUT_SIZE - 1) as f32 + 0.5) as usize;
+        lut[index.min(GRADIENT_LUT_SIZE - 1)]
+    }
+
+    /// Gets the color at the center of the given pixel
+    fn color_at(&self, x: i32, y: i32) -> ColorFormat {
+        let point = Point::new(x as f32 + 0.5, y as f32 + 0.5);
+        match self {
+            Self::Solid(color) => *color,
+            Self::Linear {
+                start,
+                direction,
+                lut,
+            } => Self::lookup(lut, point.sub(*start).dot(*direction)),
+            Self::Radial {
+                center,
+                inv_radius,
+                lut,
+            } => Self::lookup(lut, point.sub(*center).length() * inv_radius),
+        }
+    }
+}
+
+/// A line segment, always going downwards
+#[derive(Copy, Clone)]
+struct Edge {
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+    /// The winding direction (`1.0` for originally downwards edges, `-1.0` for upwards ones)
+    direction: f32,
+}
+
+/// Coverage-accumulating scanline rasterizer
+///
+/// Each edge adds the signed area it covers to an accumulation row, and the running sum of a row gives the coverage of each pixel.
+/// Rows are processed one at a time, so the memory needed only depends on the canvas width.
+pub(crate) struct Rasterizer {
+    width: u32,
+    height: u32,
+    edges: Vec<Edge>,
+}
+
+impl Rasterizer {
+    pub(crate) const fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            edges: Vec::new(),
+        }
+    }
+
+    /// Adds a line, clipping it horizontally to the canvas
+    ///
+    /// Parts left of the canvas are moved to its left border, since they still affect the winding of the pixels to their right.
+    fn add_line(&mut self, p0: Point, p1: Point) {
+        if !(p0.x.is_finite() && p0.y.is_finite() && p1.x.is_finite() && p1.y.is_finite()) {
+            return;
+        }
+        if (p0.y == p1.y) || (p0.y.max(p1.y) <= 0.0) || (p0.y.min(p1.y) >= self.height as f32) {
+            return;
+        }
+
+        // Split the line where it crosses the canvas' vertical borders
+        let width = self.width as f32;
+        let mut splits = [0.0f32, 1.0, 1.0, 1.0];
+        let mut split_count = 1;
+        for border in [0.0, width] {
+            if (p0.x - border) * (p1.x - border) < 0.0 {
+                splits[split_count] = (border - p0.x) / (p1.x - p0.x);
+                split_count += 1;
+            }
+        }
+        splits[split_count] = 1.0;
+        splits[1..split_count].sort_unstable_by(|a, b| a.total_cmp(b));
+
+        let delta = p1.sub(p0);
+        for pair in splits[..=split_count].windows(2) {
+            let start = p0.add(delta.scale(pair[0]));
+            let end = p0.add(delta.scale(pair[1]));
+            let (start_x, end_x) = (start.x.clamp(0.0, width), end.x.clamp(0.0, width));
+            if start.y < end.y {
+                self.edges.push(Edge {
+                    x0: start_x,
+                    y0: start.y,
+                    x1: end_x,
+                    y1: end.y,
+                    direction: 1.0,
+                });
+            } else if start.y > end.y {
+                self.edges.push(Edge {
+                    x0: end_x,
+                    y0: end.y,
+                    x1: start_x,
+                    y1: start.y,
+                    direction: -1.0,
+                });
+            }
+        }
+    }
+
+    /// Adds a closed polyline, as-is
+    fn add_polyline(&mut self, points: &[Point]) {
+        for (i, &point) in points.iter().enumerate() {
+            self.add_line(point, points[(i + 1) % points.len()]);
+        }
+    }
+
+    fn add_circle(&mut self, center: Point, radius: f32) {
+        let mut path = Path::new();
+        path.circle(center.x, center.y, radius);
+        for (points, _) in path.flatten() {
+            self.add_polyline(&points);
+        }
+    }
+
+    /// Adds the filled area of a path
+    pub(crate) fn add_path_fill(&mut self, path: &Path) {
+        for (points, _) in path.flatten() {
+            // Fills always implicitly close subpaths
+            self.add_polyline(&points);
+        }
+    }
+
+    /// Adds the outline of a path, stroked with the given style (to be rasterized with [`FillRule::NonZero`])
+    ///
+    /// Each subpath is stroked as a single outline (or two for closed ones) instead of overlapping segment, join and cap shapes, since the rasterizer accumulates overlapping areas: their anti-aliased edges would be drawn too opaque.
+    pub(crate) fn add_path_stroke(&mut self, path: &Path, style: &StrokeStyle) {
+        let half_width = style.width / 2.0;
+        if half_width.is_nan() || (half_width <= 0.0) {
+            return;
+        }
+
+        for (mut points, closed) in path.flatten() {
+            if closed
+                && (points.len() > 1)
+                && (points[0].sub(points[points.len() - 1]).length() < 1e-4)
+            {
+                points.pop();
+            }
+
+            if points.len() == 1 {
+                // Zero-length subpaths only show their caps
+                let point = points[0];
+                match style.cap {
+                    LineCap::Butt => {}
+                    LineCap::Square => {
+                        let offset = Point::new(half_width, half_width);
+                        let (min, max) = (point.sub(offset), point.add(offset));
+                        self.add_polyline(&[
+                            min,
+                            Point::new(max.x, min.y),
+                            max,
+                            Point::new(min.x, max.y),
+                        ]);
+                    }
+                    LineCap::Round => self.add_circle(point, half_width),
+                }
+                continue;
+            }
+
+            let mut outline = Vec::new();
+            Self::add_stroke_side(&mut outline, &points, closed, half_width, style);
+            if closed {
+                // The outer and inner outlines go in opposite directions, leaving the inside unfilled
+                self.add_polyline(&outline);
+                outline.clear();
+                points.reverse();
+                Self::add_stroke_side(&mut outline, &points, closed, half_width, style);
+            } else {
+                Self::add_cap(
+                    &mut outline,
+                    points[points.len() - 2],
+                    points[points.len() - 1],
+                    half_width,
+                    style.cap,
+                );
+                points.reverse();
+                Self::add_stroke_side(&mut outline, &points, closed, half_width, style);
+                Self::add_cap(
+                    &mut outline,
+                    points[points.len() - 2],
+                    points[points.len() - 1],
+                    half_width,
+                    style.cap,
+                );
+            }
+            self.add_polyline(&outline);
+        }
+    }
+
+    /// Adds the outline of one side of a stroked polyline (the one its segment normals point to), from its start to its end
+    fn add_stroke_side(
+        outline: &mut Vec<Point>,
+        points: &[Point],
+        closed: bool,
+        half_width: f32,
+        style: &StrokeStyle,
+    ) {
+        let count = points.len();
+        if !closed {
+            let offset = points[1].sub(points[0]).normal().scale(half_width);
+            outline.push(points[0].add(offset));
+        }
+
+        let join_range = if closed { 0..count } else { 1..count - 1 };
+        for i in join_range {
+            let prev = points[(i + count - 1) % count];
+            let (point, next) = (points[i], points[(i + 1) % count]);
+            Self::add_join(outline, prev, point, next, half_width, style);
+        }
+
+        if !closed {
+            let offset = points[count - 1]
+                .sub(points[count - 2])
+                .normal()
+                .scale(half_width);
+            outline.push(points[count - 1].add(offset));
+        }
+    }
+
+    /// Adds the outline of a join at `point`, on the side the segment normals point to
+    fn add_join(
+        outline: &mut Vec<Point>,
+        prev: Point,
+        point: Point,
+        next: Point,
+        half_width: f32,
+        style: &StrokeStyle,
+    ) {
+        let (direction_in, direction_out) = (point.sub(prev), next.sub(point));
+        let (normal_in, normal_out) = (direction_in.normal(), direction_out.normal());
+        let offset_in = point.add(normal_in.scale(half_width));
+        let offset_out = point.add(normal_out.scale(half_width));
+        // Normals are rotated counter-clockwise on screen, so this side is the outer one for clockwise (positive) turns
+        let turn = direction_in.cross(direction_out);
+        let cos = normal_in.dot(normal_out);
+        let parallel = turn.abs() < 1e-6 * direction_in.length() * direction_out.length();
+        if parallel && (cos > 0.0) {
+            // Straight continuation, the segments already meet
+            outline.push(offset_in);
+            return;
+        }
+        // The offset lines of both segments cross at this point, unless the path turns back
+        let crossing = (cos > -0.9999)
+            .then(|| point.add(normal_in.add(normal_out).scale(half_width / (1.0 + cos))));
+
+        if !parallel && (turn < 0.0) {
+            // Inner side: the outline can just go through the crossing if it's within both segments (it's as far from the point along both)
+            if let Some(crossing) = crossing {
+                let distance = -crossing.sub(point).dot(direction_in) / direction_in.length();
+                if (distance <= direction_in.length()) && (distance <= direction_out.length()) {
+                    outline.push(crossing);
+                    return;
+                }
+            }
+            // Otherwise it goes around the point, making a loop which stays within the stroke
+            outline.extend([offset_in, point, offset_out]);
+            return;
+        }
+
+        // Ratio between the miter length and the line width: 1 / sin(angle / 2) = sqrt(2 / (1 + cos))
+        let miter = crossing.filter(|_| {
+            (style.join == LineJoin::Miter) && ((2.0 / (1.0 + cos)).sqrt() <= style.miter_limit)
+        });
+        match (style.join, miter) {
+            (LineJoin::Round, _) => {
+                // Turning back goes around the front of the point
+                let sweep = if parallel {
+                    PI
+                } else {
+                    turn.atan2(direction_in.dot(direction_out))
+                };
+                outline.push(offset_in);
+                Self::add_arc(outline, point, half_width, normal_in, sweep);
+                outline.push(offset_out);
+            }
+            (_, Some(miter)) => outline.extend([offset_in, miter, offset_out]),
+            _ => outline.extend([offset_in, offset_out]),
+        }
+    }
+
+    /// Adds the outline of the cap at `end`, for the segment coming from `from`, going from the side its normal points to to the other one
+    fn add_cap(outline: &mut Vec<Point>, from: Point, end: Point, half_width: f32, cap: LineCap) {
+        let direction = end.sub(from);
+        let normal = direction.normal();
+        match cap {
+            LineCap::Butt => {}
+            LineCap::Square => {
+                let extension = direction.scale(half_width / direction.length());
+                let offset = normal.scale(half_width);
+                let tip = end.add(extension);
+                outline.extend([tip.add(offset), tip.sub(offset)]);
+            }
+            LineCap::Round => {
+                Self::add_arc(outline, end, half_width, normal, PI);
+            }
+        }
+    }
+
+    /// Adds the points within a circular arc (not its ends), starting at the given direction from its center
+    fn add_arc(outline: &mut Vec<Point>, center: Point, radius: f32, start: Point, sweep: f32) {
+        // Keep the distance between the segments' middle and the ideal arc within the flattening tolerance
+        let step = 2.0 * (1.0 - FLATTEN_TOLERANCE / radius).max(0.0).acos();
+        let count = segment_count((sweep.abs() / step).powi(2));
+        let start_angle = start.y.atan2(start.x);
+        for i in 1..count {
+            outline.push(center.polar(radius, start_angle + sweep * i as f32 / count as f32));
+        }
+    }
+
+    /// Accumulates the signed area of an edge part within a single row
+    ///
+    /// # Arguments
+    ///
+    /// * `accumulation`: The row accumulation buffer
+    /// * `xa`: The X coordinate of the part at its top
+    /// * `xb`: The X coordinate of the part at its bottom
+    /// * `d`: The part height, signed with the edge direction
+    fn accumulate_row(accumulation: &mut [f32], xa: f32, xb: f32, d: f32) {
+        let (x0, x1) = if xa < xb { (xa, xb) } else { (xb, xa) };
+        let x0_floor = x0.floor();
+        let x0i = x0_floor as usize;
+        let x1_ceil = x1.ceil();
+        let x1i = x1_ceil as usize;
+
+        if x1i <= x0i + 1 {
+            // The part is within a single pixel column
+            let mid = 0.5 * (xa + xb) - x0_floor;
+            accumulation[x0i] += d - d * mid;
+            accumulation[x0i + 1] += d * mid;
+        } else {
+            let s = (x1 - x0).recip();
+            let x0f = x0 - x0_floor;
+            let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+            let x1f = x1 - x1_ceil + 1.0;
+            let am = 0.5 * s * x1f * x1f;
+            accumulation[x0i] += d * a0;
+            if x1i == x0i + 2 {
+                accumulation[x0i + 1] += d * (1.0 - a0 - am);
+            } else {
+                let a1 = s * (1.5 - x0f);
+                accumulation[x0i + 1] += d * (a1 - a0);
+                for value in &mut accumulation[x0i + 2..x1i - 1] {
+                    *value += d * s;
+                }
+                let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                accumulation[x1i - 1] += d * (1.0 - a2 - am);
+            }
+            accumulation[x1i] += d * am;
+        }
+    }
+
+    /// Rasterizes the added edges, calling `callback` with the coordinates and coverage (`0.0`-`1.0`) of every covered pixel
+    pub(crate) fn rasterize(
+        &mut self,
+        fill_rule: FillRule,
+        mut callback: impl FnMut(i32, i32, f32),
+    ) {
+        if self.edges.is_empty() || (self.width == 0) {
+            return;
+        }
+
+        self.edges.sort_unstable_by(|a, b| a.y0.total_cmp(&b.y0));
+        let min_y = self.edges[0].y0.floor().max(0.0) as u32;
+        let max_y = self
+            .edges
+            .iter()
+            .fold(0.0f32, |max, edge| max.max(edge.y1))
+            .ceil()
+            .min(self.height as f32) as u32;
+
+        let mut accumulation = alloc::vec![0.0f32; self.width as usize + 2];
+        let mut active: Vec<Edge> = Vec::new();
+        let mut next_edge = 0;
+        for y in min_y..max_y {
+            let (row_top, row_bottom) = (y as f32, (y + 1) as f32);
+            active.retain(|edge| edge.y1 > row_top);
+            while (next_edge < self.edges.len()) && (self.edges[next_edge].y0 < row_bottom) {
+                if self.edges[next_edge].y1 > row_top {
+                    active.push(self.edges[next_edge]);
+                }
+                next_edge += 1;
+            }
+            if active.is_empty() {
+                continue;
+            }
+
+            let (mut min_x, mut max_x) = (usize::MAX, 0);
+            for edge in &active {
+                let dxdy = (edge.x1 - edge.x0) / (edge.y1 - edge.y0);
+                let top = edge.y0.max(row_top);
+                let bottom = edge.y1.min(row_bottom);
+                if bottom <= top {
+                    continue;
+                }
+                let xa = (edge.x0 + (top - edge.y0) * dxdy).clamp(0.0, self.width as f32);
+                let xb = (edge.x0 + (bottom - edge.y0) * dxdy).clamp(0.0, self.width as f32);
+                Self::accumulate_row(&mut accumulation, xa, xb, (bottom - top) * edge.direction);
+                min_x = min_x.min(xa.min(xb) as usize);
+                max_x = max_x.max(xa.max(xb).ceil() as usize + 1);
+            }
+            if min_x > max_x {
+                continue;
+            }
+
+            let mut sum = 0.0f32;
+            let end_x = max_x.min(self.width as usize);
+            for (x, value) in accumulation.iter().enumerate().take(end_x).skip(min_x) {
+                sum += value;
+                let coverage = match fill_rule {
+                    FillRule::NonZero => sum.abs().min(1.0),
+                    FillRule::EvenOdd => {
+                        let value = sum.abs() % 2.0;
+                        if value > 1.0 { 2.0 - value } else { value }
+                    }
+                };
+                if coverage >= MIN_COVERAGE {
+                    callback(x as i32, y as i32, coverage);
+                }
+            }
+            accumulation[min_x..=max_x.min(self.width as usize + 1)].fill(0.0);
+        }
+    }
+}
+
+/// Draws the rasterized shape onto a canvas with the given paint (see [`draw_coverage`])
+pub(crate) fn draw_rasterized<C: Canvas + ?Sized>(
+    canvas: &mut C,
+    mut rasterizer: Rasterizer,
+    fill_rule: FillRule,
+    paint: &Paint<C::ColorFormat>,
+    blend: AlphaBlend,
+) {
+    let shader = PaintShader::new(paint);
+    rasterizer.rasterize(fill_rule, |x, y, coverage| {
+        draw_coverage(canvas, x, y, shader.color_at(x, y), coverage, blend);
+    });
+}
+
+/// Draws a partially covered pixel, scaling the color alpha by the coverage (`0.0`-`1.0`)
+///
+/// Since [`AlphaBlend::None`] can't blend, in that case the pixel is just drawn if it's at least half covered.
+pub(crate) fn draw_coverage<C: Canvas + ?Sized>(
+    canvas: &mut C,
+    x: i32,
+    y: i32,
+    color: C::ColorFormat,
+    coverage: f32,
+    blend: AlphaBlend,
+) {
+    if matches!(blend, AlphaBlend::None) {
+        if coverage >= 0.5 {
+            canvas.draw_single(x, y, color, blend);
+        }
+    } else if coverage >= 1.0 {
+        canvas.draw_single(x, y, color, blend);
+    } else {
+        canvas.draw_single(x, y, color.scale_alpha(coverage), blend);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{OffscreenCanvas, RGBA8};
+    use super::*;
+    use core::f32::consts::FRAC_PI_4;
+
+    const SIZE: u32 = 16;
+
+    /// Rasterizes the shapes added to a [`SIZE`]x[`SIZE`] rasterizer, returning the coverage of every pixel
+    fn rasterize(fill_rule: FillRule, add: impl FnOnce(&mut Rasterizer)) -> Vec<f32> {
+        let mut rasterizer = Rasterizer::new(SIZE, SIZE);
+        add(&mut rasterizer);
+        let mut coverage = alloc::vec![0.0; (SIZE * SIZE) as usize];
+        rasterizer.rasterize(fill_rule, |x, y, pixel_coverage| {
+            assert!((0.0..=1.0).contains(&pixel_coverage));
+            let pixel = &mut coverage[(y as u32 * SIZE + x as u32) as usize];
+            assert_eq!(*pixel, 0.0, "({x}, {y}) was rasterized twice");
+            *pixel = pixel_coverage;
+        });
+        coverage
+    }
+
+    fn fill(path: &Path, fill_rule: FillRule) -> Vec<f32> {
+        rasterize(fill_rule, |rasterizer| rasterizer.add_path_fill(path))
+    }
+
+    fn stroke(path: &Path, style: StrokeStyle) -> Vec<f32> {
+        rasterize(FillRule::NonZero, |rasterizer| {
+            rasterizer.add_path_stroke(path, &style)
+        })
+    }
+
+    fn get_coverage(coverage: &[f32], x: u32, y: u32) -> f32 {
+        coverage[(y * SIZE + x) as usize]
+    }
+
+    /// Asserts that the total coverage matches the analytic area of the shape
+    fn assert_area(coverage: &[f32], area: f32, tolerance: f32) {
+        let sum: f32 = coverage.iter().sum();
+        assert!(
+            (sum - area).abs() <= tolerance,
+            "covered {sum}, expected {area}"
+        );
+    }
+
+    /// Asserts that the total coverage matches the analytic area of a shape with curves of the given total length
+    ///
+    /// Curves are flattened into chords within [`FLATTEN_TOLERANCE`] of them, which only leave out up to 2/3 of their length times the tolerance (but never add any area).
+    fn assert_curved_area(coverage: &[f32], area: f32, curve_length: f32) {
+        let sum: f32 = coverage.iter().sum();
+        let min_area = area - curve_length * FLATTEN_TOLERANCE * 2.0 / 3.0;
+        assert!(
+            (min_area - 1e-3..=area + 1e-3).contains(&sum),
+            "covered {sum}, expected {min_area} to {area}"
+        );
+    }
+
+    /// Asserts that the coverage is exactly that of the pixels in the given rectangle
+    fn assert_pixel_rect(coverage: &[f32], x: u32, y: u32, width: u32, height: u32) {
+        for py in 0..SIZE {
+            for px in 0..SIZE {
+                let inside = (x..x + width).contains(&px) && (y..y + height).contains(&py);
+                let expected = if inside { 1.0 } else { 0.0 };
+                assert!(
+                    (get_coverage(coverage, px, py) - expected).abs() < 1e-4,
+                    "({px}, {py}) has coverage {}",
+                    get_coverage(coverage, px, py)
+                );
+            }
+        }
+    }
+
+    fn line(x0: f32, y0: f32, x1: f32, y1: f32) -> Path {
+        let mut path = Path::new();
+        path.move_to(x0, y0).line_to(x1, y1);
+        path
+    }
+
+    #[test]
+    fn fill_polygons() {
+        let mut path = Path::new();
+        path.rect(2.0, 3.0, 5.0, 4.0);
+        assert_pixel_rect(&fill(&path, FillRule::NonZero), 2, 3, 5, 4);
+
+        // Pixels are covered by the part of their area within the shape
+        let mut path = Path::new();
+        path.rect(1.5, 1.5, 2.0, 2.0);
+        let coverage = fill(&path, FillRule::NonZero);
+        assert_area(&coverage, 4.0, 1e-4);
+        assert!((get_coverage(&coverage, 1, 1) - 0.25).abs() < 1e-4);
+        assert!((get_coverage(&coverage, 2, 1) - 0.5).abs() < 1e-4);
+        assert!((get_coverage(&coverage, 2, 2) - 1.0).abs() < 1e-4);
+
+        let mut path = Path::new();
+        path.polygon(&[
+            Point::new(2.0, 2.0),
+            Point::new(14.0, 2.0),
+            Point::new(2.0, 10.0),
+        ]);
+        assert_area(&fill(&path, FillRule::NonZero), 48.0, 1e-3);
+
+        // Fills are clipped to the canvas, and subpaths are implicitly closed
+        let mut path = Path::new();
+        path.move_to(-5.0, -5.0)
+            .line_to(5.0, -5.0)
+            .line_to(5.0, 5.0)
+            .line_to(-5.0, 5.0);
+        assert_pixel_rect(&fill(&path, FillRule::NonZero), 0, 0, 5, 5);
+        let mut path = Path::new();
+        path.rect(10.0, 12.0, 20.0, 20.0);
+        assert_pixel_rect(&fill(&path, FillRule::NonZero), 10, 12, 6, 4);
+    }
+
+    #[test]
+    fn fill_rules() {
+        // Overlapping squares in the same direction
+        let mut path = Path::new();
+        path.rect(2.0, 2.0, 8.0, 8.0).rect(6.0, 6.0, 8.0, 8.0);
+        assert_area(&fill(&path, FillRule::NonZero), 112.0, 1e-3);
+        let coverage = fill(&path, FillRule::EvenOdd);
+        assert_area(&coverage, 96.0, 1e-3);
+        assert_eq!(get_coverage(&coverage, 7, 7), 0.0);
+
+        // An inner square in the opposite direction is a hole with both rules
+        let mut path = Path::new();
+        path.rect(2.0, 2.0, 12.0, 12.0).polygon(&[
+            Point::new(6.0, 6.0),
+            Point::new(6.0, 10.0),
+            Point::new(10.0, 10.0),
+            Point::new(10.0, 6.0),
+        ]);
+        assert_area(&fill(&path, FillRule::NonZero), 128.0, 1e-3);
+        assert_area(&fill(&path, FillRule::EvenOdd), 128.0, 1e-3);
+    }
+
+    #[test]
+    fn fill_curves() {
+        // The area between a quadratic curve and its chord is 2/3 of the control triangle
+        let mut path = Path::new();
+        path.move_to(2.0, 14.0).quad_to(8.0, 2.0, 14.0, 14.0);
+        assert_curved_area(&fill(&path, FillRule::NonZero), 48.0, 20.0);
+
+        // ...and 3/5 of the control quadrilateral for a cubic curve like this one
+        let mut path = Path::new();
+        path.move_to(2.0, 14.0)
+            .cubic_to(2.0, 2.0, 14.0, 2.0, 14.0, 14.0);
+        assert_curved_area(&fill(&path, FillRule::NonZero), 86.4, 24.0);
+
+        let mut path = Path::new();
+        path.circle(8.0, 8.0, 6.0);
+        assert_curved_area(&fill(&path, FillRule::NonZero), PI * 36.0, PI * 12.0);
+        let mut path = Path::new();
+        path.ellipse(8.0, 8.0, 6.0, 3.0);
+        assert_curved_area(&fill(&path, FillRule::NonZero), PI * 18.0, PI * 12.0);
+    }
+
+    #[test]
+    fn flatten_curves() {
+        let mut path = Path::new();
+        path.circle(8.0, 8.0, 6.0)
+            .move_to(0.0, 0.0)
+            .line_to(1.0, 1.0);
+        let polylines = path.flatten();
+        assert_eq!(polylines.len(), 2);
+        let (points, closed) = &polylines[0];
+        assert!(closed);
+        assert!(points.len() > 8);
+        // Points are on the curve, and chords stay within the tolerance of it
+        let center = Point::new(8.0, 8.0);
+        for (i, point) in points.iter().enumerate() {
+            assert!((point.sub(center).length() - 6.0).abs() < 0.01);
+            let middle = point.add(points[(i + 1) % points.len()]).scale(0.5);
+            assert!(6.0 - middle.sub(center).length() <= FLATTEN_TOLERANCE);
+        }
+        assert_eq!(
+            polylines[1],
+            (
+                alloc::vec![Point::new(0.0, 0.0), Point::new(1.0, 1.0)],
+                false
+            )
+        );
+    }
+
+    #[test]
+    fn fill_arcs() {
+        // Pie slices, both clockwise and counter-clockwise
+        let mut path = Path::new();
+        path.move_to(2.0, 2.0)
+            .arc(2.0, 2.0, 12.0, 0.0, FRAC_PI_2)
+            .close();
+        let coverage = fill(&path, FillRule::NonZero);
+        assert_curved_area(&coverage, PI * 36.0, PI * 6.0);
+        assert_eq!(get_coverage(&coverage, 2, 12), 1.0);
+        assert_eq!(get_coverage(&coverage, 13, 13), 0.0);
+        let mut path = Path::new();
+        path.move_to(8.0, 14.0)
+            .arc(8.0, 14.0, 6.0, 0.0, -PI)
+            .close();
+        let coverage = fill(&path, FillRule::NonZero);
+        assert_curved_area(&coverage, PI * 18.0, PI * 6.0);
+        assert_eq!(get_coverage(&coverage, 8, 10), 1.0);
+        assert_eq!(get_coverage(&coverage, 8, 15), 0.0);
+
+        // Full circles, even when the sweep is larger
+        let mut path = Path::new();
+        path.arc(8.0, 8.0, 6.0, FRAC_PI_4, FRAC_PI_4 + 3.0 * PI);
+        assert_curved_area(&fill(&path, FillRule::NonZero), PI * 36.0, PI * 12.0);
+    }
+
+    #[test]
+    fn fill_rounded_rects() {
+        let mut path = Path::new();
+        path.rounded_rect(2.0, 2.0, 12.0, 8.0, 3.0);
+        let coverage = fill(&path, FillRule::NonZero);
+        assert_curved_area(&coverage, 96.0 - (4.0 - PI) * 9.0, PI * 6.0);
+        assert!(get_coverage(&coverage, 2, 2) < 0.01);
+        assert_eq!(get_coverage(&coverage, 8, 2), 1.0);
+
+        // The radius is limited to half the size
+        let mut path = Path::new();
+        path.rounded_rect(2.0, 2.0, 12.0, 8.0, 10.0);
+        assert_curved_area(
+            &fill(&path, FillRule::NonZero),
+            96.0 - (4.0 - PI) * 16.0,
+            PI * 8.0,
+        );
+
+        let mut path = Path::new();
+        path.rounded_rect(2.0, 3.0, 5.0, 4.0, 0.0);
+        assert_pixel_rect(&fill(&path, FillRule::NonZero), 2, 3, 5, 4);
+    }
+
+    #[test]
+    fn stroke_caps() {
+        let path = line(2.0, 8.0, 12.0, 8.0);
+        let style = StrokeStyle::new(4.0);
+        assert_pixel_rect(&stroke(&path, style), 2, 6, 10, 4);
+        assert_pixel_rect(&stroke(&path, style.with_cap(LineCap::Square)), 0, 6, 14, 4);
+        assert_curved_area(
+            &stroke(&path, style.with_cap(LineCap::Round)),
+            40.0 + PI * 4.0,
+            PI * 4.0,
+        );
+
+        // Zero-length lines only have their caps
+        let point = line(8.0, 8.0, 8.0, 8.0);
+        assert_area(&stroke(&point, style), 0.0, 0.0);
+        assert_pixel_rect(&stroke(&point, style.with_cap(LineCap::Square)), 6, 6, 4, 4);
+        assert_curved_area(
+            &stroke(&point, style.with_cap(LineCap::Round)),
+            PI * 4.0,
+            PI * 4.0,
+        );
+    }
+
+    #[test]
+    fn stroke_overlaps_not_accumulated() {
+        // The caps overlap the line's anti-aliased edges, which must only be covered once
+        let path = line(3.0, 3.0, 12.0, 9.0);
+        let style = StrokeStyle::new(3.0).with_cap(LineCap::Round);
+        let length = 9.0f32.hypot(6.0);
+        assert_curved_area(&stroke(&path, style), length * 3.0 + PI * 2.25, PI * 3.0);
+
+        // Flattened curves are made of many segments, each with its own join
+        let mut path = Path::new();
+        path.circle(8.0, 8.0, 5.0);
+        let style = StrokeStyle::new(2.0).with_join(LineJoin::Round);
+        assert_curved_area(&stroke(&path, style), PI * (36.0 - 16.0), PI * 20.0);
+    }
+
+    #[test]
+    fn stroke_joins() {
+        // Right angle turning clockwise, the outer corner being the top right one
+        let mut path = Path::new();
+        path.move_to(4.0, 4.0)
+            .line_to(14.0, 4.0)
+            .line_to(14.0, 14.0);
+        let style = StrokeStyle::new(2.0);
+        let coverage = stroke(&path, style);
+        assert_area(&coverage, 40.0, 1e-3);
+        assert_eq!(get_coverage(&coverage, 14, 3), 1.0);
+        let coverage = stroke(&path, style.with_join(LineJoin::Bevel));
+        assert_area(&coverage, 39.5, 1e-3);
+        assert!((get_coverage(&coverage, 14, 3) - 0.5).abs() < 1e-4);
+        assert_curved_area(
+            &stroke(&path, style.with_join(LineJoin::Round)),
+            40.0 - (1.0 - FRAC_PI_4),
+            FRAC_PI_2,
+        );
+
+        // Counter-clockwise turns too
+        let mut path = Path::new();
+        path.move_to(4.0, 14.0).line_to(4.0, 4.0).line_to(14.0, 4.0);
+        assert_area(&stroke(&path, style), 40.0, 1e-3);
+        assert_area(&stroke(&path, style.with_join(LineJoin::Bevel)), 39.5, 1e-3);
+
+        // Sharp corners past the miter limit are beveled
+        let mut path = Path::new();
+        path.move_to(2.0, 4.0).line_to(14.0, 8.0).line_to(2.0, 12.0);
+        let miter = stroke(&path, style);
+        let bevel = stroke(&path, style.with_join(LineJoin::Bevel));
+        let limited = stroke(&path, style.with_miter_limit(2.0));
+        let sum = |coverage: &[f32]| coverage.iter().sum::<f32>();
+        assert!(sum(&miter) > sum(&bevel) + 0.5);
+        assert_area(&limited, sum(&bevel), 1e-3);
+
+        // Closed paths are joined at their start, leaving their inside empty
+        let mut path = Path::new();
+        path.rect(4.0, 4.0, 8.0, 8.0);
+        let coverage = stroke(&path, style);
+        assert_area(&coverage, 100.0 - 36.0, 1e-3);
+        assert_eq!(get_coverage(&coverage, 3, 3), 1.0);
+        assert_eq!(get_coverage(&coverage, 8, 8), 0.0);
+    }
+
+    #[test]
+    fn gradients() {
+        let black = RGBA8::new_scaled(0, 0, 0, 0xFF);
+        let white = RGBA8::new_scaled(0xFF, 0xFF, 0xFF, 0xFF);
+        let draw = |paint: Paint<RGBA8>| {
+            let mut canvas = OffscreenCanvas::new(SIZE, SIZE);
+            let mut rasterizer = Rasterizer::new(SIZE, SIZE);
+            let mut path = Path::new();
+            path.rect(0.0, 0.0, SIZE as f32, SIZE as f32);
+            rasterizer.add_path_fill(&path);
+            draw_rasterized(
+                &mut canvas,
+                rasterizer,
+                FillRule::NonZero,
+                &paint,
+                AlphaBlend::None,
+            );
+            canvas
+        };
+        let stops = alloc::vec![GradientStop::new(0.0, black), GradientStop::new(1.0, white)];
+        let expected_value = |t: f32| (t.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+
+        // Colors are taken at pixel centers
+        let canvas = draw(Paint::LinearGradient {
+            start: Point::new(0.0, 0.0),
+            end: Point::new(SIZE as f32, 0.0),
+            stops: stops.clone(),
+        });
+        for x in 0..SIZE {
+            let expected = expected_value((x as f32 + 0.5) / SIZE as f32);
+            assert_eq!(canvas.get_pixel(x, 0).unwrap().to_scaled().0, expected);
+            assert_eq!(
+                canvas.get_pixel(x, SIZE - 1).unwrap().to_scaled().0,
+                expected
+            );
+        }
+
+        // Gradients are padded past their ends
+        let canvas = draw(Paint::LinearGradient {
+            start: Point::new(4.0, 0.0),
+            end: Point::new(12.0, 0.0),
+            stops: stops.clone(),
+        });
+        for x in 0..SIZE {
+            let expected = expected_value((x as f32 + 0.5 - 4.0) / 8.0);
+            assert_eq!(canvas.get_pixel(x, 0).unwrap().to_scaled().0, expected);
+        }
+        assert_eq!(canvas.get_pixel(0, 0).unwrap().to_scaled().0, 0);
+        assert_eq!(canvas.get_pixel(SIZE - 1, 0).unwrap().to_scaled().0, 0xFF);
+
+        let canvas = draw(Paint::RadialGradient {
+            center: Point::new(8.0, 8.0),
+            radius: 8.0,
+            stops,
+        });
+        for (x, y) in [(7, 7), (8, 3), (0, 8), (0, 0)] {
+            let distance = Point::new(x as f32 + 0.5, y as f32 + 0.5)
+                .sub(Point::new(8.0, 8.0))
+                .length();
+            let expected = expected_value(distance / 8.0);
+            assert_eq!(canvas.get_pixel(x, y).unwrap().to_scaled().0, expected);
+        }
+
+        // Colors are interpolated between the surrounding stops
+        let canvas = draw(Paint::LinearGradient {
+            start: Point::new(0.0, 0.0),
+            end: Point::new(SIZE as f32, 0.0),
+            stops: alloc::vec![
+                GradientStop::new(0.0, RGBA8::new_scaled(0xFF, 0, 0, 0xFF)),
+                GradientStop::new(0.5, RGBA8::new_scaled(0, 0xFF, 0, 0xFF)),
+                GradientStop::new(1.0, RGBA8::new_scaled(0, 0, 0xFF, 0xFF)),
+            ],
+        });
+        let (r, g, b, a) = canvas.get_pixel(0, 0).unwrap().to_scaled();
+        assert!((r > 0xE0) && (g < 0x20) && (b == 0) && (a == 0xFF));
+        let (r, g, b, _) = canvas.get_pixel(8, 0).unwrap().to_scaled();
+        assert!((r == 0) && (g > 0xE0) && (b < 0x20));
+        let (r, g, b, _) = canvas.get_pixel(SIZE - 1, 0).unwrap().to_scaled();
+        assert!((r == 0) && (g < 0x20) && (b > 0xE0));
+    }
+}
@@ -200,6 +200,32 @@ pub fn get_loader_info() -> &'static str {
     *G_LOADER_INFO.read()
 }
 
+static G_ARGV: RwLock<&'static str> = RwLock::new("");
+
+pub(crate) fn set_argv(argv: &'static str) {
+    *G_ARGV.write() = argv;
+}
+
+/// Gets the raw argv string sent by HBL
+///
+/// This value will only be set/useful if the current code is running through HBL
+pub fn get_argv() -> &'static str {
+    *G_ARGV.read()
+}
+
+/// Gets the path of the currently running homebrew NRO, which is the first argument in the argv string
+///
+/// This value will only be set/useful if the current code is running through HBL
+pub fn get_executable_path() -> Option<&'static str> {
+    let argv = get_argv().trim_start();
+    let path = match argv.strip_prefix('"') {
+        Some(quoted_argv) => quoted_argv.split('"').next(),
+        None => argv.split_whitespace().next(),
+    }?;
+
+    (!path.is_empty()).then_some(path)
+}
+
 pub static G_NEXT_LOAD_PATH: Mutex<Option<&'static mut ArrayString<512>>> = Mutex::new(None);
 pub static G_NEXT_LOAD_ARGV: Mutex<Option<&'static mut ArrayString<2048>>> = Mutex::new(None);
 
@@ -451,6 +451,11 @@ impl DataWalker {
 
 #[inline(always)]
 pub fn get_msg_buffer() -> *mut u8 {
+    #[cfg(feature = "transport")]
+    if let Some(msg_buffer) = transport::get_transport_msg_buffer() {
+        return msg_buffer;
+    }
+
     unsafe { (*thread::get_thread_local_region()).msg_buffer.as_mut_ptr() }
 }
 
@@ -1017,3 +1022,6 @@ pub mod cmif;
 pub mod tipc;
 
 pub mod sf;
+
+#[cfg(feature = "transport")]
+pub mod transport;
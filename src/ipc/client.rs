@@ -150,6 +150,23 @@ impl RequestCommandParameter for sf::AppletResourceUserId {
 
 //impl !ResponseCommandParameter<sf::AppletResourceUserId> for sf::AppletResourceUserId {}
 
+/// Sends the request currently written on the message buffer to the given session, leaving the response on the message buffer
+///
+/// This goes through the installed [`RequestTransport`][`super::transport::RequestTransport`] (if the `transport` feature is enabled and one was installed for the current thread), and through the kernel otherwise
+///
+/// # Arguments
+///
+/// * `handle`: The session handle
+#[inline(always)]
+pub fn send_sync_request(handle: svc::Handle) -> Result<()> {
+    #[cfg(feature = "transport")]
+    if let Some(rc) = super::transport::send_transport_request(handle) {
+        return rc;
+    }
+
+    svc::send_sync_request(handle)
+}
+
 pub trait IClientObject {
     fn new(session: sf::Session) -> Self
     where
@@ -21,5 +21,7 @@ result_define_subgroup!(rc::RESULT_MODULE, RESULT_SUBMODULE => {
     InvalidExchangeBufferCount: 15,
     InvalidBufferAttributes: 16,
     InvalidProtocol: 17,
-    InvalidBufferPointer: 18
+    InvalidBufferPointer: 18,
+    NoTransportResponse: 19,
+    InvalidTransportMessageSize: 20
 });
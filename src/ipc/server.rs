@@ -1,8 +1,15 @@
 use super::*;
+use crate::diag::log::{LogSeverity, Logger};
 use crate::sync::Mutex;
+use crate::thread;
 use crate::wait;
 use alloc::rc::Rc;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::mem::MaybeUninit;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
 use sf::hipc::IHipcManager;
 use sf::hipc::IMitmQueryServiceServer;
 
@@ -241,6 +248,17 @@ pub trait ISessionObject {
         protocol: CommandProtocol,
         server_ctx: &mut ServerContext,
     ) -> Option<Result<()>>;
+
+    /// Called after a command handler of this object fails with [`rc::ResultRequestDeferred`], with the token to reply to that request later on
+    ///
+    /// By default the token is just dropped, thus the client gets [`rc::ResultReplyTokenDropped`] as the reply
+    ///
+    /// # Arguments
+    ///
+    /// * `token`: The [`ReplyToken`] of the deferred request
+    fn on_request_deferred(&mut self, token: ReplyToken) {
+        drop(token);
+    }
 }
 
 pub trait IServerObject: ISessionObject {
@@ -682,35 +700,235 @@ pub trait IMitmService: IMitmServerObject {
 
 // TODO: use const generics to reduce memory usage, like libstratosphere does?
 
-pub struct ServerManager<const P: usize> {
-    server_holders: Vec<ServerHolder>,
-    wait_handles: [svc::Handle; MAX_COUNT],
-    pointer_buffer: [u8; P],
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum ProcessMode {
+    /// Receive the request from the kernel and reply to it
+    Receive,
+    /// The request is already on the message buffer, leave the response on it without replying
+    InProcess,
 }
 
-impl<const P: usize> ServerManager<P> {
-    pub fn new() -> Result<Self> {
+/// The maximum size of the data replied through a [`ReplyToken`], so that the response always fits in the message buffer
+pub const MAX_DEFERRED_REPLY_DATA_SIZE: usize = 0x80;
+
+static G_NEXT_DEFERRED_REQUEST_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Represents a request whose handling was deferred (see [`rc::ResultRequestDeferred`]), waiting for its [`ReplyToken`] to be used
+struct DeferredRequest {
+    id: usize,
+    object_info: ObjectInfo,
+    command_type: cmif::CommandType,
+    reply: Option<(ResultCode, Vec<MaybeUninit<u8>>)>,
+}
+
+impl DeferredRequest {
+    /// Writes the reply on the message buffer and sends it to the session the request was received from
+    fn send_reply(&self, rc: ResultCode, data: &[MaybeUninit<u8>]) -> Result<()> {
+        let mut ctx = CommandContext::new_server(self.object_info, core::ptr::null_mut());
+        ctx.out_params.data_size = data.len() as u32;
+        match self.object_info.protocol {
+            CommandProtocol::Cmif => cmif::server::write_request_command_response_on_msg_buffer(
+                &mut ctx,
+                rc,
+                self.command_type,
+            ),
+            CommandProtocol::Tipc => {
+                tipc::server::write_request_command_response_on_msg_buffer(&mut ctx, rc, 16)
+            }
+        };
+        // The data may contain (uninitialized) padding bytes, which are just copied as they are
+        unsafe {
+            core::ptr::copy(
+                data.as_ptr() as *const u8,
+                ctx.out_params.data_offset,
+                data.len(),
+            );
+        }
+
+        reply_to_session(self.object_info.handle)
+    }
+}
+
+#[cfg(not(test))]
+type ReplyEvent = wait::SystemEvent;
+
+// Host unit tests can't create kernel events, so signals are just counted there
+#[cfg(test)]
+struct ReplyEvent {
+    client_handle: svc::Handle,
+    signal_count: AtomicUsize,
+}
+
+#[cfg(test)]
+impl ReplyEvent {
+    fn new() -> Result<Self> {
         Ok(Self {
-            server_holders: Vec::new(),
-            wait_handles: [0; MAX_COUNT],
-            pointer_buffer: [0; P],
+            client_handle: svc::INVALID_HANDLE,
+            signal_count: AtomicUsize::new(0),
         })
     }
 
-    #[inline(always)]
-    fn prepare_wait_handles(&mut self) -> &[svc::Handle] {
-        let mut handles_index: usize = 0;
-        for server_holder in &mut self.server_holders {
-            let server_info = server_holder.info;
-            if server_info.handle != svc::INVALID_HANDLE {
-                self.wait_handles[handles_index] = server_info.handle;
-                handles_index += 1;
+    fn signal(&self) -> Result<()> {
+        self.signal_count.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Represents the deferred requests of a [`ServerManager`], shared with their [`ReplyToken`]s
+struct ReplyQueue {
+    requests: Mutex<Vec<DeferredRequest>>,
+    /// Signaled when a reply is ready to be sent
+    event: ReplyEvent,
+}
+
+impl ReplyQueue {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            requests: Mutex::new(Vec::new()),
+            event: ReplyEvent::new()?,
+        })
+    }
+
+    /// Takes the requests which were replied to and whose sessions are ready to send the replies, leaving the rest queued
+    fn take_replied_requests(
+        &self,
+        is_session_ready: impl Fn(svc::Handle) -> bool,
+    ) -> Vec<DeferredRequest> {
+        let mut requests = self.requests.lock();
+        let (replied_requests, pending_requests) = core::mem::take(&mut *requests)
+            .into_iter()
+            .partition(|request: &DeferredRequest| {
+                request.reply.is_some() && is_session_ready(request.object_info.handle)
+            });
+        *requests = pending_requests;
+        replied_requests
+    }
+
+    /// Drops the deferred requests received from a session which is being closed, thus replies to them will be ignored
+    fn remove_session_requests(&self, handle: svc::Handle) {
+        self.requests
+            .lock()
+            .retain(|request| request.object_info.handle != handle);
+    }
+}
+
+/// Represents a deferred request (see [`rc::ResultRequestDeferred`]), which must be replied to through this token
+///
+/// Replies are sent by the [`ServerManager`] which received the request, the next time it processes (see [`ServerManager::send_deferred_replies`]), thus tokens may be used from any thread. If the session is closed in the meantime, the reply is just ignored.
+///
+/// Dropping the token without replying makes the client get [`rc::ResultReplyTokenDropped`] as the reply.
+pub struct ReplyToken {
+    id: usize,
+    request_id: u32,
+    queue: Arc<ReplyQueue>,
+    replied: bool,
+}
+
+impl ReplyToken {
+    /// Gets the command ID of the deferred request
+    #[inline]
+    pub fn get_request_id(&self) -> u32 {
+        self.request_id
+    }
+
+    fn complete(&mut self, rc: ResultCode, data: Vec<MaybeUninit<u8>>) -> Result<()> {
+        self.replied = true;
+        if let Some(request) = self
+            .queue
+            .requests
+            .lock()
+            .iter_mut()
+            .find(|request| request.id == self.id)
+        {
+            request.reply = Some((rc, data));
+        }
+        self.queue.event.signal()
+    }
+
+    /// Replies to the deferred request
+    ///
+    /// Only plain output data is supported (no handles, sessions or buffers), and its size can't exceed [`MAX_DEFERRED_REPLY_DATA_SIZE`]
+    ///
+    /// Like regular command responses, the data is written as the type's raw representation, padding included
+    ///
+    /// # Arguments
+    ///
+    /// * `result`: The command result, with the output data on success (or `()` for commands without any)
+    pub fn reply<T: Copy>(mut self, result: Result<T>) -> Result<()> {
+        let data = match result {
+            Ok(value) => {
+                let data_size = core::mem::size_of::<T>();
+                result_return_if!(
+                    data_size > MAX_DEFERRED_REPLY_DATA_SIZE,
+                    rc::ResultInvalidReplyDataSize
+                );
+                // The value is copied as a whole instead of being read as bytes, since any padding bytes are uninitialized
+                let mut data = Vec::with_capacity(data_size);
+                unsafe {
+                    core::ptr::write_unaligned(data.as_mut_ptr() as *mut T, value);
+                    data.set_len(data_size);
+                }
+                data
             }
+            Err(rc) => return self.complete(rc, Vec::new()),
+        };
+        self.complete(ResultSuccess::make(), data)
+    }
+}
+
+impl Drop for ReplyToken {
+    /// Replies with [`rc::ResultReplyTokenDropped`] if no reply was sent
+    fn drop(&mut self) {
+        if !self.replied {
+            let _ = self.complete(rc::ResultReplyTokenDropped::make(), Vec::new());
         }
-        &self.wait_handles[..handles_index]
     }
+}
 
-    #[inline(always)]
+/// Replies with the response on the message buffer to the given session, without receiving anything
+fn reply_to_session(handle: svc::Handle) -> Result<()> {
+    match unsafe { svc::reply_and_receive(&handle, 0, handle, 0) } {
+        Err(rc)
+            if !svc::rc::ResultTimedOut::matches(rc)
+                && !svc::rc::ResultSessionClosed::matches(rc) =>
+        {
+            Err(rc)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Manages server objects and their sessions
+///
+/// Command handlers may defer requests by failing with [`rc::ResultRequestDeferred`]: the client won't receive a reply until the server object uses the [`ReplyToken`] it then gets (see [`ISessionObject::on_request_deferred`]), while the rest of the requests keep being processed
+pub struct ServerManager<const P: usize> {
+    server_holders: Vec<ServerHolder>,
+    wait_handles: [svc::Handle; MAX_COUNT],
+    pointer_buffer: [u8; P],
+    reply_queue: Arc<ReplyQueue>,
+}
+
+/// Represents the outcome of processing a [`ServerHolder`]
+#[derive(Default)]
+struct ProcessOutcome {
+    close_session: bool,
+    new_sessions: Vec<ServerHolder>,
+}
+
+impl ServerHolder {
+    fn forward_request(&self, ipc_buf_backup: &[u8]) -> Result<()> {
+        let ipc_buf = get_msg_buffer();
+        unsafe {
+            core::ptr::copy(ipc_buf_backup.as_ptr(), ipc_buf, ipc_buf_backup.len());
+        }
+        // Let the original service take care of the command for us.
+        svc::send_sync_request(self.mitm_forward_info.handle)
+    }
+
+    /// Handles a request command, returning whether it was deferred
+    ///
+    /// Deferring isn't supported (thus fails with [`rc::ResultRequestDeferred`]) without a [`ReplyQueue`]
+    #[allow(clippy::too_many_arguments)]
     fn handle_request_command(
         &mut self,
         ctx: &mut CommandContext,
@@ -718,133 +936,99 @@ impl<const P: usize> ServerManager<P> {
         command_type: cmif::CommandType,
         domain_command_type: cmif::DomainCommandType,
         ipc_buf_backup: &[u8],
-        domain_table: Option<Rc<Mutex<DomainTable>>>,
-    ) -> Result<()> {
+        new_sessions: &mut Vec<ServerHolder>,
+        reply_queue: Option<&Arc<ReplyQueue>>,
+    ) -> Result<bool> {
         let is_domain = ctx.object_info.is_domain();
-        let domain_table_clone = domain_table.clone();
-        let do_handle_request = || -> Result<()> {
-            let mut new_sessions: Vec<ServerHolder> = Vec::new();
-            for server_holder in &mut self.server_holders {
-                let server_info = server_holder.info;
-                if server_info.handle == ctx.object_info.handle {
-                    let send_to_forward_handle = || -> Result<()> {
-                        let ipc_buf = get_msg_buffer();
-                        unsafe {
-                            core::ptr::copy(ipc_buf_backup.as_ptr(), ipc_buf, ipc_buf_backup.len());
-                        }
-                        // Let the original service take care of the command for us.
-                        svc::send_sync_request(server_holder.mitm_forward_info.handle)
-                    };
-
-                    let target_server = match is_domain {
-                        true => match ctx.object_info.owns_handle {
-                            true => server_holder
-                                .server
-                                .clone()
-                                .ok_or(rc::ResultSignaledServerNotFound::make())?,
-                            false => domain_table
-                                .ok_or(rc::ResultDomainNotFound::make())?
-                                .lock()
-                                .find_domain(ctx.object_info.domain_object_id)?,
-                        },
-                        false => server_holder
-                            .server
-                            .clone()
-                            .ok_or(rc::ResultSignaledServerNotFound::make())?,
-                    };
-                    // Nothing done on success here, as if the command succeeds it will automatically respond by itself.
-                    let mut command_found = false;
-                    {
-                        let protocol = ctx.object_info.protocol;
-                        let mut server_ctx = ServerContext::new(
-                            ctx,
-                            DataWalker::empty(),
-                            domain_table_clone.clone(),
-                            &mut new_sessions,
-                        );
-                        if let Some(result) = target_server.lock().try_handle_request_by_id(
-                            rq_id,
-                            protocol,
-                            &mut server_ctx,
-                        ) {
-                            command_found = true;
-                            if let Err(rc) = result {
-                                if server_holder.is_mitm_service
-                                    && sm::mitm::rc::ResultShouldForwardToSession::matches(rc)
-                                {
-                                    if let Err(rc) = send_to_forward_handle() {
-                                        cmif::server::write_request_command_response_on_msg_buffer(
-                                            ctx,
-                                            rc,
-                                            command_type,
-                                        );
-                                    }
-                                } else {
-                                    cmif::server::write_request_command_response_on_msg_buffer(
-                                        ctx,
-                                        rc,
-                                        command_type,
-                                    );
-                                }
-                            }
-                        }
-                    }
-                    if !command_found {
-                        if server_holder.is_mitm_service {
-                            if let Err(rc) = send_to_forward_handle() {
-                                cmif::server::write_request_command_response_on_msg_buffer(
-                                    ctx,
-                                    rc,
-                                    command_type,
-                                );
-                            }
-                        } else {
-                            cmif::server::write_request_command_response_on_msg_buffer(
-                                ctx,
-                                cmif::rc::ResultInvalidCommandRequestId::make(),
-                                command_type,
-                            );
-                        }
-                    }
-                    break;
-                }
-            }
-
-            self.server_holders.append(&mut new_sessions);
-
-            Ok(())
-        };
-
+        let domain_table = self.domain_table.clone();
         match domain_command_type {
+            // Invalid command type might mean that the session isn't a domain :P
             cmif::DomainCommandType::Invalid => {
-                // Invalid command type might mean that the session isn't a domain :P
-                match is_domain {
-                    false => do_handle_request()?,
-                    true => return rc::ResultInvalidDomainCommandType::make_err(),
-                };
+                result_return_if!(is_domain, rc::ResultInvalidDomainCommandType);
             }
-            cmif::DomainCommandType::SendMessage => do_handle_request()?,
+            cmif::DomainCommandType::SendMessage => {}
             cmif::DomainCommandType::Close => {
                 if !ctx.object_info.owns_handle {
-                    domain_table_clone
+                    domain_table
                         .ok_or(rc::ResultDomainNotFound::make())?
                         .lock()
                         .deallocate_domain(ctx.object_info.domain_object_id);
                 } else {
                     // TODO: Abort? Error?
                 }
+                return Ok(false);
             }
         }
 
-        Ok(())
+        let target_server = match is_domain && !ctx.object_info.owns_handle {
+            true => domain_table
+                .clone()
+                .ok_or(rc::ResultDomainNotFound::make())?
+                .lock()
+                .find_domain(ctx.object_info.domain_object_id)?,
+            false => self
+                .server
+                .clone()
+                .ok_or(rc::ResultSignaledServerNotFound::make())?,
+        };
+
+        let protocol = ctx.object_info.protocol;
+        let result = {
+            let mut server_ctx =
+                ServerContext::new(ctx, DataWalker::empty(), domain_table, new_sessions);
+            target_server
+                .lock()
+                .try_handle_request_by_id(rq_id, protocol, &mut server_ctx)
+        };
+
+        // Nothing done on success here, as if the command succeeds it will automatically respond by itself.
+        let error_rc = match result {
+            Some(Ok(())) => None,
+            Some(Err(rc)) if rc::ResultRequestDeferred::matches(rc) => {
+                // There's nobody to reply to later on when processing in-process
+                let reply_queue = reply_queue.ok_or(rc)?;
+
+                let id = G_NEXT_DEFERRED_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+                reply_queue.requests.lock().push(DeferredRequest {
+                    id,
+                    object_info: ctx.object_info,
+                    command_type,
+                    reply: None,
+                });
+                target_server.lock().on_request_deferred(ReplyToken {
+                    id,
+                    request_id: rq_id,
+                    queue: reply_queue.clone(),
+                    replied: false,
+                });
+                return Ok(true);
+            }
+            Some(Err(rc))
+                if self.is_mitm_service
+                    && sm::mitm::rc::ResultShouldForwardToSession::matches(rc) =>
+            {
+                self.forward_request(ipc_buf_backup).err()
+            }
+            Some(Err(rc)) => Some(rc),
+            None => match self.is_mitm_service {
+                true => self.forward_request(ipc_buf_backup).err(),
+                false => Some(cmif::rc::ResultInvalidCommandRequestId::make()),
+            },
+        };
+        if let Some(rc) = error_rc {
+            cmif::server::write_request_command_response_on_msg_buffer(ctx, rc, command_type);
+        }
+
+        Ok(false)
     }
 
-    #[inline(always)]
     fn handle_control_command(
         &mut self,
         ctx: &mut CommandContext,
         rq_id: u32,
         command_type: cmif::CommandType,
+        pointer_buf_size: usize,
+        new_sessions: &mut Vec<ServerHolder>,
     ) -> Result<()> {
         // Control commands only exist in CMIF...
         result_return_unless!(
@@ -852,225 +1036,233 @@ impl<const P: usize> ServerManager<P> {
             super::rc::ResultInvalidProtocol
         );
 
-        for server_holder in &mut self.server_holders {
-            let server_info = server_holder.info;
-            if server_info.handle == ctx.object_info.handle {
-                let mut hipc_manager = HipcManager::new(server_holder, P);
-                // Nothing done on success here, as if the command succeeds it will automatically respond by itself.
-                let mut command_found = false;
-                {
-                    let mut unused_new_sessions: Vec<ServerHolder> = Vec::new();
-                    let mut server_ctx = ServerContext::new(
-                        ctx,
-                        DataWalker::empty(),
-                        None,
-                        &mut unused_new_sessions,
-                    );
-                    if let Some(result) = <HipcManager as ISessionObject>::try_handle_request_by_id(
-                        &mut hipc_manager,
-                        rq_id,
-                        CommandProtocol::Cmif,
-                        &mut server_ctx,
-                    ) {
-                        command_found = true;
-                        if let Err(rc) = result {
-                            cmif::server::write_control_command_response_on_msg_buffer(
-                                ctx,
-                                rc,
-                                command_type,
-                            );
-                        }
-                    }
-                }
-                if !command_found {
-                    cmif::server::write_control_command_response_on_msg_buffer(
-                        ctx,
-                        cmif::rc::ResultInvalidCommandRequestId::make(),
-                        command_type,
-                    );
-                }
+        let mut hipc_manager = HipcManager::new(self, pointer_buf_size);
+        let result = {
+            let mut unused_new_sessions: Vec<ServerHolder> = Vec::new();
+            let mut server_ctx =
+                ServerContext::new(ctx, DataWalker::empty(), None, &mut unused_new_sessions);
+            <HipcManager as ISessionObject>::try_handle_request_by_id(
+                &mut hipc_manager,
+                rq_id,
+                CommandProtocol::Cmif,
+                &mut server_ctx,
+            )
+        };
 
-                if hipc_manager.has_cloned_object() {
-                    let cloned_holder = hipc_manager.clone_object()?;
-                    self.server_holders.push(cloned_holder);
-                }
-                break;
-            }
+        // Nothing done on success here, as if the command succeeds it will automatically respond by itself.
+        let error_rc = match result {
+            Some(Ok(())) => None,
+            Some(Err(rc)) => Some(rc),
+            None => Some(cmif::rc::ResultInvalidCommandRequestId::make()),
+        };
+        if let Some(rc) = error_rc {
+            cmif::server::write_control_command_response_on_msg_buffer(ctx, rc, command_type);
         }
 
+        if hipc_manager.has_cloned_object() {
+            new_sessions.push(hipc_manager.clone_object()?);
+        }
         Ok(())
     }
 
-    fn process_signaled_handle(&mut self, handle: svc::Handle) -> Result<()> {
-        let mut server_found = false;
-        let mut index: usize = 0;
-        let mut should_close_session = false;
-        let mut new_sessions: Vec<ServerHolder> = Vec::new();
-
-        let mut ctx = CommandContext::empty();
-        let mut command_type = cmif::CommandType::Invalid;
-        let mut domain_cmd_type = cmif::DomainCommandType::Invalid;
-        let mut rq_id: u32 = 0;
-        let mut ipc_buf_backup: [u8; 0x100] = [0; 0x100];
-        let mut domain_table: Option<Rc<Mutex<DomainTable>>> = None;
-
-        for server_holder in &mut self.server_holders {
-            let server_info = server_holder.info;
-            if server_info.handle == handle {
-                server_found = true;
-                match server_holder.handle_type {
-                    WaitHandleType::Session => {
-                        if P > 0 {
-                            // Send our pointer buffer as a C descriptor for kernel - why are Pointer buffers so fucking weird?
-                            let mut tmp_ctx = CommandContext::new_client(server_info);
-                            tmp_ctx.add_receive_static(ReceiveStaticDescriptor::new(
-                                self.pointer_buffer.as_ptr(),
-                                P,
-                            ))?;
-                            cmif::client::write_command_on_msg_buffer(
-                                &mut tmp_ctx,
-                                cmif::CommandType::Invalid,
-                                0,
-                            );
-                        }
-
-                        if let Err(rc) = unsafe { svc::reply_and_receive(&handle, 1, 0, -1) } {
-                            if svc::rc::ResultSessionClosed::matches(rc) {
-                                should_close_session = true;
-                                break;
-                            } else {
-                                return Err(rc);
-                            }
-                        };
-
-                        unsafe {
-                            core::ptr::copy(
-                                get_msg_buffer(),
-                                ipc_buf_backup.as_mut_ptr(),
-                                ipc_buf_backup.len(),
-                            )
-                        };
-
-                        ctx = CommandContext::new_server(
-                            server_info,
-                            self.pointer_buffer.as_mut_ptr(),
-                        );
-                        command_type = cmif::server::read_command_from_msg_buffer(&mut ctx);
-                        match command_type {
-                            cmif::CommandType::Request | cmif::CommandType::RequestWithContext => {
-                                match cmif::server::read_request_command_from_msg_buffer(&mut ctx) {
-                                    Ok((request_id, domain_command_type, domain_object_id)) => {
-                                        let mut base_info = server_info;
-                                        if server_info.is_domain() {
-                                            // This is a domain request
-                                            base_info.domain_object_id = domain_object_id;
-                                            base_info.owns_handle =
-                                                server_info.domain_object_id == domain_object_id;
-                                        }
-                                        ctx.object_info = base_info;
-                                        domain_cmd_type = domain_command_type;
-                                        rq_id = request_id;
-                                        domain_table = server_holder.domain_table.clone();
-                                    }
-                                    Err(rc) => return Err(rc),
-                                };
-                            }
-                            cmif::CommandType::Control | cmif::CommandType::ControlWithContext => {
-                                match cmif::server::read_control_command_from_msg_buffer(&mut ctx) {
-                                    Ok(control_rq_id) => {
-                                        rq_id = control_rq_id as u32;
-                                    }
-                                    Err(rc) => return Err(rc),
-                                };
-                            }
-                            cmif::CommandType::Close => {
-                                should_close_session = true;
-                            }
-                            _ => return rc::ResultInvalidCommandType::make_err(),
-                        }
-                    }
-                    WaitHandleType::Server => {
-                        let new_handle = svc::accept_session(handle)?;
-
-                        if server_holder.is_mitm_service {
-                            #[cfg(feature = "services")]
-                            {
-                                let sm = service::new_named_port_object::<sm::UserInterface>()?;
-                                let (info, session_handle) = sm
-                                    .atmosphere_acknowledge_mitm_session(
-                                        server_holder.service_name,
-                                    )?;
-                                new_sessions.push(server_holder.make_new_mitm_session(
-                                    new_handle,
-                                    session_handle.handle,
-                                    info,
-                                    server_holder.service_name,
-                                )?);
-                                sm.detach_client(sf::ProcessId::new())?;
-                            }
-                        } else {
-                            new_sessions.push(server_holder.make_new_session(new_handle)?);
-                        }
-                    }
-                };
-                break;
+    /// Processes a request (or a new session, for server handles) for this holder
+    ///
+    /// # Arguments
+    ///
+    /// * `pointer_buffer`: The pointer buffer of the current thread
+    /// * `mode`: The [`ProcessMode`]
+    /// * `reply_queue`: The [`ReplyQueue`] deferred requests are registered on
+    fn process(
+        &mut self,
+        pointer_buffer: &mut [u8],
+        mode: ProcessMode,
+        reply_queue: &Arc<ReplyQueue>,
+    ) -> Result<ProcessOutcome> {
+        let handle = self.info.handle;
+        let mut outcome = ProcessOutcome::default();
+
+        if self.handle_type == WaitHandleType::Server {
+            result_return_unless!(mode == ProcessMode::Receive, rc::ResultInvalidCommandType);
+
+            let new_handle = svc::accept_session(handle)?;
+            if self.is_mitm_service {
+                #[cfg(feature = "services")]
+                {
+                    let sm = service::new_named_port_object::<sm::UserInterface>()?;
+                    let (info, session_handle) =
+                        sm.atmosphere_acknowledge_mitm_session(self.service_name)?;
+                    outcome.new_sessions.push(self.make_new_mitm_session(
+                        new_handle,
+                        session_handle.handle,
+                        info,
+                        self.service_name,
+                    )?);
+                    sm.detach_client(sf::ProcessId::new())?;
+                }
+            } else {
+                outcome
+                    .new_sessions
+                    .push(self.make_new_session(new_handle)?);
             }
-            index += 1;
+            return Ok(outcome);
         }
 
-        let reply_impl = || -> Result<()> {
-            match unsafe { svc::reply_and_receive(&handle, 0, handle, 0) } {
-                Err(rc) => {
-                    if svc::rc::ResultTimedOut::matches(rc)
-                        || svc::rc::ResultSessionClosed::matches(rc)
-                    {
-                        Ok(())
-                    } else {
-                        Err(rc)
-                    }
-                }
-                _ => Ok(()),
+        // When not receiving, the request was already placed on the message buffer (see `process_request`)
+        if mode == ProcessMode::Receive {
+            if !pointer_buffer.is_empty() {
+                // Send our pointer buffer as a C descriptor for kernel - why are Pointer buffers so fucking weird?
+                let mut tmp_ctx = CommandContext::new_client(self.info);
+                tmp_ctx.add_receive_static(ReceiveStaticDescriptor::new(
+                    pointer_buffer.as_ptr(),
+                    pointer_buffer.len(),
+                ))?;
+                cmif::client::write_command_on_msg_buffer(
+                    &mut tmp_ctx,
+                    cmif::CommandType::Invalid,
+                    0,
+                );
             }
+
+            if let Err(rc) = unsafe { svc::reply_and_receive(&handle, 1, 0, -1) } {
+                if svc::rc::ResultSessionClosed::matches(rc) {
+                    outcome.close_session = true;
+                    return Ok(outcome);
+                } else {
+                    return Err(rc);
+                }
+            };
+        }
+
+        let mut ipc_buf_backup: [u8; 0x100] = [0; 0x100];
+        unsafe {
+            core::ptr::copy(
+                get_msg_buffer(),
+                ipc_buf_backup.as_mut_ptr(),
+                ipc_buf_backup.len(),
+            )
         };
 
+        let mut ctx = CommandContext::new_server(self.info, pointer_buffer.as_mut_ptr());
+        let command_type = cmif::server::read_command_from_msg_buffer(&mut ctx);
         match command_type {
             cmif::CommandType::Request | cmif::CommandType::RequestWithContext => {
-                self.handle_request_command(
+                let (rq_id, domain_command_type, domain_object_id) =
+                    cmif::server::read_request_command_from_msg_buffer(&mut ctx)?;
+                let mut base_info = self.info;
+                if self.info.is_domain() {
+                    // This is a domain request
+                    base_info.domain_object_id = domain_object_id;
+                    base_info.owns_handle = self.info.domain_object_id == domain_object_id;
+                }
+                ctx.object_info = base_info;
+
+                let deferred = self.handle_request_command(
                     &mut ctx,
                     rq_id,
                     command_type,
-                    domain_cmd_type,
+                    domain_command_type,
                     &ipc_buf_backup,
-                    domain_table,
+                    &mut outcome.new_sessions,
+                    (mode == ProcessMode::Receive).then_some(reply_queue),
                 )?;
-                reply_impl()?;
+                if deferred {
+                    // Don't reply yet, the client will remain blocked until the reply token is used
+                    return Ok(outcome);
+                }
             }
             cmif::CommandType::Control | cmif::CommandType::ControlWithContext => {
-                self.handle_control_command(&mut ctx, rq_id, command_type)?;
-                reply_impl()?;
+                let rq_id = cmif::server::read_control_command_from_msg_buffer(&mut ctx)?;
+                self.handle_control_command(
+                    &mut ctx,
+                    rq_id as u32,
+                    command_type,
+                    pointer_buffer.len(),
+                    &mut outcome.new_sessions,
+                )?;
             }
             cmif::CommandType::Close => {
                 cmif::server::write_close_command_response_on_msg_buffer(&mut ctx);
-                reply_impl()?;
+                outcome.close_session = true;
             }
-            _ => {
-                // Do nothing, since it might not be set at all without having failed (for instance, if a new session was accepted)
-                // If this actually failed and it reached this point server_found will be false, which is handled below
-            }
-        };
+            _ => return rc::ResultInvalidCommandType::make_err(),
+        }
+
+        // When processing in-process, the response is just left on the message buffer
+        if mode != ProcessMode::InProcess {
+            reply_to_session(handle)?;
+        }
+
+        Ok(outcome)
+    }
+}
+
+impl<const P: usize> ServerManager<P> {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            server_holders: Vec::new(),
+            wait_handles: [0; MAX_COUNT],
+            pointer_buffer: [0; P],
+            reply_queue: Arc::new(ReplyQueue::new()?),
+        })
+    }
 
-        if should_close_session {
-            self.server_holders.remove(index);
+    #[inline(always)]
+    fn prepare_wait_handles(&mut self) -> Result<&[svc::Handle]> {
+        self.wait_handles[0] = self.reply_queue.event.client_handle;
+        let mut handles_index: usize = 1;
+        for server_holder in &mut self.server_holders {
+            let server_info = server_holder.info;
+            if server_info.handle != svc::INVALID_HANDLE {
+                result_return_if!(handles_index >= MAX_COUNT, rc::ResultTooManyWaitHandles);
+                self.wait_handles[handles_index] = server_info.handle;
+                handles_index += 1;
+            }
         }
+        Ok(&self.wait_handles[..handles_index])
+    }
 
-        self.server_holders.append(&mut new_sessions);
+    /// Takes a [`ServerHolder`] away (thus, from the wait list too) while it's being processed
+    fn take_server_holder(&mut self, handle: svc::Handle) -> Result<ServerHolder> {
+        let index = self
+            .server_holders
+            .iter()
+            .position(|server_holder| server_holder.info.handle == handle)
+            .ok_or(rc::ResultSignaledServerNotFound::make())?;
+        Ok(self.server_holders.remove(index))
+    }
 
-        match server_found {
-            true => Ok(()),
-            false => rc::ResultSignaledServerNotFound::make_err(),
+    /// Gives back a [`ServerHolder`] taken with `take_server_holder`, applying the outcome of its processing
+    fn return_server_holder(
+        &mut self,
+        server_holder: ServerHolder,
+        outcome: Result<ProcessOutcome>,
+    ) -> Result<()> {
+        match outcome {
+            Ok(mut outcome) => {
+                if outcome.close_session {
+                    self.reply_queue
+                        .remove_session_requests(server_holder.info.handle);
+                    drop(server_holder);
+                } else {
+                    self.server_holders.push(server_holder);
+                }
+
+                self.server_holders.append(&mut outcome.new_sessions);
+                Ok(())
+            }
+            Err(rc) => {
+                self.server_holders.push(server_holder);
+                Err(rc)
+            }
         }
     }
 
+    fn process_signaled_handle(&mut self, handle: svc::Handle, mode: ProcessMode) -> Result<()> {
+        let mut server_holder = self.take_server_holder(handle)?;
+        let outcome = server_holder.process(&mut self.pointer_buffer, mode, &self.reply_queue);
+        self.return_server_holder(server_holder, outcome)
+    }
+
     pub fn register_server<S: IServerObject + 'static>(
         &mut self,
         handle: svc::Handle,
@@ -1136,15 +1328,60 @@ impl<const P: usize> ServerManager<P> {
     }
 
     pub fn process(&mut self) -> Result<()> {
-        let handles = self.prepare_wait_handles();
+        let handles = self.prepare_wait_handles()?;
         let index = wait::wait_handles(handles, 100_000)?;
 
         let signaled_handle = self.wait_handles[index];
-        self.process_signaled_handle(signaled_handle)?;
+        if signaled_handle == self.reply_queue.event.client_handle {
+            svc::reset_signal(signaled_handle)?;
+            return self.send_deferred_replies();
+        }
+
+        self.process_signaled_handle(signaled_handle, ProcessMode::Receive)?;
 
         Ok(())
     }
 
+    /// Handles a request already placed on the current message buffer as if it had been received through the given session handle, leaving the response on the message buffer instead of replying
+    ///
+    /// This is meant for in-process dispatching (see [`LoopbackTransport`][`super::transport::LoopbackTransport`]), thus no kernel calls are involved, which also means that pointer (`C`) buffers and newly created sessions aren't supported
+    ///
+    /// # Arguments
+    ///
+    /// * `handle`: The registered session handle
+    pub fn process_request(&mut self, handle: svc::Handle) -> Result<()> {
+        self.process_signaled_handle(handle, ProcessMode::InProcess)
+    }
+
+    /// Gets the number of deferred requests which weren't replied to yet
+    #[inline]
+    pub fn get_deferred_request_count(&self) -> usize {
+        self.reply_queue.requests.lock().len()
+    }
+
+    /// Sends the replies to the deferred requests whose [`ReplyToken`]s were used
+    ///
+    /// This is automatically done while processing, but may be called to send them right away. Replies to sessions which are currently being processed are kept until the next call.
+    pub fn send_deferred_replies(&mut self) -> Result<()> {
+        let ready_requests = self.reply_queue.take_replied_requests(|handle| {
+            self.server_holders
+                .iter()
+                .any(|server_holder| server_holder.info.handle == handle)
+        });
+
+        // Try to send every reply, even if some of them fail
+        let mut result = Ok(());
+        for request in ready_requests {
+            if let Some((rc, data)) = &request.reply
+                && let Err(send_rc) = request.send_reply(*rc, data)
+                && result.is_ok()
+            {
+                result = Err(send_rc);
+            }
+        }
+        result
+    }
+
     pub fn loop_process(&mut self) -> Result<()> {
         loop {
             if let Err(rc) = self.process() {
@@ -1162,3 +1399,553 @@ impl<const P: usize> ServerManager<P> {
         Ok(())
     }
 }
+
+/// Represents which group of sessions a session belongs to
+///
+/// Sessions created while processing another session (cloned objects or objects returned by commands) may share (non thread-safe) state with it, thus they belong to its group, while sessions accepted on server ports start a new group.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct SessionGroup {
+    handle: svc::Handle,
+    group_id: usize,
+}
+
+/// Represents the state of a [`ThreadedServerManager`], shared between its workers
+struct WorkerState<const P: usize> {
+    manager: ServerManager<P>,
+    session_groups: Vec<SessionGroup>,
+    busy_group_ids: Vec<usize>,
+    next_group_id: usize,
+    /// Where the wait list starts when not every session fits on it
+    wait_offset: usize,
+}
+
+impl<const P: usize> WorkerState<P> {
+    /// Gets the group of a session, making a new one for sessions which don't have it yet (like the ones registered before processing)
+    fn get_group_id(&mut self, handle: svc::Handle) -> usize {
+        if let Some(session_group) = self
+            .session_groups
+            .iter()
+            .find(|session_group| session_group.handle == handle)
+        {
+            return session_group.group_id;
+        }
+
+        let group_id = self.next_group_id;
+        self.next_group_id += 1;
+        self.session_groups.push(SessionGroup { handle, group_id });
+        group_id
+    }
+
+    /// Adds the handles of the sessions whose group isn't being processed to the wait list, up to [`wait::MAX_OBJECT_COUNT`] handles
+    ///
+    /// Returns whether every session fitted: otherwise the next call starts from the sessions which didn't
+    fn prepare_wait_handles(&mut self, handles: &mut Vec<svc::Handle>) -> bool {
+        let mut session_handles = Vec::new();
+        for i in 0..self.manager.server_holders.len() {
+            let handle = self.manager.server_holders[i].info.handle;
+            if handle != svc::INVALID_HANDLE {
+                let group_id = self.get_group_id(handle);
+                if !self.busy_group_ids.contains(&group_id) {
+                    session_handles.push(handle);
+                }
+            }
+        }
+
+        let capacity = MAX_COUNT.saturating_sub(handles.len());
+        if session_handles.len() <= capacity {
+            handles.extend_from_slice(&session_handles);
+            return true;
+        }
+
+        let start = self.wait_offset % session_handles.len();
+        handles.extend(session_handles.iter().cycle().skip(start).take(capacity));
+        self.wait_offset = start + capacity;
+        false
+    }
+
+    /// Takes a [`ServerHolder`] away to be processed, marking its group as busy
+    fn take_server_holder(&mut self, handle: svc::Handle) -> Result<(ServerHolder, usize)> {
+        let server_holder = self.manager.take_server_holder(handle)?;
+        let group_id = self.get_group_id(handle);
+        self.busy_group_ids.push(group_id);
+        Ok((server_holder, group_id))
+    }
+
+    /// Gives back a [`ServerHolder`] taken with `take_server_holder`, applying the outcome of its processing
+    fn return_server_holder(
+        &mut self,
+        server_holder: ServerHolder,
+        group_id: usize,
+        outcome: Result<ProcessOutcome>,
+    ) -> Result<()> {
+        let handle = server_holder.info.handle;
+        let is_session = server_holder.handle_type == WaitHandleType::Session;
+        self.busy_group_ids
+            .retain(|&busy_group_id| busy_group_id != group_id);
+
+        let outcome = match outcome {
+            // Close sessions which failed, since their clients may be left waiting for a reply otherwise
+            Err(rc) if is_session => {
+                self.manager.return_server_holder(
+                    server_holder,
+                    Ok(ProcessOutcome {
+                        close_session: true,
+                        new_sessions: Vec::new(),
+                    }),
+                )?;
+                self.session_groups
+                    .retain(|session_group| session_group.handle != handle);
+                return Err(rc);
+            }
+            outcome => outcome,
+        };
+
+        if let Ok(outcome) = &outcome {
+            if outcome.close_session {
+                self.session_groups
+                    .retain(|session_group| session_group.handle != handle);
+            }
+            if is_session {
+                for new_session in &outcome.new_sessions {
+                    self.session_groups.push(SessionGroup {
+                        handle: new_session.info.handle,
+                        group_id,
+                    });
+                }
+            }
+        }
+        self.manager.return_server_holder(server_holder, outcome)
+    }
+}
+
+/// Represents the state shared by the workers of a [`ThreadedServerManager`]
+struct WorkerPool<const P: usize> {
+    state: Mutex<WorkerState<P>>,
+    /// Only one worker may wait on the wait list at a time
+    wait_lock: Mutex<()>,
+    /// Signaled when the wait list changes, so that the waiting worker waits on the updated one
+    update_event: wait::SystemEvent,
+    exiting: AtomicBool,
+}
+
+// SAFETY: the server objects (and anything they share through `Rc`s) belong to a session group, which is only ever accessed by one worker at a time, with the state lock held or after taking the group's sessions away (see `WorkerState`)
+unsafe impl<const P: usize> Send for WorkerPool<P> {}
+unsafe impl<const P: usize> Sync for WorkerPool<P> {}
+
+impl<const P: usize> WorkerPool<P> {
+    fn exit(&self) {
+        self.exiting.store(true, Ordering::Release);
+        let _ = self.update_event.signal();
+    }
+
+    /// Runs a worker until the pool exits or the worker fails
+    fn run_worker<L: Logger>(&self) -> Result<()> {
+        let mut pointer_buffer = vec![0u8; P];
+        let result = self.worker_loop::<L>(&mut pointer_buffer);
+        if let Err(rc) = result {
+            if svc::rc::ResultCancelled::matches(rc) {
+                // Cancelling stops the whole pool
+                self.exit();
+                return Ok(());
+            }
+
+            diag_log!(L { LogSeverity::Error, true } => "IPC worker failed: {0} ({0:?})\n", rc);
+        }
+        result
+    }
+
+    fn worker_loop<L: Logger>(&self, pointer_buffer: &mut [u8]) -> Result<()> {
+        loop {
+            let wait_guard = self.wait_lock.lock();
+            if self.exiting.load(Ordering::Acquire) {
+                return Ok(());
+            }
+
+            let (handles, reply_event_handle, all_handles_fit) = {
+                let mut state = self.state.lock();
+                let reply_event_handle = state.manager.reply_queue.event.client_handle;
+                let mut handles = vec![self.update_event.client_handle, reply_event_handle];
+                let all_handles_fit = state.prepare_wait_handles(&mut handles);
+                (handles, reply_event_handle, all_handles_fit)
+            };
+
+            // Sessions which didn't fit on the wait list are waited on next time
+            let timeout = match all_handles_fit {
+                true => -1,
+                false => 100_000,
+            };
+            let signaled_handle = match wait::wait_handles(&handles, timeout) {
+                Ok(index) => handles[index],
+                Err(rc) if svc::rc::ResultTimedOut::matches(rc) => continue,
+                Err(rc) => return Err(rc),
+            };
+            if signaled_handle == self.update_event.client_handle {
+                svc::reset_signal(signaled_handle)?;
+                continue;
+            }
+
+            let mut state = self.state.lock();
+            if signaled_handle == reply_event_handle {
+                svc::reset_signal(signaled_handle)?;
+                if let Err(rc) = state.manager.send_deferred_replies() {
+                    diag_log!(L { LogSeverity::Error, true } => "Failed to send deferred replies: {0} ({0:?})\n", rc);
+                }
+                continue;
+            }
+
+            let (mut server_holder, group_id) = match state.take_server_holder(signaled_handle) {
+                Ok(taken) => taken,
+                Err(rc) => {
+                    diag_log!(L { LogSeverity::Error, true } => "Failed to find session {0:#X}: {1} ({1:?})\n", signaled_handle, rc);
+                    continue;
+                }
+            };
+            let reply_queue = state.manager.reply_queue.clone();
+            drop(state);
+            drop(wait_guard);
+
+            let outcome = server_holder.process(pointer_buffer, ProcessMode::Receive, &reply_queue);
+            {
+                let mut state = self.state.lock();
+                if let Err(rc) = state.return_server_holder(server_holder, group_id, outcome) {
+                    diag_log!(L { LogSeverity::Error, true } => "Failed to process session {0:#X}: {1} ({1:?})\n", signaled_handle, rc);
+                }
+
+                // Replies to this session may have been kept while it was being processed
+                if let Err(rc) = state.manager.send_deferred_replies() {
+                    diag_log!(L { LogSeverity::Error, true } => "Failed to send deferred replies: {0} ({0:?})\n", rc);
+                }
+            }
+            self.update_event.signal()?;
+        }
+    }
+}
+
+/// Manages server objects and their sessions like a [`ServerManager`], but processing requests on a pool of worker threads, so that independent sessions are processed in parallel
+///
+/// Workers take turns waiting on the wait list: once a request is received, its session is taken away from the wait list while the request is processed, so that other workers may keep receiving requests on the remaining sessions.
+///
+/// Sessions which may share state with each other (see below) are never processed in parallel, thus server objects don't need to be thread-safe: sessions accepted on server ports (and registered sessions) are independent, while sessions created when processing another one (objects returned by commands, cloned objects, etc.) are processed one at a time along with it. Note that server objects may still be processed on different threads over time.
+///
+/// Like with [`ServerManager`], there can't be more than [`wait::MAX_OBJECT_COUNT`] handles waited on at once: when there are more sessions than that, workers go through them in turns.
+pub struct ThreadedServerManager<const P: usize> {
+    state: WorkerState<P>,
+}
+
+impl<const P: usize> ThreadedServerManager<P> {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            state: WorkerState {
+                manager: ServerManager::new()?,
+                session_groups: Vec::new(),
+                busy_group_ids: Vec::new(),
+                next_group_id: 0,
+                wait_offset: 0,
+            },
+        })
+    }
+
+    pub fn register_server<S: IServerObject + Send + 'static>(
+        &mut self,
+        handle: svc::Handle,
+        service_name: sm::ServiceName,
+    ) {
+        self.state
+            .manager
+            .register_server::<S>(handle, service_name);
+    }
+
+    pub fn register_mitm_server<S: IMitmServerObject + Send + 'static>(
+        &mut self,
+        handle: svc::Handle,
+        service_name: sm::ServiceName,
+    ) {
+        self.state
+            .manager
+            .register_mitm_server::<S>(handle, service_name);
+    }
+
+    /// Registers a session object, which is owned by the server manager from now on (since it may be processed on any worker)
+    ///
+    /// # Arguments
+    ///
+    /// * `handle`: The session handle
+    /// * `session_obj`: The session object
+    pub fn register_session<S: ISessionObject + Send + 'static>(
+        &mut self,
+        handle: svc::Handle,
+        session_obj: S,
+    ) {
+        self.state
+            .manager
+            .register_session(handle, Rc::new(Mutex::new(session_obj)));
+    }
+
+    #[cfg(feature = "services")]
+    pub fn register_service_server<S: IService + Send + 'static>(&mut self) -> Result<()> {
+        self.state.manager.register_service_server::<S>()
+    }
+
+    #[cfg(feature = "services")]
+    pub fn register_mitm_service_server<S: IMitmService + Send + 'static>(&mut self) -> Result<()> {
+        self.state.manager.register_mitm_service_server::<S>()
+    }
+
+    pub fn register_named_port_server<S: INamedPort + Send + 'static>(&mut self) -> Result<()> {
+        self.state.manager.register_named_port_server::<S>()
+    }
+
+    /// Gets the number of deferred requests which weren't replied to yet
+    #[inline]
+    pub fn get_deferred_request_count(&self) -> usize {
+        self.state.manager.get_deferred_request_count()
+    }
+
+    /// Processes requests on a pool of worker threads until a wait is cancelled or every worker fails
+    ///
+    /// Errors when processing a session are logged with the given [`Logger`] type, closing that session, while the rest of the errors make the worker (only) exit after logging them
+    ///
+    /// # Arguments
+    ///
+    /// * `worker_count`: The number of workers, including the current thread (which becomes a worker too)
+    /// * `worker_stack_size`: The stack size of the spawned worker threads
+    pub fn loop_process<L: Logger>(
+        self,
+        worker_count: usize,
+        worker_stack_size: usize,
+    ) -> Result<()> {
+        let pool = Arc::new(WorkerPool {
+            state: Mutex::new(self.state),
+            wait_lock: Mutex::new(()),
+            update_event: wait::SystemEvent::new()?,
+            exiting: AtomicBool::new(false),
+        });
+
+        let mut workers = Vec::new();
+        for i in 1..worker_count {
+            let worker_pool = pool.clone();
+            let worker = thread::Builder::new()
+                .name(format!("ipc.worker.{i}"))
+                .stack_size(worker_stack_size)
+                .spawn(move || worker_pool.run_worker::<L>());
+            match worker {
+                Ok(worker) => workers.push(worker),
+                Err(rc) => {
+                    pool.exit();
+                    return Err(rc);
+                }
+            }
+        }
+
+        let mut result = pool.run_worker::<L>();
+        for worker in workers {
+            let worker_result = worker
+                .join()
+                .unwrap_or_else(|_| crate::rc::ResultPanicked::make_err());
+            if result.is_ok() {
+                result = worker_result;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SESSION_HANDLE: svc::Handle = 0xABCD;
+
+    fn defer_request(queue: &Arc<ReplyQueue>, handle: svc::Handle, request_id: u32) -> ReplyToken {
+        let id = G_NEXT_DEFERRED_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+        queue.requests.lock().push(DeferredRequest {
+            id,
+            object_info: ObjectInfo::from_handle(handle),
+            command_type: cmif::CommandType::Request,
+            reply: None,
+        });
+        ReplyToken {
+            id,
+            request_id,
+            queue: queue.clone(),
+            replied: false,
+        }
+    }
+
+    fn get_reply(
+        queue: &ReplyQueue,
+        token_id: usize,
+    ) -> Option<(ResultCode, Vec<MaybeUninit<u8>>)> {
+        queue
+            .requests
+            .lock()
+            .iter()
+            .find(|request| request.id == token_id)
+            .and_then(|request| request.reply.clone())
+    }
+
+    fn get_signal_count(queue: &ReplyQueue) -> usize {
+        queue.event.signal_count.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn reply_with_data() {
+        let queue = Arc::new(ReplyQueue::new().unwrap());
+        let token = defer_request(&queue, SESSION_HANDLE, 12);
+        let token_id = token.id;
+        assert_eq!(token.get_request_id(), 12);
+        assert!(get_reply(&queue, token_id).is_none());
+
+        token.reply(Ok(0x1122_3344_5566_7788u64)).unwrap();
+        let (rc, data) = get_reply(&queue, token_id).unwrap();
+        assert!(rc.is_success());
+        let data: Vec<u8> = data.iter().map(|b| unsafe { b.assume_init() }).collect();
+        assert_eq!(data, 0x1122_3344_5566_7788u64.to_ne_bytes());
+        assert_eq!(get_signal_count(&queue), 1);
+    }
+
+    #[test]
+    fn reply_with_padded_data() {
+        #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+        #[repr(C)]
+        struct Padded {
+            flag: u8,
+            value: u32,
+            id: u16,
+        }
+        let value = Padded {
+            flag: 1,
+            value: 0xCAFE_BABE,
+            id: 7,
+        };
+
+        let queue = Arc::new(ReplyQueue::new().unwrap());
+        let token = defer_request(&queue, SESSION_HANDLE, 0);
+        let token_id = token.id;
+        token.reply(Ok(value)).unwrap();
+
+        // Only the fields are read back, the padding bytes are left untouched
+        let (rc, data) = get_reply(&queue, token_id).unwrap();
+        assert!(rc.is_success());
+        assert_eq!(data.len(), core::mem::size_of::<Padded>());
+        let read_value = unsafe { core::ptr::read_unaligned(data.as_ptr() as *const Padded) };
+        assert_eq!(read_value, value);
+    }
+
+    #[test]
+    fn reply_without_data() {
+        let queue = Arc::new(ReplyQueue::new().unwrap());
+        let token = defer_request(&queue, SESSION_HANDLE, 0);
+        let token_id = token.id;
+        token.reply(Ok(())).unwrap();
+        let (rc, data) = get_reply(&queue, token_id).unwrap();
+        assert!(rc.is_success());
+        assert!(data.is_empty());
+
+        let token = defer_request(&queue, SESSION_HANDLE, 0);
+        let token_id = token.id;
+        token
+            .reply::<u32>(crate::rc::ResultNotSupported::make_err())
+            .unwrap();
+        let (rc, data) = get_reply(&queue, token_id).unwrap();
+        assert!(crate::rc::ResultNotSupported::matches(rc));
+        assert!(data.is_empty());
+        assert_eq!(get_signal_count(&queue), 2);
+    }
+
+    #[test]
+    fn reply_data_too_big() {
+        let queue = Arc::new(ReplyQueue::new().unwrap());
+        let token = defer_request(&queue, SESSION_HANDLE, 0);
+        let token_id = token.id;
+        token
+            .reply(Ok([0u8; MAX_DEFERRED_REPLY_DATA_SIZE]))
+            .unwrap();
+
+        let token = defer_request(&queue, SESSION_HANDLE, 0);
+        let big_token_id = token.id;
+        let rc = token
+            .reply(Ok([0u8; MAX_DEFERRED_REPLY_DATA_SIZE + 1]))
+            .unwrap_err();
+        assert!(rc::ResultInvalidReplyDataSize::matches(rc));
+
+        // The client still gets a reply, since the token was dropped
+        let (rc, data) = get_reply(&queue, token_id).unwrap();
+        assert!(rc.is_success());
+        assert_eq!(data.len(), MAX_DEFERRED_REPLY_DATA_SIZE);
+        let (rc, data) = get_reply(&queue, big_token_id).unwrap();
+        assert!(rc::ResultReplyTokenDropped::matches(rc));
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn drop_token() {
+        let queue = Arc::new(ReplyQueue::new().unwrap());
+        let token = defer_request(&queue, SESSION_HANDLE, 0);
+        let token_id = token.id;
+        drop(token);
+
+        let (rc, _) = get_reply(&queue, token_id).unwrap();
+        assert!(rc::ResultReplyTokenDropped::matches(rc));
+        assert_eq!(get_signal_count(&queue), 1);
+    }
+
+    #[test]
+    fn reply_to_closed_session() {
+        let queue = Arc::new(ReplyQueue::new().unwrap());
+        let closed_token = defer_request(&queue, SESSION_HANDLE, 0);
+        let other_token = defer_request(&queue, SESSION_HANDLE + 1, 0);
+        let other_token_id = other_token.id;
+
+        queue.remove_session_requests(SESSION_HANDLE);
+        assert_eq!(queue.requests.lock().len(), 1);
+
+        // The reply is just ignored
+        closed_token.reply(Ok(1u32)).unwrap();
+        assert_eq!(queue.requests.lock().len(), 1);
+        assert!(queue.take_replied_requests(|_| true).is_empty());
+
+        drop(other_token);
+        let replied_requests = queue.take_replied_requests(|_| true);
+        assert_eq!(replied_requests.len(), 1);
+        assert_eq!(replied_requests[0].id, other_token_id);
+        assert!(queue.requests.lock().is_empty());
+    }
+
+    #[test]
+    fn take_replied_requests() {
+        let queue = Arc::new(ReplyQueue::new().unwrap());
+        let busy_handle = SESSION_HANDLE + 1;
+        let tokens: Vec<_> = [SESSION_HANDLE, busy_handle, SESSION_HANDLE]
+            .into_iter()
+            .map(|handle| defer_request(&queue, handle, 0))
+            .collect();
+        let token_ids: Vec<_> = tokens.iter().map(|token| token.id).collect();
+
+        // Nothing is ready until replied to
+        assert!(queue.take_replied_requests(|_| true).is_empty());
+        assert_eq!(queue.requests.lock().len(), 3);
+
+        for token in tokens {
+            token.reply(Ok(())).unwrap();
+        }
+
+        // Replies to sessions which aren't ready are kept for later
+        let is_session_ready = |handle| handle != busy_handle;
+        let replied_ids: Vec<_> = queue
+            .take_replied_requests(is_session_ready)
+            .iter()
+            .map(|request| request.id)
+            .collect();
+        assert_eq!(replied_ids, [token_ids[0], token_ids[2]]);
+        assert!(queue.take_replied_requests(is_session_ready).is_empty());
+
+        let replied_ids: Vec<_> = queue
+            .take_replied_requests(|_| true)
+            .iter()
+            .map(|request| request.id)
+            .collect();
+        assert_eq!(replied_ids, [token_ids[1]]);
+        assert!(queue.requests.lock().is_empty());
+    }
+}
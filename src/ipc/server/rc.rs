@@ -9,5 +9,9 @@ result_define_subgroup!(rc::RESULT_MODULE, RESULT_SUBMODULE => {
     InvalidCommandType: 3,
     InvalidDomainCommandType: 4,
     SignaledServerNotFound: 5,
-    AlreadyDomain: 6
+    AlreadyDomain: 6,
+    RequestDeferred: 7,
+    ReplyTokenDropped: 8,
+    InvalidReplyDataSize: 9,
+    TooManyWaitHandles: 10
 });
@@ -714,7 +714,7 @@ impl Session {
                     None,
                     cmif::DomainCommandType::Close,
                 );
-                let _ = svc::send_sync_request(self.object_info.handle);
+                let _ = client::send_sync_request(self.object_info.handle);
             } else if self.object_info.owns_handle {
                 let mut ctx = CommandContext::new_client(self.object_info);
 
@@ -727,7 +727,7 @@ impl Session {
                     }
                 };
 
-                let _ = svc::send_sync_request(self.object_info.handle);
+                let _ = client::send_sync_request(self.object_info.handle);
             }
             if self.object_info.owns_handle {
                 let _ = svc::close_handle(self.object_info.handle);
@@ -788,3 +788,11 @@ pub mod ncm;
 pub mod lr;
 
 pub mod bsd;
+
+pub mod account;
+
+pub mod time;
+
+pub mod nifm;
+
+pub mod pl;
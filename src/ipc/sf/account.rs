@@ -0,0 +1,147 @@
+use crate::ipc::sf;
+use crate::util;
+use crate::version;
+use core::fmt::{Debug, Display, Formatter, Result as FmtResult};
+
+use nx_derive::{Request, Response};
+
+/// The maximum amount of user accounts in the system
+pub const USER_COUNT_MAX: usize = 8;
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[repr(C)]
+pub struct Uid {
+    pub uid: [u64; 2],
+}
+
+impl Uid {
+    #[inline]
+    pub const fn new(uid: [u64; 2]) -> Self {
+        Self { uid }
+    }
+
+    /// Gets whether this [`Uid`] is valid (non-zero)
+    #[inline]
+    pub const fn is_valid(&self) -> bool {
+        (self.uid[0] != 0) || (self.uid[1] != 0)
+    }
+}
+
+impl Display for Uid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{:016X}{:016X}", self.uid[1], self.uid[0])
+    }
+}
+
+impl Debug for Uid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{:016X}{:016X}", self.uid[1], self.uid[0])
+    }
+}
+
+pub type Nickname = util::ArrayString<0x20>;
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct ProfileBase {
+    pub uid: Uid,
+    pub last_edit_timestamp: u64,
+    pub nickname: Nickname,
+}
+const_assert!(core::mem::size_of::<ProfileBase>() == 0x38);
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct UserData {
+    pub unk_x0: u32,
+    pub icon_id: u32,
+    pub icon_background_color_id: u8,
+    pub unk_x9: [u8; 7],
+    pub mii_id: [u8; 0x10],
+    pub unk_x20: [u8; 0x60],
+}
+const_assert!(core::mem::size_of::<UserData>() == 0x80);
+
+impl Default for UserData {
+    fn default() -> Self {
+        Self {
+            unk_x0: 0,
+            icon_id: 0,
+            icon_background_color_id: 0,
+            unk_x9: [0; 7],
+            mii_id: [0; 0x10],
+            unk_x20: [0; 0x60],
+        }
+    }
+}
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+#[repr(C)]
+pub struct NetworkServiceAccountId(pub u64);
+
+#[nx_derive::ipc_trait]
+#[default_client]
+pub trait Profile {
+    #[ipc_rid(0)]
+    fn get(&self, out_user_data: sf::OutFixedPointerBuffer<'_, UserData>) -> ProfileBase;
+    #[ipc_rid(1)]
+    fn get_base(&self) -> ProfileBase;
+    #[ipc_rid(10)]
+    fn get_image_size(&self) -> u32;
+    #[ipc_rid(11)]
+    fn load_image(&self, out_image: sf::OutMapAliasBuffer<'_, u8>) -> u32;
+}
+
+#[nx_derive::ipc_trait]
+#[default_client]
+pub trait ManagerForApplication {
+    #[ipc_rid(0)]
+    fn check_availability(&self);
+    #[ipc_rid(1)]
+    fn get_account_id(&self) -> NetworkServiceAccountId;
+}
+
+#[nx_derive::ipc_trait]
+pub trait ApplicationAccount {
+    #[ipc_rid(0)]
+    fn get_user_count(&self) -> u32;
+    #[ipc_rid(1)]
+    fn get_user_existence(&self, uid: Uid) -> bool;
+    #[ipc_rid(2)]
+    fn list_all_users(&self, out_uids: sf::OutPointerBuffer<'_, Uid>);
+    #[ipc_rid(3)]
+    fn list_open_users(&self, out_uids: sf::OutPointerBuffer<'_, Uid>);
+    #[ipc_rid(4)]
+    fn get_last_opened_user(&self) -> Uid;
+    #[ipc_rid(5)]
+    #[return_session]
+    fn get_profile(&self, uid: Uid) -> Profile;
+    #[ipc_rid(51)]
+    fn try_select_user_without_interaction(&self, network_service_account_required: bool) -> Uid;
+    #[ipc_rid(100)]
+    #[version(version::VersionInterval::to(version::Version::new(5, 1, 0)))]
+    fn initialize_application_info_v0(&self, process_id: sf::ProcessId);
+    #[ipc_rid(101)]
+    #[return_session]
+    fn get_baas_account_manager_for_application(&self, uid: Uid) -> ManagerForApplication;
+    #[ipc_rid(140)]
+    #[version(version::VersionInterval::from(version::Version::new(6, 0, 0)))]
+    fn initialize_application_info(&self, process_id: sf::ProcessId);
+}
+
+#[nx_derive::ipc_trait]
+pub trait SystemAccount {
+    #[ipc_rid(0)]
+    fn get_user_count(&self) -> u32;
+    #[ipc_rid(1)]
+    fn get_user_existence(&self, uid: Uid) -> bool;
+    #[ipc_rid(2)]
+    fn list_all_users(&self, out_uids: sf::OutPointerBuffer<'_, Uid>);
+    #[ipc_rid(3)]
+    fn list_open_users(&self, out_uids: sf::OutPointerBuffer<'_, Uid>);
+    #[ipc_rid(4)]
+    fn get_last_opened_user(&self) -> Uid;
+    #[ipc_rid(5)]
+    #[return_session]
+    fn get_profile(&self, uid: Uid) -> Profile;
+}
@@ -144,6 +144,37 @@ pub enum AppletMessage {
     RecordingSaved = 93,
 }
 
+impl AppletMessage {
+    /// Converts a raw message value, if it's a known one
+    ///
+    /// # Arguments
+    ///
+    /// * `raw`: The raw message value
+    pub const fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            4 => Some(Self::ExitRequest),
+            15 => Some(Self::FocusStateChanged),
+            16 => Some(Self::Resume),
+            30 => Some(Self::OperationModeChanged),
+            31 => Some(Self::PerformanceMode),
+            51 => Some(Self::DisplayRequested),
+            90 => Some(Self::CaptureButtonPressedShort),
+            92 => Some(Self::ScreenShotTaken),
+            93 => Some(Self::RecordingSaved),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u8)]
+pub enum FocusState {
+    #[default]
+    InFocus = 1,
+    OutOfFocus = 2,
+    Background = 3,
+}
+
 #[nx_derive::ipc_trait]
 #[default_client]
 pub trait StorageAccessor {
@@ -173,6 +204,12 @@ pub trait LibraryAppletAccessor {
     fn push_in_data(&mut self, storage: Storage);
     #[ipc_rid(101)]
     fn pop_out_data(&mut self) -> Storage;
+    #[ipc_rid(103)]
+    fn push_interactive_in_data(&mut self, storage: Storage);
+    #[ipc_rid(104)]
+    fn pop_interactive_out_data(&mut self) -> Storage;
+    #[ipc_rid(106)]
+    fn get_pop_interactive_out_data_event(&mut self) -> sf::CopyHandle;
 }
 
 #[nx_derive::ipc_trait]
@@ -187,6 +224,27 @@ pub trait LibraryAppletCreator {
     ) -> LibraryAppletAccessor;
     #[ipc_rid(10)]
     fn create_storage(&self, size: usize) -> Storage;
+    #[ipc_rid(11)]
+    fn create_transfer_memory_storage(
+        &self,
+        transfer_memory: sf::CopyHandle,
+        writable: bool,
+        size: usize,
+    ) -> Storage;
+}
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum LaunchParameterKind {
+    UserChannel = 1,
+    PreselectedUser = 2,
+}
+
+#[nx_derive::ipc_trait]
+#[default_client]
+pub trait ApplicationFunctions {
+    #[ipc_rid(1)]
+    fn pop_launch_parameter(&self, kind: LaunchParameterKind) -> Storage;
 }
 
 #[nx_derive::ipc_trait]
@@ -201,8 +259,28 @@ pub trait WindowController {
 #[nx_derive::ipc_trait]
 #[default_client]
 pub trait SelfController {
+    #[ipc_rid(0)]
+    fn exit(&self);
+    #[ipc_rid(1)]
+    fn lock_exit(&self);
+    #[ipc_rid(2)]
+    fn unlock_exit(&self);
     #[ipc_rid(10)]
     fn set_screenshot_permission(&self, permission: ScreenShotPermission);
+    #[ipc_rid(11)]
+    fn set_operation_mode_changed_notification(&self, enabled: bool);
+    #[ipc_rid(12)]
+    fn set_performance_mode_changed_notification(&self, enabled: bool);
+    #[ipc_rid(13)]
+    fn set_focus_handling_mode(
+        &self,
+        suspend_on_focus_lost: bool,
+        notify_on_focus_lost: bool,
+        notify_on_focus_gained: bool,
+    );
+    #[ipc_rid(16)]
+    #[version(version::VersionInterval::from(version::Version::new(2, 0, 0)))]
+    fn set_out_of_focus_suspending_enabled(&self, enabled: bool);
     #[ipc_rid(40)]
     fn create_managed_display_layer(&self) -> u64;
 }
@@ -240,8 +318,20 @@ pub trait ProcessWindingController {
 pub trait CommonStateGetter {
     #[ipc_rid(0)]
     fn get_event_handle(&self) -> Handle;
+    /// Same command as [`get_event_handle`][`Self::get_event_handle`], but receiving the event as an actual copy handle
+    #[ipc_rid(0)]
+    fn get_event(&self) -> sf::CopyHandle;
     #[ipc_rid(1)]
     fn receive_message(&self) -> AppletMessage;
+    /// Same command as [`receive_message`][`Self::receive_message`], but receiving the raw message (see [`AppletMessage::from_raw`]), since messages unknown to us may be received
+    #[ipc_rid(1)]
+    fn receive_raw_message(&self) -> u32;
+    #[ipc_rid(5)]
+    fn get_operation_mode(&self) -> u8;
+    #[ipc_rid(6)]
+    fn get_performance_mode(&self) -> u32;
+    #[ipc_rid(9)]
+    fn get_current_focus_state(&self) -> u8;
 }
 
 #[nx_derive::ipc_trait]
@@ -294,6 +384,9 @@ pub trait ApplicationProxy {
     #[ipc_rid(11)]
     #[return_session]
     fn get_library_applet_creator(&self) -> LibraryAppletCreator;
+    #[ipc_rid(20)]
+    #[return_session]
+    fn get_application_functions(&self) -> ApplicationFunctions;
 }
 
 #[nx_derive::ipc_trait]
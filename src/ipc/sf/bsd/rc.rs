@@ -4,5 +4,7 @@ result_define_group!(RESULT_MODULE => {
     NotInitialized: 1,
     InvalidSocketString: 2,
     InvalidSockAddr:3,
-    InvalidTimeout: 4
+    InvalidTimeout: 4,
+    NotConnected: 5,
+    ConnectionTimedOut: 6
 });
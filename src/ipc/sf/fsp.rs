@@ -1,4 +1,6 @@
 use crate::ipc::sf;
+use crate::ipc::sf::account;
+use crate::ipc::sf::ncm;
 use crate::util;
 use crate::version;
 
@@ -108,6 +110,172 @@ pub enum OperationId {
     ReadLazyLoadFileForciblyForDebug = 10001,
 }
 
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u8)]
+pub enum SaveDataSpaceId {
+    #[default]
+    System = 0,
+    User = 1,
+    SdSystem = 2,
+    Temporary = 3,
+    SdUser = 4,
+    ProperSystem = 100,
+    SafeMode = 101,
+}
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u8)]
+pub enum SaveDataType {
+    #[default]
+    System = 0,
+    Account = 1,
+    Bcat = 2,
+    Device = 3,
+    Temporary = 4,
+    Cache = 5,
+    SystemBcat = 6,
+}
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u8)]
+pub enum SaveDataRank {
+    #[default]
+    Primary = 0,
+    Secondary = 1,
+}
+
+macro_rules! impl_try_from_u8 {
+    ($enum:ident { $($variant:ident),* }) => {
+        impl TryFrom<u8> for $enum {
+            /// The unknown raw value
+            type Error = u8;
+
+            fn try_from(raw: u8) -> core::result::Result<Self, Self::Error> {
+                $(
+                    if raw == Self::$variant as u8 {
+                        return Ok(Self::$variant);
+                    }
+                )*
+                Err(raw)
+            }
+        }
+    };
+}
+
+impl_try_from_u8!(SaveDataSpaceId {
+    System,
+    User,
+    SdSystem,
+    Temporary,
+    SdUser,
+    ProperSystem,
+    SafeMode
+});
+impl_try_from_u8!(SaveDataType {
+    System,
+    Account,
+    Bcat,
+    Device,
+    Temporary,
+    Cache,
+    SystemBcat
+});
+impl_try_from_u8!(SaveDataRank { Primary, Secondary });
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct SaveDataAttribute {
+    pub application_id: ncm::ApplicationId,
+    pub uid: account::Uid,
+    pub system_save_data_id: u64,
+    pub save_data_type: SaveDataType,
+    pub save_data_rank: SaveDataRank,
+    pub save_data_index: u16,
+    pub pad: u32,
+    pub reserved: [u8; 0x18],
+}
+const_assert!(core::mem::size_of::<SaveDataAttribute>() == 0x40);
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum FileSystemType {
+    Code = 0,
+    Rom = 1,
+    Logo = 2,
+    ContentControl = 3,
+    ContentManual = 4,
+    ContentMeta = 5,
+    ContentData = 6,
+    ApplicationPackage = 7,
+    RegisteredUpdate = 8,
+}
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum ContentStorageId {
+    System = 0,
+    User = 1,
+    SdCard = 2,
+}
+
+/// Save data info as read by [`ISaveDataInfoReaderClient::read_save_data_info`]
+///
+/// The enum fields are kept raw since they are filled by `fsp-srv`, use the accessors to get them
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct SaveDataInfo {
+    pub save_data_id: u64,
+    pub space_id: u8,
+    pub save_data_type: u8,
+    pub pad: [u8; 6],
+    pub uid: account::Uid,
+    pub system_save_data_id: u64,
+    pub application_id: ncm::ApplicationId,
+    pub size: u64,
+    pub save_data_index: u16,
+    pub save_data_rank: u8,
+    pub reserved: [u8; 0x25],
+}
+const_assert!(core::mem::size_of::<SaveDataInfo>() == 0x60);
+
+impl Default for SaveDataInfo {
+    fn default() -> Self {
+        Self {
+            save_data_id: 0,
+            space_id: 0,
+            save_data_type: 0,
+            pad: [0; 6],
+            uid: account::Uid::default(),
+            system_save_data_id: 0,
+            application_id: ncm::ApplicationId::default(),
+            size: 0,
+            save_data_index: 0,
+            save_data_rank: 0,
+            reserved: [0; 0x25],
+        }
+    }
+}
+
+impl SaveDataInfo {
+    /// Gets the [`SaveDataSpaceId`], if valid
+    #[inline]
+    pub fn get_space_id(&self) -> Option<SaveDataSpaceId> {
+        SaveDataSpaceId::try_from(self.space_id).ok()
+    }
+
+    /// Gets the [`SaveDataType`], if valid
+    #[inline]
+    pub fn get_save_data_type(&self) -> Option<SaveDataType> {
+        SaveDataType::try_from(self.save_data_type).ok()
+    }
+
+    /// Gets the [`SaveDataRank`], if valid
+    #[inline]
+    pub fn get_save_data_rank(&self) -> Option<SaveDataRank> {
+        SaveDataRank::try_from(self.save_data_rank).ok()
+    }
+}
+
 #[nx_derive::ipc_trait]
 #[default_client]
 pub trait File {
@@ -230,6 +398,13 @@ pub trait FileSystem {
     );
 }
 
+#[nx_derive::ipc_trait]
+#[default_client]
+pub trait SaveDataInfoReader {
+    #[ipc_rid(0)]
+    fn read_save_data_info(&mut self, out_infos: sf::OutMapAliasBuffer<'_, SaveDataInfo>) -> u64;
+}
+
 #[nx_derive::ipc_trait]
 #[default_client]
 pub trait FileSystemProxy {
@@ -238,6 +413,49 @@ pub trait FileSystemProxy {
     #[ipc_rid(18)]
     #[return_session]
     fn open_sd_card_filesystem(&self) -> FileSystem;
+    #[ipc_rid(8)]
+    #[return_session]
+    #[version(version::VersionInterval::from(version::Version::new(2, 0, 0)))]
+    fn open_file_system_with_id(
+        &self,
+        path: sf::InFixedPointerBuffer<'_, Path>,
+        fs_type: FileSystemType,
+        id: u64,
+    ) -> FileSystem;
+    #[ipc_rid(51)]
+    #[return_session]
+    fn open_save_data_file_system(
+        &self,
+        space_id: SaveDataSpaceId,
+        attribute: SaveDataAttribute,
+    ) -> FileSystem;
+    #[ipc_rid(52)]
+    #[return_session]
+    fn open_save_data_file_system_by_system_save_data_id(
+        &self,
+        space_id: SaveDataSpaceId,
+        attribute: SaveDataAttribute,
+    ) -> FileSystem;
+    #[ipc_rid(53)]
+    #[return_session]
+    #[version(version::VersionInterval::from(version::Version::new(2, 0, 0)))]
+    fn open_read_only_save_data_file_system(
+        &self,
+        space_id: SaveDataSpaceId,
+        attribute: SaveDataAttribute,
+    ) -> FileSystem;
+    #[ipc_rid(60)]
+    #[return_session]
+    fn open_save_data_info_reader(&self) -> SaveDataInfoReader;
+    #[ipc_rid(61)]
+    #[return_session]
+    fn open_save_data_info_reader_by_save_data_space_id(
+        &self,
+        space_id: SaveDataSpaceId,
+    ) -> SaveDataInfoReader;
+    #[ipc_rid(110)]
+    #[return_session]
+    fn open_content_storage_file_system(&self, storage_id: ContentStorageId) -> FileSystem;
     #[ipc_rid(1006)]
     fn output_access_log_to_sd_card(&self, log_buf: sf::InMapAliasBuffer<'_, u8>);
 }
@@ -1,6 +1,11 @@
 use crate::ipc::sf;
 
-use super::FileSystem;
+use crate::version;
+
+use super::{
+    ContentStorageId, FileSystem, FileSystemType, Path, SaveDataAttribute, SaveDataInfoReader,
+    SaveDataSpaceId,
+};
 
 #[nx_derive::ipc_trait]
 pub trait FileSystemProxy {
@@ -8,6 +13,42 @@ pub trait FileSystemProxy {
     fn set_current_process(&self, process_id: sf::ProcessId);
     #[ipc_rid(18)]
     fn open_sd_card_filesystem(&self) -> FileSystem;
+    #[ipc_rid(8)]
+    #[version(version::VersionInterval::from(version::Version::new(2, 0, 0)))]
+    fn open_file_system_with_id(
+        &self,
+        path: sf::InFixedPointerBuffer<'_, Path>,
+        fs_type: FileSystemType,
+        id: u64,
+    ) -> FileSystem;
+    #[ipc_rid(51)]
+    fn open_save_data_file_system(
+        &self,
+        space_id: SaveDataSpaceId,
+        attribute: SaveDataAttribute,
+    ) -> FileSystem;
+    #[ipc_rid(52)]
+    fn open_save_data_file_system_by_system_save_data_id(
+        &self,
+        space_id: SaveDataSpaceId,
+        attribute: SaveDataAttribute,
+    ) -> FileSystem;
+    #[ipc_rid(53)]
+    #[version(version::VersionInterval::from(version::Version::new(2, 0, 0)))]
+    fn open_read_only_save_data_file_system(
+        &self,
+        space_id: SaveDataSpaceId,
+        attribute: SaveDataAttribute,
+    ) -> FileSystem;
+    #[ipc_rid(60)]
+    fn open_save_data_info_reader(&self) -> SaveDataInfoReader;
+    #[ipc_rid(61)]
+    fn open_save_data_info_reader_by_save_data_space_id(
+        &self,
+        space_id: SaveDataSpaceId,
+    ) -> SaveDataInfoReader;
+    #[ipc_rid(110)]
+    fn open_content_storage_file_system(&self, storage_id: ContentStorageId) -> FileSystem;
     #[ipc_rid(1006)]
     fn output_access_log_to_sd_card(&self, log_buf: sf::InMapAliasBuffer<'_, u8>);
 }
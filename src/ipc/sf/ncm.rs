@@ -112,6 +112,12 @@ pub struct ContentId {
     pub id: [u8; 0x10],
 }
 
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct PlaceHolderId {
+    pub id: [u8; 0x10],
+}
+
 #[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
 #[repr(C)]
 pub struct LegacyContentInfo {
@@ -232,8 +238,95 @@ pub trait ContentMetaDatabase {
 }
 
 #[nx_derive::ipc_trait]
-pub trait ContentManager {
+#[default_client]
+pub trait ContentStorage {
     #[ipc_rid(0)]
+    fn generate_placeholder_id(&self) -> PlaceHolderId;
+    #[ipc_rid(1)]
+    fn create_placeholder(&self, content_id: ContentId, placeholder_id: PlaceHolderId, size: i64);
+    #[ipc_rid(2)]
+    fn delete_placeholder(&self, placeholder_id: PlaceHolderId);
+    #[ipc_rid(3)]
+    fn has_placeholder(&self, placeholder_id: PlaceHolderId) -> bool;
+    #[ipc_rid(4)]
+    fn write_placeholder(
+        &self,
+        placeholder_id: PlaceHolderId,
+        offset: u64,
+        buf: sf::InMapAliasBuffer<'_, u8>,
+    );
+    #[ipc_rid(5)]
+    fn register(&self, content_id: ContentId, placeholder_id: PlaceHolderId);
+    #[ipc_rid(6)]
+    fn delete(&self, content_id: ContentId);
+    #[ipc_rid(7)]
+    fn has(&self, content_id: ContentId) -> bool;
+    #[ipc_rid(8)]
+    fn get_path(&self, out_path: sf::OutFixedPointerBuffer<'_, ContentPath>, content_id: ContentId);
+    #[ipc_rid(9)]
+    fn get_placeholder_path(
+        &self,
+        out_path: sf::OutFixedPointerBuffer<'_, ContentPath>,
+        placeholder_id: PlaceHolderId,
+    );
+    #[ipc_rid(10)]
+    fn cleanup_all_placeholder(&self);
+    #[ipc_rid(11)]
+    fn list_placeholder(
+        &self,
+        out_placeholder_ids: sf::OutMapAliasBuffer<'_, PlaceHolderId>,
+    ) -> u32;
+    #[ipc_rid(12)]
+    fn get_content_count(&self) -> u32;
+    #[ipc_rid(13)]
+    fn list_content_id(
+        &self,
+        out_content_ids: sf::OutMapAliasBuffer<'_, ContentId>,
+        offset: u32,
+    ) -> u32;
+    #[ipc_rid(14)]
+    fn get_size_from_content_id(&self, content_id: ContentId) -> i64;
+    #[ipc_rid(15)]
+    fn disable_forcibly(&self);
+    #[ipc_rid(16)]
+    #[version(version::VersionInterval::from(version::Version::new(2, 0, 0)))]
+    fn revert_to_placeholder(
+        &self,
+        placeholder_id: PlaceHolderId,
+        old_content_id: ContentId,
+        new_content_id: ContentId,
+    );
+    #[ipc_rid(17)]
+    #[version(version::VersionInterval::from(version::Version::new(2, 0, 0)))]
+    fn set_placeholder_size(&self, placeholder_id: PlaceHolderId, size: i64);
+    #[ipc_rid(18)]
+    #[version(version::VersionInterval::from(version::Version::new(2, 0, 0)))]
+    fn read_content_id_file(
+        &self,
+        out_buf: sf::OutMapAliasBuffer<'_, u8>,
+        content_id: ContentId,
+        offset: i64,
+    );
+    #[ipc_rid(22)]
+    #[version(version::VersionInterval::from(version::Version::new(2, 0, 0)))]
+    fn get_free_space_size(&self) -> i64;
+    #[ipc_rid(23)]
+    #[version(version::VersionInterval::from(version::Version::new(2, 0, 0)))]
+    fn get_total_space_size(&self) -> i64;
+    #[ipc_rid(24)]
+    #[version(version::VersionInterval::from(version::Version::new(3, 0, 0)))]
+    fn flush_placeholder(&self);
+    #[ipc_rid(25)]
+    #[version(version::VersionInterval::from(version::Version::new(4, 0, 0)))]
+    fn get_size_from_placeholder_id(&self, placeholder_id: PlaceHolderId) -> i64;
+}
+
+#[nx_derive::ipc_trait]
+pub trait ContentManager {
+    #[ipc_rid(4)]
+    #[return_session]
+    fn open_content_storage(&self, storage_id: StorageId) -> ContentStorage;
+    #[ipc_rid(5)]
     #[return_session]
     fn open_content_meta_database(&self, storage_id: StorageId) -> ContentMetaDatabase;
 }
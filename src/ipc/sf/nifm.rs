@@ -0,0 +1,254 @@
+use crate::ipc::sf;
+use crate::util;
+use crate::version;
+
+use nx_derive::{Request, Response};
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct ClientId {
+    pub id: u32,
+}
+
+/// Represents the states of a network request, as returned by [`IRequestClient::get_request_state`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum RequestState {
+    /// The request wasn't submitted yet (or failed)
+    Invalid = 1,
+    OnHold = 2,
+    Accepted = 3,
+    Blocking = 4,
+}
+
+impl RequestState {
+    /// Converts a raw request state value, if valid
+    ///
+    /// # Arguments
+    ///
+    /// * `raw`: The raw value
+    pub const fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            1 => Some(Self::Invalid),
+            2 => Some(Self::OnHold),
+            3 => Some(Self::Accepted),
+            4 => Some(Self::Blocking),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum InternetConnectionType {
+    WiFi = 1,
+    Ethernet = 2,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum InternetConnectionStatus {
+    ConnectingUnknown1 = 0,
+    ConnectingUnknown2 = 1,
+    ConnectingUnknown3 = 2,
+    ConnectingUnknown4 = 3,
+    Connected = 4,
+}
+
+/// Represents the raw values returned by [`IGeneralServiceClient::get_internet_connection_status`]
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct InternetConnectionStatusData {
+    pub connection_type: u8,
+    /// Wi-Fi signal strength (`0`-`3`)
+    pub wifi_strength: u8,
+    pub status: u8,
+}
+
+impl InternetConnectionStatusData {
+    /// Gets the connection type, if valid
+    pub const fn get_connection_type(&self) -> Option<InternetConnectionType> {
+        match self.connection_type {
+            1 => Some(InternetConnectionType::WiFi),
+            2 => Some(InternetConnectionType::Ethernet),
+            _ => None,
+        }
+    }
+
+    /// Gets the connection status, if valid
+    pub const fn get_status(&self) -> Option<InternetConnectionStatus> {
+        match self.status {
+            0 => Some(InternetConnectionStatus::ConnectingUnknown1),
+            1 => Some(InternetConnectionStatus::ConnectingUnknown2),
+            2 => Some(InternetConnectionStatus::ConnectingUnknown3),
+            3 => Some(InternetConnectionStatus::ConnectingUnknown4),
+            4 => Some(InternetConnectionStatus::Connected),
+            _ => None,
+        }
+    }
+
+    /// Gets whether the connection is established
+    #[inline]
+    pub const fn is_connected(&self) -> bool {
+        matches!(self.get_status(), Some(InternetConnectionStatus::Connected))
+    }
+}
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct IpAddressSetting {
+    pub is_automatic: bool,
+    pub current_addr: [u8; 4],
+    pub subnet_mask: [u8; 4],
+    pub gateway: [u8; 4],
+}
+const_assert!(core::mem::size_of::<IpAddressSetting>() == 0xD);
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct DnsSetting {
+    pub is_automatic: bool,
+    pub primary_dns_server: [u8; 4],
+    pub secondary_dns_server: [u8; 4],
+}
+const_assert!(core::mem::size_of::<DnsSetting>() == 0x9);
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct ProxySetting {
+    pub enabled: u8,
+    pub pad: u8,
+    pub port: u16,
+    pub server: util::ArrayString<0x64>,
+    pub auto_auth_enabled: u8,
+    pub user: util::ArrayString<0x20>,
+    pub password: util::ArrayString<0x20>,
+    pub pad_2: u8,
+}
+const_assert!(core::mem::size_of::<ProxySetting>() == 0xAA);
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct IpSettingData {
+    pub ip_address_setting: IpAddressSetting,
+    pub dns_setting: DnsSetting,
+    pub proxy_setting: ProxySetting,
+    pub mtu: u16,
+}
+const_assert!(core::mem::size_of::<IpSettingData>() == 0xC2);
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct WirelessSettingData {
+    pub ssid_len: u8,
+    /// The SSID bytes (not NUL-terminated, see `ssid_len`)
+    pub ssid: [u8; 0x20],
+    pub unk_x21: u8,
+    pub unk_x22: u8,
+    pub unk_x23: u8,
+    pub passphrase: util::ArrayString<0x41>,
+}
+const_assert!(core::mem::size_of::<WirelessSettingData>() == 0x65);
+
+impl WirelessSettingData {
+    /// Gets the SSID bytes
+    pub fn get_ssid(&self) -> &[u8] {
+        &self.ssid[..(self.ssid_len as usize).min(self.ssid.len())]
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct NetworkProfileData {
+    pub ip_setting_data: IpSettingData,
+    pub uuid: [u8; 0x10],
+    pub network_name: util::ArrayString<0x40>,
+    pub unk_x112: u8,
+    pub unk_x113: u8,
+    pub unk_x114: u8,
+    pub unk_x115: u8,
+    pub wireless_setting_data: WirelessSettingData,
+    pub pad: u8,
+}
+const_assert!(core::mem::size_of::<NetworkProfileData>() == 0x17C);
+
+#[nx_derive::ipc_trait]
+#[default_client]
+pub trait Request {
+    #[ipc_rid(0)]
+    fn get_request_state(&self) -> u32;
+    #[ipc_rid(1)]
+    fn get_result(&self);
+    #[ipc_rid(2)]
+    fn get_system_event_readable_handles(&self) -> (sf::CopyHandle, sf::CopyHandle);
+    #[ipc_rid(3)]
+    fn cancel(&self);
+    #[ipc_rid(4)]
+    fn submit(&self);
+    #[ipc_rid(6)]
+    fn set_requirement_preset(&self, requirement_preset: u32);
+    #[ipc_rid(8)]
+    fn set_priority(&self, priority: u32);
+    #[ipc_rid(10)]
+    fn set_rejectable(&self, rejectable: bool);
+    #[ipc_rid(11)]
+    fn set_connection_confirmation_option(&self, option: i8);
+    #[ipc_rid(12)]
+    fn set_persistent(&self, persistent: bool);
+    #[ipc_rid(13)]
+    fn set_instant(&self, instant: bool);
+    #[ipc_rid(15)]
+    fn set_raw_priority(&self, raw_priority: u8);
+    #[ipc_rid(16)]
+    fn set_greedy(&self, greedy: bool);
+    #[ipc_rid(17)]
+    fn set_sharable(&self, sharable: bool);
+    #[ipc_rid(20)]
+    fn get_revision(&self) -> u32;
+}
+
+#[nx_derive::ipc_trait]
+#[default_client]
+pub trait GeneralService {
+    #[ipc_rid(1)]
+    fn get_client_id(&self, out_client_id: sf::OutFixedPointerBuffer<'_, ClientId>);
+    #[ipc_rid(4)]
+    #[return_session]
+    fn create_request(&self, requirement_preset: u32) -> Request;
+    #[ipc_rid(5)]
+    fn get_current_network_profile(
+        &self,
+        out_profile: sf::OutFixedPointerBuffer<'_, NetworkProfileData>,
+    );
+    #[ipc_rid(12)]
+    fn get_current_ip_address(&self) -> u32;
+    #[ipc_rid(15)]
+    fn get_current_ip_config_info(&self) -> (IpAddressSetting, DnsSetting);
+    #[ipc_rid(16)]
+    fn set_wireless_communication_enabled(&self, enabled: bool);
+    #[ipc_rid(17)]
+    fn is_wireless_communication_enabled(&self) -> bool;
+    #[ipc_rid(18)]
+    fn get_internet_connection_status(&self) -> InternetConnectionStatusData;
+    #[ipc_rid(20)]
+    fn is_ethernet_communication_enabled(&self) -> bool;
+    #[ipc_rid(21)]
+    fn is_any_internet_request_accepted(
+        &self,
+        client_id: sf::InFixedPointerBuffer<'_, ClientId>,
+    ) -> bool;
+    #[ipc_rid(22)]
+    fn is_any_foreground_request_accepted(&self) -> bool;
+}
+
+#[nx_derive::ipc_trait]
+pub trait StaticService {
+    #[ipc_rid(4)]
+    #[return_session]
+    #[version(version::VersionInterval::to(version::Version::new(2, 3, 0)))]
+    fn create_general_service_old(&self) -> GeneralService;
+    #[ipc_rid(5)]
+    #[return_session]
+    #[version(version::VersionInterval::from(version::Version::new(3, 0, 0)))]
+    fn create_general_service(&self, process_id: sf::ProcessId, reserved: u64) -> GeneralService;
+}
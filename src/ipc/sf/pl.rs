@@ -0,0 +1,59 @@
+use crate::ipc::sf;
+
+use nx_derive::{Request, Response};
+
+/// Represents the system shared fonts
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum SharedFontType {
+    /// Japanese, US and European characters
+    Standard = 0,
+    ChineseSimplified = 1,
+    /// Extended characters for [`SharedFontType::ChineseSimplified`]
+    ExtendedChineseSimplified = 2,
+    ChineseTraditional = 3,
+    Korean = 4,
+    /// Nintendo's symbols (controller buttons, etc.)
+    NintendoExtended = 5,
+}
+
+impl SharedFontType {
+    /// All the shared font types, in their standard order
+    pub const ALL: [Self; 6] = [
+        Self::Standard,
+        Self::ChineseSimplified,
+        Self::ExtendedChineseSimplified,
+        Self::ChineseTraditional,
+        Self::Korean,
+        Self::NintendoExtended,
+    ];
+}
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum LoadState {
+    Loading = 0,
+    Loaded = 1,
+}
+
+#[nx_derive::ipc_trait]
+pub trait PlatformServiceManager {
+    #[ipc_rid(0)]
+    fn request_load(&self, font_type: SharedFontType);
+    #[ipc_rid(1)]
+    fn get_load_state(&self, font_type: SharedFontType) -> LoadState;
+    #[ipc_rid(2)]
+    fn get_size(&self, font_type: SharedFontType) -> u32;
+    #[ipc_rid(3)]
+    fn get_shared_memory_address_offset(&self, font_type: SharedFontType) -> u32;
+    #[ipc_rid(4)]
+    fn get_shared_memory_native_handle(&self) -> sf::CopyHandle;
+    #[ipc_rid(5)]
+    fn get_shared_font_in_order_of_priority(
+        &self,
+        language_code: u64,
+        out_font_codes: sf::OutMapAliasBuffer<'_, u32>,
+        out_offsets: sf::OutMapAliasBuffer<'_, u32>,
+        out_sizes: sf::OutMapAliasBuffer<'_, u32>,
+    ) -> (bool, u32);
+}
@@ -0,0 +1,164 @@
+use crate::ipc::sf;
+use crate::util;
+use crate::version;
+
+use nx_derive::{Request, Response};
+
+/// Represents a POSIX time value (seconds since the UNIX epoch)
+pub type PosixTime = i64;
+
+/// Represents a time zone location name (for instance, `Europe/Madrid`)
+pub type LocationName = util::ArrayString<0x24>;
+
+/// Represents a time zone abbreviation (for instance, `CEST`)
+pub type TimeZoneName = util::ArrayString<0x8>;
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct SteadyClockTimePoint {
+    pub time_point: i64,
+    pub source_id: [u8; 0x10],
+}
+const_assert!(core::mem::size_of::<SteadyClockTimePoint>() == 0x18);
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct SystemClockContext {
+    pub offset: i64,
+    pub timestamp: SteadyClockTimePoint,
+}
+const_assert!(core::mem::size_of::<SystemClockContext>() == 0x20);
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct CalendarTime {
+    pub year: i16,
+    pub month: i8,
+    pub day: i8,
+    pub hour: i8,
+    pub minute: i8,
+    pub second: i8,
+    pub pad: u8,
+}
+const_assert!(core::mem::size_of::<CalendarTime>() == 0x8);
+
+#[derive(Request, Response, Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct CalendarAdditionalInfo {
+    /// Day of the week, where `0` is Sunday
+    pub day_of_week: u32,
+    /// Day of the year, where `0` is January 1st
+    pub day_of_year: u32,
+    pub time_zone_name: TimeZoneName,
+    pub is_dst: u32,
+    pub utc_offset_seconds: i32,
+}
+const_assert!(core::mem::size_of::<CalendarAdditionalInfo>() == 0x18);
+
+/// Represents the system's (opaque) time zone rule layout
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct TimeZoneRule {
+    pub data: [u8; 0x4000],
+}
+const_assert!(core::mem::size_of::<TimeZoneRule>() == 0x4000);
+
+impl Default for TimeZoneRule {
+    fn default() -> Self {
+        Self { data: [0; 0x4000] }
+    }
+}
+
+#[nx_derive::ipc_trait]
+#[default_client]
+pub trait SystemClock {
+    #[ipc_rid(0)]
+    fn get_current_time(&self) -> PosixTime;
+    #[ipc_rid(1)]
+    fn set_current_time(&self, time: PosixTime);
+    #[ipc_rid(2)]
+    fn get_system_clock_context(&self) -> SystemClockContext;
+    #[ipc_rid(3)]
+    fn set_system_clock_context(&self, context: SystemClockContext);
+}
+
+#[nx_derive::ipc_trait]
+#[default_client]
+pub trait SteadyClock {
+    #[ipc_rid(0)]
+    fn get_current_time_point(&self) -> SteadyClockTimePoint;
+}
+
+#[nx_derive::ipc_trait]
+#[default_client]
+pub trait TimeZoneService {
+    #[ipc_rid(0)]
+    fn get_device_location_name(&self) -> LocationName;
+    #[ipc_rid(1)]
+    fn set_device_location_name(&self, location_name: LocationName);
+    #[ipc_rid(2)]
+    fn get_total_location_name_count(&self) -> u32;
+    #[ipc_rid(3)]
+    fn load_location_name_list(
+        &self,
+        out_names: sf::OutMapAliasBuffer<'_, LocationName>,
+        offset: u32,
+    ) -> u32;
+    #[ipc_rid(4)]
+    fn load_time_zone_rule(
+        &self,
+        location_name: LocationName,
+        out_rule: sf::OutMapAliasBuffer<'_, TimeZoneRule>,
+    );
+    #[ipc_rid(100)]
+    fn to_calendar_time(
+        &self,
+        time: PosixTime,
+        rule: sf::InMapAliasBuffer<'_, TimeZoneRule>,
+    ) -> (CalendarTime, CalendarAdditionalInfo);
+    #[ipc_rid(101)]
+    fn to_calendar_time_with_my_rule(
+        &self,
+        time: PosixTime,
+    ) -> (CalendarTime, CalendarAdditionalInfo);
+    #[ipc_rid(201)]
+    fn to_posix_time(
+        &self,
+        calendar_time: CalendarTime,
+        rule: sf::InMapAliasBuffer<'_, TimeZoneRule>,
+        out_times: sf::OutPointerBuffer<'_, PosixTime>,
+    ) -> u32;
+    #[ipc_rid(202)]
+    fn to_posix_time_with_my_rule(
+        &self,
+        calendar_time: CalendarTime,
+        out_times: sf::OutPointerBuffer<'_, PosixTime>,
+    ) -> u32;
+}
+
+#[nx_derive::ipc_trait]
+pub trait StaticService {
+    #[ipc_rid(0)]
+    #[return_session]
+    fn get_standard_user_system_clock(&self) -> SystemClock;
+    #[ipc_rid(1)]
+    #[return_session]
+    fn get_standard_network_system_clock(&self) -> SystemClock;
+    #[ipc_rid(2)]
+    #[return_session]
+    fn get_standard_steady_clock(&self) -> SteadyClock;
+    #[ipc_rid(3)]
+    #[return_session]
+    fn get_time_zone_service(&self) -> TimeZoneService;
+    #[ipc_rid(4)]
+    #[return_session]
+    fn get_standard_local_system_clock(&self) -> SystemClock;
+    #[ipc_rid(5)]
+    #[return_session]
+    #[version(version::VersionInterval::from(version::Version::new(4, 0, 0)))]
+    fn get_ephemeral_network_system_clock(&self) -> SystemClock;
+    #[ipc_rid(100)]
+    fn is_standard_user_system_clock_automatic_correction_enabled(&self) -> bool;
+    #[ipc_rid(101)]
+    fn set_standard_user_system_clock_automatic_correction_enabled(&self, enabled: bool);
+}
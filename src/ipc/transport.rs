@@ -0,0 +1,436 @@
+//! Pluggable IPC request transports
+//!
+//! All client requests (including the code generated by `#[nx_derive::ipc_trait]`) are sent through [`client::send_sync_request`][`super::client::send_sync_request`], and all message buffer accesses go through [`get_msg_buffer`][`super::get_msg_buffer`].
+//!
+//! By default both of them use the kernel (`svcSendSyncRequest` and the thread-local message buffer), but a [`RequestTransport`] may be installed for the current thread instead, which allows exercising IPC code without a Horizon kernel (for instance, in host tests):
+//!
+//! ```ignore
+//! let transport = Arc::new(ReplayTransport::new());
+//! transport.push_response(&recorded_open_file_response)?;
+//! let _guard = nx::ipc::transport::set_transport(transport.clone());
+//!
+//! let fs = fsp::FileSystem::new(sf::Session::from_handle(0xCAFE));
+//! let file = fs.open_file(fsp::FileOpenMode::Read(), sf::Buffer::from_var(&path))?;
+//!
+//! let requests = transport.take_requests();
+//! assert_eq!(requests[0].handle, 0xCAFE);
+//! // Check the request header, descriptors, etc.
+//! ```
+//!
+//! Each installed transport gets its own message buffer, which lives (and is used by the installing thread) until the returned [`TransportGuard`] is dropped.
+//!
+//! Threads are told apart by their thread-local region, thus on hosts without one (where it's always null) the installed transports are effectively shared by all threads.
+//!
+//! Note that only the message exchange itself is replaced: closing handles still goes through the kernel, and anything the kernel would do between processes (copying pointer buffers, creating sessions, etc.) isn't emulated.
+
+use super::*;
+use crate::sync::Mutex;
+use crate::sync::RwLock;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use server::ServerManager;
+
+/// The size of an IPC message buffer
+pub const MESSAGE_BUFFER_SIZE: usize = 0x100;
+
+/// Represents a way to exchange IPC messages, replacing `svcSendSyncRequest`
+pub trait RequestTransport: Send + Sync {
+    /// Sends the request written on the given message buffer, leaving the response on it
+    ///
+    /// # Arguments
+    ///
+    /// * `handle`: The session handle the request is sent to
+    /// * `msg_buffer`: The message buffer of the current thread's transport installation
+    fn send_sync_request(
+        &self,
+        handle: svc::Handle,
+        msg_buffer: &mut [u8; MESSAGE_BUFFER_SIZE],
+    ) -> Result<()>;
+}
+
+/// Represents a message buffer owned by a transport installation
+#[repr(C, align(16))]
+struct MessageBuffer(UnsafeCell<[u8; MESSAGE_BUFFER_SIZE]>);
+
+impl MessageBuffer {
+    const fn new() -> Self {
+        Self(UnsafeCell::new([0; MESSAGE_BUFFER_SIZE]))
+    }
+
+    #[inline]
+    fn as_mut_ptr(&self) -> *mut u8 {
+        self.0.get() as *mut u8
+    }
+}
+
+// SAFETY: like the thread-local message buffer, the buffer is only accessed by the thread which installed the transport
+unsafe impl Sync for MessageBuffer {}
+unsafe impl Send for MessageBuffer {}
+
+struct Installation {
+    id: usize,
+    thread_key: usize,
+    transport: Arc<dyn RequestTransport>,
+    // Boxed so that its address is kept when other installations are added/removed
+    msg_buffer: Box<MessageBuffer>,
+}
+
+static G_INSTALLATIONS: RwLock<Vec<Installation>> = RwLock::new(Vec::new());
+static G_NEXT_INSTALLATION_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Gets a value identifying the current thread, which transport installations are scoped to
+#[inline]
+fn get_current_thread_key() -> usize {
+    // The unit tests run on a regular host, without a thread-local region
+    #[cfg(test)]
+    {
+        std::thread_local!(static KEY: u8 = const { 0 });
+        KEY.with(|key| key as *const u8 as usize)
+    }
+
+    #[cfg(not(test))]
+    {
+        thread::get_thread_local_region() as usize
+    }
+}
+
+/// Represents a [`RequestTransport`] installed for the current thread, which is uninstalled when this guard is dropped
+///
+/// The guard can't be sent to other threads, since the installation belongs to the thread which created it
+#[must_use = "the transport is uninstalled as soon as the guard is dropped"]
+pub struct TransportGuard {
+    id: usize,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for TransportGuard {
+    /// Uninstalls the [`RequestTransport`], freeing its message buffer
+    fn drop(&mut self) {
+        G_INSTALLATIONS
+            .write()
+            .retain(|installation| installation.id != self.id);
+    }
+}
+
+/// Installs a [`RequestTransport`] for the current thread, which will be used for all IPC requests from this thread while the returned [`TransportGuard`] is alive
+///
+/// Installing another transport while one is already installed on the same thread temporarily overrides it
+///
+/// # Arguments
+///
+/// * `transport`: The transport to install
+pub fn set_transport(transport: Arc<dyn RequestTransport>) -> TransportGuard {
+    let id = G_NEXT_INSTALLATION_ID.fetch_add(1, Ordering::Relaxed);
+    G_INSTALLATIONS.write().push(Installation {
+        id,
+        thread_key: get_current_thread_key(),
+        transport,
+        msg_buffer: Box::new(MessageBuffer::new()),
+    });
+
+    TransportGuard {
+        id,
+        _not_send: PhantomData,
+    }
+}
+
+fn with_current_installation<R>(f: impl FnOnce(&Installation) -> R) -> Option<R> {
+    let thread_key = get_current_thread_key();
+    G_INSTALLATIONS
+        .read()
+        .iter()
+        .rev()
+        .find(|installation| installation.thread_key == thread_key)
+        .map(f)
+}
+
+/// Gets the [`RequestTransport`] installed for the current thread, if any
+pub fn get_transport() -> Option<Arc<dyn RequestTransport>> {
+    with_current_installation(|installation| installation.transport.clone())
+}
+
+/// Gets the message buffer of the [`RequestTransport`] installed for the current thread, if any
+///
+/// The buffer stays valid while the installation's [`TransportGuard`] (which only the current thread can drop) is alive
+#[inline]
+pub(crate) fn get_transport_msg_buffer() -> Option<*mut u8> {
+    with_current_installation(|installation| installation.msg_buffer.as_mut_ptr())
+}
+
+/// Sends the request on the current message buffer through the [`RequestTransport`] installed for the current thread, if any
+///
+/// # Arguments
+///
+/// * `handle`: The session handle the request is sent to
+pub(crate) fn send_transport_request(handle: svc::Handle) -> Option<Result<()>> {
+    let (transport, msg_buffer) = with_current_installation(|installation| {
+        (
+            installation.transport.clone(),
+            installation.msg_buffer.as_mut_ptr(),
+        )
+    })?;
+
+    // SAFETY: the installation (and thus the buffer) can only be removed by the current thread, which is busy sending this request
+    let msg_buffer = unsafe { &mut *(msg_buffer as *mut [u8; MESSAGE_BUFFER_SIZE]) };
+    Some(transport.send_sync_request(handle, msg_buffer))
+}
+
+/// Represents a request sent through a [`ReplayTransport`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RecordedRequest {
+    /// The session handle the request was sent to
+    pub handle: svc::Handle,
+    /// The message buffer contents when the request was sent
+    pub data: [u8; MESSAGE_BUFFER_SIZE],
+}
+
+/// Represents a transport which records all sent requests and answers them with previously recorded responses, in order
+pub struct ReplayTransport {
+    responses: RwLock<VecDeque<Vec<u8>>>,
+    requests: RwLock<Vec<RecordedRequest>>,
+}
+
+impl ReplayTransport {
+    /// Creates a new [`ReplayTransport`] with no responses
+    pub const fn new() -> Self {
+        Self {
+            responses: RwLock::new(VecDeque::new()),
+            requests: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Queues a response, which will be placed on the message buffer when the next unanswered request is sent
+    ///
+    /// # Arguments
+    ///
+    /// * `response`: The raw response message, at most [`MESSAGE_BUFFER_SIZE`] bytes long
+    pub fn push_response(&self, response: &[u8]) -> Result<()> {
+        result_return_if!(
+            response.len() > MESSAGE_BUFFER_SIZE,
+            rc::ResultInvalidTransportMessageSize
+        );
+
+        self.responses.write().push_back(response.to_vec());
+        Ok(())
+    }
+
+    /// Gets the number of queued responses which weren't consumed yet
+    pub fn get_pending_response_count(&self) -> usize {
+        self.responses.read().len()
+    }
+
+    /// Takes all the requests sent so far, clearing them
+    pub fn take_requests(&self) -> Vec<RecordedRequest> {
+        core::mem::take(&mut *self.requests.write())
+    }
+}
+
+impl Default for ReplayTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestTransport for ReplayTransport {
+    fn send_sync_request(
+        &self,
+        handle: svc::Handle,
+        msg_buffer: &mut [u8; MESSAGE_BUFFER_SIZE],
+    ) -> Result<()> {
+        self.requests.write().push(RecordedRequest {
+            handle,
+            data: *msg_buffer,
+        });
+
+        let response = self
+            .responses
+            .write()
+            .pop_front()
+            .ok_or(rc::ResultNoTransportResponse::make())?;
+        msg_buffer.fill(0);
+        msg_buffer[..response.len()].copy_from_slice(&response);
+        Ok(())
+    }
+}
+
+/// Represents a transport which dispatches requests to the sessions registered on an in-process [`ServerManager`]
+///
+/// Sessions must be registered (see [`ServerManager::register_session`]) with handles which are unique for this transport, which clients then send requests to
+pub struct LoopbackTransport<const P: usize> {
+    manager: Mutex<ServerManager<P>>,
+}
+
+impl<const P: usize> LoopbackTransport<P> {
+    /// Creates a new [`LoopbackTransport`] from a [`ServerManager`]
+    ///
+    /// # Arguments
+    ///
+    /// * `manager`: The [`ServerManager`] with the registered sessions
+    ///
+    /// # Safety
+    ///
+    /// The manager must own its server objects: no clones of the sessions registered on it may be kept elsewhere, since the transport may be used (and dropped) from any thread
+    pub unsafe fn new(manager: ServerManager<P>) -> Self {
+        Self {
+            manager: Mutex::new(manager),
+        }
+    }
+
+    /// Runs a function with the inner [`ServerManager`], for instance to register more sessions
+    ///
+    /// Both the function and its result must be [`Send`], so that no (non thread-safe) server objects can escape the manager through them, since the transport may be used from any thread
+    ///
+    /// # Arguments
+    ///
+    /// * `f`: The function to run
+    pub fn with_manager<R: Send>(&self, f: impl FnOnce(&mut ServerManager<P>) -> R + Send) -> R {
+        f(&mut self.manager.lock())
+    }
+}
+
+// SAFETY: the (non thread-safe) server objects are owned by the manager, which is only ever accessed with the inner lock held, and `with_manager` only lets `Send` values in or out of it
+unsafe impl<const P: usize> Send for LoopbackTransport<P> {}
+unsafe impl<const P: usize> Sync for LoopbackTransport<P> {}
+
+impl<const P: usize> RequestTransport for LoopbackTransport<P> {
+    fn send_sync_request(
+        &self,
+        handle: svc::Handle,
+        _msg_buffer: &mut [u8; MESSAGE_BUFFER_SIZE],
+    ) -> Result<()> {
+        // The server reads the request from (and writes the response to) the same message buffer, since it runs on the requesting thread
+        self.manager.lock().process_request(handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::client::IClientObject;
+    use crate::ipc::sf::fsp::{self, IFileSystemClient};
+
+    const FS_HANDLE: svc::Handle = 0xCAFE;
+    const FILE_HANDLE: svc::Handle = 0xBEEF;
+
+    fn push_raw<T: Copy>(buf: &mut Vec<u8>, value: T) {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(&value as *const T as *const u8, core::mem::size_of::<T>())
+        };
+        buf.extend_from_slice(bytes);
+    }
+
+    fn read_raw<T: Copy>(data: &[u8], offset: usize) -> T {
+        assert!(offset + core::mem::size_of::<T>() <= data.len());
+        unsafe { core::ptr::read_unaligned(data.as_ptr().add(offset) as *const T) }
+    }
+
+    fn make_open_file_response() -> Vec<u8> {
+        let mut response = Vec::new();
+        let data_word_count = ((DATA_PADDING as usize + core::mem::size_of::<cmif::DataHeader>())
+            / core::mem::size_of::<u32>()) as u32;
+        push_raw(
+            &mut response,
+            CommandHeader::new(0, 0, 0, 0, 0, data_word_count, 0, true),
+        );
+        push_raw(&mut response, CommandSpecialHeader::new(false, 0, 1));
+        push_raw(&mut response, FILE_HANDLE);
+        // The data header is already aligned here (0x10)
+        push_raw(
+            &mut response,
+            cmif::DataHeader::new(cmif::OUT_DATA_HEADER_MAGIC, 0, 0, 0),
+        );
+        response
+    }
+
+    #[test]
+    fn open_file_request_serialization() {
+        let transport = Arc::new(ReplayTransport::new());
+        transport.push_response(&make_open_file_response()).unwrap();
+        let guard = set_transport(transport.clone());
+
+        let fs = fsp::FileSystem::new(sf::Session::from_handle(FS_HANDLE));
+        let path = fsp::Path::from_str("/file.bin");
+        let file = fs
+            .open_file(fsp::FileOpenMode::Read(), sf::Buffer::from_var(&path))
+            .unwrap();
+        assert_eq!(file.get_info().handle, FILE_HANDLE);
+        assert_eq!(transport.get_pending_response_count(), 0);
+
+        let requests = transport.take_requests();
+        assert_eq!(requests.len(), 1);
+        let request = &requests[0];
+        assert_eq!(request.handle, FS_HANDLE);
+
+        let header: CommandHeader = read_raw(&request.data, 0);
+        assert_eq!(header.get_command_type(), cmif::CommandType::Request as u32);
+        assert_eq!(header.get_send_static_count(), 1);
+        assert_eq!(header.get_send_buffer_count(), 0);
+        assert!(!header.get_has_special_header());
+
+        let descriptor: SendStaticDescriptor =
+            read_raw(&request.data, core::mem::size_of::<CommandHeader>());
+        // Descriptors only hold the lower 42 bits of the address, which is enough for Horizon
+        let path_address = &path as *const fsp::Path as usize;
+        assert_eq!(
+            descriptor.get_address() as usize,
+            path_address & ((1 << 42) - 1)
+        );
+        assert_eq!(descriptor.get_size(), core::mem::size_of::<fsp::Path>());
+
+        // Header + descriptor are 0x10 bytes, thus the data header is already aligned
+        let data_header: cmif::DataHeader = read_raw(&request.data, 0x10);
+        assert_eq!(data_header.magic, cmif::IN_DATA_HEADER_MAGIC);
+        assert_eq!(data_header.value, 8);
+        let mode: u32 = read_raw(
+            &request.data,
+            0x10 + core::mem::size_of::<cmif::DataHeader>(),
+        );
+        assert_eq!(mode, fsp::FileOpenMode::Read().get());
+
+        // Closing the sessions would go through the kernel
+        core::mem::forget(file);
+        core::mem::forget(fs);
+
+        drop(guard);
+        assert!(get_transport().is_none());
+    }
+
+    #[test]
+    fn missing_response() {
+        let transport = Arc::new(ReplayTransport::new());
+        let _guard = set_transport(transport.clone());
+
+        let rc = send_transport_request(FS_HANDLE).unwrap();
+        assert!(rc::ResultNoTransportResponse::matches(rc.unwrap_err()));
+        assert_eq!(transport.take_requests().len(), 1);
+        assert!(
+            transport
+                .push_response(&[0; MESSAGE_BUFFER_SIZE + 1])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn nested_installations() {
+        let outer = Arc::new(ReplayTransport::new());
+        let inner = Arc::new(ReplayTransport::new());
+        let _outer_guard = set_transport(outer.clone());
+        let outer_buffer = get_transport_msg_buffer().unwrap();
+        {
+            let _inner_guard = set_transport(inner.clone());
+            assert_ne!(get_transport_msg_buffer().unwrap(), outer_buffer);
+            let _ = send_transport_request(FS_HANDLE);
+            assert_eq!(inner.take_requests().len(), 1);
+        }
+
+        assert_eq!(get_transport_msg_buffer().unwrap(), outer_buffer);
+        let _ = send_transport_request(FS_HANDLE);
+        assert_eq!(outer.take_requests().len(), 1);
+        assert!(inner.take_requests().is_empty());
+    }
+}
@@ -7,7 +7,7 @@ use crate::service::applet;
 use crate::service::applet::ILibraryAppletAccessorClient;
 use crate::service::applet::ILibraryAppletCreatorClient;
 use crate::service::applet::{IStorageAccessorClient, IStorageClient, Storage};
-use crate::service::sm::rc;
+use crate::service::sm::rc as sm_rc;
 use crate::svc;
 use crate::sync::{Mutex, MutexGuard};
 use crate::wait;
@@ -15,6 +15,18 @@ use alloc::boxed::Box;
 use applet::LibraryAppletCreator;
 use core::mem as cmem;
 
+pub mod rc;
+
+pub mod swkbd;
+
+pub mod error;
+
+pub mod psel;
+
+pub mod web;
+
+pub mod album;
+
 /// Represents the common arguments layout sent as starting input by/to all library applets
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
 #[repr(C)]
@@ -110,6 +122,34 @@ impl LibraryAppletHolder {
         let mut o_st = self.pop_out_data_storage()?;
         read_storage(&mut o_st)
     }
+
+    /// Pushes an interactive input [`IStorageClient`] shared object to the (running) library applet
+    #[inline]
+    pub fn push_interactive_in_data_storage(&mut self, storage: Storage) -> Result<()> {
+        self.accessor.push_interactive_in_data(storage)
+    }
+
+    /// Pushes interactive input data to the (running) library applet
+    ///
+    /// This is a wrapper which creates an [`IStorageClient`] object with the given value and pushes it
+    pub fn push_interactive_in_data<T: Copy>(&mut self, t: T) -> Result<()> {
+        let t_st = create_write_storage(t)?;
+        self.push_interactive_in_data_storage(t_st)
+    }
+
+    /// Pops an interactive output [`IStorageClient`] shared object from the (running) library applet
+    #[inline]
+    pub fn pop_interactive_out_data_storage(&mut self) -> Result<Storage> {
+        self.accessor.pop_interactive_out_data()
+    }
+
+    /// Gets the event signaled when the library applet pushes interactive output data
+    ///
+    /// The returned handle must be closed by the caller
+    #[inline]
+    pub fn get_pop_interactive_out_data_event(&mut self) -> Result<svc::Handle> {
+        Ok(self.accessor.get_pop_interactive_out_data_event()?.handle)
+    }
 }
 
 impl Drop for LibraryAppletHolder {
@@ -196,7 +236,7 @@ pub fn create_write_storage<T: Copy>(t: T) -> Result<Storage> {
 
     let mut storage = get_creator()
         .as_ref()
-        .ok_or(rc::ResultNotInitialized::make())?
+        .ok_or(sm_rc::ResultNotInitialized::make())?
         .create_storage(cmem::size_of::<T>())?;
     write_storage(&mut storage, t)?;
 
@@ -221,7 +261,7 @@ pub fn create_library_applet(
 
     let accessor = get_creator()
         .as_ref()
-        .ok_or(rc::ResultNotInitialized::make())?
+        .ok_or(sm_rc::ResultNotInitialized::make())?
         .create_library_applet(id, mode)?;
 
     let mut holder = LibraryAppletHolder::new(Box::new(accessor))?;
@@ -257,4 +297,4 @@ pub fn launch_wait_library_applet<I: Copy, O: Copy>(
     holder.pop_out_data()
 }
 
-// TODO: specific library applet implementations in submodules (err, psel, swkbd, etc.)
+// TODO: more specific library applet implementations in submodules (controller, mii edit, etc.)
@@ -0,0 +1,43 @@
+//! Photo viewer (album) library applet support
+
+use super::*;
+
+/// The applet API version used for the photo viewer applet
+const API_VERSION: u32 = 0x10000;
+
+/// Represents what the photo viewer applet shows
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u8)]
+pub enum ShowKind {
+    /// The screenshots/videos of the current application
+    #[default]
+    AlbumFiles = 0,
+    /// All the screenshots/videos
+    AllAlbumFiles = 1,
+    /// All the screenshots/videos, as the home menu shows them
+    AllAlbumFilesForHomeMenu = 2,
+}
+
+/// Launches the photo viewer applet and waits for it to exit
+///
+/// # Arguments
+///
+/// * `kind`: What the applet shows
+pub fn show(kind: ShowKind) -> Result<()> {
+    let common_args = CommonArguments {
+        version: 1,
+        size: cmem::size_of::<CommonArguments>() as u32,
+        la_api_version: API_VERSION,
+        play_startup_sound: kind == ShowKind::AllAlbumFilesForHomeMenu,
+        ..Default::default()
+    };
+
+    let mut holder = create_library_applet(
+        applet::AppletId::LibraryAppletPhotoViewer,
+        applet::LibraryAppletMode::AllForeground,
+        common_args,
+    )?;
+    holder.push_in_data(kind as u8)?;
+    holder.start()?;
+    holder.join(None)
+}
@@ -0,0 +1,272 @@
+//! Error library applet support
+//!
+//! This allows showing the system's error dialogs, either for a [`ResultCode`] (see [`show_result`]) or with custom messages (see [`SystemErrorBuilder`]):
+//!
+//! ```ignore
+//! if let Err(rc) = load_save() {
+//!     nx::la::error::show_result(rc, true)?;
+//! }
+//!
+//! nx::la::error::SystemErrorBuilder::new("Could not load the save", "The save data is corrupted...")
+//!     .result(rc)
+//!     .show()?;
+//! ```
+
+use super::*;
+use crate::util;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+
+/// The applet API version used for the error applet
+const API_VERSION: u32 = 1;
+
+/// The maximum length (in bytes, including the NUL terminator) of the custom messages
+pub const MESSAGE_MAX_SIZE: usize = 0x800;
+
+/// Represents the error applet argument types
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u8)]
+pub enum ErrorType {
+    #[default]
+    Normal = 0,
+    System = 1,
+    Application = 2,
+    Eula = 3,
+    ParentalControl = 4,
+    Record = 5,
+    SystemUpdateEula = 8,
+}
+
+/// Represents an error code, as shown by the error applet (`2XXX-YYYY`)
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct ErrorCode {
+    /// The error category (`2XXX`)
+    pub category: u32,
+    /// The error number (`YYYY`)
+    pub number: u32,
+}
+
+impl ErrorCode {
+    /// Creates a new [`ErrorCode`]
+    ///
+    /// # Arguments
+    ///
+    /// * `category`: The error category
+    /// * `number`: The error number
+    #[inline]
+    pub const fn new(category: u32, number: u32) -> Self {
+        Self { category, number }
+    }
+
+    /// Creates the [`ErrorCode`] corresponding to a [`ResultCode`], as the system does
+    ///
+    /// # Arguments
+    ///
+    /// * `rc`: The [`ResultCode`] to use
+    #[inline]
+    pub const fn from_result(rc: ResultCode) -> Self {
+        Self::new(2000 + rc.get_module(), rc.get_description())
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{:04}-{:04}", self.category, self.number)
+    }
+}
+
+/// Represents the header common to all error applet arguments
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct CommonHeader {
+    /// The argument type
+    pub error_type: ErrorType,
+    /// Whether the applet may jump to the error's help page (otherwise it's shown "without jump")
+    pub jump: bool,
+    /// Unknown bytes
+    pub unk: [u8; 3],
+    /// Whether an error context storage is pushed after the arguments
+    pub context_flag: bool,
+    /// Whether the applet generates the error code from the [`ResultCode`] (with [`ErrorType::Normal`]), instead of using the given [`ErrorCode`]
+    pub result_flag: bool,
+    /// Whether an error context storage is pushed after the arguments (with [`ErrorType::Normal`])
+    pub context_flag_2: bool,
+}
+const_assert!(cmem::size_of::<CommonHeader>() == 0x8);
+
+/// Represents the arguments for showing an error code/[`ResultCode`] (with [`ErrorType::Normal`])
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct CommonArgument {
+    /// The common header
+    pub header: CommonHeader,
+    /// The error code, used unless the header's result flag is set
+    pub error_code: ErrorCode,
+    /// The [`ResultCode`], used if the header's result flag is set
+    pub rc: ResultCode,
+}
+const_assert!(cmem::size_of::<CommonArgument>() == 0x14);
+
+/// Represents the arguments for showing an error with custom messages (with [`ErrorType::System`])
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct SystemArgument {
+    /// The common header
+    pub header: CommonHeader,
+    /// The error code
+    pub error_code: ErrorCode,
+    /// The language code of the messages
+    pub language_code: u64,
+    /// The (NUL-terminated UTF-8) message of the error dialog
+    pub dialog_message: util::ArrayString<MESSAGE_MAX_SIZE>,
+    /// The (NUL-terminated UTF-8) message of the details page
+    pub fullscreen_message: util::ArrayString<MESSAGE_MAX_SIZE>,
+}
+const_assert!(cmem::size_of::<SystemArgument>() == 0x1018);
+
+/// Launches the error applet with the given arguments and waits for it to exit
+///
+/// # Arguments
+///
+/// * `args`: The arguments to push
+pub fn show_raw<T: Copy>(args: T) -> Result<()> {
+    let common_args = CommonArguments {
+        version: 1,
+        size: cmem::size_of::<CommonArguments>() as u32,
+        la_api_version: API_VERSION,
+        ..Default::default()
+    };
+
+    let mut holder = create_library_applet(
+        applet::AppletId::LibraryAppletError,
+        applet::LibraryAppletMode::AllForeground,
+        common_args,
+    )?;
+    holder.push_in_data(args)?;
+    holder.start()?;
+    holder.join(None)
+}
+
+/// Shows the error dialog for a [`ResultCode`], and waits until the user closes it
+///
+/// The applet shows the [`ErrorCode`] corresponding to the [`ResultCode`] along with the system's description for it, if any
+///
+/// # Arguments
+///
+/// * `rc`: The [`ResultCode`] to show
+/// * `jump`: Whether the dialog allows jumping to the error's help page
+pub fn show_result(rc: ResultCode, jump: bool) -> Result<()> {
+    show_raw(CommonArgument {
+        header: CommonHeader {
+            error_type: ErrorType::Normal,
+            jump,
+            result_flag: true,
+            ..Default::default()
+        },
+        error_code: ErrorCode::default(),
+        rc,
+    })
+}
+
+/// Shows the error dialog for an [`ErrorCode`], and waits until the user closes it
+///
+/// # Arguments
+///
+/// * `error_code`: The [`ErrorCode`] to show
+/// * `jump`: Whether the dialog allows jumping to the error's help page
+pub fn show_error_code(error_code: ErrorCode, jump: bool) -> Result<()> {
+    show_raw(CommonArgument {
+        header: CommonHeader {
+            error_type: ErrorType::Normal,
+            jump,
+            ..Default::default()
+        },
+        error_code,
+        rc: ResultSuccess::make(),
+    })
+}
+
+/// Represents a builder for showing an error dialog with custom messages
+///
+/// The dialog shows the short message and the error code, with a "Details" button leading to a page with the long message
+#[derive(Copy, Clone)]
+#[must_use = "must eventually show the error"]
+pub struct SystemErrorBuilder {
+    args: SystemArgument,
+}
+
+impl SystemErrorBuilder {
+    /// Creates a new [`SystemErrorBuilder`]
+    ///
+    /// Messages longer than [`MESSAGE_MAX_SIZE`] (minus the NUL terminator) are truncated
+    ///
+    /// # Arguments
+    ///
+    /// * `dialog_message`: The message of the error dialog
+    /// * `fullscreen_message`: The message of the details page
+    pub fn new(dialog_message: &str, fullscreen_message: &str) -> Self {
+        Self {
+            args: SystemArgument {
+                header: CommonHeader {
+                    error_type: ErrorType::System,
+                    jump: true,
+                    ..Default::default()
+                },
+                error_code: ErrorCode::default(),
+                language_code: 0,
+                dialog_message: util::ArrayString::from_str_truncate_null(dialog_message),
+                fullscreen_message: util::ArrayString::from_str_truncate_null(fullscreen_message),
+            },
+        }
+    }
+
+    /// Sets the [`ErrorCode`] shown along with the messages
+    ///
+    /// # Arguments
+    ///
+    /// * `error_code`: The [`ErrorCode`] to show
+    pub fn error_code(mut self, error_code: ErrorCode) -> Self {
+        self.args.error_code = error_code;
+        self
+    }
+
+    /// Sets the [`ErrorCode`] shown along with the messages from a [`ResultCode`]
+    ///
+    /// # Arguments
+    ///
+    /// * `rc`: The [`ResultCode`] whose [`ErrorCode`] to show
+    pub fn result(self, rc: ResultCode) -> Self {
+        self.error_code(ErrorCode::from_result(rc))
+    }
+
+    /// Sets the language code of the messages
+    ///
+    /// # Arguments
+    ///
+    /// * `language_code`: The language code to set
+    pub fn language_code(mut self, language_code: u64) -> Self {
+        self.args.language_code = language_code;
+        self
+    }
+
+    /// Sets whether the dialog allows jumping to the error's help page
+    ///
+    /// # Arguments
+    ///
+    /// * `jump`: Whether jumping is allowed
+    pub fn jump(mut self, jump: bool) -> Self {
+        self.args.header.jump = jump;
+        self
+    }
+
+    /// Gets the raw arguments
+    #[inline]
+    pub fn get_args(&self) -> &SystemArgument {
+        &self.args
+    }
+
+    /// Shows the error dialog, and waits until the user closes it
+    pub fn show(&self) -> Result<()> {
+        show_raw(self.args)
+    }
+}
@@ -0,0 +1,180 @@
+//! Player select library applet support
+//!
+//! ```ignore
+//! let uid = nx::la::psel::Builder::new().skip_if_single_user(true).show()?;
+//! ```
+
+use super::*;
+use crate::ipc::sf::account::{USER_COUNT_MAX, Uid};
+
+/// The applet API version used for the player select applet
+///
+/// Later applet versions take extended settings, but the applet keeps accepting this layout for compatibility with older software
+const API_VERSION: u32 = 0;
+
+/// Represents the player select applet modes
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u32)]
+pub enum UiMode {
+    /// Select a user account
+    #[default]
+    UserSelector = 0,
+    /// Create a new user account
+    UserCreator = 1,
+    /// Ensure the user account has a network service account linked
+    EnsureNetworkServiceAccountAvailable = 2,
+    /// Edit the user account's icon
+    UserIconEditor = 3,
+    /// Edit the user account's nickname
+    UserNicknameEditor = 4,
+}
+
+/// Represents the user selection settings
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct UserSelectionSettings {
+    /// The user accounts which can't be selected (invalid [`Uid`]s are ignored)
+    pub invalid_uids: [Uid; USER_COUNT_MAX],
+    /// Whether the selected user account must have a network service account linked
+    pub is_network_service_account_required: bool,
+    /// Whether the applet is skipped (selecting the only user account) if there's a single user account
+    pub is_skip_enabled: bool,
+    /// Unknown/reserved bytes
+    pub unk: [u8; 0x16],
+}
+const_assert!(cmem::size_of::<UserSelectionSettings>() == 0x98);
+
+/// Represents the player select applet settings
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+pub struct UiSettings {
+    /// The applet mode
+    pub mode: UiMode,
+    /// Padding bytes
+    pub pad: [u8; 4],
+    /// The user selection settings
+    pub settings: UserSelectionSettings,
+}
+const_assert!(cmem::size_of::<UiSettings>() == 0xA0);
+
+/// Represents the output layout of the player select applet
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Output {
+    result: u64,
+    uid: Uid,
+}
+const_assert!(cmem::size_of::<Output>() == 0x18);
+
+/// Represents a builder for configuring and showing the player select applet
+#[derive(Copy, Clone, Debug, Default)]
+#[must_use = "must eventually show the applet"]
+pub struct Builder {
+    settings: UiSettings,
+}
+
+impl Builder {
+    /// Creates a new [`Builder`] for selecting a user account
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new [`Builder`] from raw settings
+    ///
+    /// # Arguments
+    ///
+    /// * `settings`: The settings to use
+    pub fn from_settings(settings: UiSettings) -> Self {
+        Self { settings }
+    }
+
+    /// Sets the applet mode
+    ///
+    /// # Arguments
+    ///
+    /// * `mode`: The mode to set
+    pub fn mode(mut self, mode: UiMode) -> Self {
+        self.settings.mode = mode;
+        self
+    }
+
+    /// Prevents a user account from being selected
+    ///
+    /// At most [`USER_COUNT_MAX`] user accounts can be invalidated, further ones are ignored
+    ///
+    /// # Arguments
+    ///
+    /// * `uid`: The [`Uid`] of the user account
+    pub fn invalid_user(mut self, uid: Uid) -> Self {
+        if let Some(slot) = self
+            .settings
+            .settings
+            .invalid_uids
+            .iter_mut()
+            .find(|slot| !slot.is_valid())
+        {
+            *slot = uid;
+        }
+        self
+    }
+
+    /// Sets whether the selected user account must have a network service account linked
+    ///
+    /// # Arguments
+    ///
+    /// * `required`: Whether the network service account is required
+    pub fn network_service_account_required(mut self, required: bool) -> Self {
+        self.settings.settings.is_network_service_account_required = required;
+        self
+    }
+
+    /// Sets whether the applet is skipped if there's a single user account, selecting it directly
+    ///
+    /// # Arguments
+    ///
+    /// * `skip`: Whether the applet is skipped
+    pub fn skip_if_single_user(mut self, skip: bool) -> Self {
+        self.settings.settings.is_skip_enabled = skip;
+        self
+    }
+
+    /// Gets the raw settings
+    #[inline]
+    pub fn get_settings(&self) -> &UiSettings {
+        &self.settings
+    }
+
+    /// Launches the applet and waits for the user to finish, returning the selected user account
+    ///
+    /// This will fail with [`ResultCancelled`][`rc::ResultCancelled`] if the user cancelled the selection
+    pub fn show(&self) -> Result<Uid> {
+        let common_args = CommonArguments {
+            version: 1,
+            size: cmem::size_of::<CommonArguments>() as u32,
+            la_api_version: API_VERSION,
+            ..Default::default()
+        };
+
+        let mut holder = create_library_applet(
+            applet::AppletId::LibraryAppletPlayerSelect,
+            applet::LibraryAppletMode::AllForeground,
+            common_args,
+        )?;
+        holder.push_in_data(self.settings)?;
+        holder.start()?;
+        holder.join(None)?;
+        let output: Output = holder.pop_out_data()?;
+
+        result_return_unless!(output.result == 0, rc::ResultCancelled);
+        result_return_unless!(output.uid.is_valid(), rc::ResultInvalidOutputData);
+        Ok(output.uid)
+    }
+}
+
+/// Launches the player select applet with the default settings and waits for the user to select a user account
+///
+/// This is a wrapper for [`Builder::show`]
+#[inline]
+pub fn select_user() -> Result<Uid> {
+    Builder::new().show()
+}
@@ -0,0 +1,1041 @@
+//! Software keyboard (swkbd) library applet support
+//!
+//! The regular keyboard is launched, shown and waited for with a [`Builder`]:
+//!
+//! ```ignore
+//! let name = nx::la::swkbd::Builder::new()
+//!     .header_text("Enter your name")
+//!     .guide_text("Name")
+//!     .max_length(16)
+//!     .show()?;
+//! ```
+//!
+//! The inline keyboard ([`InlineKeyboard`]) instead runs in the background while the caller keeps rendering, and must be periodically updated.
+
+use super::*;
+use crate::mem::alloc::{Buffer, PAGE_ALIGNMENT};
+use crate::mem::wait_for_permission;
+use crate::svc::MemoryPermission;
+use crate::util;
+use crate::version;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Represents the keyboard type/layout
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u32)]
+pub enum KeyboardMode {
+    /// The full keyboard, with the system's language layout
+    #[default]
+    Normal = 0,
+    /// A numeric keypad
+    NumPad = 1,
+    /// A QWERTY keyboard (only allowing ASCII characters)
+    Qwerty = 2,
+    /// A Latin keyboard
+    Latin = 4,
+    /// A Simplified Chinese keyboard
+    SimplifiedChinese = 5,
+    /// A Traditional Chinese keyboard
+    TraditionalChinese = 6,
+    /// A Korean keyboard
+    Korean = 7,
+    /// All the keyboard types, switchable by the user
+    All = 8,
+}
+
+define_bit_set! {
+    /// Represents the characters which can't be entered (their keys are disabled)
+    InvalidCharFlags (u32) {
+        Space = bit!(1),
+        AtMark = bit!(2),
+        Percent = bit!(3),
+        Slash = bit!(4),
+        BackSlash = bit!(5),
+        Numbers = bit!(6),
+        OutsideOfDownloadCode = bit!(7),
+        OutsideOfMiiNickName = bit!(8)
+    }
+}
+
+/// Represents the initial position of the text cursor
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u32)]
+pub enum InitialCursorPosition {
+    /// The cursor starts at the beginning of the text
+    Start = 0,
+    /// The cursor starts at the end of the text
+    #[default]
+    End = 1,
+}
+
+/// Represents whether the entered text is shown
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u32)]
+pub enum PasswordMode {
+    /// The text is shown
+    #[default]
+    Show = 0,
+    /// The text is hidden, as with passwords
+    Hide = 1,
+}
+
+/// Represents how the text input field is drawn
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u32)]
+pub enum InputFormMode {
+    /// A single-line field
+    #[default]
+    OneLine = 0,
+    /// A multi-line box
+    MultiLine = 1,
+    /// Separate boxes for each character, as used for download codes
+    Separate = 2,
+}
+
+/// The maximum length (in UTF-16 units) of the output text
+pub const TEXT_MAX_LENGTH: usize = 500;
+
+/// The size of the work buffer shared with the applet (containing the initial text)
+const WORK_BUFFER_SIZE: usize = 0x1000;
+
+/// The applet API version used in 3.0.0+, which accepts [`Config`]
+const API_VERSION: u32 = 0x30007;
+
+/// The applet API version used before 3.0.0, which accepts [`ConfigV0`]
+const API_VERSION_V0: u32 = 0x5;
+
+/// Represents the common keyboard configuration layout, shared by all applet API versions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct ConfigCommon {
+    /// The keyboard type/layout
+    pub mode: KeyboardMode,
+    /// The (NUL-terminated UTF-16) text of the OK button
+    pub ok_text: [u16; 9],
+    /// The (UTF-16) character of the left optional symbol key, only available with [`KeyboardMode::NumPad`]
+    pub left_optional_symbol_key: u16,
+    /// The (UTF-16) character of the right optional symbol key, only available with [`KeyboardMode::NumPad`]
+    pub right_optional_symbol_key: u16,
+    /// Whether word prediction (dictionary usage) is enabled
+    pub enable_prediction: bool,
+    /// Padding bytes
+    pub pad: u8,
+    /// The characters which can't be entered
+    pub invalid_char_flags: InvalidCharFlags,
+    /// The initial position of the text cursor
+    pub initial_cursor_position: InitialCursorPosition,
+    /// The (NUL-terminated UTF-16) header text
+    pub header_text: [u16; 65],
+    /// The (NUL-terminated UTF-16) text below the header
+    pub sub_text: [u16; 129],
+    /// The (NUL-terminated UTF-16) text shown in the input field while empty
+    pub guide_text: [u16; 257],
+    /// Padding bytes
+    pub pad_2: u16,
+    /// The maximum text length, zero meaning no limit
+    pub max_length: u32,
+    /// The minimum text length, zero meaning no limit
+    pub min_length: u32,
+    /// Whether the entered text is shown
+    pub password_mode: PasswordMode,
+    /// How the text input field is drawn
+    pub input_form_mode: InputFormMode,
+    /// Whether new lines can be entered
+    pub enable_new_line: bool,
+    /// Whether the output text is UTF-8 instead of UTF-16
+    pub enable_utf8: bool,
+    /// Whether the caller's screen is blurred behind the keyboard
+    pub enable_blur_background: bool,
+    /// Padding bytes
+    pub pad_3: u8,
+    /// The offset of the initial text in the work buffer
+    pub initial_text_offset: u32,
+    /// The length (in UTF-16 units) of the initial text in the work buffer
+    pub initial_text_length: u32,
+    /// The offset of the user dictionary in the work buffer
+    pub user_dictionary_offset: u32,
+    /// The number of user dictionary words in the work buffer
+    pub user_dictionary_count: u32,
+    /// Whether the text is sent to the caller to be checked before accepting it
+    pub enable_text_check: bool,
+    /// Padding bytes
+    pub pad_4: [u8; 7],
+}
+const_assert!(cmem::size_of::<ConfigCommon>() == 0x3D8);
+
+impl ConfigCommon {
+    /// Creates a new [`ConfigCommon`] with all the values set to zero/default
+    pub const fn new() -> Self {
+        Self {
+            mode: KeyboardMode::Normal,
+            ok_text: [0; 9],
+            left_optional_symbol_key: 0,
+            right_optional_symbol_key: 0,
+            enable_prediction: false,
+            pad: 0,
+            invalid_char_flags: InvalidCharFlags::from(0),
+            initial_cursor_position: InitialCursorPosition::Start,
+            header_text: [0; 65],
+            sub_text: [0; 129],
+            guide_text: [0; 257],
+            pad_2: 0,
+            max_length: 0,
+            min_length: 0,
+            password_mode: PasswordMode::Show,
+            input_form_mode: InputFormMode::OneLine,
+            enable_new_line: false,
+            enable_utf8: false,
+            enable_blur_background: false,
+            pad_3: 0,
+            initial_text_offset: 0,
+            initial_text_length: 0,
+            user_dictionary_offset: 0,
+            user_dictionary_count: 0,
+            enable_text_check: false,
+            pad_4: [0; 7],
+        }
+    }
+}
+
+impl Default for ConfigCommon {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Represents the keyboard configuration layout used before 3.0.0
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct ConfigV0 {
+    /// The common configuration
+    pub common: ConfigCommon,
+    /// The text check callback (unused by the applet)
+    pub text_check_callback: u64,
+}
+const_assert!(cmem::size_of::<ConfigV0>() == 0x3E0);
+
+/// Represents the keyboard configuration layout used in 3.0.0+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct Config {
+    /// The common configuration
+    pub common: ConfigCommon,
+    /// The text check callback (unused by the applet)
+    pub text_check_callback: u64,
+    /// The positions where the text is split into groups, used with [`InputFormMode::Separate`]
+    pub text_grouping: [u32; 8],
+}
+const_assert!(cmem::size_of::<Config>() == 0x400);
+
+/// Represents the output layout of the (regular) keyboard
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Output {
+    close_result: u32,
+    text: [u16; 0x3EA],
+}
+const_assert!(cmem::size_of::<Output>() == 0x7D8);
+
+/// Writes a string as NUL-terminated UTF-16 into a buffer, truncating it if needed, and returns the amount of units written (excluding the NUL terminator)
+fn write_utf16(buf: &mut [u16], string: &str) -> usize {
+    buf.fill(0);
+    let max_len = buf.len().saturating_sub(1);
+    let mut len = 0;
+    let mut char_buf = [0u16; 2];
+    for ch in string.chars() {
+        let encoded = ch.encode_utf16(&mut char_buf);
+        if (len + encoded.len()) > max_len {
+            break;
+        }
+        buf[len..len + encoded.len()].copy_from_slice(encoded);
+        len += encoded.len();
+    }
+    len
+}
+
+/// Reads a (possibly NUL-terminated) UTF-16 string from a buffer
+fn read_utf16(buf: &[u16]) -> Result<String> {
+    let len = buf.iter().position(|&unit| unit == 0).unwrap_or(buf.len());
+    String::from_utf16(&buf[..len]).map_err(|_| util::rc::ResultInvalidUtf16Conversion::make())
+}
+
+/// Reads a (possibly NUL-terminated) little-endian UTF-16 string from raw bytes
+fn read_utf16_bytes(buf: &[u8]) -> Result<String> {
+    let units: Vec<u16> = buf
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .collect();
+    read_utf16(&units)
+}
+
+/// Represents a page-aligned buffer shared with the applet as transfer memory
+struct WorkBuffer {
+    buffer: Buffer<u8>,
+    handle: svc::Handle,
+}
+
+impl WorkBuffer {
+    fn new(initial_text: &str) -> Result<(Self, usize)> {
+        let buffer = Buffer::<u8>::new(PAGE_ALIGNMENT, WORK_BUFFER_SIZE)?;
+        // SAFETY: the buffer was just allocated with the size we're using
+        let units = unsafe {
+            core::ptr::write_bytes(buffer.ptr, 0, WORK_BUFFER_SIZE);
+            core::slice::from_raw_parts_mut(buffer.ptr as *mut u16, TEXT_MAX_LENGTH + 1)
+        };
+        let initial_text_len = write_utf16(units, initial_text);
+
+        let handle =
+            svc::create_transfer_memory(buffer.ptr, WORK_BUFFER_SIZE, MemoryPermission::None())?;
+        Ok((Self { buffer, handle }, initial_text_len))
+    }
+
+    fn create_storage(&self) -> Result<Storage> {
+        get_creator()
+            .as_ref()
+            .ok_or(crate::rc::ResultNotInitialized::make())?
+            .create_transfer_memory_storage(
+                sf::CopyHandle::from(self.handle),
+                false,
+                WORK_BUFFER_SIZE,
+            )
+    }
+}
+
+impl Drop for WorkBuffer {
+    fn drop(&mut self) {
+        let _ = svc::close_handle(self.handle);
+        // Don't free the memory until the applet is done with it
+        if wait_for_permission(self.buffer.ptr, MemoryPermission::Write(), None).is_err() {
+            cmem::forget(cmem::replace(&mut self.buffer, Buffer::empty()));
+        }
+    }
+}
+
+/// Represents a builder for configuring and showing the (regular) software keyboard
+///
+/// The defaults are a QWERTY keyboard with a blurred background, the cursor at the end of the initial text and no length limits
+#[derive(Clone, Debug)]
+#[must_use = "must eventually show the keyboard"]
+pub struct Builder {
+    config: ConfigCommon,
+    initial_text: String,
+}
+
+impl Builder {
+    /// Creates a new [`Builder`] with the default configuration
+    pub fn new() -> Self {
+        let mut config = ConfigCommon::new();
+        config.mode = KeyboardMode::Qwerty;
+        config.initial_cursor_position = InitialCursorPosition::End;
+        config.enable_blur_background = true;
+
+        Self {
+            config,
+            initial_text: String::new(),
+        }
+    }
+
+    /// Creates a new [`Builder`] from a raw configuration
+    ///
+    /// Note that the initial text and user dictionary offsets/lengths are always overwritten when showing the keyboard
+    ///
+    /// # Arguments
+    ///
+    /// * `config`: The configuration to use
+    pub fn from_config(config: ConfigCommon) -> Self {
+        Self {
+            config,
+            initial_text: String::new(),
+        }
+    }
+
+    /// Sets the keyboard type/layout
+    ///
+    /// # Arguments
+    ///
+    /// * `mode`: The keyboard type/layout
+    pub fn mode(mut self, mode: KeyboardMode) -> Self {
+        self.config.mode = mode;
+        self
+    }
+
+    /// Sets the text of the OK button
+    ///
+    /// # Arguments
+    ///
+    /// * `text`: The text to set
+    pub fn ok_text(mut self, text: &str) -> Self {
+        write_utf16(&mut self.config.ok_text, text);
+        self
+    }
+
+    /// Sets the header text
+    ///
+    /// # Arguments
+    ///
+    /// * `text`: The text to set
+    pub fn header_text(mut self, text: &str) -> Self {
+        write_utf16(&mut self.config.header_text, text);
+        self
+    }
+
+    /// Sets the text below the header
+    ///
+    /// # Arguments
+    ///
+    /// * `text`: The text to set
+    pub fn sub_text(mut self, text: &str) -> Self {
+        write_utf16(&mut self.config.sub_text, text);
+        self
+    }
+
+    /// Sets the text shown in the input field while it's empty
+    ///
+    /// # Arguments
+    ///
+    /// * `text`: The text to set
+    pub fn guide_text(mut self, text: &str) -> Self {
+        write_utf16(&mut self.config.guide_text, text);
+        self
+    }
+
+    /// Sets the text the input field starts with
+    ///
+    /// At most [`TEXT_MAX_LENGTH`] UTF-16 units are used
+    ///
+    /// # Arguments
+    ///
+    /// * `text`: The text to set
+    pub fn initial_text(mut self, text: &str) -> Self {
+        self.initial_text = String::from(text);
+        self
+    }
+
+    /// Sets the initial position of the text cursor
+    ///
+    /// # Arguments
+    ///
+    /// * `position`: The position to set
+    pub fn initial_cursor_position(mut self, position: InitialCursorPosition) -> Self {
+        self.config.initial_cursor_position = position;
+        self
+    }
+
+    /// Sets the minimum text length, the OK button being disabled until it's reached
+    ///
+    /// # Arguments
+    ///
+    /// * `length`: The length to set, zero meaning no limit
+    pub fn min_length(mut self, length: u32) -> Self {
+        self.config.min_length = length;
+        self
+    }
+
+    /// Sets the maximum text length, no more characters being accepted once it's reached
+    ///
+    /// # Arguments
+    ///
+    /// * `length`: The length to set, zero meaning no limit
+    pub fn max_length(mut self, length: u32) -> Self {
+        self.config.max_length = length;
+        self
+    }
+
+    /// Sets whether the entered text is hidden, as with passwords
+    ///
+    /// # Arguments
+    ///
+    /// * `password`: Whether the text is hidden
+    pub fn password(mut self, password: bool) -> Self {
+        self.config.password_mode = match password {
+            true => PasswordMode::Hide,
+            false => PasswordMode::Show,
+        };
+        self
+    }
+
+    /// Sets the characters which can't be entered
+    ///
+    /// # Arguments
+    ///
+    /// * `flags`: The characters to disable
+    pub fn invalid_chars(mut self, flags: InvalidCharFlags) -> Self {
+        self.config.invalid_char_flags = flags;
+        self
+    }
+
+    /// Sets how the text input field is drawn
+    ///
+    /// # Arguments
+    ///
+    /// * `mode`: The mode to set
+    pub fn input_form_mode(mut self, mode: InputFormMode) -> Self {
+        self.config.input_form_mode = mode;
+        self
+    }
+
+    /// Sets the characters of the optional symbol keys, only available with [`KeyboardMode::NumPad`]
+    ///
+    /// # Arguments
+    ///
+    /// * `left`: The left key character, if any
+    /// * `right`: The right key character, if any
+    pub fn optional_symbol_keys(mut self, left: Option<char>, right: Option<char>) -> Self {
+        let to_unit = |ch: Option<char>| {
+            ch.map(|ch| ch.encode_utf16(&mut [0; 2])[0])
+                .unwrap_or_default()
+        };
+        self.config.left_optional_symbol_key = to_unit(left);
+        self.config.right_optional_symbol_key = to_unit(right);
+        self
+    }
+
+    /// Sets whether new lines can be entered
+    ///
+    /// # Arguments
+    ///
+    /// * `enable`: Whether new lines are enabled
+    pub fn new_line(mut self, enable: bool) -> Self {
+        self.config.enable_new_line = enable;
+        self
+    }
+
+    /// Sets whether word prediction is enabled
+    ///
+    /// # Arguments
+    ///
+    /// * `enable`: Whether prediction is enabled
+    pub fn prediction(mut self, enable: bool) -> Self {
+        self.config.enable_prediction = enable;
+        self
+    }
+
+    /// Sets whether the caller's screen is blurred behind the keyboard
+    ///
+    /// # Arguments
+    ///
+    /// * `enable`: Whether the background is blurred
+    pub fn blur_background(mut self, enable: bool) -> Self {
+        self.config.enable_blur_background = enable;
+        self
+    }
+
+    /// Gets the raw configuration
+    #[inline]
+    pub fn get_config(&self) -> &ConfigCommon {
+        &self.config
+    }
+
+    /// Launches the keyboard and waits for the user to finish, returning the entered text
+    ///
+    /// This will fail with [`ResultCancelled`][`rc::ResultCancelled`] if the user cancelled the input
+    pub fn show(&self) -> Result<String> {
+        let (work_buffer, initial_text_len) = WorkBuffer::new(&self.initial_text)?;
+
+        let mut config = self.config;
+        config.enable_utf8 = false;
+        config.enable_text_check = false;
+        config.initial_text_offset = 0;
+        config.initial_text_length = initial_text_len as u32;
+        config.user_dictionary_offset = 0;
+        config.user_dictionary_count = 0;
+
+        let new_api = version::get_version() >= version::Version::new(3, 0, 0);
+        let common_args = CommonArguments {
+            version: 1,
+            size: cmem::size_of::<CommonArguments>() as u32,
+            la_api_version: if new_api { API_VERSION } else { API_VERSION_V0 },
+            ..Default::default()
+        };
+
+        let output = {
+            // The holder must be gone (thus the applet done with the work buffer) before the work buffer is dropped
+            let mut holder = create_library_applet(
+                applet::AppletId::LibraryAppletSwkbd,
+                applet::LibraryAppletMode::AllForeground,
+                common_args,
+            )?;
+            if new_api {
+                holder.push_in_data(Config {
+                    common: config,
+                    text_check_callback: 0,
+                    text_grouping: [0; 8],
+                })?;
+            } else {
+                holder.push_in_data(ConfigV0 {
+                    common: config,
+                    text_check_callback: 0,
+                })?;
+            }
+            holder.push_in_data_storage(work_buffer.create_storage()?)?;
+            holder.start()?;
+            holder.join(None)?;
+            holder.pop_out_data::<Output>()?
+        };
+        drop(work_buffer);
+
+        result_return_unless!(output.close_result == 0, rc::ResultCancelled);
+        read_utf16(&output.text)
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Represents the state of the [`InlineKeyboard`], as reported by the applet
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u32)]
+pub enum InlineState {
+    /// The applet didn't report anything yet, or isn't running anymore
+    #[default]
+    Inactive = 0,
+    /// The applet is initialized, but the keyboard isn't shown
+    Initialized = 1,
+    /// The keyboard is appearing/disappearing
+    Transition = 2,
+    /// The keyboard is shown and the user is entering text
+    Shown = 3,
+    /// The user submitted or cancelled the text, and the keyboard is disappearing
+    Decided = 4,
+    /// The keyboard is disappearing after being hidden
+    Hiding = 5,
+}
+
+impl InlineState {
+    const fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            0 => Some(Self::Inactive),
+            1 => Some(Self::Initialized),
+            2 => Some(Self::Transition),
+            3 => Some(Self::Shown),
+            4 => Some(Self::Decided),
+            5 => Some(Self::Hiding),
+            _ => None,
+        }
+    }
+}
+
+/// Represents the configuration the [`InlineKeyboard`] appears with
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct InlineAppearArguments {
+    /// The keyboard type/layout
+    pub mode: KeyboardMode,
+    /// The (NUL-terminated UTF-16) text of the OK button
+    pub ok_text: [u16; 9],
+    /// The (UTF-16) character of the left optional symbol key, only available with [`KeyboardMode::NumPad`]
+    pub left_optional_symbol_key: u16,
+    /// The (UTF-16) character of the right optional symbol key, only available with [`KeyboardMode::NumPad`]
+    pub right_optional_symbol_key: u16,
+    /// Whether word prediction is enabled
+    pub enable_prediction: bool,
+    /// Whether the cancel button is disabled
+    pub disable_cancel_button: bool,
+    /// The characters which can't be entered
+    pub invalid_char_flags: InvalidCharFlags,
+    /// The maximum text length, `-1` meaning no limit
+    pub max_length: i32,
+    /// The minimum text length, `-1` meaning no limit
+    pub min_length: i32,
+    /// Whether new lines can be entered
+    pub enable_new_line: bool,
+    /// Reserved bytes
+    pub reserved: [u8; 3],
+    /// Appear flags
+    pub flags: u32,
+    /// Reserved bytes
+    pub reserved_2: [u8; 0x18],
+}
+const_assert!(cmem::size_of::<InlineAppearArguments>() == 0x48);
+
+impl InlineAppearArguments {
+    /// Creates a new [`InlineAppearArguments`] with the given keyboard type/layout and no length limits
+    ///
+    /// # Arguments
+    ///
+    /// * `mode`: The keyboard type/layout
+    pub const fn new(mode: KeyboardMode) -> Self {
+        Self {
+            mode,
+            ok_text: [0; 9],
+            left_optional_symbol_key: 0,
+            right_optional_symbol_key: 0,
+            enable_prediction: false,
+            disable_cancel_button: false,
+            invalid_char_flags: InvalidCharFlags::from(0),
+            max_length: -1,
+            min_length: -1,
+            enable_new_line: false,
+            reserved: [0; 3],
+            flags: 0,
+            reserved_2: [0; 0x18],
+        }
+    }
+
+    /// Sets the text of the OK button
+    ///
+    /// # Arguments
+    ///
+    /// * `text`: The text to set
+    pub fn set_ok_text(&mut self, text: &str) {
+        write_utf16(&mut self.ok_text, text);
+    }
+}
+
+impl Default for InlineAppearArguments {
+    fn default() -> Self {
+        Self::new(KeyboardMode::Qwerty)
+    }
+}
+
+define_bit_set! {
+    /// Represents which [`InlineCalcArguments`] fields are applied by the applet
+    InlineCalcFlags (u64) {
+        Initialize = bit!(0),
+        SetVolume = bit!(1),
+        Appear = bit!(2),
+        SetInputText = bit!(3),
+        SetCursorPosition = bit!(4),
+        SetUtf8Mode = bit!(5),
+        Disappear = bit!(7),
+        SetBackspaceEnabled = bit!(15)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(C)]
+struct InlineInitializeArguments {
+    unk_x0: u32,
+    mode: u8,
+    unk_x5: u8,
+    pad: [u8; 2],
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct InlineCalcArguments {
+    unk_x0: u32,
+    size: u16,
+    unk_x6: [u8; 2],
+    flags: InlineCalcFlags,
+    initialize_args: InlineInitializeArguments,
+    volume: f32,
+    cursor_position: i32,
+    appear_args: InlineAppearArguments,
+    input_text: [u16; 0x1FA],
+    enable_utf8: bool,
+    unk_x45d: u8,
+    enable_backspace: bool,
+    unk_x45f: [u8; 0x11],
+    keytop_scale: [f32; 2],
+    keytop_translate: [f32; 2],
+    keytop_bg_alpha: f32,
+    footer_bg_alpha: f32,
+    balloon_scale: f32,
+    unk_x48c: f32,
+    unk_x490: [u8; 0x10],
+}
+const_assert!(cmem::size_of::<InlineCalcArguments>() == 0x4A0);
+
+impl InlineCalcArguments {
+    const fn new() -> Self {
+        Self {
+            unk_x0: 0x30000,
+            size: cmem::size_of::<Self>() as u16,
+            unk_x6: [0; 2],
+            flags: InlineCalcFlags::Initialize(),
+            initialize_args: InlineInitializeArguments {
+                unk_x0: 0,
+                mode: 0,
+                unk_x5: 1,
+                pad: [0; 2],
+            },
+            volume: 1.0,
+            cursor_position: 0,
+            appear_args: InlineAppearArguments::new(KeyboardMode::Qwerty),
+            input_text: [0; 0x1FA],
+            enable_utf8: false,
+            unk_x45d: 0,
+            enable_backspace: true,
+            unk_x45f: [0; 0x11],
+            keytop_scale: [1.0; 2],
+            keytop_translate: [0.0; 2],
+            keytop_bg_alpha: 1.0,
+            footer_bg_alpha: 1.0,
+            balloon_scale: 1.0,
+            unk_x48c: 1.0,
+            unk_x490: [0; 0x10],
+        }
+    }
+}
+
+/// The applet API version used for the inline keyboard (5.0.0+)
+const INLINE_API_VERSION: u32 = 0x50009;
+
+/// The timeout for the inline keyboard applet to exit after being finalized
+const INLINE_FINALIZE_TIMEOUT: i64 = 1_000_000_000;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u32)]
+enum InlineRequestCommand {
+    Finalize = 0x4,
+    Calc = 0xA,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u32)]
+enum InlineReplyType {
+    FinishedInitialize = 0x0,
+    ChangedString = 0x2,
+    MovedCursor = 0x3,
+    DecidedEnter = 0x5,
+    DecidedCancel = 0x6,
+}
+
+/// The size of the UTF-16 text sent in replies
+const INLINE_REPLY_TEXT_SIZE: usize = 0x3FC;
+
+/// The maximum reply size we care about (header + text + cursor/length info)
+const INLINE_REPLY_MAX_SIZE: usize = 8 + INLINE_REPLY_TEXT_SIZE + 0x10;
+
+/// Represents the inline (in-process) software keyboard, which runs in the background over the caller's UI
+///
+/// It must be [`update`][`InlineKeyboard::update`]d periodically (usually once per frame) to send the changed configuration to the applet and process its replies
+///
+/// The applet is finalized when this gets dropped
+pub struct InlineKeyboard {
+    holder: LibraryAppletHolder,
+    reply_event_handle: svc::Handle,
+    calc_args: InlineCalcArguments,
+    state: InlineState,
+    text: String,
+    cursor_position: i32,
+}
+
+impl InlineKeyboard {
+    /// [5.0.0+] Launches the inline keyboard applet, initially hidden (see [`appear`][`InlineKeyboard::appear`])
+    pub fn new() -> Result<Self> {
+        result_return_unless!(
+            version::get_version() >= version::Version::new(5, 0, 0),
+            crate::rc::ResultNotSupported
+        );
+
+        let common_args = CommonArguments {
+            version: 1,
+            size: cmem::size_of::<CommonArguments>() as u32,
+            la_api_version: INLINE_API_VERSION,
+            ..Default::default()
+        };
+        let calc_args = InlineCalcArguments::new();
+
+        let mut holder = create_library_applet(
+            applet::AppletId::LibraryAppletSwkbd,
+            applet::LibraryAppletMode::BackgroundIndirectDisplay,
+            common_args,
+        )?;
+        holder.push_in_data(calc_args.initialize_args)?;
+        let reply_event_handle = holder.get_pop_interactive_out_data_event()?;
+        let mut keyboard = Self {
+            holder,
+            reply_event_handle,
+            calc_args,
+            state: InlineState::Inactive,
+            text: String::new(),
+            cursor_position: 0,
+        };
+        keyboard.holder.start()?;
+
+        Ok(keyboard)
+    }
+
+    fn push_request<T: Copy>(
+        &mut self,
+        command: InlineRequestCommand,
+        data: Option<&T>,
+    ) -> Result<()> {
+        let data_size = data.map(|_| cmem::size_of::<T>()).unwrap_or(0);
+        let storage = get_creator()
+            .as_ref()
+            .ok_or(crate::rc::ResultNotInitialized::make())?
+            .create_storage(cmem::size_of::<u32>() + data_size)?;
+        {
+            let storage_accessor = storage.open()?;
+            storage_accessor.write(0, sf::Buffer::from_other_var(&(command as u32)))?;
+            if let Some(data) = data {
+                storage_accessor.write(cmem::size_of::<u32>(), sf::Buffer::from_other_var(data))?;
+            }
+        }
+        self.holder.push_interactive_in_data_storage(storage)
+    }
+
+    fn handle_reply(&mut self, storage: &mut Storage) -> Result<Option<Result<String>>> {
+        let storage_accessor = storage.open()?;
+        let size = storage_accessor.get_size()?.min(INLINE_REPLY_MAX_SIZE);
+        result_return_if!(size < 8, rc::ResultInvalidOutputData);
+
+        let mut reply = [0u8; INLINE_REPLY_MAX_SIZE];
+        storage_accessor.read(0, sf::Buffer::from_mut_array(&mut reply[..size]))?;
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([
+                reply[offset],
+                reply[offset + 1],
+                reply[offset + 2],
+                reply[offset + 3],
+            ])
+        };
+
+        if let Some(state) = InlineState::from_raw(read_u32(0)) {
+            self.state = state;
+        }
+
+        let reply_type = read_u32(4);
+        let text_end = 8 + INLINE_REPLY_TEXT_SIZE;
+        if reply_type == InlineReplyType::FinishedInitialize as u32 {
+            Ok(None)
+        } else if reply_type == InlineReplyType::ChangedString as u32
+            || reply_type == InlineReplyType::MovedCursor as u32
+        {
+            // ChangedString: length, dictionary start/end cursor positions, cursor position
+            // MovedCursor: length, cursor position
+            let cursor_offset = match reply_type == InlineReplyType::ChangedString as u32 {
+                true => text_end + 0xC,
+                false => text_end + 0x4,
+            };
+            result_return_if!(size < cursor_offset + 4, rc::ResultInvalidOutputData);
+
+            self.text = read_utf16_bytes(&reply[8..text_end])?;
+            self.cursor_position = read_u32(cursor_offset) as i32;
+            Ok(None)
+        } else if reply_type == InlineReplyType::DecidedEnter as u32 {
+            result_return_if!(size < text_end, rc::ResultInvalidOutputData);
+
+            self.text = read_utf16_bytes(&reply[8..text_end])?;
+            Ok(Some(Ok(self.text.clone())))
+        } else if reply_type == InlineReplyType::DecidedCancel as u32 {
+            Ok(Some(rc::ResultCancelled::make_err()))
+        } else {
+            // Other replies (dictionary-related, etc.) aren't relevant here
+            Ok(None)
+        }
+    }
+
+    /// Sends the changed configuration (if any) to the applet and processes its replies
+    ///
+    /// Returns the submitted text once the user submits it, or fails with [`ResultCancelled`][`rc::ResultCancelled`] if the user cancels it (the keyboard disappears in both cases)
+    pub fn update(&mut self) -> Result<Option<String>> {
+        if self.calc_args.flags.get() != 0 {
+            let calc_args = self.calc_args;
+            self.push_request(InlineRequestCommand::Calc, Some(&calc_args))?;
+            self.calc_args.flags = InlineCalcFlags::from(0);
+        }
+
+        let mut decided = None;
+        if svc::wait_synchronization_one(self.reply_event_handle, 0).is_ok() {
+            while let Ok(mut storage) = self.holder.pop_interactive_out_data_storage() {
+                if let Some(result) = self.handle_reply(&mut storage)? {
+                    decided = Some(result);
+                }
+            }
+        }
+
+        decided.transpose()
+    }
+
+    /// Makes the keyboard appear with the given configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `args`: The configuration to appear with
+    pub fn appear(&mut self, args: InlineAppearArguments) {
+        self.calc_args.appear_args = args;
+        self.calc_args.flags = InlineCalcFlags::from(
+            (self.calc_args.flags.get() & !InlineCalcFlags::Disappear().get())
+                | InlineCalcFlags::Appear().get(),
+        );
+    }
+
+    /// Makes the keyboard disappear
+    pub fn disappear(&mut self) {
+        self.calc_args.flags = InlineCalcFlags::from(
+            (self.calc_args.flags.get() & !InlineCalcFlags::Appear().get())
+                | InlineCalcFlags::Disappear().get(),
+        );
+    }
+
+    /// Sets the text in the input field
+    ///
+    /// # Arguments
+    ///
+    /// * `text`: The text to set
+    pub fn set_input_text(&mut self, text: &str) {
+        write_utf16(&mut self.calc_args.input_text, text);
+        self.text = read_utf16(&self.calc_args.input_text).unwrap_or_default();
+        self.calc_args.flags |= InlineCalcFlags::SetInputText();
+    }
+
+    /// Sets the text cursor position
+    ///
+    /// # Arguments
+    ///
+    /// * `position`: The position to set
+    pub fn set_cursor_position(&mut self, position: i32) {
+        self.calc_args.cursor_position = position;
+        self.cursor_position = position;
+        self.calc_args.flags |= InlineCalcFlags::SetCursorPosition();
+    }
+
+    /// Sets the keyboard sound volume
+    ///
+    /// # Arguments
+    ///
+    /// * `volume`: The volume to set, between `0.0` and `1.0`
+    pub fn set_volume(&mut self, volume: f32) {
+        self.calc_args.volume = volume;
+        self.calc_args.flags |= InlineCalcFlags::SetVolume();
+    }
+
+    /// Sets whether the backspace key is enabled
+    ///
+    /// # Arguments
+    ///
+    /// * `enable`: Whether the key is enabled
+    pub fn set_backspace_enabled(&mut self, enable: bool) {
+        self.calc_args.enable_backspace = enable;
+        self.calc_args.flags |= InlineCalcFlags::SetBackspaceEnabled();
+    }
+
+    /// Gets the last state reported by the applet
+    #[inline]
+    pub fn get_state(&self) -> InlineState {
+        self.state
+    }
+
+    /// Gets the current text in the input field, as last reported by the applet
+    #[inline]
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// Gets the current text cursor position, as last reported by the applet
+    #[inline]
+    pub fn get_cursor_position(&self) -> i32 {
+        self.cursor_position
+    }
+}
+
+impl Drop for InlineKeyboard {
+    /// Finalizes the applet, waiting (for a bit) until it exits
+    fn drop(&mut self) {
+        if self
+            .push_request::<()>(InlineRequestCommand::Finalize, None)
+            .is_ok()
+        {
+            let _ = self.holder.join(Some(INLINE_FINALIZE_TIMEOUT));
+        }
+        let _ = svc::close_handle(self.reply_event_handle);
+    }
+}
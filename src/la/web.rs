@@ -0,0 +1,884 @@
+//! Web library applet support
+//!
+//! The web, offline web and Wi-Fi authentication applets are supported:
+//!
+//! ```ignore
+//! let ret = nx::la::web::Builder::new("https://example.com/help")?
+//!     .whitelist("^https://example\\.com/")?
+//!     .footer(false)?
+//!     .show()?;
+//! if ret.get_exit_reason() == Some(ExitReason::LastPage) {
+//!     // ...
+//! }
+//! ```
+//!
+//! The web and offline web applets take a TLV-like argument storage (a header followed by type/size/data entries), which [`Builder`] encodes
+
+use super::*;
+use crate::ipc::sf::account::Uid;
+use crate::util;
+use crate::version;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The size of the argument storage pushed to the web/offline web applets
+pub const ARGUMENT_STORAGE_SIZE: usize = 0x2000;
+
+/// The size of the output storage popped from the web/offline web applets
+const RETURN_STORAGE_SIZE: usize = 0x1010;
+
+/// The size of URL (and document path) arguments
+pub const URL_MAX_SIZE: usize = 0xC00;
+
+/// The size of callback URL arguments
+pub const CALLBACK_URL_MAX_SIZE: usize = 0x400;
+
+/// The size of the whitelist argument
+pub const WHITELIST_MAX_SIZE: usize = 0x1000;
+
+/// The size of the additional user agent string argument
+pub const USER_AGENT_ADDITIONAL_STRING_MAX_SIZE: usize = 0x80;
+
+/// The size of the last URL in the return value
+const LAST_URL_MAX_SIZE: usize = 0x1000;
+
+/// Represents the kind of web applet the arguments are meant for
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum ShimKind {
+    Login = 2,
+    Offline = 3,
+    Share = 4,
+    Web = 5,
+    Wifi = 6,
+    Lobby = 7,
+}
+
+/// Represents the argument types (not all of them are supported by all applets/versions)
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum ArgType {
+    Url = 0x1,
+    CallbackUrl = 0x3,
+    CallbackableUrl = 0x4,
+    ApplicationId = 0x5,
+    DocumentPath = 0x6,
+    DocumentKind = 0x7,
+    SystemDataId = 0x8,
+    Whitelist = 0xA,
+    UserId = 0xE,
+    ScreenShotEnabled = 0x10,
+    BootDisplayKind = 0x17,
+    FooterEnabled = 0x19,
+    PointerEnabled = 0x1A,
+    LeftStickMode = 0x1B,
+    DisplayUrlKind = 0x1F,
+    JsExtensionEnabled = 0x27,
+    UserAgentAdditionalString = 0x2A,
+    PageCacheEnabled = 0x2D,
+    WebAudioEnabled = 0x2E,
+    FooterFixedKind = 0x32,
+    BootLoadingIconEnabled = 0x35,
+    PageScrollIndicatorEnabled = 0x36,
+}
+
+/// Represents the return value types, in TLV-like output storages (3.0.0+)
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum ReturnType {
+    ExitReason = 0x1,
+    LastUrl = 0x2,
+    LastUrlSize = 0x3,
+}
+
+/// Represents the kind of document shown by the offline web applet
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum DocumentKind {
+    /// A page from the application's HTML document content
+    OfflineHtmlPage = 1,
+    /// The application's legal information
+    ApplicationLegalInformation = 2,
+    /// A page from a system data archive
+    SystemDataPage = 3,
+}
+
+/// Represents what's shown while the first page is loading
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u32)]
+pub enum BootDisplayKind {
+    #[default]
+    Default = 0,
+    White = 1,
+    Black = 2,
+}
+
+/// Represents how the footer is shown
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u32)]
+pub enum FooterFixedKind {
+    #[default]
+    Default = 0,
+    Always = 1,
+    Hidden = 2,
+}
+
+/// Represents what the left stick controls
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u32)]
+pub enum LeftStickMode {
+    /// The stick moves a pointer
+    #[default]
+    Pointer = 0,
+    /// The stick moves the cursor between links
+    Cursor = 1,
+}
+
+/// Represents the reason the web applet exited
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum ExitReason {
+    /// The user pressed the exit button
+    ExitButton = 0,
+    /// The user pressed the back button on the first page
+    BackButton = 1,
+    /// The applet was requested to exit
+    Requested = 2,
+    /// A callback URL was reached (see [`Builder::callback_url`])
+    LastPage = 3,
+    /// The applet exited after showing an error
+    ErrorDialog = 7,
+}
+
+impl ExitReason {
+    const fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            0 => Some(Self::ExitButton),
+            1 => Some(Self::BackButton),
+            2 => Some(Self::Requested),
+            3 => Some(Self::LastPage),
+            7 => Some(Self::ErrorDialog),
+            _ => None,
+        }
+    }
+}
+
+/// Represents the return value of the web/offline web applets
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ReturnValue {
+    /// The raw exit reason (see [`ExitReason`])
+    pub exit_reason: u32,
+    /// The last URL the applet was at
+    pub last_url: String,
+}
+
+impl ReturnValue {
+    /// Gets the reason the applet exited, if it's a known one
+    #[inline]
+    pub fn get_exit_reason(&self) -> Option<ExitReason> {
+        ExitReason::from_raw(self.exit_reason)
+    }
+}
+
+/// The size of both the storage header and the entry headers
+const TLV_HEADER_SIZE: usize = 8;
+
+#[inline]
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+#[inline]
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+#[inline]
+fn write_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Iterates the entries of a TLV-like storage, as `(type, data offset, size)`, stopping at the first malformed one
+fn tlv_entries(buf: &[u8]) -> impl Iterator<Item = (u16, usize, usize)> + '_ {
+    let entry_count = read_u16(buf, 0) as usize;
+    let mut offset = TLV_HEADER_SIZE;
+    (0..entry_count).map_while(move |_| {
+        if (offset + TLV_HEADER_SIZE) > buf.len() {
+            return None;
+        }
+        let entry_type = read_u16(buf, offset);
+        let size = read_u16(buf, offset + 2) as usize;
+        let data_offset = offset + TLV_HEADER_SIZE;
+        if (data_offset + size) > buf.len() {
+            return None;
+        }
+        offset = data_offset + size;
+        Some((entry_type, data_offset, size))
+    })
+}
+
+/// Reads a NUL-terminated (or size-limited) string
+fn read_string(buf: &[u8]) -> String {
+    let len = buf.iter().position(|&ch| ch == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+/// Gets the web applet API version for the current system version, which matches the system version itself
+fn get_api_version() -> u32 {
+    let ver = version::get_version();
+    ((ver.major as u32) << 16) | ((ver.minor as u32) << 8) | (ver.micro as u32)
+}
+
+/// Creates a [`IStorageClient`] shared object with the given bytes
+fn create_write_storage_bytes(data: &[u8]) -> Result<Storage> {
+    let storage = get_creator()
+        .as_ref()
+        .ok_or(crate::rc::ResultNotInitialized::make())?
+        .create_storage(data.len())?;
+    storage.open()?.write(0, sf::Buffer::from_array(data))?;
+    Ok(storage)
+}
+
+/// Represents a builder for configuring and showing the web or offline web applets
+///
+/// Every argument setter fails if the argument doesn't fit in its entry (see [`ResultStringTooLong`][`rc::ResultStringTooLong`]) or the argument storage is full (see [`ResultArgumentStorageFull`][`rc::ResultArgumentStorageFull`])
+#[derive(Clone)]
+#[must_use = "must eventually show the applet"]
+pub struct Builder {
+    applet_id: applet::AppletId,
+    args: Vec<u8>,
+}
+
+impl Builder {
+    fn new_with_shim(applet_id: applet::AppletId, shim_kind: ShimKind) -> Self {
+        let mut args = vec![0u8; ARGUMENT_STORAGE_SIZE];
+        args[4..8].copy_from_slice(&(shim_kind as u32).to_le_bytes());
+        Self { applet_id, args }
+    }
+
+    /// Creates a new [`Builder`] for the web applet, opening the given URL
+    ///
+    /// # Arguments
+    ///
+    /// * `url`: The URL to open
+    pub fn new(url: &str) -> Result<Self> {
+        Self::new_with_shim(applet::AppletId::LibAppletWeb, ShimKind::Web).string_arg(
+            ArgType::Url,
+            url,
+            URL_MAX_SIZE,
+        )
+    }
+
+    /// Creates a new [`Builder`] for the offline web applet, opening a document from the application's (or system's) content
+    ///
+    /// # Arguments
+    ///
+    /// * `kind`: The kind of document to open
+    /// * `id`: The application ID (with [`DocumentKind::OfflineHtmlPage`] and [`DocumentKind::ApplicationLegalInformation`]) or the system data ID (with [`DocumentKind::SystemDataPage`])
+    /// * `document_path`: The path of the document to open, relative to the content's HTML document directory
+    pub fn new_offline(kind: DocumentKind, id: u64, document_path: &str) -> Result<Self> {
+        let id_type = match kind {
+            DocumentKind::SystemDataPage => ArgType::SystemDataId,
+            _ => ArgType::ApplicationId,
+        };
+
+        Self::new_with_shim(applet::AppletId::LibraryAppletOfflineWeb, ShimKind::Offline)
+            .raw_arg(ArgType::DocumentKind as u16, &(kind as u32).to_le_bytes())?
+            .raw_arg(id_type as u16, &id.to_le_bytes())?
+            .string_arg(ArgType::DocumentPath, document_path, URL_MAX_SIZE)
+    }
+
+    /// Sets a raw argument, replacing any existing one of the same type
+    ///
+    /// # Arguments
+    ///
+    /// * `arg_type`: The argument type (see [`ArgType`])
+    /// * `data`: The argument data
+    pub fn raw_arg(mut self, arg_type: u16, data: &[u8]) -> Result<Self> {
+        let existing = tlv_entries(&self.args).find(|&(entry_type, _, _)| entry_type == arg_type);
+        match existing {
+            Some((_, data_offset, size)) if size == data.len() => {
+                self.args[data_offset..data_offset + size].copy_from_slice(data);
+                return Ok(self);
+            }
+            Some((_, data_offset, size)) => {
+                // Remove the existing entry, moving the following ones back
+                let entry_offset = data_offset - TLV_HEADER_SIZE;
+                let entry_end = data_offset + size;
+                self.args.copy_within(entry_end.., entry_offset);
+                let entry_count = read_u16(&self.args, 0);
+                write_u16(&mut self.args, 0, entry_count - 1);
+            }
+            None => {}
+        }
+
+        let end_offset = tlv_entries(&self.args)
+            .last()
+            .map(|(_, data_offset, size)| data_offset + size)
+            .unwrap_or(TLV_HEADER_SIZE);
+        let entry_end = end_offset + TLV_HEADER_SIZE + data.len();
+        result_return_if!(
+            (entry_end > self.args.len()) || (data.len() > u16::MAX as usize),
+            rc::ResultArgumentStorageFull
+        );
+
+        self.args[end_offset..entry_end].fill(0);
+        write_u16(&mut self.args, end_offset, arg_type);
+        write_u16(&mut self.args, end_offset + 2, data.len() as u16);
+        self.args[end_offset + TLV_HEADER_SIZE..entry_end].copy_from_slice(data);
+        let entry_count = read_u16(&self.args, 0);
+        write_u16(&mut self.args, 0, entry_count + 1);
+        Ok(self)
+    }
+
+    fn string_arg(self, arg_type: ArgType, string: &str, size: usize) -> Result<Self> {
+        result_return_unless!(string.len() < size, rc::ResultStringTooLong);
+
+        let mut data = vec![0u8; size];
+        data[..string.len()].copy_from_slice(string.as_bytes());
+        self.raw_arg(arg_type as u16, &data)
+    }
+
+    fn bool_arg(self, arg_type: ArgType, value: bool) -> Result<Self> {
+        self.raw_arg(arg_type as u16, &[value as u8])
+    }
+
+    fn u32_arg(self, arg_type: ArgType, value: u32) -> Result<Self> {
+        self.raw_arg(arg_type as u16, &value.to_le_bytes())
+    }
+
+    /// Sets the callback URL: the applet exits (with [`ExitReason::LastPage`]) once a URL starting with it is reached
+    ///
+    /// # Arguments
+    ///
+    /// * `url`: The callback URL
+    pub fn callback_url(self, url: &str) -> Result<Self> {
+        self.string_arg(ArgType::CallbackUrl, url, CALLBACK_URL_MAX_SIZE)
+    }
+
+    /// Sets the URL the callback URL must be reached from for the applet to exit
+    ///
+    /// # Arguments
+    ///
+    /// * `url`: The callbackable URL
+    pub fn callbackable_url(self, url: &str) -> Result<Self> {
+        self.string_arg(ArgType::CallbackableUrl, url, CALLBACK_URL_MAX_SIZE)
+    }
+
+    /// Sets the whitelist: URLs not matching it are opened (if at all) as an external page
+    ///
+    /// # Arguments
+    ///
+    /// * `whitelist`: The whitelist, as newline-separated regular expressions
+    pub fn whitelist(self, whitelist: &str) -> Result<Self> {
+        self.string_arg(ArgType::Whitelist, whitelist, WHITELIST_MAX_SIZE)
+    }
+
+    /// Sets the user account whose web data (cookies, etc.) is used
+    ///
+    /// # Arguments
+    ///
+    /// * `uid`: The [`Uid`] of the user account
+    pub fn user(self, uid: Uid) -> Result<Self> {
+        let mut data = [0u8; 0x10];
+        data[..8].copy_from_slice(&uid.uid[0].to_le_bytes());
+        data[8..].copy_from_slice(&uid.uid[1].to_le_bytes());
+        self.raw_arg(ArgType::UserId as u16, &data)
+    }
+
+    /// Sets whether screenshots can be taken
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled`: Whether screenshots are enabled
+    pub fn screenshot(self, enabled: bool) -> Result<Self> {
+        self.bool_arg(ArgType::ScreenShotEnabled, enabled)
+    }
+
+    /// Sets what's shown while the first page is loading
+    ///
+    /// # Arguments
+    ///
+    /// * `kind`: The kind to set
+    pub fn boot_display_kind(self, kind: BootDisplayKind) -> Result<Self> {
+        self.u32_arg(ArgType::BootDisplayKind, kind as u32)
+    }
+
+    /// Sets whether the footer is shown
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled`: Whether the footer is shown
+    pub fn footer(self, enabled: bool) -> Result<Self> {
+        self.bool_arg(ArgType::FooterEnabled, enabled)
+    }
+
+    /// Sets how the footer is shown
+    ///
+    /// # Arguments
+    ///
+    /// * `kind`: The kind to set
+    pub fn footer_fixed_kind(self, kind: FooterFixedKind) -> Result<Self> {
+        self.u32_arg(ArgType::FooterFixedKind, kind as u32)
+    }
+
+    /// Sets whether the pointer is enabled
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled`: Whether the pointer is enabled
+    pub fn pointer(self, enabled: bool) -> Result<Self> {
+        self.bool_arg(ArgType::PointerEnabled, enabled)
+    }
+
+    /// Sets what the left stick controls
+    ///
+    /// # Arguments
+    ///
+    /// * `mode`: The mode to set
+    pub fn left_stick_mode(self, mode: LeftStickMode) -> Result<Self> {
+        self.u32_arg(ArgType::LeftStickMode, mode as u32)
+    }
+
+    /// Sets whether the current URL is displayed
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled`: Whether the URL is displayed
+    pub fn display_url(self, enabled: bool) -> Result<Self> {
+        self.bool_arg(ArgType::DisplayUrlKind, enabled)
+    }
+
+    /// Sets whether the JavaScript extensions (the `nx` object) are available to pages
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled`: Whether the extensions are enabled
+    pub fn js_extension(self, enabled: bool) -> Result<Self> {
+        self.bool_arg(ArgType::JsExtensionEnabled, enabled)
+    }
+
+    /// Sets a string appended to the applet's user agent
+    ///
+    /// # Arguments
+    ///
+    /// * `string`: The string to append
+    pub fn user_agent_additional_string(self, string: &str) -> Result<Self> {
+        self.string_arg(
+            ArgType::UserAgentAdditionalString,
+            string,
+            USER_AGENT_ADDITIONAL_STRING_MAX_SIZE,
+        )
+    }
+
+    /// Sets whether pages are cached
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled`: Whether the cache is enabled
+    pub fn page_cache(self, enabled: bool) -> Result<Self> {
+        self.bool_arg(ArgType::PageCacheEnabled, enabled)
+    }
+
+    /// Sets whether the Web Audio API is available to pages
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled`: Whether Web Audio is enabled
+    pub fn web_audio(self, enabled: bool) -> Result<Self> {
+        self.bool_arg(ArgType::WebAudioEnabled, enabled)
+    }
+
+    /// Sets whether a loading icon is shown while the first page is loading
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled`: Whether the icon is shown
+    pub fn boot_loading_icon(self, enabled: bool) -> Result<Self> {
+        self.bool_arg(ArgType::BootLoadingIconEnabled, enabled)
+    }
+
+    /// Sets whether the page scroll indicator is shown
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled`: Whether the indicator is shown
+    pub fn page_scroll_indicator(self, enabled: bool) -> Result<Self> {
+        self.bool_arg(ArgType::PageScrollIndicatorEnabled, enabled)
+    }
+
+    /// Gets the raw argument storage contents
+    #[inline]
+    pub fn get_args(&self) -> &[u8] {
+        &self.args
+    }
+
+    /// Launches the applet and waits for it to exit, returning its return value
+    pub fn show(&self) -> Result<ReturnValue> {
+        let api_version = get_api_version();
+        let common_args = CommonArguments {
+            version: 1,
+            size: cmem::size_of::<CommonArguments>() as u32,
+            la_api_version: api_version,
+            ..Default::default()
+        };
+
+        let mut holder = create_library_applet(
+            self.applet_id,
+            applet::LibraryAppletMode::AllForeground,
+            common_args,
+        )?;
+        holder.push_in_data_storage(create_write_storage_bytes(&self.args)?)?;
+        holder.start()?;
+        holder.join(None)?;
+        let ret: [u8; RETURN_STORAGE_SIZE] = holder.pop_out_data()?;
+        parse_return_value(&ret, api_version)
+    }
+}
+
+/// Parses the output storage of the web/offline web applets, whose layout depends on the applet API version
+fn parse_return_value(ret: &[u8; RETURN_STORAGE_SIZE], api_version: u32) -> Result<ReturnValue> {
+    if api_version >= 0x30000 {
+        let mut ret_value = ReturnValue::default();
+        let mut last_url: &[u8] = &[];
+        let mut last_url_size = None;
+        for (entry_type, data_offset, size) in tlv_entries(ret) {
+            let data = &ret[data_offset..data_offset + size];
+            if entry_type == ReturnType::ExitReason as u16 {
+                result_return_unless!(size >= 4, rc::ResultInvalidOutputData);
+                ret_value.exit_reason = read_u32(data, 0);
+            } else if entry_type == ReturnType::LastUrl as u16 {
+                last_url = data;
+            } else if entry_type == ReturnType::LastUrlSize as u16 {
+                result_return_unless!(size >= 8, rc::ResultInvalidOutputData);
+                last_url_size = Some(read_u32(data, 0) as usize);
+            }
+        }
+        // Apply the size to the raw bytes, truncating the decoded string could split a UTF-8 sequence
+        if let Some(last_url_size) = last_url_size {
+            last_url = &last_url[..last_url_size.min(last_url.len())];
+        }
+        ret_value.last_url = read_string(last_url);
+        Ok(ret_value)
+    } else {
+        // Before 3.0.0 the output is a plain struct: exit reason, padding, last URL and last URL size
+        let last_url_size = (read_u32(ret, 8 + LAST_URL_MAX_SIZE) as usize).min(LAST_URL_MAX_SIZE);
+        Ok(ReturnValue {
+            exit_reason: read_u32(ret, 0),
+            last_url: read_string(&ret[8..8 + last_url_size]),
+        })
+    }
+}
+
+/// Represents the arguments of the Wi-Fi authentication applet
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct WifiPageArgument {
+    /// Unknown value
+    pub unk: u32,
+    /// The connection test URL, whose response determines whether the network requires authentication
+    pub connection_test_url: util::ArrayString<0x100>,
+    /// The URL the applet opens
+    pub initial_url: util::ArrayString<0x400>,
+    /// The ID of the network profile being authenticated
+    pub network_profile_id: [u8; 0x10],
+    /// The network requirement revision
+    pub requirement_revision: u32,
+}
+const_assert!(cmem::size_of::<WifiPageArgument>() == 0x518);
+
+/// Represents the output layout of the Wi-Fi authentication applet
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct WifiReturnValue {
+    unk: u32,
+    rc: ResultCode,
+}
+
+/// Launches the Wi-Fi authentication applet (for captive portal login) and waits for it to exit
+///
+/// This fails with the result reported by the applet if the authentication didn't succeed
+///
+/// # Arguments
+///
+/// * `connection_test_url`: The connection test URL
+/// * `initial_url`: The URL the applet opens (usually the one the connection test was redirected to)
+/// * `network_profile_id`: The ID of the network profile being authenticated
+/// * `requirement_revision`: The network requirement revision
+pub fn show_wifi_auth(
+    connection_test_url: &str,
+    initial_url: &str,
+    network_profile_id: [u8; 0x10],
+    requirement_revision: u32,
+) -> Result<()> {
+    result_return_unless!(connection_test_url.len() < 0x100, rc::ResultStringTooLong);
+    result_return_unless!(initial_url.len() < 0x400, rc::ResultStringTooLong);
+
+    let args = WifiPageArgument {
+        unk: 0,
+        connection_test_url: util::ArrayString::from_str_truncate_null(connection_test_url),
+        initial_url: util::ArrayString::from_str_truncate_null(initial_url),
+        network_profile_id,
+        requirement_revision,
+    };
+    let common_args = CommonArguments {
+        version: 1,
+        size: cmem::size_of::<CommonArguments>() as u32,
+        la_api_version: 0,
+        ..Default::default()
+    };
+
+    let mut holder = create_library_applet(
+        applet::AppletId::LibraryAppletWifiWebAuth,
+        applet::LibraryAppletMode::AllForeground,
+        common_args,
+    )?;
+    holder.push_in_data(args)?;
+    holder.start()?;
+    holder.join(None)?;
+    let ret: WifiReturnValue = holder.pop_out_data()?;
+
+    pack(ret.rc, ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_entries(args: &[u8]) -> Vec<(u16, Vec<u8>)> {
+        tlv_entries(args)
+            .map(|(entry_type, data_offset, size)| {
+                (entry_type, args[data_offset..data_offset + size].to_vec())
+            })
+            .collect()
+    }
+
+    fn new_builder() -> Builder {
+        Builder::new_with_shim(applet::AppletId::LibAppletWeb, ShimKind::Web)
+    }
+
+    fn make_return_storage(entries: &[(u16, &[u8])]) -> [u8; RETURN_STORAGE_SIZE] {
+        let mut ret = [0u8; RETURN_STORAGE_SIZE];
+        write_u16(&mut ret, 0, entries.len() as u16);
+        let mut offset = TLV_HEADER_SIZE;
+        for (entry_type, data) in entries {
+            write_u16(&mut ret, offset, *entry_type);
+            write_u16(&mut ret, offset + 2, data.len() as u16);
+            offset += TLV_HEADER_SIZE;
+            ret[offset..offset + data.len()].copy_from_slice(data);
+            offset += data.len();
+        }
+        ret
+    }
+
+    #[test]
+    fn new_web_args() {
+        let builder = Builder::new("https://example.com").unwrap();
+        let args = builder.get_args();
+        assert_eq!(args.len(), ARGUMENT_STORAGE_SIZE);
+        assert_eq!(read_u16(args, 0), 1);
+        assert_eq!(read_u32(args, 4), ShimKind::Web as u32);
+
+        let entries = get_entries(args);
+        assert_eq!(entries.len(), 1);
+        let (entry_type, data) = &entries[0];
+        assert_eq!(*entry_type, ArgType::Url as u16);
+        assert_eq!(data.len(), URL_MAX_SIZE);
+        assert_eq!(read_string(data), "https://example.com");
+
+        // The string must fit with its NUL terminator
+        let url = "a".repeat(URL_MAX_SIZE);
+        let rc = Builder::new(&url).err().unwrap();
+        assert!(rc::ResultStringTooLong::matches(rc));
+        assert!(Builder::new(&url[1..]).is_ok());
+    }
+
+    #[test]
+    fn replace_arg() {
+        let builder = new_builder()
+            .footer(true)
+            .unwrap()
+            .u32_arg(ArgType::BootDisplayKind, 1)
+            .unwrap()
+            .pointer(false)
+            .unwrap();
+        let args_before = builder.get_args().to_vec();
+
+        // Same size entries are replaced in place
+        let builder = builder.footer(false).unwrap();
+        assert_eq!(
+            get_entries(builder.get_args()),
+            [
+                (ArgType::FooterEnabled as u16, vec![0]),
+                (ArgType::BootDisplayKind as u16, vec![1, 0, 0, 0]),
+                (ArgType::PointerEnabled as u16, vec![0]),
+            ]
+        );
+        let changed_offsets: Vec<_> = (0..ARGUMENT_STORAGE_SIZE)
+            .filter(|&i| builder.get_args()[i] != args_before[i])
+            .collect();
+        assert_eq!(changed_offsets, [2 * TLV_HEADER_SIZE]);
+    }
+
+    #[test]
+    fn resize_arg() {
+        let builder = new_builder()
+            .raw_arg(0x100, &[1, 2, 3])
+            .unwrap()
+            .raw_arg(0x101, &[4])
+            .unwrap()
+            .raw_arg(0x102, &[5, 6])
+            .unwrap();
+
+        // Resized entries are moved to the end, keeping the rest in order
+        let builder = builder.raw_arg(0x100, &[7, 8, 9, 10, 11]).unwrap();
+        assert_eq!(
+            get_entries(builder.get_args()),
+            [
+                (0x101, vec![4]),
+                (0x102, vec![5, 6]),
+                (0x100, vec![7, 8, 9, 10, 11]),
+            ]
+        );
+
+        let builder = builder.raw_arg(0x102, &[]).unwrap();
+        assert_eq!(
+            get_entries(builder.get_args()),
+            [
+                (0x101, vec![4]),
+                (0x100, vec![7, 8, 9, 10, 11]),
+                (0x102, vec![]),
+            ]
+        );
+        assert_eq!(read_u16(builder.get_args(), 0), 3);
+    }
+
+    #[test]
+    fn argument_storage_full() {
+        let max_data_size = ARGUMENT_STORAGE_SIZE - 2 * TLV_HEADER_SIZE;
+        let builder = new_builder()
+            .raw_arg(0x100, &vec![0xAA; max_data_size])
+            .unwrap();
+
+        let rc = builder.clone().raw_arg(0x101, &[]).err().unwrap();
+        assert!(rc::ResultArgumentStorageFull::matches(rc));
+        let rc = builder
+            .clone()
+            .raw_arg(0x100, &vec![0xBB; max_data_size + 1])
+            .err()
+            .unwrap();
+        assert!(rc::ResultArgumentStorageFull::matches(rc));
+
+        // Replacing the entry (with the same or a smaller size) still works
+        let builder = builder.raw_arg(0x100, &vec![0xBB; max_data_size]).unwrap();
+        assert_eq!(
+            get_entries(builder.get_args()),
+            [(0x100, vec![0xBB; max_data_size])]
+        );
+        let builder = builder
+            .raw_arg(0x100, &[0xCC])
+            .unwrap()
+            .raw_arg(0x101, &[])
+            .unwrap();
+        assert_eq!(
+            get_entries(builder.get_args()),
+            [(0x100, vec![0xCC]), (0x101, vec![])]
+        );
+
+        // Sizes are 16-bit
+        let rc = Builder {
+            args: vec![0u8; 0x20000],
+            ..new_builder()
+        }
+        .raw_arg(0x100, &vec![0; u16::MAX as usize + 1])
+        .err()
+        .unwrap();
+        assert!(rc::ResultArgumentStorageFull::matches(rc));
+    }
+
+    #[test]
+    fn tlv_entries_malformed() {
+        let mut ret = make_return_storage(&[(0x1, &[1, 2, 3, 4]), (0x2, &[5])]);
+        assert_eq!(get_entries(&ret), [(0x1, vec![1, 2, 3, 4]), (0x2, vec![5])]);
+
+        // Iteration stops at the first entry going past the end
+        write_u16(&mut ret, TLV_HEADER_SIZE + 2, RETURN_STORAGE_SIZE as u16);
+        assert!(get_entries(&ret).is_empty());
+        write_u16(&mut ret, 0, u16::MAX);
+        write_u16(&mut ret, TLV_HEADER_SIZE + 2, 4);
+        let entries = get_entries(&ret);
+        assert_eq!(entries[..2], [(0x1, vec![1, 2, 3, 4]), (0x2, vec![5])]);
+        assert!(entries.len() < RETURN_STORAGE_SIZE / TLV_HEADER_SIZE);
+    }
+
+    #[test]
+    fn parse_return_value_tlv() {
+        let mut last_url = [0u8; 0x100];
+        last_url[..13].copy_from_slice(b"https://a.com");
+        let ret = make_return_storage(&[
+            (ReturnType::ExitReason as u16, &3u32.to_le_bytes()),
+            (0x7F, &[0xFF; 0x10]),
+            (ReturnType::LastUrl as u16, &last_url),
+        ]);
+        let ret_value = parse_return_value(&ret, 0x30000).unwrap();
+        assert_eq!(ret_value.get_exit_reason(), Some(ExitReason::LastPage));
+        assert_eq!(ret_value.last_url, "https://a.com");
+
+        let ret = make_return_storage(&[(ReturnType::ExitReason as u16, &[3, 0])]);
+        let rc = parse_return_value(&ret, 0x30000).unwrap_err();
+        assert!(rc::ResultInvalidOutputData::matches(rc));
+        let ret = make_return_storage(&[(ReturnType::LastUrlSize as u16, &[3, 0, 0, 0])]);
+        let rc = parse_return_value(&ret, 0x30000).unwrap_err();
+        assert!(rc::ResultInvalidOutputData::matches(rc));
+    }
+
+    #[test]
+    fn parse_return_value_truncated_utf8() {
+        // "é" is encoded as 0xC3 0xA9, the size cuts it in half
+        let url = "https://a.com/caf\u{E9}".as_bytes();
+        let mut last_url = [0u8; 0x100];
+        last_url[..url.len()].copy_from_slice(url);
+        let last_url_size = (url.len() - 1) as u64;
+        let ret = make_return_storage(&[
+            (ReturnType::LastUrl as u16, &last_url),
+            (ReturnType::LastUrlSize as u16, &last_url_size.to_le_bytes()),
+            (ReturnType::ExitReason as u16, &7u32.to_le_bytes()),
+        ]);
+        let ret_value = parse_return_value(&ret, 0x30000).unwrap();
+        assert_eq!(ret_value.get_exit_reason(), Some(ExitReason::ErrorDialog));
+        assert_eq!(ret_value.last_url, "https://a.com/caf\u{FFFD}");
+
+        // Sizes past the entry are clamped
+        let ret = make_return_storage(&[
+            (ReturnType::LastUrl as u16, url),
+            (ReturnType::LastUrlSize as u16, &u64::MAX.to_le_bytes()),
+        ]);
+        let ret_value = parse_return_value(&ret, 0x30000).unwrap();
+        assert_eq!(ret_value.last_url, "https://a.com/caf\u{E9}");
+        assert_eq!(ret_value.exit_reason, 0);
+    }
+
+    #[test]
+    fn parse_return_value_legacy() {
+        let url = b"https://a.com/page";
+        let mut ret = [0u8; RETURN_STORAGE_SIZE];
+        ret[..4].copy_from_slice(&1u32.to_le_bytes());
+        ret[8..8 + url.len()].copy_from_slice(url);
+        ret[8 + LAST_URL_MAX_SIZE..8 + LAST_URL_MAX_SIZE + 4]
+            .copy_from_slice(&(url.len() as u32 - 5).to_le_bytes());
+        let ret_value = parse_return_value(&ret, 0x20000).unwrap();
+        assert_eq!(ret_value.get_exit_reason(), Some(ExitReason::BackButton));
+        assert_eq!(ret_value.last_url, "https://a.com");
+
+        ret[8 + LAST_URL_MAX_SIZE..8 + LAST_URL_MAX_SIZE + 4]
+            .copy_from_slice(&u32::MAX.to_le_bytes());
+        let ret_value = parse_return_value(&ret, 0x20000).unwrap();
+        assert_eq!(ret_value.last_url, "https://a.com/page");
+    }
+}
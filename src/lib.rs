@@ -14,7 +14,7 @@
 //!
 //! - `vty`: Enables virtual tty support, AKA, the `nx::console::vty` module (also enables `console` as well as the dependencies `embedded-term` and `embedded-graphics-core`)
 //!
-//! - `fs`: Enables support for this library's FS implementation, aka the `nx::fs` module (also enables `services`)
+//! - `fs`: Enables support for this library's FS implementation, aka the `nx::fs` module (also enables `services` and the `sha2` and `aes` dependencies)
 //!
 //! - `input`: Enables input support, AKA the `nx::input` module (also enables `services`)
 //!
@@ -28,6 +28,10 @@
 //!
 //! - `mii` : Enables mii support, AKA the `nx::mii` module (also enables `services`)
 //!
+//! - `time` : Enables wall-clock time and time zone support, AKA the `nx::time` module (also enables `services`)
+//!
+//! - `transport` : Enables pluggable IPC request transports, AKA the `nx::ipc::transport` module (mainly meant for testing IPC code without a Horizon kernel)
+//!
 //! Note that most of these features/modules are just simplified and easy-to-use wrappers around IPC/raw system features, so not using them doesn't fully block those features (for instance, you could use services using IPC commands more directly without the `services` feature).
 //!
 //! # Contributing
@@ -38,7 +42,8 @@
 //!
 //! Library examples are located at this other [repository](https://github.com/aarch64-switch-rs/examples)
 
-#![no_std]
+// unit tests are built for a regular (std) host target
+#![cfg_attr(not(test), no_std)]
 // needed to implement the APIs for collection types with custom allocators, and doing raw allocations
 #![feature(allocator_api)]
 // needed to specify weak linkage on some items
@@ -51,12 +56,14 @@
 #![feature(str_from_utf16_endian)]
 //#![warn(missing_docs)]
 #![macro_use]
+#[cfg(not(test))]
 use core::arch::global_asm;
 
 // Required assembly bits (those which essentially cannot/shouldn't be inlined)
+#[cfg(not(test))]
 global_asm!(include_str!("rrt0.s"));
+#[cfg(not(test))]
 global_asm!(include_str!("mod0.s"));
-//global_asm!(include_str!("exception.s"));
 
 extern crate self as nx;
 
@@ -112,6 +119,8 @@ pub mod arm;
 
 pub mod wait;
 
+pub mod executor;
+
 pub mod version;
 
 #[cfg(feature = "applet")]
@@ -142,3 +151,6 @@ pub mod socket;
 
 #[cfg(feature = "mii")]
 pub mod mii;
+
+#[cfg(feature = "time")]
+pub mod time;
@@ -48,7 +48,7 @@ macro_rules! ipc_sf_define_interface_trait {
                         walker.reset_with(ctx.in_params.data_offset);
                         $( $crate::ipc::client::RequestCommandParameter::before_send_sync_request(&$in_param_name, &mut walker, &mut ctx)?; )*
 
-                        $crate::svc::send_sync_request(self.get_session().object_info.handle)?;
+                        $crate::ipc::client::send_sync_request(self.get_session().object_info.handle)?;
 
                         match self.get_session().object_info.protocol {
                             $crate::ipc::CommandProtocol::Cmif => $crate::ipc::cmif::client::read_request_command_response_from_msg_buffer(&mut ctx)?,
@@ -115,7 +115,8 @@ pub unsafe trait AllocatorEx: Allocator {
 
 unsafe impl AllocatorEx for Global {}
 
-#[global_allocator]
+// unit tests run on the host allocator
+#[cfg_attr(not(test), global_allocator)]
 static GLOBAL_ALLOCATOR: linked_list_allocator::LockedHeap =
     linked_list_allocator::LockedHeap::empty();
 
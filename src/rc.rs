@@ -22,6 +22,11 @@
 //! * `1100`: gpu/binder
 //! * `1200`: gpu/parcel
 //! * `1300`: ipc/server
+//! * `1400`: la
+//! * `1500`: applet
+//! * `1600`: time
+//! * `1700`: gpu/canvas/image
+//! * `1800`: gpu/canvas/shared_font
 
 pub const RESULT_MODULE: u32 = 430;
 /// Result submodule for the base `rc` module.
@@ -52,5 +57,10 @@ result_define_subgroup!(RESULT_MODULE, RESULT_SUBMODULE => {
 1100: gpu/binder
 1200: gpu/parcel
 1300: ipc/server
+1400: la
+1500: applet
+1600: time
+1700: gpu/canvas/image
+1800: gpu/canvas/shared_font
 
 */
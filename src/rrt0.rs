@@ -244,7 +244,12 @@ unsafe fn normal_entry(loader_mode: LoaderMode, exit_config: Option<ExitFn>) ->
                             // todo!("OverrideService");
                         }
                         hbl::AbiConfigEntryKey::Argv => {
-                            // todo!("Argv");
+                            let argv_data = (*abi_entry).value[1] as *const core::ffi::c_char;
+                            if !argv_data.is_null()
+                                && let Ok(argv) = core::ffi::CStr::from_ptr(argv_data).to_str()
+                            {
+                                hbl::set_argv(argv);
+                            }
                         }
                         hbl::AbiConfigEntryKey::SyscallAvailableHint => {
                             // todo!("SyscallAvailableHint");
@@ -338,6 +343,11 @@ unsafe fn normal_entry(loader_mode: LoaderMode, exit_config: Option<ExitFn>) ->
         crate::socket::finalize();
     }
 
+    #[cfg(feature = "time")]
+    {
+        crate::time::finalize();
+    }
+
     // Successful exit by default
     exit(ResultSuccess::make());
 }
@@ -398,20 +408,11 @@ unsafe extern "C" fn __nx_rrt0_entry(arg0: usize, arg1: usize) -> ! {
     Possible entry arguments:
     - NSO/KIP: x0 = 0, x1 = <main-thread-handle>
     - NRO (hbl): x0 = <abi-config-entries-ptr>, x1 = usize::MAX
-    - Exception: x0 = <exception-type>, x1 = <stack-top>
+    (exceptions are diverted to the exception entry in `rrt0.s` before reaching this)
     */
-    let loader_mode = match (arg0, arg1) {
-        (0, main_thread_handle) => LoaderMode::Nso(main_thread_handle as u32),
-        (config_pointer, usize::MAX) => {
-            LoaderMode::Nro(aslr_base_address.with_addr(config_pointer) as _)
-        }
-        (exception_type, exception_stack_top) => {
-            let exception_type: svc::ExceptionType = mem::transmute(exception_type as u32);
-            crate::exception::__nx_exception_dispatch(
-                exception_type,
-                core::ptr::with_exposed_provenance_mut(exception_stack_top),
-            );
-        }
+    let loader_mode = match arg0 {
+        0 => LoaderMode::Nso(arg1 as u32),
+        config_pointer => LoaderMode::Nro(aslr_base_address.with_addr(config_pointer) as _),
     };
 
     normal_entry(loader_mode, lr_exit_fn);
@@ -3,6 +3,19 @@
 
 .global _start
 _start:
-	b __nx_rrt0_entry
+	b __nx_rrt0_start
 	.word __module_header - _start
 	.ascii "HOMEBREW"
+
+.section .text.__nx_rrt0_start, "ax", %progbits
+.align 2
+
+// Exceptions (x0 = <exception-type>, x1 = <exception-info>) must be diverted before anything else runs,
+// since the regular entry clobbers registers and clears .bss
+__nx_rrt0_start:
+	cbz x0, 1f
+	cmn x1, #1
+	b.eq 1f
+	b __nx_exception_dispatch
+1:
+	b __nx_rrt0_entry
@@ -126,3 +126,15 @@ pub mod bsd;
 
 /// "aud*" auudio service definitions
 pub mod audio;
+
+/// "acc:u*" account service definitions
+pub mod account;
+
+/// "time:*" time service definitions
+pub mod time;
+
+/// "nifm:*" network interface manager service definitions
+pub mod nifm;
+
+/// "pl:u" shared font service definitions
+pub mod pl;
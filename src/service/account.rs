@@ -0,0 +1,54 @@
+use crate::ipc::sf;
+use crate::ipc::sf::sm;
+use crate::result::*;
+use crate::service;
+use crate::version;
+
+pub use crate::ipc::sf::account::*;
+
+ipc_client_define_client_default!(ApplicationAccountService);
+ipc_client_define_client_default!(SystemAccountService);
+
+impl IApplicationAccountClient for ApplicationAccountService {}
+impl ISystemAccountClient for SystemAccountService {}
+
+impl ApplicationAccountService {
+    /// Initializes the application info for the current process, required for some commands (like [`get_baas_account_manager_for_application`][`IApplicationAccountClient::get_baas_account_manager_for_application`])
+    ///
+    /// This must be done once per process, and only works for applications
+    pub fn initialize_application_info_auto(&self) -> Result<()> {
+        if version::get_version() >= version::Version::new(6, 0, 0) {
+            self.initialize_application_info(sf::ProcessId::new())
+        } else {
+            self.initialize_application_info_v0(sf::ProcessId::new())
+        }
+    }
+}
+
+impl service::IService for ApplicationAccountService {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new("acc:u0")
+    }
+
+    fn as_domain() -> bool {
+        true
+    }
+
+    fn post_initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl service::IService for SystemAccountService {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new("acc:u1")
+    }
+
+    fn as_domain() -> bool {
+        true
+    }
+
+    fn post_initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use crate::ipc::sf;
+use crate::ipc::sf::sm;
+use crate::result::*;
+use crate::service;
+use crate::version;
+
+pub use crate::ipc::sf::nifm::*;
+
+ipc_client_define_client_default!(UserNifmService);
+ipc_client_define_client_default!(SystemNifmService);
+ipc_client_define_client_default!(AdminNifmService);
+
+impl IStaticServiceClient for UserNifmService {}
+impl IStaticServiceClient for SystemNifmService {}
+impl IStaticServiceClient for AdminNifmService {}
+
+/// Creates a [`GeneralService`] object, using the command available in the current system version
+///
+/// # Arguments
+///
+/// * `static_srv`: The `nifm:*` service to use
+pub fn create_general_service_auto(
+    static_srv: &dyn IStaticServiceClient,
+) -> Result<GeneralService> {
+    if version::get_version() >= version::Version::new(3, 0, 0) {
+        static_srv.create_general_service(sf::ProcessId::new(), 0)
+    } else {
+        static_srv.create_general_service_old()
+    }
+}
+
+impl service::IService for UserNifmService {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new("nifm:u")
+    }
+
+    fn as_domain() -> bool {
+        false
+    }
+
+    fn post_initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl service::IService for SystemNifmService {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new("nifm:s")
+    }
+
+    fn as_domain() -> bool {
+        false
+    }
+
+    fn post_initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl service::IService for AdminNifmService {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new("nifm:a")
+    }
+
+    fn as_domain() -> bool {
+        false
+    }
+
+    fn post_initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
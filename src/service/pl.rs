@@ -0,0 +1,22 @@
+use crate::ipc::sf::sm;
+use crate::result::*;
+use crate::service;
+
+pub use crate::ipc::sf::pl::*;
+
+ipc_client_define_client_default!(PlatformServiceManagerService);
+impl IPlatformServiceManagerClient for PlatformServiceManagerService {}
+
+impl service::IService for PlatformServiceManagerService {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new("pl:u")
+    }
+
+    fn as_domain() -> bool {
+        false
+    }
+
+    fn post_initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
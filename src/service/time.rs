@@ -0,0 +1,39 @@
+use crate::ipc::sf::sm;
+use crate::result::*;
+use crate::service;
+
+pub use crate::ipc::sf::time::*;
+
+ipc_client_define_client_default!(UserTimeService);
+impl IStaticServiceClient for UserTimeService {}
+
+impl service::IService for UserTimeService {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new("time:u")
+    }
+
+    fn as_domain() -> bool {
+        false
+    }
+
+    fn post_initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+ipc_client_define_client_default!(SystemTimeService);
+impl IStaticServiceClient for SystemTimeService {}
+
+impl service::IService for SystemTimeService {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new("time:s")
+    }
+
+    fn as_domain() -> bool {
+        false
+    }
+
+    fn post_initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -291,6 +291,8 @@ pub mod net {
     use crate::ipc::sf::OutAutoSelectBuffer;
     use crate::service::bsd::PollFlags;
     use crate::service::bsd::{PollFd, SocketOptions};
+    use crate::service::nifm;
+    use crate::service::nifm::{IGeneralServiceClient, IRequestClient};
     use crate::socket::{BsdDuration, Linger, SOL_SOCKET};
     use crate::{
         ipc::sf::Buffer,
@@ -800,6 +802,189 @@ pub mod net {
         }))
     }
 
+    /// The interval between internet connection status checks in [`wait_for_connection`]
+    const CONNECTION_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+    fn create_nifm_general_service() -> Result<nifm::GeneralService> {
+        let nifm_srv = new_service_object::<nifm::UserNifmService>()?;
+        nifm::create_general_service_auto(&nifm_srv)
+    }
+
+    /// Gets the current IPv4 address of the console, as assigned by the network it's connected to
+    ///
+    /// This fails if the console isn't connected to a network
+    pub fn get_current_ip_address() -> Result<Ipv4Addr> {
+        let general_srv = create_nifm_general_service()?;
+        // The address is returned in network byte order
+        let raw_addr = general_srv.get_current_ip_address()?;
+        Ok(Ipv4Addr::from(raw_addr.to_le_bytes()))
+    }
+
+    /// Waits until the console has an internet connection, returning its current IPv4 address
+    ///
+    /// This doesn't request a connection by itself (see [`ConnectionRequest`]), it waits for the system to bring one up
+    ///
+    /// This will fail with [`ResultNotConnected`][`rc::ResultNotConnected`] right away if wireless communication is disabled (flight mode) and the console has no ethernet connection enabled, or with [`ResultConnectionTimedOut`][`rc::ResultConnectionTimedOut`] if no connection is established in time
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout`: The maximum time to wait for, [`None`] means waiting indefinitely
+    pub fn wait_for_connection(timeout: Option<Duration>) -> Result<Ipv4Addr> {
+        let general_srv = create_nifm_general_service()?;
+
+        let start_tick = crate::arm::get_system_tick();
+        let timeout_ticks =
+            timeout.map(|timeout| crate::arm::nanoseconds_to_ticks(timeout.as_nanos() as u64));
+
+        loop {
+            // The status can't be retrieved (it fails) while not connected at all
+            if general_srv
+                .get_internet_connection_status()
+                .is_ok_and(|status| status.is_connected())
+            {
+                let raw_addr = general_srv.get_current_ip_address()?;
+                return Ok(Ipv4Addr::from(raw_addr.to_le_bytes()));
+            }
+
+            result_return_unless!(
+                general_srv.is_wireless_communication_enabled()?
+                    || general_srv.is_ethernet_communication_enabled()?,
+                rc::ResultNotConnected
+            );
+
+            if let Some(timeout_ticks) = timeout_ticks {
+                let elapsed_ticks = crate::arm::get_system_tick().wrapping_sub(start_tick);
+                result_return_if!(elapsed_ticks >= timeout_ticks, rc::ResultConnectionTimedOut);
+            }
+
+            crate::thread::sleep(CONNECTION_CHECK_INTERVAL.as_nanos() as i64)?;
+        }
+    }
+
+    /// Represents a network connection request submitted to `nifm`
+    ///
+    /// The system brings up a connection (if possible) while the request is accepted, and may tear it down once it's dropped
+    pub struct ConnectionRequest {
+        request: nifm::Request,
+        event_handles: [Handle; 2],
+        _general_srv: nifm::GeneralService,
+    }
+
+    impl ConnectionRequest {
+        /// Creates a new (not yet submitted) [`ConnectionRequest`]
+        ///
+        /// # Arguments
+        ///
+        /// * `requirement_preset`: The raw requirement preset the request is created with
+        pub fn new(requirement_preset: u32) -> Result<Self> {
+            let general_srv = create_nifm_general_service()?;
+            let request = general_srv.create_request(requirement_preset)?;
+            let (event_handle_0, event_handle_1) = request.get_system_event_readable_handles()?;
+
+            Ok(Self {
+                request,
+                event_handles: [event_handle_0.handle, event_handle_1.handle],
+                _general_srv: general_srv,
+            })
+        }
+
+        /// Gets the underlying [`nifm::IRequestClient`] object, for setting further request options
+        #[inline]
+        pub fn get_request(&self) -> &nifm::Request {
+            &self.request
+        }
+
+        /// Submits the request
+        #[inline]
+        pub fn submit(&mut self) -> Result<()> {
+            self.request.submit()
+        }
+
+        /// Cancels the request
+        #[inline]
+        pub fn cancel(&mut self) -> Result<()> {
+            self.request.cancel()
+        }
+
+        /// Gets the current [`RequestState`][`nifm::RequestState`] of the request
+        pub fn get_state(&self) -> Result<Option<nifm::RequestState>> {
+            Ok(nifm::RequestState::from_raw(
+                self.request.get_request_state()?,
+            ))
+        }
+
+        /// Gets the result of the request, which is an error if it was rejected
+        #[inline]
+        pub fn get_result(&self) -> Result<()> {
+            self.request.get_result()
+        }
+
+        /// Waits until the request is processed (accepted or rejected) or the timeout expires
+        ///
+        /// The returned state is the one after waiting, and this will fail with the request result if it was rejected
+        ///
+        /// # Arguments
+        ///
+        /// * `timeout`: The maximum time to wait for, [`None`] means waiting indefinitely
+        pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Option<nifm::RequestState>> {
+            let start_tick = crate::arm::get_system_tick();
+            let timeout_ticks =
+                timeout.map(|timeout| crate::arm::nanoseconds_to_ticks(timeout.as_nanos() as u64));
+            loop {
+                for handle in self.event_handles {
+                    let _ = crate::svc::reset_signal(handle);
+                }
+
+                match self.get_state()? {
+                    Some(nifm::RequestState::OnHold) => {}
+                    Some(nifm::RequestState::Invalid) => {
+                        self.get_result()?;
+                        return Ok(Some(nifm::RequestState::Invalid));
+                    }
+                    state => return Ok(state),
+                }
+
+                let wait_timeout = match timeout_ticks {
+                    Some(timeout_ticks) => {
+                        let elapsed_ticks = crate::arm::get_system_tick().wrapping_sub(start_tick);
+                        if elapsed_ticks >= timeout_ticks {
+                            return self.get_state();
+                        }
+                        crate::arm::ticks_to_nanoseconds(timeout_ticks - elapsed_ticks) as i64
+                    }
+                    None => -1,
+                };
+                match crate::wait::wait_handles(&self.event_handles, wait_timeout) {
+                    Ok(_) => {}
+                    Err(rc) if crate::svc::rc::ResultTimedOut::matches(rc) => {
+                        return self.get_state();
+                    }
+                    Err(rc) => return Err(rc),
+                }
+            }
+        }
+
+        /// Submits the request and waits until it's processed (see [`ConnectionRequest::wait`]), returning whether it was accepted
+        ///
+        /// # Arguments
+        ///
+        /// * `timeout`: The maximum time to wait for, [`None`] means waiting indefinitely
+        pub fn submit_and_wait(&mut self, timeout: Option<Duration>) -> Result<bool> {
+            self.submit()?;
+            Ok(self.wait(timeout)? == Some(nifm::RequestState::Accepted))
+        }
+    }
+
+    impl Drop for ConnectionRequest {
+        /// Drops the [`ConnectionRequest`], cancelling it and closing the acquired event handles
+        fn drop(&mut self) {
+            let _ = self.request.cancel();
+            for handle in self.event_handles {
+                let _ = crate::svc::close_handle(handle);
+            }
+        }
+    }
+
     pub struct TcpListener(i32);
 
     impl TcpListener {
@@ -1,12 +1,25 @@
 //use core::arch::asm;
 
+#[cfg(not(test))]
 use crate::thread;
 
 pub mod futex;
 pub mod mutex;
 pub mod rwlock;
 
+#[cfg(not(test))]
 #[inline(always)]
 fn get_current_thread_handle() -> u32 {
     unsafe { (*thread::get_thread_local_region()).nx_thread_vars.handle }
 }
+
+// Host unit tests have no thread local region: any unique non-zero value per thread is enough for (uncontended) locking
+#[cfg(test)]
+fn get_current_thread_handle() -> u32 {
+    use core::sync::atomic::{AtomicU32, Ordering};
+    static NEXT_HANDLE: AtomicU32 = AtomicU32::new(1);
+    std::thread_local! {
+        static HANDLE: u32 = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    }
+    HANDLE.with(|handle| *handle)
+}
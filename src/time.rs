@@ -0,0 +1,355 @@
+//! Wall-clock time support
+//!
+//! This wraps the `time:u` services, which must be initialized first (see [`initialize`]):
+//!
+//! ```ignore
+//! nx::time::initialize()?;
+//!
+//! let now = nx::time::SystemTime::now()?;
+//! let local = now.to_calendar()?;
+//! let text = alloc::format!("It's {local}");
+//! ```
+
+use crate::ipc::sf as ipc_sf;
+use crate::result::*;
+use crate::service;
+use crate::service::time::{
+    IStaticServiceClient, ISystemClockClient, ITimeZoneServiceClient, SystemClock, TimeZoneService,
+    UserTimeService,
+};
+use crate::sync::RwLock;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use core::time::Duration;
+
+pub use crate::service::time::{
+    CalendarAdditionalInfo, CalendarTime, LocationName, PosixTime, TimeZoneName,
+};
+
+pub mod rc;
+
+pub mod tz;
+
+/// The maximum amount of POSIX times a calendar time can correspond to
+const POSIX_TIME_COUNT_MAX: usize = 2;
+
+/// Represents the system clocks
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Clock {
+    /// The user-configurable clock (synchronized with the network clock if automatic correction is enabled)
+    #[default]
+    User,
+    /// The network-synchronized clock
+    Network,
+    /// The local clock, which is set by the system
+    Local,
+}
+
+struct TimeContext {
+    user_clock: SystemClock,
+    network_clock: SystemClock,
+    local_clock: SystemClock,
+    time_zone_service: TimeZoneService,
+}
+
+static G_TIME_CONTEXT: RwLock<Option<TimeContext>> = RwLock::new(None);
+
+/// Initializes time support, opening the clocks and the time zone service through `time:u`
+pub fn initialize() -> Result<()> {
+    let mut guard = G_TIME_CONTEXT.write();
+    if guard.is_none() {
+        let time_srv = service::new_service_object::<UserTimeService>()?;
+        *guard = Some(TimeContext {
+            user_clock: time_srv.get_standard_user_system_clock()?,
+            network_clock: time_srv.get_standard_network_system_clock()?,
+            local_clock: time_srv.get_standard_local_system_clock()?,
+            time_zone_service: time_srv.get_time_zone_service()?,
+        });
+    }
+
+    Ok(())
+}
+
+/// Gets whether time support was initialized
+#[inline]
+pub fn is_initialized() -> bool {
+    G_TIME_CONTEXT.read().is_some()
+}
+
+/// Finalizes time support, closing the opened clocks and time zone service. Gets run in the rrt0 runtime after the main function runs.
+#[inline]
+pub(crate) fn finalize() {
+    *G_TIME_CONTEXT.write() = None;
+}
+
+fn with_context<T>(f: impl FnOnce(&TimeContext) -> Result<T>) -> Result<T> {
+    let guard = G_TIME_CONTEXT.read();
+    let ctx = guard
+        .as_ref()
+        .ok_or(super::rc::ResultNotInitialized::make())?;
+    f(ctx)
+}
+
+/// Gets the current POSIX time of a system clock
+///
+/// This will fail with [`ResultNotInitialized`][`super::rc::ResultNotInitialized`] if time support isn't initialized
+///
+/// # Arguments
+///
+/// * `clock`: The clock to use
+pub fn get_current_time(clock: Clock) -> Result<PosixTime> {
+    with_context(|ctx| match clock {
+        Clock::User => ctx.user_clock.get_current_time(),
+        Clock::Network => ctx.network_clock.get_current_time(),
+        Clock::Local => ctx.local_clock.get_current_time(),
+    })
+}
+
+/// Gets the location name of the device's time zone (for instance, `Europe/Madrid`)
+///
+/// This will fail with [`ResultNotInitialized`][`super::rc::ResultNotInitialized`] if time support isn't initialized
+pub fn get_device_location_name() -> Result<LocationName> {
+    with_context(|ctx| ctx.time_zone_service.get_device_location_name())
+}
+
+/// Converts a POSIX time to calendar time using the device's time zone rule
+///
+/// This will fail with [`ResultNotInitialized`][`super::rc::ResultNotInitialized`] if time support isn't initialized
+///
+/// # Arguments
+///
+/// * `time`: The POSIX time to convert
+pub fn to_calendar_time(time: PosixTime) -> Result<CalendarDateTime> {
+    let (time, info) =
+        with_context(|ctx| ctx.time_zone_service.to_calendar_time_with_my_rule(time))?;
+    Ok(CalendarDateTime { time, info })
+}
+
+/// Converts a calendar time to POSIX time using the device's time zone rule
+///
+/// The result holds two times if the calendar time is ambiguous (when clocks are set back), or none if it doesn't exist (when clocks are set forward)
+///
+/// This will fail with [`ResultNotInitialized`][`super::rc::ResultNotInitialized`] if time support isn't initialized
+///
+/// # Arguments
+///
+/// * `calendar_time`: The calendar time to convert
+pub fn to_posix_time(calendar_time: CalendarTime) -> Result<Vec<PosixTime>> {
+    let mut times = [0; POSIX_TIME_COUNT_MAX];
+    let count = with_context(|ctx| {
+        ctx.time_zone_service
+            .to_posix_time_with_my_rule(calendar_time, ipc_sf::Buffer::from_mut_array(&mut times))
+    })?;
+    Ok(times[..(count as usize).min(POSIX_TIME_COUNT_MAX)].to_vec())
+}
+
+/// Loads the time zone of a location from the system's time zone binary archive
+///
+/// The TZif data is read from the archive and parsed locally, so no time service is needed
+///
+/// This will fail with [`ResultNotInitialized`][`super::rc::ResultNotInitialized`] if `fsp-srv` support isn't initialized
+///
+/// # Arguments
+///
+/// * `location_name`: The location name (for instance, `Europe/Madrid`)
+#[cfg(feature = "fs")]
+pub fn load_time_zone(location_name: &str) -> Result<tz::TimeZone> {
+    use crate::fs::{self, FileSystem};
+    use crate::ipc::sf::ncm::{ContentType, ProgramId, StorageId};
+
+    const TIME_ZONE_BINARY_PROGRAM_ID: ProgramId = ProgramId(0x010000000000080E);
+
+    let archive_fs = fs::open_program_content(
+        TIME_ZONE_BINARY_PROGRAM_ID,
+        StorageId::BuiltInSystem,
+        ContentType::Data,
+        fs::FileSystemType::ContentData,
+    )?;
+    let path = alloc::format!("/zoneinfo/{location_name}");
+    let mut file = archive_fs.open_file(&path, fs::FileOpenMode::Read())?;
+
+    let mut data = alloc::vec![0u8; file.get_size()?];
+    let mut offset = 0;
+    while offset < data.len() {
+        let read_size = file.read(offset, &mut data[offset..], fs::FileReadOption::None())?;
+        result_return_if!(read_size == 0, rc::ResultInvalidTimeZoneBinary);
+        offset += read_size;
+    }
+
+    tz::TimeZone::parse(&data)
+}
+
+/// Represents a calendar time along with its additional info (day of week, time zone...)
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct CalendarDateTime {
+    /// The calendar time
+    pub time: CalendarTime,
+    /// The additional info
+    pub info: CalendarAdditionalInfo,
+}
+
+impl CalendarDateTime {
+    /// The English names of the days of the week, starting on Sunday
+    const WEEKDAY_NAMES: [&'static str; 7] = [
+        "Sunday",
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+    ];
+
+    /// Gets the English name of the day of the week
+    pub fn get_weekday_name(&self) -> &'static str {
+        Self::WEEKDAY_NAMES
+            .get(self.info.day_of_week as usize)
+            .copied()
+            .unwrap_or("")
+    }
+
+    /// Formats the date and time in ISO 8601 format (for instance, `2024-03-31T02:30:00+02:00`)
+    pub fn to_iso8601(&self) -> String {
+        let offset = self.info.utc_offset_seconds;
+        let sign = if offset < 0 { '-' } else { '+' };
+        let offset_minutes = offset.unsigned_abs() / 60;
+        alloc::format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}{:02}:{:02}",
+            self.time.year,
+            self.time.month,
+            self.time.day,
+            self.time.hour,
+            self.time.minute,
+            self.time.second,
+            sign,
+            offset_minutes / 60,
+            offset_minutes % 60
+        )
+    }
+}
+
+impl Display for CalendarDateTime {
+    /// Formats the date and time along with the time zone abbreviation (for instance, `2024-03-31 02:30:00 CEST`)
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.time.year,
+            self.time.month,
+            self.time.day,
+            self.time.hour,
+            self.time.minute,
+            self.time.second
+        )?;
+        match self.info.time_zone_name.get_str() {
+            Ok(name) if !name.is_empty() => write!(f, " {name}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Represents a point in wall-clock time, as a POSIX time
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct SystemTime(PosixTime);
+
+impl SystemTime {
+    /// The UNIX epoch (`1970-01-01 00:00:00 UTC`)
+    pub const UNIX_EPOCH: Self = Self(0);
+
+    /// Gets the current time from the user clock
+    ///
+    /// This will fail with [`ResultNotInitialized`][`super::rc::ResultNotInitialized`] if time support isn't initialized
+    #[inline]
+    pub fn now() -> Result<Self> {
+        Self::now_from(Clock::User)
+    }
+
+    /// Gets the current time from a given clock
+    ///
+    /// This will fail with [`ResultNotInitialized`][`super::rc::ResultNotInitialized`] if time support isn't initialized
+    ///
+    /// # Arguments
+    ///
+    /// * `clock`: The clock to use
+    #[inline]
+    pub fn now_from(clock: Clock) -> Result<Self> {
+        get_current_time(clock).map(Self)
+    }
+
+    /// Creates a [`SystemTime`] from a POSIX time
+    ///
+    /// # Arguments
+    ///
+    /// * `time`: The POSIX time
+    #[inline]
+    pub const fn from_posix_time(time: PosixTime) -> Self {
+        Self(time)
+    }
+
+    /// Gets the POSIX time
+    #[inline]
+    pub const fn get_posix_time(&self) -> PosixTime {
+        self.0
+    }
+
+    /// Gets the time elapsed since an earlier [`SystemTime`], if it's actually earlier
+    ///
+    /// # Arguments
+    ///
+    /// * `earlier`: The earlier time
+    pub fn duration_since(&self, earlier: SystemTime) -> Option<Duration> {
+        let seconds = self.0.checked_sub(earlier.0)?;
+        u64::try_from(seconds).ok().map(Duration::from_secs)
+    }
+
+    /// Adds a [`Duration`] (truncated to seconds), returning [`None`] on overflow
+    ///
+    /// # Arguments
+    ///
+    /// * `duration`: The duration to add
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        let seconds = i64::try_from(duration.as_secs()).ok()?;
+        self.0.checked_add(seconds).map(Self)
+    }
+
+    /// Subtracts a [`Duration`] (truncated to seconds), returning [`None`] on overflow
+    ///
+    /// # Arguments
+    ///
+    /// * `duration`: The duration to subtract
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        let seconds = i64::try_from(duration.as_secs()).ok()?;
+        self.0.checked_sub(seconds).map(Self)
+    }
+
+    /// Converts the time to calendar time using the device's time zone rule
+    ///
+    /// This will fail with [`ResultNotInitialized`][`super::rc::ResultNotInitialized`] if time support isn't initialized
+    #[inline]
+    pub fn to_calendar(&self) -> Result<CalendarDateTime> {
+        to_calendar_time(self.0)
+    }
+
+    /// Converts the time to calendar time in a given [`TimeZone`][`tz::TimeZone`]
+    ///
+    /// # Arguments
+    ///
+    /// * `time_zone`: The time zone to use
+    pub fn to_calendar_in(&self, time_zone: &tz::TimeZone) -> Result<CalendarDateTime> {
+        let (time, info) = time_zone.to_calendar_time(self.0)?;
+        Ok(CalendarDateTime { time, info })
+    }
+
+    /// Converts the time to UTC calendar time
+    #[inline]
+    pub fn to_calendar_utc(&self) -> Result<CalendarDateTime> {
+        self.to_calendar_in(&tz::TimeZone::utc())
+    }
+}
+
+impl From<PosixTime> for SystemTime {
+    fn from(time: PosixTime) -> Self {
+        Self(time)
+    }
+}
@@ -0,0 +1,13 @@
+//! Time-related result definitions
+
+use crate::rc;
+
+/// Result Submodule ID for the parent module
+pub const RESULT_SUBMODULE: u32 = 1600;
+
+result_define_subgroup!(rc::RESULT_MODULE, RESULT_SUBMODULE => {
+    InvalidTimeZoneBinary: 1,
+    InvalidTimeZoneRule: 2,
+    InvalidCalendarTime: 3,
+    OutOfRange: 4
+});
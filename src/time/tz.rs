@@ -0,0 +1,1056 @@
+//! Time zone rule support
+//!
+//! This parses TZif data (the format of the system's time zone binary archive, see [RFC 8536](https://datatracker.ietf.org/doc/html/rfc8536)) and POSIX TZ strings, and converts between POSIX and calendar times without relying on any service:
+//!
+//! ```ignore
+//! let tz = nx::time::tz::TimeZone::from_posix_rule("CET-1CEST,M3.5.0,M10.5.0/3")?;
+//! let (calendar_time, additional_info) = tz.to_calendar_time(1700000000)?;
+//! ```
+
+use super::rc;
+use crate::ipc::sf::time::{CalendarAdditionalInfo, CalendarTime, PosixTime, TimeZoneName};
+use crate::result::*;
+use alloc::vec::Vec;
+
+const SECONDS_PER_MINUTE: i64 = 60;
+const SECONDS_PER_HOUR: i64 = 60 * SECONDS_PER_MINUTE;
+const SECONDS_PER_DAY: i64 = 24 * SECONDS_PER_HOUR;
+
+/// The rule used when a POSIX TZ string has a DST name but no rule (the US rules)
+const DEFAULT_DST_RULE: &str = "M3.2.0,M11.1.0";
+
+/// Gets whether a year is a leap year (in the proleptic Gregorian calendar)
+///
+/// # Arguments
+///
+/// * `year`: The year to check
+#[inline]
+pub const fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0) && ((year % 100 != 0) || (year % 400 == 0))
+}
+
+/// Gets the amount of days of a month
+///
+/// # Arguments
+///
+/// * `year`: The year of the month
+/// * `month`: The month (`1`-`12`)
+pub const fn get_month_length(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Converts a civil date to days since the UNIX epoch
+const fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let shifted_month = (month + 9) % 12;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Converts days since the UNIX epoch to a civil date (year, month, day)
+const fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/// Gets the day of the week of a day since the UNIX epoch, where `0` is Sunday
+#[inline]
+const fn weekday_from_days(days: i64) -> i64 {
+    // 1970-01-01 was a Thursday
+    (days + 4).rem_euclid(7)
+}
+
+/// Represents a local time type of a time zone
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LocalTimeType {
+    /// The offset from UTC, in seconds (positive east of Greenwich)
+    pub utc_offset: i32,
+    /// Whether this is a daylight saving time type
+    pub is_dst: bool,
+    /// The abbreviation of the type (for instance, `CEST`)
+    pub name: TimeZoneName,
+}
+
+impl LocalTimeType {
+    /// The UTC [`LocalTimeType`]
+    pub const UTC: Self = Self {
+        utc_offset: 0,
+        is_dst: false,
+        name: TimeZoneName::from_str_truncate_null("UTC"),
+    };
+}
+
+/// Represents the day a POSIX TZ string rule transition happens in
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum RuleDay {
+    /// `Jn`: Julian day `1`-`365`, February 29th is never counted
+    Julian1(i64),
+    /// `n`: Zero-based Julian day `0`-`365`, February 29th is counted in leap years
+    Julian0(i64),
+    /// `Mm.w.d`: Day `d` (`0` is Sunday) of week `w` (`5` means the last one) of month `m`
+    MonthWeekDay { month: i64, week: i64, day: i64 },
+}
+
+impl RuleDay {
+    /// Gets the zero-based day of the year this rule day falls on
+    fn get_day_of_year(self, year: i64) -> i64 {
+        match self {
+            Self::Julian1(day) => {
+                if is_leap_year(year) && day >= 60 {
+                    day
+                } else {
+                    day - 1
+                }
+            }
+            Self::Julian0(day) => day,
+            Self::MonthWeekDay { month, week, day } => {
+                let month_start = days_from_civil(year, month, 1);
+                let first_weekday = weekday_from_days(month_start);
+                let mut month_day = (day - first_weekday).rem_euclid(7) + (week - 1) * 7;
+                if month_day >= get_month_length(year, month) {
+                    month_day -= 7;
+                }
+                month_start - days_from_civil(year, 1, 1) + month_day
+            }
+        }
+    }
+}
+
+/// Represents the DST part of a POSIX TZ string
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct DstRule {
+    dst: LocalTimeType,
+    start_day: RuleDay,
+    /// Local (standard) time of the day when DST starts, in seconds
+    start_time: i64,
+    end_day: RuleDay,
+    /// Local (daylight) time of the day when DST ends, in seconds
+    end_time: i64,
+}
+
+/// Represents a parsed POSIX TZ string (for instance, `CET-1CEST,M3.5.0,M10.5.0/3`)
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct PosixRule {
+    std: LocalTimeType,
+    dst: Option<DstRule>,
+}
+
+impl PosixRule {
+    /// Gets the UTC time of a transition in a given year
+    fn get_transition_time(day: RuleDay, time: i64, year: i64, utc_offset: i32) -> i64 {
+        let year_start = days_from_civil(year, 1, 1);
+        (year_start + day.get_day_of_year(year)) * SECONDS_PER_DAY + time - utc_offset as i64
+    }
+
+    fn find_local_time_type(&self, time: PosixTime) -> &LocalTimeType {
+        match &self.dst {
+            None => &self.std,
+            Some(dst) => {
+                let local_std = time.saturating_add(self.std.utc_offset as i64);
+                let (year, _, _) = civil_from_days(local_std.div_euclid(SECONDS_PER_DAY));
+                let start = Self::get_transition_time(
+                    dst.start_day,
+                    dst.start_time,
+                    year,
+                    self.std.utc_offset,
+                );
+                let end =
+                    Self::get_transition_time(dst.end_day, dst.end_time, year, dst.dst.utc_offset);
+
+                let is_dst = if start <= end {
+                    // Northern hemisphere: DST in the middle of the year
+                    (start <= time) && (time < end)
+                } else {
+                    // Southern hemisphere: DST at the start and end of the year
+                    (time < end) || (start <= time)
+                };
+                if is_dst { &dst.dst } else { &self.std }
+            }
+        }
+    }
+}
+
+/// Simple parser for POSIX TZ strings
+struct RuleParser<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> RuleParser<'a> {
+    fn new(rule: &'a str) -> Self {
+        Self {
+            data: rule.as_bytes(),
+            offset: 0,
+        }
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.offset).copied()
+    }
+
+    #[inline]
+    fn is_done(&self) -> bool {
+        self.offset >= self.data.len()
+    }
+
+    fn accept(&mut self, ch: u8) -> bool {
+        if self.peek() == Some(ch) {
+            self.offset += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, ch: u8) -> Result<()> {
+        result_return_unless!(self.accept(ch), rc::ResultInvalidTimeZoneRule);
+        Ok(())
+    }
+
+    fn parse_name(&mut self) -> Result<TimeZoneName> {
+        let (start, end) = if self.accept(b'<') {
+            let start = self.offset;
+            while let Some(ch) = self.peek() {
+                if ch == b'>' {
+                    break;
+                }
+                result_return_unless!(
+                    ch.is_ascii_alphanumeric() || (ch == b'+') || (ch == b'-'),
+                    rc::ResultInvalidTimeZoneRule
+                );
+                self.offset += 1;
+            }
+            let end = self.offset;
+            self.expect(b'>')?;
+            (start, end)
+        } else {
+            let start = self.offset;
+            while self.peek().is_some_and(|ch| ch.is_ascii_alphabetic()) {
+                self.offset += 1;
+            }
+            (start, self.offset)
+        };
+
+        result_return_unless!(end - start >= 3, rc::ResultInvalidTimeZoneRule);
+        // Only ASCII characters were accepted above
+        let name = core::str::from_utf8(&self.data[start..end])
+            .map_err(|_| rc::ResultInvalidTimeZoneRule::make())?;
+        Ok(TimeZoneName::from_str_truncate_null(name))
+    }
+
+    fn parse_number(&mut self, min: i64, max: i64) -> Result<i64> {
+        let start = self.offset;
+        let mut value: i64 = 0;
+        while let Some(ch) = self.peek().filter(u8::is_ascii_digit) {
+            value = value * 10 + (ch - b'0') as i64;
+            result_return_unless!(value <= max, rc::ResultInvalidTimeZoneRule);
+            self.offset += 1;
+        }
+        result_return_unless!(
+            (self.offset > start) && (value >= min),
+            rc::ResultInvalidTimeZoneRule
+        );
+        Ok(value)
+    }
+
+    /// Parses a `[+-]hh[:mm[:ss]]` value, returning it in seconds
+    fn parse_hms(&mut self, max_hours: i64) -> Result<i64> {
+        let sign = if self.accept(b'-') {
+            -1
+        } else {
+            self.accept(b'+');
+            1
+        };
+
+        let mut seconds = self.parse_number(0, max_hours)? * SECONDS_PER_HOUR;
+        if self.accept(b':') {
+            seconds += self.parse_number(0, 59)? * SECONDS_PER_MINUTE;
+            if self.accept(b':') {
+                seconds += self.parse_number(0, 59)?;
+            }
+        }
+        Ok(sign * seconds)
+    }
+
+    /// Parses a POSIX offset, which is positive west of Greenwich (the opposite of UTC offsets)
+    fn parse_utc_offset(&mut self) -> Result<i32> {
+        Ok(-self.parse_hms(24)? as i32)
+    }
+
+    fn parse_rule_day(&mut self) -> Result<RuleDay> {
+        if self.accept(b'J') {
+            Ok(RuleDay::Julian1(self.parse_number(1, 365)?))
+        } else if self.accept(b'M') {
+            let month = self.parse_number(1, 12)?;
+            self.expect(b'.')?;
+            let week = self.parse_number(1, 5)?;
+            self.expect(b'.')?;
+            let day = self.parse_number(0, 6)?;
+            Ok(RuleDay::MonthWeekDay { month, week, day })
+        } else {
+            Ok(RuleDay::Julian0(self.parse_number(0, 365)?))
+        }
+    }
+
+    fn parse_rule_time(&mut self) -> Result<i64> {
+        if self.accept(b'/') {
+            // Version 3+ TZif footers allow hours in the -167..=167 range
+            self.parse_hms(167)
+        } else {
+            Ok(2 * SECONDS_PER_HOUR)
+        }
+    }
+
+    fn parse_rule_days(&mut self) -> Result<(RuleDay, i64, RuleDay, i64)> {
+        let start_day = self.parse_rule_day()?;
+        let start_time = self.parse_rule_time()?;
+        self.expect(b',')?;
+        let end_day = self.parse_rule_day()?;
+        let end_time = self.parse_rule_time()?;
+        Ok((start_day, start_time, end_day, end_time))
+    }
+
+    fn parse(mut self) -> Result<PosixRule> {
+        let std = LocalTimeType {
+            name: self.parse_name()?,
+            utc_offset: self.parse_utc_offset()?,
+            is_dst: false,
+        };
+        if self.is_done() {
+            return Ok(PosixRule { std, dst: None });
+        }
+
+        let dst_name = self.parse_name()?;
+        let dst_utc_offset = match self.peek() {
+            None | Some(b',') => std.utc_offset + SECONDS_PER_HOUR as i32,
+            Some(_) => self.parse_utc_offset()?,
+        };
+
+        let (start_day, start_time, end_day, end_time) = if self.accept(b',') {
+            self.parse_rule_days()?
+        } else {
+            result_return_unless!(self.is_done(), rc::ResultInvalidTimeZoneRule);
+            RuleParser::new(DEFAULT_DST_RULE).parse_rule_days()?
+        };
+        result_return_unless!(self.is_done(), rc::ResultInvalidTimeZoneRule);
+
+        Ok(PosixRule {
+            std,
+            dst: Some(DstRule {
+                dst: LocalTimeType {
+                    name: dst_name,
+                    utc_offset: dst_utc_offset,
+                    is_dst: true,
+                },
+                start_day,
+                start_time,
+                end_day,
+                end_time,
+            }),
+        })
+    }
+}
+
+/// Simple big-endian reader for TZif data
+struct TzifReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> TzifReader<'a> {
+    fn read_bytes(&mut self, size: usize) -> Result<&'a [u8]> {
+        let end = self
+            .offset
+            .checked_add(size)
+            .ok_or(rc::ResultInvalidTimeZoneBinary::make())?;
+        let bytes = self
+            .data
+            .get(self.offset..end)
+            .ok_or(rc::ResultInvalidTimeZoneBinary::make())?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn get_remaining_size(&self) -> usize {
+        self.data.len() - self.offset
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.read_array()?))
+    }
+
+    fn read_i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.read_array()?))
+    }
+
+    fn read_i64(&mut self) -> Result<i64> {
+        Ok(i64::from_be_bytes(self.read_array()?))
+    }
+
+    fn read_time(&mut self, time_size: usize) -> Result<PosixTime> {
+        match time_size {
+            4 => Ok(self.read_i32()? as PosixTime),
+            _ => self.read_i64(),
+        }
+    }
+}
+
+/// Represents a TZif header
+struct TzifHeader {
+    version: u8,
+    is_ut_count: usize,
+    is_std_count: usize,
+    leap_count: usize,
+    time_count: usize,
+    type_count: usize,
+    char_count: usize,
+    data_size: usize,
+}
+
+impl TzifHeader {
+    const MAGIC: &'static [u8; 4] = b"TZif";
+
+    fn read(reader: &mut TzifReader, time_size: usize) -> Result<Self> {
+        result_return_unless!(
+            reader.read_bytes(4)? == Self::MAGIC,
+            rc::ResultInvalidTimeZoneBinary
+        );
+        let version = reader.read_u8()?;
+        reader.read_bytes(15)?;
+
+        let mut header = Self {
+            version,
+            is_ut_count: reader.read_u32()? as usize,
+            is_std_count: reader.read_u32()? as usize,
+            leap_count: reader.read_u32()? as usize,
+            time_count: reader.read_u32()? as usize,
+            type_count: reader.read_u32()? as usize,
+            char_count: reader.read_u32()? as usize,
+            data_size: 0,
+        };
+        result_return_unless!(
+            (header.type_count != 0)
+                && (header.char_count != 0)
+                && ((header.is_ut_count == 0) || (header.is_ut_count == header.type_count))
+                && ((header.is_std_count == 0) || (header.is_std_count == header.type_count)),
+            rc::ResultInvalidTimeZoneBinary
+        );
+
+        // The counts are untrusted, check that the data block they describe is actually there
+        header.data_size = header
+            .get_data_size(time_size)
+            .ok_or(rc::ResultInvalidTimeZoneBinary::make())?;
+        result_return_unless!(
+            header.data_size <= reader.get_remaining_size(),
+            rc::ResultInvalidTimeZoneBinary
+        );
+        Ok(header)
+    }
+
+    /// Gets the size of the data block following the header, if it doesn't overflow
+    fn get_data_size(&self, time_size: usize) -> Option<usize> {
+        self.time_count
+            .checked_mul(time_size + 1)?
+            .checked_add(self.type_count.checked_mul(6)?)?
+            .checked_add(self.char_count)?
+            .checked_add(self.leap_count.checked_mul(time_size + 4)?)?
+            .checked_add(self.is_std_count)?
+            .checked_add(self.is_ut_count)
+    }
+}
+
+/// Represents a time zone, with its historical transitions and its rule for future times
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TimeZone {
+    transitions: Vec<PosixTime>,
+    transition_types: Vec<usize>,
+    types: Vec<LocalTimeType>,
+    rule: Option<PosixRule>,
+}
+
+impl TimeZone {
+    /// Creates the UTC [`TimeZone`]
+    pub fn utc() -> Self {
+        Self {
+            transitions: Vec::new(),
+            transition_types: Vec::new(),
+            types: alloc::vec![LocalTimeType::UTC],
+            rule: None,
+        }
+    }
+
+    /// Creates a [`TimeZone`] from a POSIX TZ string (for instance, `CET-1CEST,M3.5.0,M10.5.0/3`)
+    ///
+    /// # Arguments
+    ///
+    /// * `rule`: The TZ string to parse
+    pub fn from_posix_rule(rule: &str) -> Result<Self> {
+        let rule = RuleParser::new(rule).parse()?;
+        Ok(Self {
+            transitions: Vec::new(),
+            transition_types: Vec::new(),
+            types: alloc::vec![rule.std],
+            rule: Some(rule),
+        })
+    }
+
+    /// Parses a [`TimeZone`] from TZif data
+    ///
+    /// Version 1 data and version 2+ data (using the 64-bit data block and the TZ string footer) are supported
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: The TZif data
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut reader = TzifReader { data, offset: 0 };
+        let mut time_size = 4;
+        let mut header = TzifHeader::read(&mut reader, time_size)?;
+        if header.version >= b'2' {
+            // Skip the legacy 32-bit data block, the second header and block follow it
+            reader.read_bytes(header.data_size)?;
+            time_size = 8;
+            header = TzifHeader::read(&mut reader, time_size)?;
+        }
+
+        let mut transitions = Vec::with_capacity(header.time_count);
+        for _ in 0..header.time_count {
+            let time = reader.read_time(time_size)?;
+            result_return_unless!(
+                transitions.last().is_none_or(|&last| last < time),
+                rc::ResultInvalidTimeZoneBinary
+            );
+            transitions.push(time);
+        }
+
+        let mut transition_types = Vec::with_capacity(header.time_count);
+        for _ in 0..header.time_count {
+            let type_idx = reader.read_u8()? as usize;
+            result_return_unless!(
+                type_idx < header.type_count,
+                rc::ResultInvalidTimeZoneBinary
+            );
+            transition_types.push(type_idx);
+        }
+
+        let mut raw_types = Vec::with_capacity(header.type_count);
+        for _ in 0..header.type_count {
+            let utc_offset = reader.read_i32()?;
+            let is_dst = reader.read_u8()?;
+            let name_idx = reader.read_u8()? as usize;
+            result_return_unless!(
+                (utc_offset != i32::MIN) && (is_dst <= 1) && (name_idx < header.char_count),
+                rc::ResultInvalidTimeZoneBinary
+            );
+            raw_types.push((utc_offset, is_dst != 0, name_idx));
+        }
+
+        let names = reader.read_bytes(header.char_count)?;
+        let types = raw_types
+            .into_iter()
+            .map(|(utc_offset, is_dst, name_idx)| {
+                let name = &names[name_idx..];
+                let name_len = name.iter().position(|&ch| ch == 0).unwrap_or(name.len());
+                let name = core::str::from_utf8(&name[..name_len])
+                    .map_err(|_| rc::ResultInvalidTimeZoneBinary::make())?;
+                Ok(LocalTimeType {
+                    utc_offset,
+                    is_dst,
+                    name: TimeZoneName::from_str_truncate_null(name),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Leap second records and standard/UT indicators aren't needed for conversions
+        reader.read_bytes(
+            header.leap_count * (time_size + 4) + header.is_std_count + header.is_ut_count,
+        )?;
+
+        let mut rule = None;
+        if header.version >= b'2' {
+            result_return_unless!(reader.read_u8()? == b'\n', rc::ResultInvalidTimeZoneBinary);
+            let footer = &data[reader.offset..];
+            let footer_len = footer
+                .iter()
+                .position(|&ch| ch == b'\n')
+                .ok_or(rc::ResultInvalidTimeZoneBinary::make())?;
+            if footer_len > 0 {
+                let footer = core::str::from_utf8(&footer[..footer_len])
+                    .map_err(|_| rc::ResultInvalidTimeZoneBinary::make())?;
+                rule = Some(RuleParser::new(footer).parse()?);
+            }
+        }
+
+        Ok(Self {
+            transitions,
+            transition_types,
+            types,
+            rule,
+        })
+    }
+
+    /// Gets the [`LocalTimeType`] in effect at a given time
+    ///
+    /// # Arguments
+    ///
+    /// * `time`: The POSIX time
+    pub fn find_local_time_type(&self, time: PosixTime) -> &LocalTimeType {
+        if let Some(rule) = &self.rule
+            && self.transitions.last().is_none_or(|&last| time >= last)
+        {
+            return rule.find_local_time_type(time);
+        }
+
+        match self
+            .transitions
+            .partition_point(|&transition| transition <= time)
+        {
+            // Times before the first transition use the first type
+            0 => &self.types[0],
+            count => &self.types[self.transition_types[count - 1]],
+        }
+    }
+
+    /// Converts a POSIX time to calendar time in this time zone
+    ///
+    /// # Arguments
+    ///
+    /// * `time`: The POSIX time to convert
+    pub fn to_calendar_time(
+        &self,
+        time: PosixTime,
+    ) -> Result<(CalendarTime, CalendarAdditionalInfo)> {
+        let local_time_type = self.find_local_time_type(time);
+        let local_time = time
+            .checked_add(local_time_type.utc_offset as i64)
+            .ok_or(rc::ResultOutOfRange::make())?;
+
+        let days = local_time.div_euclid(SECONDS_PER_DAY);
+        let day_seconds = local_time.rem_euclid(SECONDS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+
+        let calendar_time = CalendarTime {
+            year: i16::try_from(year).map_err(|_| rc::ResultOutOfRange::make())?,
+            month: month as i8,
+            day: day as i8,
+            hour: (day_seconds / SECONDS_PER_HOUR) as i8,
+            minute: ((day_seconds % SECONDS_PER_HOUR) / SECONDS_PER_MINUTE) as i8,
+            second: (day_seconds % SECONDS_PER_MINUTE) as i8,
+            pad: 0,
+        };
+        let additional_info = CalendarAdditionalInfo {
+            day_of_week: weekday_from_days(days) as u32,
+            day_of_year: (days - days_from_civil(year, 1, 1)) as u32,
+            time_zone_name: local_time_type.name,
+            is_dst: local_time_type.is_dst as u32,
+            utc_offset_seconds: local_time_type.utc_offset,
+        };
+        Ok((calendar_time, additional_info))
+    }
+
+    /// Converts a calendar time in this time zone to POSIX time
+    ///
+    /// The result is sorted and holds two times if the calendar time is ambiguous (when clocks are set back), or none if it doesn't exist (when clocks are set forward)
+    ///
+    /// # Arguments
+    ///
+    /// * `calendar_time`: The calendar time to convert
+    pub fn to_posix_time(&self, calendar_time: &CalendarTime) -> Result<Vec<PosixTime>> {
+        let year = calendar_time.year as i64;
+        let month = calendar_time.month as i64;
+        let day = calendar_time.day as i64;
+        let hour = calendar_time.hour as i64;
+        let minute = calendar_time.minute as i64;
+        let second = calendar_time.second as i64;
+        result_return_unless!(
+            (1..=12).contains(&month)
+                && (1..=get_month_length(year, month)).contains(&day)
+                && (0..24).contains(&hour)
+                && (0..60).contains(&minute)
+                && (0..60).contains(&second),
+            rc::ResultInvalidCalendarTime
+        );
+
+        let local_time = days_from_civil(year, month, day) * SECONDS_PER_DAY
+            + hour * SECONDS_PER_HOUR
+            + minute * SECONDS_PER_MINUTE
+            + second;
+
+        let rule_types = self.rule.iter().flat_map(|rule| {
+            core::iter::once(&rule.std).chain(rule.dst.as_ref().map(|dst| &dst.dst))
+        });
+        let mut times = Vec::new();
+        for local_time_type in self.types.iter().chain(rule_types) {
+            // The time is valid if the offset it was computed with is the one in effect at it
+            let time = local_time - local_time_type.utc_offset as i64;
+            if self.find_local_time_type(time).utc_offset == local_time_type.utc_offset {
+                times.push(time);
+            }
+        }
+        times.sort_unstable();
+        times.dedup();
+        Ok(times)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a TZif header and data block with no leap seconds nor indicators
+    fn make_tzif_block(
+        version: u8,
+        time_size: usize,
+        transitions: &[(i64, u8)],
+        types: &[(i32, u8, u8)],
+        names: &[u8],
+    ) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"TZif");
+        data.push(version);
+        data.extend_from_slice(&[0; 15]);
+        for count in [0, 0, 0, transitions.len(), types.len(), names.len()] {
+            data.extend_from_slice(&(count as u32).to_be_bytes());
+        }
+        for &(time, _) in transitions {
+            data.extend_from_slice(&time.to_be_bytes()[8 - time_size..]);
+        }
+        data.extend(transitions.iter().map(|&(_, type_idx)| type_idx));
+        for &(utc_offset, is_dst, name_idx) in types {
+            data.extend_from_slice(&utc_offset.to_be_bytes());
+            data.extend_from_slice(&[is_dst, name_idx]);
+        }
+        data.extend_from_slice(names);
+        data
+    }
+
+    const TRANSITIONS: &[(i64, u8)] = &[(1000, 1), (2000, 0)];
+    const TYPES: &[(i32, u8, u8)] = &[(0, 0, 0), (3600, 1, 4)];
+    const NAMES: &[u8] = b"STD\0DST\0";
+
+    fn get_type_name(tz: &TimeZone, time: PosixTime) -> &str {
+        tz.find_local_time_type(time).name.get_str().unwrap()
+    }
+
+    #[test]
+    fn parse_v1() {
+        let data = make_tzif_block(0, 4, TRANSITIONS, TYPES, NAMES);
+        let tz = TimeZone::parse(&data).unwrap();
+        assert_eq!(get_type_name(&tz, 0), "STD");
+        assert_eq!(get_type_name(&tz, 1500), "DST");
+        assert_eq!(tz.find_local_time_type(1500).utc_offset, 3600);
+        assert_eq!(get_type_name(&tz, 2500), "STD");
+
+        let (calendar_time, additional_info) = tz.to_calendar_time(1500).unwrap();
+        assert_eq!((calendar_time.hour, calendar_time.minute), (1, 25));
+        assert_eq!(additional_info.is_dst, 1);
+    }
+
+    #[test]
+    fn parse_v2_footer() {
+        let mut data = make_tzif_block(b'2', 4, TRANSITIONS, TYPES, NAMES);
+        data.extend(make_tzif_block(b'2', 8, TRANSITIONS, TYPES, NAMES));
+        data.extend_from_slice(b"\nFOO-2\n");
+        let tz = TimeZone::parse(&data).unwrap();
+        assert_eq!(get_type_name(&tz, 1500), "DST");
+        assert_eq!(get_type_name(&tz, 2500), "FOO");
+        assert_eq!(tz.find_local_time_type(2500).utc_offset, 7200);
+    }
+
+    #[test]
+    fn parse_v2_dst_footer() {
+        let mut data = make_tzif_block(b'2', 4, TRANSITIONS, TYPES, NAMES);
+        data.extend(make_tzif_block(b'2', 8, TRANSITIONS, TYPES, NAMES));
+        data.extend_from_slice(b"\nCET-1CEST,M3.5.0,M10.5.0/3\n");
+        let tz = TimeZone::parse(&data).unwrap();
+
+        // The transitions are used up to the last one, then the rule
+        assert_eq!(get_type_name(&tz, 500), "STD");
+        assert_eq!(get_type_name(&tz, 1500), "DST");
+        assert_eq!(get_type_name(&tz, 2000), "CET");
+        assert_eq!(get_type_name(&tz, 1711846799), "CET");
+        assert_eq!(get_type_name(&tz, 1711846800), "CEST");
+
+        // Only the types in effect at the resulting times count
+        let times = tz
+            .to_posix_time(&make_calendar_time(2024, 10, 27, 2, 30, 0))
+            .unwrap();
+        assert_eq!(times, [1729989000, 1729992600]);
+    }
+
+    #[test]
+    fn parse_truncated() {
+        let data = make_tzif_block(0, 4, TRANSITIONS, TYPES, NAMES);
+        for size in [0, 4, 0x2C, data.len() - 1] {
+            let rc = TimeZone::parse(&data[..size]).unwrap_err();
+            assert!(rc::ResultInvalidTimeZoneBinary::matches(rc));
+        }
+    }
+
+    #[test]
+    fn parse_oversized_counts() {
+        // Counts way past the data size must be rejected before anything is allocated for them
+        let time_count_offset = 0x20;
+        for count in [0x1000_0000u32, u32::MAX] {
+            let mut data = make_tzif_block(0, 4, TRANSITIONS, TYPES, NAMES);
+            data[time_count_offset..time_count_offset + 4].copy_from_slice(&count.to_be_bytes());
+            let rc = TimeZone::parse(&data).unwrap_err();
+            assert!(rc::ResultInvalidTimeZoneBinary::matches(rc));
+        }
+
+        let mut data = make_tzif_block(b'2', 4, TRANSITIONS, TYPES, NAMES);
+        let second_header_offset = data.len();
+        data.extend(make_tzif_block(b'2', 8, TRANSITIONS, TYPES, NAMES));
+        data.extend_from_slice(b"\n\n");
+        let char_count_offset = second_header_offset + 0x28;
+        data[char_count_offset..char_count_offset + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        let rc = TimeZone::parse(&data).unwrap_err();
+        assert!(rc::ResultInvalidTimeZoneBinary::matches(rc));
+    }
+
+    fn make_calendar_time(
+        year: i16,
+        month: i8,
+        day: i8,
+        hour: i8,
+        minute: i8,
+        second: i8,
+    ) -> CalendarTime {
+        CalendarTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            pad: 0,
+        }
+    }
+
+    #[test]
+    fn rule_day_of_year() {
+        let month_week_day = |month, week, day| RuleDay::MonthWeekDay { month, week, day };
+
+        // Last Sundays of March and October (the EU rules)
+        assert_eq!(month_week_day(3, 5, 0).get_day_of_year(2024), 90);
+        assert_eq!(month_week_day(3, 5, 0).get_day_of_year(2023), 84);
+        assert_eq!(month_week_day(10, 5, 0).get_day_of_year(2024), 300);
+        // Second Sunday of March (the US rule)
+        assert_eq!(month_week_day(3, 2, 0).get_day_of_year(2024), 69);
+        // The first day of the month is the requested weekday (2024-01-01 was a Monday)
+        assert_eq!(month_week_day(1, 1, 1).get_day_of_year(2024), 0);
+        assert_eq!(month_week_day(1, 1, 0).get_day_of_year(2024), 6);
+        // Week 5 falls on the 5th weekday if there's one (February 29th 2024 was a Thursday), else on the 4th one
+        assert_eq!(month_week_day(2, 5, 4).get_day_of_year(2024), 59);
+        assert_eq!(month_week_day(2, 5, 0).get_day_of_year(2024), 55);
+        assert_eq!(month_week_day(12, 5, 0).get_day_of_year(2023), 364);
+
+        // February 29th is never counted by `Jn`, thus J60 is always March 1st
+        assert_eq!(RuleDay::Julian1(59).get_day_of_year(2024), 58);
+        assert_eq!(RuleDay::Julian1(60).get_day_of_year(2024), 60);
+        assert_eq!(RuleDay::Julian1(60).get_day_of_year(2023), 59);
+        assert_eq!(RuleDay::Julian1(365).get_day_of_year(2024), 365);
+        assert_eq!(RuleDay::Julian1(365).get_day_of_year(2023), 364);
+        assert_eq!(RuleDay::Julian0(59).get_day_of_year(2024), 59);
+        assert_eq!(RuleDay::Julian0(59).get_day_of_year(2023), 59);
+    }
+
+    #[test]
+    fn parse_posix_rule() {
+        let rule = RuleParser::new("CET-1CEST,M3.5.0,M10.5.0/3")
+            .parse()
+            .unwrap();
+        assert_eq!(rule.std.utc_offset, 3600);
+        let dst = rule.dst.unwrap();
+        assert_eq!(dst.dst.utc_offset, 7200);
+        assert!(dst.dst.is_dst);
+        assert_eq!(
+            (dst.start_day, dst.start_time),
+            (
+                RuleDay::MonthWeekDay {
+                    month: 3,
+                    week: 5,
+                    day: 0
+                },
+                2 * SECONDS_PER_HOUR
+            )
+        );
+        assert_eq!(
+            (dst.end_day, dst.end_time),
+            (
+                RuleDay::MonthWeekDay {
+                    month: 10,
+                    week: 5,
+                    day: 0
+                },
+                3 * SECONDS_PER_HOUR
+            )
+        );
+
+        let rule = RuleParser::new("<+0330>-3:30<+0430>,J79/24,J263/24")
+            .parse()
+            .unwrap();
+        assert_eq!(rule.std.name.get_str().unwrap(), "+0330");
+        assert_eq!(rule.std.utc_offset, 3 * 3600 + 1800);
+        let dst = rule.dst.unwrap();
+        assert_eq!(dst.dst.utc_offset, rule.std.utc_offset + 3600);
+        assert_eq!(
+            (dst.start_day, dst.start_time),
+            (RuleDay::Julian1(79), 24 * SECONDS_PER_HOUR)
+        );
+
+        for rule in [
+            "",
+            "CET",
+            "CET-1CEST,M3.5.0",
+            "CET-1CEST,M13.1.0,M10.5.0",
+            "CET-1CEST,M3.5.0,M10.5.0/3x",
+        ] {
+            let rc = RuleParser::new(rule).parse().unwrap_err();
+            assert!(rc::ResultInvalidTimeZoneRule::matches(rc));
+        }
+    }
+
+    #[test]
+    fn northern_hemisphere_rule() {
+        let tz = TimeZone::from_posix_rule("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+
+        // DST starts at 2024-03-31 01:00 UTC and ends at 2024-10-27 01:00 UTC
+        let dst_start = 1711846800;
+        let dst_end = 1729990800;
+        assert_eq!(get_type_name(&tz, 1704067200), "CET");
+        assert_eq!(get_type_name(&tz, dst_start - 1), "CET");
+        assert_eq!(get_type_name(&tz, dst_start), "CEST");
+        assert_eq!(get_type_name(&tz, dst_end - 1), "CEST");
+        assert_eq!(get_type_name(&tz, dst_end), "CET");
+
+        let (calendar_time, additional_info) = tz.to_calendar_time(dst_start - 1).unwrap();
+        assert_eq!(calendar_time, make_calendar_time(2024, 3, 31, 1, 59, 59));
+        assert_eq!(additional_info.is_dst, 0);
+        let (calendar_time, additional_info) = tz.to_calendar_time(dst_start).unwrap();
+        assert_eq!(calendar_time, make_calendar_time(2024, 3, 31, 3, 0, 0));
+        assert_eq!(additional_info.is_dst, 1);
+        assert_eq!(additional_info.utc_offset_seconds, 7200);
+        assert_eq!(additional_info.day_of_week, 0);
+        assert_eq!(additional_info.day_of_year, 90);
+        assert_eq!(additional_info.time_zone_name.get_str().unwrap(), "CEST");
+    }
+
+    #[test]
+    fn southern_hemisphere_rule() {
+        let tz = TimeZone::from_posix_rule("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+
+        // DST ends at 2024-04-06 16:00 UTC and starts again at 2024-10-05 16:00 UTC
+        let dst_end = 1712419200;
+        let dst_start = 1728144000;
+        assert_eq!(get_type_name(&tz, 1704067200), "AEDT");
+        assert_eq!(get_type_name(&tz, dst_end - 1), "AEDT");
+        assert_eq!(get_type_name(&tz, dst_end), "AEST");
+        assert_eq!(get_type_name(&tz, 1719828000), "AEST");
+        assert_eq!(get_type_name(&tz, dst_start - 1), "AEST");
+        assert_eq!(get_type_name(&tz, dst_start), "AEDT");
+
+        // Around the new year, on both sides of the (standard) local year change
+        assert_eq!(get_type_name(&tz, 1735651800), "AEDT");
+        assert_eq!(get_type_name(&tz, 1735655400), "AEDT");
+        let (calendar_time, _) = tz.to_calendar_time(1735651800).unwrap();
+        assert_eq!(calendar_time, make_calendar_time(2025, 1, 1, 0, 30, 0));
+    }
+
+    #[test]
+    fn default_dst_rule() {
+        let tz = TimeZone::from_posix_rule("EST5EDT").unwrap();
+
+        // The US rules: DST starts at 2024-03-10 07:00 UTC and ends at 2024-11-03 06:00 UTC
+        assert_eq!(get_type_name(&tz, 1710054000 - 1), "EST");
+        assert_eq!(get_type_name(&tz, 1710054000), "EDT");
+        assert_eq!(tz.find_local_time_type(1710054000).utc_offset, -4 * 3600);
+        assert_eq!(get_type_name(&tz, 1730613600 - 1), "EDT");
+        assert_eq!(get_type_name(&tz, 1730613600), "EST");
+    }
+
+    #[test]
+    fn to_posix_time_gap_and_overlap() {
+        let tz = TimeZone::from_posix_rule("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        let to_posix_time = |calendar_time| tz.to_posix_time(&calendar_time).unwrap();
+
+        // Regular times
+        assert_eq!(
+            to_posix_time(make_calendar_time(2024, 7, 1, 12, 0, 0)),
+            [1719828000]
+        );
+        assert_eq!(
+            to_posix_time(make_calendar_time(2024, 3, 31, 1, 59, 59)),
+            [1711846799]
+        );
+        assert_eq!(
+            to_posix_time(make_calendar_time(2024, 3, 31, 3, 0, 0)),
+            [1711846800]
+        );
+
+        // Clocks are set forward from 02:00 to 03:00, so these don't exist
+        assert!(to_posix_time(make_calendar_time(2024, 3, 31, 2, 0, 0)).is_empty());
+        assert!(to_posix_time(make_calendar_time(2024, 3, 31, 2, 30, 0)).is_empty());
+
+        // Clocks are set back from 03:00 to 02:00, so these happen twice (CEST first)
+        assert_eq!(
+            to_posix_time(make_calendar_time(2024, 10, 27, 2, 30, 0)),
+            [1729989000, 1729992600]
+        );
+        assert_eq!(
+            to_posix_time(make_calendar_time(2024, 10, 27, 2, 0, 0)),
+            [1729987200, 1729990800]
+        );
+        assert_eq!(
+            to_posix_time(make_calendar_time(2024, 10, 27, 3, 0, 0)),
+            [1729994400]
+        );
+
+        // Round trip of both ambiguous times
+        for time in [1729989000, 1729992600] {
+            let (calendar_time, _) = tz.to_calendar_time(time).unwrap();
+            assert_eq!(calendar_time, make_calendar_time(2024, 10, 27, 2, 30, 0));
+        }
+
+        for calendar_time in [
+            make_calendar_time(2023, 2, 29, 0, 0, 0),
+            make_calendar_time(2024, 13, 1, 0, 0, 0),
+            make_calendar_time(2024, 1, 1, 24, 0, 0),
+            make_calendar_time(2024, 1, 1, 0, 60, 0),
+        ] {
+            let rc = tz.to_posix_time(&calendar_time).unwrap_err();
+            assert!(rc::ResultInvalidCalendarTime::matches(rc));
+        }
+    }
+}
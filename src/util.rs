@@ -144,6 +144,21 @@ pub(crate) const fn const_usize_min(a: usize, b: usize) -> usize {
     // TODO: const min traits
     if a > b { b } else { a }
 }
+
+/// Reads a plain-data value from a byte slice at the given offset, without alignment requirements.
+///
+/// Returns [`None`] if the slice is too small to contain the value at that offset.
+///
+/// # Safety
+///
+/// `T` must be valid for any bit pattern, since it's read straight from the bytes: this is only meant for `repr(C)` structs made of integers/byte arrays (no `bool`s, enums, `char`s, references, etc.)
+pub(crate) unsafe fn read_unaligned_at<T: Copy>(data: &[u8], offset: usize) -> Option<T> {
+    let end = offset.checked_add(core::mem::size_of::<T>())?;
+    let bytes = data.get(offset..end)?;
+    // SAFETY: the range was just bounds-checked, and the caller guarantees any bit pattern is a valid `T`
+    Some(unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) })
+}
+
 #[allow(dead_code)]
 pub(crate) const fn const_usize_max(a: usize, b: usize) -> usize {
     // TODO: const min traits